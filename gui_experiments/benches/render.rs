@@ -0,0 +1,43 @@
+//! Compares the single threaded renderer with the tiled renderer on the output of
+//! [`guilib::gui_test`].
+//!
+//! run with `cargo bench -p gui_experiments`
+
+use std::time::{Duration, Instant};
+
+use imglib::{Rgba, RgbaImage};
+use renderlib::primitive::Primitive;
+
+const ITERATIONS: u32 = 20;
+
+fn bench(name: &str, primitives: &[Primitive], draw: impl Fn(&[Primitive], &mut RgbaImage)) {
+    let mut total = Duration::ZERO;
+    for _ in 0..ITERATIONS {
+        let mut image = RgbaImage::new(1000, 1000, Rgba::BLACK);
+        let start = Instant::now();
+        draw(primitives, &mut image);
+        total += start.elapsed();
+    }
+    println!("{name}: {:?} per frame", total / ITERATIONS);
+}
+
+fn main() {
+    let shapes = guilib::gui_test();
+    let primitives = drawlib::tesselate(&shapes);
+
+    let mut single = RgbaImage::new(1000, 1000, Rgba::BLACK);
+    renderlib::draw_primitives(&primitives, &mut single);
+    let mut tiled = RgbaImage::new(1000, 1000, Rgba::BLACK);
+    renderlib::tiled::draw_primitives_tiled(&primitives, &mut tiled);
+    assert_eq!(
+        single, tiled,
+        "the tiled renderer has to match the single threaded one"
+    );
+
+    bench("single threaded", &primitives, renderlib::draw_primitives);
+    bench(
+        "tiled",
+        &primitives,
+        renderlib::tiled::draw_primitives_tiled,
+    );
+}
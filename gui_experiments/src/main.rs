@@ -12,17 +12,9 @@ use mathlib::{bezier::CubicBezier, elliptical_arc::EllipticalArc, vectors::Vec2}
 fn main() {
     // let file = fs::read("../test-data/qoi_test_images/wikipedia_008.qoi").unwrap();
 
-    // let mut reader = corelib::reader::Reader::new_big_endian(&file, 0);
+    // let img = imglib::qoi::decode(&file).unwrap();
 
-    // let reader = qoilib::reader::QoiReader::new(&mut reader);
-
-    // let (header, img) = reader.read_entire_image();
-
-    // let mut file = fs::File::create("test.qoi").unwrap();
-
-    // let mut writer = qoilib::QoiWriter::new(header, &img, &mut file);
-
-    // writer.write();
+    // img.save("test.qoi").unwrap();
     let shapes = guilib::gui_test();
 
     // use drawlib::path::PathSeg::*;
@@ -101,7 +93,7 @@ fn main() {
 
     // println!("{a_glyf:?}");
 
-    // println!("font: {:?}", font.get_glyph('a'));
+    // println!("font: {:?}", font.outline(font.glyph_id('a').unwrap()));
 
     // let rect = Rect2::new(
     //     Vec2::splat(100.0),
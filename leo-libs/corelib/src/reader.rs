@@ -2,6 +2,11 @@
 
 pub trait ByteReader {
     fn read_byte(&self, data: &[u8], index: usize) -> u8;
+
+    /// whether numbers are stored with their most significant byte first
+    fn big_endian(&self) -> bool {
+        true
+    }
 }
 
 /// A reader that is both generic over the type of reader (usually big or little endian) and the
@@ -52,6 +57,15 @@ impl<'a, R: ByteReader> Reader<'a, R> {
     pub fn get_pos(&mut self) -> usize {
         self.index
     }
+
+    pub fn is_big_endian(&self) -> bool {
+        self.int.big_endian()
+    }
+
+    /// the amount of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.index)
+    }
 }
 
 pub struct BigEndianReader;
@@ -66,12 +80,17 @@ pub struct LittleEndianReader;
 
 impl ByteReader for LittleEndianReader {
     fn read_byte(&self, data: &[u8], index: usize) -> u8 {
-        data[data.len() - index - 1]
+        data[index]
+    }
+
+    fn big_endian(&self) -> bool {
+        false
     }
 }
 
 pub trait Readable {
-    /// implement read for your own type. You can assume the reader is big endian
+    /// implement read for your own type. Numbers read through the reader already have the
+    /// right byte order
     ///
     /// you also generally probably want to inline reads
     fn read(reader: &mut Reader<impl ByteReader>) -> Self;
@@ -83,7 +102,12 @@ macro_rules! impl_number_readable {
             fn read(reader: &mut Reader<impl ByteReader>) -> Self {
                 let dat1 = reader.read::<$lower>();
                 let dat2 = reader.read::<$lower>();
-                (dat1 as $number) << $shift | (dat2 as $number)
+                let (high, low) = if reader.is_big_endian() {
+                    (dat1, dat2)
+                } else {
+                    (dat2, dat1)
+                };
+                (high as $number) << $shift | (low as $number)
             }
         }
     };
@@ -101,7 +125,12 @@ impl Readable for u16 {
     fn read(reader: &mut Reader<impl ByteReader>) -> Self {
         let dat1 = reader.read_byte();
         let dat2 = reader.read_byte();
-        (dat1 as u16) << 8 | (dat2 as u16)
+        let (high, low) = if reader.is_big_endian() {
+            (dat1, dat2)
+        } else {
+            (dat2, dat1)
+        };
+        (high as u16) << 8 | (low as u16)
     }
 }
 
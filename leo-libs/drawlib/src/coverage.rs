@@ -0,0 +1,123 @@
+//! Anti-aliased rasterization of polygons into coverage bitmaps, used for glyphs.
+
+use corelib::types::Float;
+use imglib::Image;
+use mathlib::vectors::Vec2F;
+
+/// Rows are sampled this many times per pixel, columns are covered exactly
+const SUBSAMPLES: usize = 4;
+
+/// Fill the closed polygons with the non-zero rule. The coordinates are pixels of the
+/// `width` x `height` image, a value of 255 means the pixel is fully covered.
+pub fn rasterize(contours: &[Vec<Vec2F>], width: usize, height: usize) -> Image<u8> {
+    // the edges that are not horizontal, from top to bottom with their direction
+    let mut edges = Vec::new();
+    for contour in contours {
+        for (i, &a) in contour.iter().enumerate() {
+            let b = contour[(i + 1) % contour.len()];
+            if a.y < b.y {
+                edges.push((a, b, 1));
+            } else if a.y > b.y {
+                edges.push((b, a, -1));
+            }
+        }
+    }
+
+    let mut image = Image::new(width, height, 0);
+    let mut coverage = vec![0.0; width];
+    let mut crossings = Vec::new();
+    for y in 0..height {
+        coverage.fill(0.0);
+        for sample in 0..SUBSAMPLES {
+            let sample_y = y as Float + (sample as Float + 0.5) / SUBSAMPLES as Float;
+            crossings.clear();
+            for &(top, bottom, dir) in &edges {
+                if top.y <= sample_y && sample_y < bottom.y {
+                    let t = (sample_y - top.y) / (bottom.y - top.y);
+                    crossings.push((top.x + (bottom.x - top.x) * t, dir));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+            let mut winding = 0;
+            let mut span_start = 0.0;
+            for &(x, dir) in &crossings {
+                if winding == 0 {
+                    span_start = x;
+                }
+                winding += dir;
+                if winding == 0 {
+                    add_span(&mut coverage, span_start, x, 1.0 / SUBSAMPLES as Float);
+                }
+            }
+        }
+        for (pixel, value) in image.row_mut(y).iter_mut().zip(&coverage) {
+            *pixel = (value.min(1.0) * 255.0).round() as u8;
+        }
+    }
+    image
+}
+
+/// Add `weight` times the part of every pixel that lies between `start` and `end`
+fn add_span(coverage: &mut [Float], start: Float, end: Float, weight: Float) {
+    let width = coverage.len() as Float;
+    let (start, end) = (start.clamp(0.0, width), end.clamp(0.0, width));
+    if start >= end {
+        return;
+    }
+    let (first, last) = (start as usize, end as usize);
+    if first == last {
+        coverage[first] += (end - start) * weight;
+        return;
+    }
+    coverage[first] += (first as Float + 1.0 - start) * weight;
+    for pixel in &mut coverage[first + 1..last] {
+        *pixel += weight;
+    }
+    if last < coverage.len() {
+        coverage[last] += (end - last as Float) * weight;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn squares_and_holes() {
+        let square = |x0: Float, y0: Float, x1: Float, y1: Float| {
+            vec![
+                Vec2F::new(x0, y0),
+                Vec2F::new(x1, y0),
+                Vec2F::new(x1, y1),
+                Vec2F::new(x0, y1),
+            ]
+        };
+
+        let image = rasterize(&[square(1.0, 1.0, 3.0, 3.0)], 4, 4);
+        assert_eq!(image.row(0), [0, 0, 0, 0]);
+        assert_eq!(image.row(1), [0, 255, 255, 0]);
+        assert_eq!(image.row(2), [0, 255, 255, 0]);
+
+        // half pixels at the left and right, a quarter at the top
+        let image = rasterize(&[square(0.5, 0.75, 2.5, 2.0)], 3, 2);
+        assert_eq!(image.row(0), [32, 64, 32]);
+        assert_eq!(image.row(1), [128, 255, 128]);
+
+        // a hole wound the other way, and an overlapping square wound the same way
+        let mut hole = square(1.0, 1.0, 2.0, 2.0);
+        hole.reverse();
+        let image = rasterize(&[square(0.0, 0.0, 3.0, 3.0), hole], 3, 3);
+        assert_eq!(image.row(1), [255, 0, 255]);
+        let image = rasterize(
+            &[square(0.0, 0.0, 2.0, 1.0), square(1.0, 0.0, 3.0, 1.0)],
+            3,
+            1,
+        );
+        assert_eq!(image.row(0), [255, 255, 255]);
+
+        // shapes outside of the image are cut off
+        let image = rasterize(&[square(-5.0, -5.0, 1.0, 10.0)], 2, 2);
+        assert_eq!(image.row(1), [255, 0]);
+    }
+}
@@ -0,0 +1,389 @@
+//! Rasterized glyphs in a shared atlas, so that every glyph is only rasterized once per
+//! size. The least recently used glyphs make room when the atlas is full. Glyphs are
+//! hinted by the programs of their font unless that is turned off.
+
+use std::{collections::HashMap, sync::Arc};
+
+use corelib::types::Float;
+use imglib::Image;
+use mathlib::vectors::Vec2F;
+use ttflib::{tables::glyf::GlyphTable, Font, GlyphId, Hinter, Outline};
+
+use crate::{coverage::rasterize, text::outline_contours};
+
+/// The horizontal positions between two pixels a glyph is rasterized for
+pub const SUBPIXEL_STEPS: u8 = 4;
+
+/// Which glyph is rasterized how
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    /// see [`Font::id`]
+    pub font: u64,
+    pub glyph: GlyphId,
+    /// the font size in 1/64 pixels
+    pub size: u32,
+    /// the horizontal offset in 1/[`SUBPIXEL_STEPS`] pixels
+    pub subpixel: u8,
+}
+
+impl GlyphKey {
+    /// The key for a glyph drawn at the horizontal pixel position `x`, the rest of the
+    /// position is whole pixels
+    pub fn new(font: &Font, glyph: GlyphId, font_size: Float, x: Float) -> Self {
+        let steps = SUBPIXEL_STEPS as Float;
+        let subpixel = ((x - x.floor()) * steps).round() as u8 % SUBPIXEL_STEPS;
+        Self {
+            font: font.id(),
+            glyph,
+            size: (font_size * 64.0).round() as u32,
+            subpixel,
+        }
+    }
+
+    pub fn font_size(&self) -> Float {
+        self.size as Float / 64.0
+    }
+}
+
+/// Where a rasterized glyph is in the atlas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachedGlyph {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+    /// the position of the top left pixel relative to the pen on the baseline
+    pub left: i32,
+    pub top: i32,
+}
+
+#[derive(Debug)]
+struct Entry {
+    glyph: CachedGlyph,
+    /// the generation the glyph was last used in
+    last_used: u64,
+}
+
+/// A row of the atlas that glyphs up to its height are placed in from left to right
+#[derive(Debug)]
+struct Shelf {
+    y: usize,
+    height: usize,
+    /// where the unused part of the shelf starts
+    end: usize,
+    /// the places of evicted glyphs as x and width
+    free: Vec<(usize, usize)>,
+}
+
+#[derive(Debug)]
+pub struct GlyphCache {
+    /// shared with the texture materials that draw from it, it is copied when it changes
+    /// while a material still uses it
+    atlas: Arc<Image<u8>>,
+    shelves: Vec<Shelf>,
+    entries: HashMap<GlyphKey, Entry>,
+    generation: u64,
+    hinting: bool,
+    /// by font and size in 1/64 pixels, None if the font can't be hinted
+    hinters: HashMap<(u64, u32), Option<Hinter>>,
+}
+
+impl GlyphCache {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            atlas: Arc::new(Image::new(width, height, 0)),
+            shelves: Vec::new(),
+            entries: HashMap::new(),
+            generation: 0,
+            hinting: true,
+            hinters: HashMap::new(),
+        }
+    }
+
+    pub fn hinting(&self) -> bool {
+        self.hinting
+    }
+
+    /// Turn hinting on or off, the cached glyphs are dropped when it changes
+    pub fn set_hinting(&mut self, hinting: bool) {
+        if hinting != self.hinting {
+            self.hinting = hinting;
+            self.entries.clear();
+            self.shelves.clear();
+        }
+    }
+
+    pub fn atlas(&self) -> &Arc<Image<u8>> {
+        &self.atlas
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Start a new generation. The glyphs used since the last call are never evicted, so
+    /// call it before drawing a frame or a text.
+    pub fn next_generation(&mut self) {
+        self.generation += 1;
+    }
+
+    /// The place of a glyph in the atlas, it is rasterized if it is not cached yet. None
+    /// if the glyph can't be read or doesn't fit into the atlas next to the glyphs of the
+    /// current generation.
+    pub fn get(&mut self, font: &Font, key: GlyphKey) -> Option<CachedGlyph> {
+        debug_assert_eq!(font.id(), key.font, "the key belongs to another font");
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.last_used = self.generation;
+            return Some(entry.glyph);
+        }
+
+        let (outline, scale) = match self.hinted_outline(font, key) {
+            Some(outline) => (Outline::Glyf(outline), 1.0 / 64.0),
+            None => (
+                font.glyph_outline(key.glyph).ok()?,
+                font.scale(key.font_size()),
+            ),
+        };
+        let shift = key.subpixel as Float / SUBPIXEL_STEPS as Float;
+        let points: Vec<Vec2F> = outline
+            .points()
+            .into_iter()
+            .map(|(x, y)| Vec2F::new(x as Float * scale + shift, -y as Float * scale))
+            .collect();
+        let (left, top, right, bottom) = points.iter().fold(
+            (i32::MAX, i32::MAX, i32::MIN, i32::MIN),
+            |(left, top, right, bottom), p| {
+                (
+                    left.min(p.x.floor() as i32),
+                    top.min(p.y.floor() as i32),
+                    right.max(p.x.ceil() as i32),
+                    bottom.max(p.y.ceil() as i32),
+                )
+            },
+        );
+        if points.is_empty() {
+            // nothing to draw, like a space
+            let glyph = CachedGlyph {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+                left: 0,
+                top: 0,
+            };
+            self.insert(key, glyph);
+            return Some(glyph);
+        }
+
+        let (width, height) = ((right - left) as usize, (bottom - top) as usize);
+        if width > self.atlas.width || height > self.atlas.height {
+            return None;
+        }
+        let (x, y) = self.allocate(width, height)?;
+
+        let offset = Vec2F::new(shift - left as Float, -top as Float);
+        let contours = outline_contours(&outline, scale, offset);
+        let bitmap = rasterize(&contours, width, height);
+        Arc::make_mut(&mut self.atlas).blit(&bitmap, x as isize, y as isize);
+
+        let glyph = CachedGlyph {
+            x,
+            y,
+            width,
+            height,
+            left,
+            top,
+        };
+        self.insert(key, glyph);
+        Some(glyph)
+    }
+
+    /// The outline in 1/64 pixels, None if hinting is off or the font can't be hinted
+    fn hinted_outline(&mut self, font: &Font, key: GlyphKey) -> Option<GlyphTable> {
+        if !self.hinting {
+            return None;
+        }
+        let hinter = self
+            .hinters
+            .entry((key.font, key.size))
+            .or_insert_with(|| Hinter::new(font, key.font_size()).ok());
+        hinter.as_ref()?.outline(font, key.glyph).ok()
+    }
+
+    fn insert(&mut self, key: GlyphKey, glyph: CachedGlyph) {
+        let last_used = self.generation;
+        self.entries.insert(key, Entry { glyph, last_used });
+    }
+
+    /// Find room for a glyph, evicting the least recently used ones until it fits
+    fn allocate(&mut self, width: usize, height: usize) -> Option<(usize, usize)> {
+        loop {
+            if let Some(place) = self.find_place(width, height) {
+                return Some(place);
+            }
+
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.last_used < self.generation)
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key)?;
+            let glyph = self.entries.remove(&oldest)?.glyph;
+            if self.entries.is_empty() {
+                // start over when everything is gone, the shelves may not fit anymore
+                self.shelves.clear();
+            } else if glyph.width > 0 {
+                if let Some(shelf) = self.shelves.iter_mut().find(|s| s.y == glyph.y) {
+                    shelf.free.push((glyph.x, glyph.width));
+                }
+            }
+        }
+    }
+
+    fn find_place(&mut self, width: usize, height: usize) -> Option<(usize, usize)> {
+        let atlas_width = self.atlas.width;
+        // the shelf that wastes the least height
+        let shelves = self.shelves.iter_mut().filter(|s| s.height >= height);
+        let mut best: Option<&mut Shelf> = None;
+        for shelf in shelves {
+            let fits = shelf.end + width <= atlas_width
+                || shelf.free.iter().any(|&(_, free)| free >= width);
+            if fits && best.as_ref().is_none_or(|b| shelf.height < b.height) {
+                best = Some(shelf);
+            }
+        }
+        if let Some(shelf) = best {
+            // reuse the smallest free place that fits before the end of the shelf
+            let free = (0..shelf.free.len())
+                .filter(|&i| shelf.free[i].1 >= width)
+                .min_by_key(|&i| shelf.free[i].1);
+            if let Some(i) = free {
+                let (x, free_width) = shelf.free.swap_remove(i);
+                if free_width > width {
+                    shelf.free.push((x + width, free_width - width));
+                }
+                return Some((x, shelf.y));
+            }
+            let x = shelf.end;
+            shelf.end += width;
+            return Some((x, shelf.y));
+        }
+
+        let y = self.shelves.last().map_or(0, |s| s.y + s.height);
+        if y + height > self.atlas.height {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            end: width,
+            free: Vec::new(),
+        });
+        Some((0, y))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roboto() -> Vec<u8> {
+        std::fs::read("../../test-data/Roboto-Regular.ttf").unwrap()
+    }
+
+    #[test]
+    fn cached_once() {
+        let data = roboto();
+        let font = ttflib::load_ttf(&data).unwrap();
+        let mut cache = GlyphCache::new(256, 256);
+        cache.set_hinting(false);
+        let a = font.glyph_id('a').unwrap();
+
+        let key = GlyphKey::new(&font, a, 32.0, 10.0);
+        let glyph = cache.get(&font, key).unwrap();
+        // 'a' goes from about 1.7 to 15.7 pixels and from 17.2 to -0.3 at this size
+        assert_eq!((glyph.left, glyph.top), (1, -18));
+        assert_eq!((glyph.width, glyph.height), (15, 19));
+        let atlas = cache.atlas().clone();
+        let covered = atlas.pixels().filter(|p| p.2 > 0).count();
+        assert!(covered > 100, "{covered} pixels are covered");
+
+        // the same glyph doesn't change the atlas
+        assert_eq!(cache.get(&font, key), Some(glyph));
+        assert!(Arc::ptr_eq(&atlas, cache.atlas()));
+
+        // other sizes and subpixel positions are separate glyphs
+        let shifted = GlyphKey::new(&font, a, 32.0, 10.5);
+        assert_eq!(shifted.subpixel, 2);
+        assert_ne!(cache.get(&font, shifted).unwrap().x, glyph.x);
+        cache
+            .get(&font, GlyphKey::new(&font, a, 12.0, 0.0))
+            .unwrap();
+        assert_eq!(cache.len(), 3);
+        // the old atlas is still there for the materials that use it
+        assert_eq!(atlas.pixels().filter(|p| p.2 > 0).count(), covered);
+
+        let space = font.glyph_id(' ').unwrap();
+        let space = cache.get(&font, GlyphKey::new(&font, space, 32.0, 0.0));
+        assert_eq!(space.map(|g| g.width), Some(0));
+    }
+
+    #[test]
+    fn least_recently_used_are_evicted() {
+        let data = roboto();
+        let font = ttflib::load_ttf(&data).unwrap();
+        // room for about two glyphs
+        let mut cache = GlyphCache::new(40, 32);
+        cache.set_hinting(false);
+        let key = |c| GlyphKey::new(&font, font.glyph_id(c).unwrap(), 32.0, 0.0);
+
+        cache.get(&font, key('a')).unwrap();
+        cache.next_generation();
+        cache.get(&font, key('b')).unwrap();
+        cache.next_generation();
+        cache.get(&font, key('b')).unwrap();
+        cache.get(&font, key('c')).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert!(cache.entries.contains_key(&key('b')));
+        assert!(!cache.entries.contains_key(&key('a')));
+
+        // the glyphs of the current generation stay even if a new one doesn't fit
+        assert_eq!(cache.get(&font, key('d')), None);
+        assert_eq!(cache.len(), 2);
+        cache.next_generation();
+        assert!(cache.get(&font, key('d')).is_some());
+
+        // too large for the atlas
+        let big = GlyphKey::new(&font, font.glyph_id('W').unwrap(), 200.0, 0.0);
+        assert_eq!(cache.get(&font, big), None);
+    }
+
+    #[test]
+    fn hinted() {
+        let data = roboto();
+        let font = ttflib::load_ttf(&data).unwrap();
+        let mut cache = GlyphCache::new(256, 256);
+        assert!(cache.hinting());
+        let key = GlyphKey::new(&font, font.glyph_id('H').unwrap(), 12.0, 0.0);
+        // the coverage of the top row and of the crossbar of 'H'
+        let rows = |cache: &mut GlyphCache| {
+            let glyph = cache.get(&font, key).unwrap();
+            assert_eq!((glyph.top, glyph.height), (-9, 9));
+            let atlas = cache.atlas();
+            let row = |y| atlas.get_pixel(glyph.x + 1, glyph.y + y);
+            let bar = atlas.get_pixel(glyph.x + 4, glyph.y + 4);
+            (row(0), bar)
+        };
+
+        // the cap height and the crossbar fall on whole pixels
+        assert_eq!(rows(&mut cache), (255, 255));
+        cache.set_hinting(false);
+        assert!(cache.is_empty());
+        let (top, bar) = rows(&mut cache);
+        assert!(top < 200 && bar < 200, "{top} {bar}");
+    }
+}
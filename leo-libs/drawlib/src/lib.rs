@@ -2,14 +2,17 @@ use drawable::Drawable;
 use path::Path;
 use renderlib::primitive::Primitive;
 
+pub mod coverage;
 pub mod draw_target;
 pub mod drawable;
+pub mod glyph_cache;
 pub mod path;
 pub mod path_attr;
 pub mod ptri;
 pub mod shape_primitive;
 pub mod stroking;
 pub mod text;
+pub mod text_layout;
 
 pub fn tesselate(primitives: &[Path]) -> Vec<Primitive> {
     primitives
@@ -1,12 +1,54 @@
 use corelib::types::Float;
-use mathlib::vectors::Vec2;
-use ttflib::Font;
+use mathlib::{
+    color::ColA,
+    vectors::{Vec2, Vec2F},
+};
+use renderlib::{
+    material::{Material, Texture},
+    primitive::{Mesh, MeshType, Primitive},
+};
+use ttflib::{
+    tables::{cff::Segment, glyf::GlyphTable},
+    Font, GlyphId, Outline,
+};
 
-use crate::path::Path;
+use crate::{
+    glyph_cache::{GlyphCache, GlyphKey},
+    path::Path,
+    text_layout::{layout_text, LayoutOptions, TextLayout},
+};
 
-pub fn get_char_path(c: char, font: &Font) -> Path {
-    // TODO: improve this / make it more performant (lots of low hanging fruit)
-    let glyf = font.get_glyph(c);
+/// The outline of a character as a path, with the ascender at the top and the pen at the
+/// left. Use [`text_primitives`] to draw text.
+pub fn get_char_path(c: char, font: &Font, font_size: Float) -> Path {
+    let scale = font.scale(font_size);
+    let ascender = font.metrics().ascender as Float;
+    // a glyph that can't be read draws nothing
+    let id = font.glyph_id(c).unwrap_or(GlyphId::NOTDEF);
+    let glyf = match font.glyph_outline(id) {
+        Ok(Outline::Glyf(glyf)) => glyf,
+        Ok(Outline::Cff(segments)) => {
+            let point = |(x, y): (f32, f32)| Vec2::new(x as Float, ascender - y as Float) * scale;
+            let mut path = Path::new();
+            for (i, segment) in segments.iter().enumerate() {
+                match *segment {
+                    Segment::MoveTo(p) => {
+                        if i > 0 {
+                            path.close_path();
+                        }
+                        path.move_to(point(p));
+                    }
+                    Segment::LineTo(p) => path.line_to(point(p)),
+                    Segment::CurveTo(a, b, c) => path.c_bezier_to(point(a), point(b), point(c)),
+                }
+            }
+            if !segments.is_empty() {
+                path.close_path();
+            }
+            return path;
+        }
+        Err(_) => return Path::new(),
+    };
     let mut path = Path::new();
     let mut last_was_on_curve = false;
     let mut last_off_curve = Vec2::ZERO;
@@ -17,7 +59,7 @@ pub fn get_char_path(c: char, font: &Font) -> Path {
         .enumerate()
         .zip(glyf.flags)
     {
-        let p = Vec2::new(*x as Float, 2000.0 - y as Float) / 4.0;
+        let p = Vec2::new(*x as Float, ascender - y as Float) * scale;
 
         if i == 0 || glyf.end_pts_of_contours.contains(&(i as u16 - 1)) {
             path.move_to(p);
@@ -43,3 +85,192 @@ pub fn get_char_path(c: char, font: &Font) -> Path {
     }
     path
 }
+
+/// Points along the contours of an outline of either kind, see [`glyph_contours`]
+pub fn outline_contours(outline: &Outline, scale: Float, offset: Vec2F) -> Vec<Vec<Vec2F>> {
+    let segments = match outline {
+        Outline::Glyf(glyph) => return glyph_contours(glyph, scale, offset),
+        Outline::Cff(segments) => segments,
+    };
+    let point = |(x, y): (f32, f32)| {
+        Vec2F::new(
+            x as Float * scale + offset.x,
+            -y as Float * scale + offset.y,
+        )
+    };
+    let mut contours: Vec<Vec<Vec2F>> = Vec::new();
+    for segment in segments {
+        match (*segment, contours.last_mut()) {
+            (Segment::MoveTo(p), _) => contours.push(vec![point(p)]),
+            (Segment::LineTo(p), Some(contour)) => contour.push(point(p)),
+            (Segment::CurveTo(a, b, c), Some(contour)) => {
+                cubic_to(contour, point(a), point(b), point(c));
+            }
+            // the charstrings always start with a move
+            (_, None) => {}
+        }
+    }
+    // the contours are closed, a last point on the start is not needed
+    for contour in &mut contours {
+        if contour.len() > 1 && contour.first() == contour.last() {
+            contour.pop();
+        }
+    }
+    contours
+}
+
+/// Points along the contours of a glyph, with the curves split into lines. Font units are
+/// multiplied with `scale`, y is flipped to point down and `offset` is added.
+pub fn glyph_contours(glyph: &GlyphTable, scale: Float, offset: Vec2F) -> Vec<Vec<Vec2F>> {
+    let point = |i: usize| {
+        Vec2F::new(
+            glyph.x_coordinates[i] as Float * scale + offset.x,
+            -glyph.y_coordinates[i] as Float * scale + offset.y,
+        )
+    };
+    let num_points = glyph.flags.len().min(glyph.x_coordinates.len());
+
+    let mut contours = Vec::new();
+    let mut start = 0;
+    for &end in &glyph.end_pts_of_contours {
+        let end = (end as usize + 1).min(num_points);
+        if end <= start {
+            continue;
+        }
+        let on_curve = |i: usize| glyph.flags[i].on_curve_point;
+        let len = end - start;
+        // start at a point on the curve, between two control points if there is none
+        let first = (0..len).find(|&i| on_curve(start + i));
+        let begin = match first {
+            Some(i) => point(start + i),
+            None => (point(start) + point(end - 1)) / 2.0,
+        };
+        let first = first.unwrap_or(0);
+
+        let mut contour = vec![begin];
+        let mut control = None;
+        for step in 1..=len {
+            let i = start + (first + step) % len;
+            let p = point(i);
+            match (on_curve(i), control) {
+                (true, None) => contour.push(p),
+                (true, Some(c)) => {
+                    quad_to(&mut contour, c, p);
+                    control = None;
+                }
+                (false, None) => control = Some(p),
+                (false, Some(c)) => {
+                    // two control points imply a point on the curve between them
+                    quad_to(&mut contour, c, (c + p) / 2.0);
+                    control = Some(p);
+                }
+            }
+        }
+        // an off curve start closes the contour with a curve to it
+        if let Some(c) = control {
+            quad_to(&mut contour, c, begin);
+        }
+        contour.pop();
+        contours.push(contour);
+        start = end;
+    }
+    contours
+}
+
+/// Add the points of a quadratic curve from the last point of the contour
+fn quad_to(contour: &mut Vec<Vec2F>, control: Vec2F, to: Vec2F) {
+    let from = *contour.last().unwrap();
+    // about one line per two pixels of the control polygon
+    let length = (control - from).length() + (to - control).length();
+    let steps = (length / 2.0).ceil().clamp(1.0, 32.0) as usize;
+    for i in 1..=steps {
+        let t = i as Float / steps as Float;
+        let a = from + (control - from) * t;
+        let b = control + (to - control) * t;
+        contour.push(a + (b - a) * t);
+    }
+}
+
+/// Add the points of a cubic curve from the last point of the contour
+fn cubic_to(contour: &mut Vec<Vec2F>, control1: Vec2F, control2: Vec2F, to: Vec2F) {
+    let from = *contour.last().unwrap();
+    let length =
+        (control1 - from).length() + (control2 - control1).length() + (to - control2).length();
+    let steps = (length / 2.0).ceil().clamp(1.0, 32.0) as usize;
+    for i in 1..=steps {
+        let t = i as Float / steps as Float;
+        let u = 1.0 - t;
+        contour.push(
+            from * (u * u * u)
+                + control1 * (3.0 * u * u * t)
+                + control2 * (3.0 * u * t * t)
+                + to * (t * t * t),
+        );
+    }
+}
+
+/// Shape a text and draw its glyphs from the glyph cache. `origin` is where the first line
+/// starts on the baseline, lines only break at the line breaks in the text.
+pub fn text_primitives(
+    text: &str,
+    font: &Font,
+    font_size: Float,
+    origin: Vec2F,
+    color: ColA,
+    cache: &mut GlyphCache,
+) -> Vec<Primitive> {
+    let options = LayoutOptions {
+        font_size,
+        ..Default::default()
+    };
+    let layout = layout_text(text, font, &options);
+    let top = origin - Vec2F::new(0.0, layout.lines[0].baseline);
+    layout_primitives(&layout, font, top, color, cache)
+}
+
+/// Draw the glyphs of a layout from the glyph cache, `origin` is the top left corner of the
+/// layout
+pub fn layout_primitives(
+    layout: &TextLayout,
+    font: &Font,
+    origin: Vec2F,
+    color: ColA,
+    cache: &mut GlyphCache,
+) -> Vec<Primitive> {
+    cache.next_generation();
+
+    // rasterize all glyphs first so that the primitives share one version of the atlas
+    let mut placed = Vec::new();
+    for glyph in layout.glyphs() {
+        let x = origin.x + glyph.pos.x;
+        let y = (origin.y + glyph.pos.y).round();
+        let key = GlyphKey::new(font, glyph.id, layout.font_size, x);
+        let Some(cached) = cache.get(font, key) else {
+            continue;
+        };
+        if cached.width > 0 {
+            placed.push((cached, Vec2F::new(x.floor(), y)));
+        }
+    }
+
+    let atlas = cache.atlas();
+    placed
+        .into_iter()
+        .map(|(cached, pen)| {
+            let min = pen + Vec2F::new(cached.left as Float, cached.top as Float);
+            let max = min + Vec2F::new(cached.width as Float, cached.height as Float);
+            Primitive {
+                mesh: Mesh {
+                    ty: MeshType::Triangle,
+                    vertices: vec![min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)],
+                    indices: vec![0, 1, 2, 0, 2, 3],
+                },
+                material: Material::Texture(Texture {
+                    image: atlas.clone(),
+                    origin: min - Vec2F::new(cached.x as Float, cached.y as Float),
+                    color,
+                }),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,623 @@
+//! Breaking shaped text into lines that fit a width and aligning them. Lines break at the
+//! places a simplified version of the Unicode line breaking algorithm (UAX #14) allows:
+//! after spaces and hyphens and around ideographs, but not at no-break spaces or before
+//! closing punctuation. A word that is wider than a line is broken between its grapheme
+//! clusters.
+//!
+//! Every paragraph is ordered with the bidirectional algorithm, the runs of one direction
+//! are shaped separately and the runs of a line are placed in display order.
+
+use std::ops::Range;
+
+use corelib::types::Float;
+use mathlib::vectors::Vec2F;
+use ttflib::{Features, Font, GlyphId, PositionedGlyph};
+use unicodelib::{
+    bidi::{mirrored, BidiParagraph, Direction},
+    grapheme::{grapheme_break, grapheme_indices, GraphemeBreak},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// the space between words is stretched so that the lines fill the width, except for
+    /// the last line of a paragraph which is aligned to the side the paragraph starts at
+    Justify,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutOptions {
+    /// in pixels per em
+    pub font_size: Float,
+    /// lines that are wider are broken, infinite for no breaks other than line breaks in
+    /// the text
+    pub max_width: Float,
+    pub align: TextAlign,
+    /// the direction of the paragraphs, None for the direction of their first letter
+    pub direction: Option<Direction>,
+    pub features: Features,
+}
+
+impl Default for LayoutOptions {
+    fn default() -> Self {
+        Self {
+            font_size: 16.0,
+            max_width: Float::INFINITY,
+            align: TextAlign::Left,
+            direction: None,
+            features: Features::default(),
+        }
+    }
+}
+
+/// A glyph placed in a layout, in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LaidOutGlyph {
+    pub id: GlyphId,
+    /// the byte index of the first character of the text the glyph stands for
+    pub cluster: usize,
+    /// where to draw the glyph, on the baseline relative to the top left of the layout
+    pub pos: Vec2F,
+    pub advance: Float,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub glyphs: Vec<LaidOutGlyph>,
+    /// the bytes of the text that are on the line, with the spaces it ends with but
+    /// without the line break
+    pub text: Range<usize>,
+    /// from the left edge of the layout
+    pub x: Float,
+    /// from the top of the layout
+    pub baseline: Float,
+    /// without the spaces the line ends with
+    pub width: Float,
+    /// the line is part of a right to left paragraph
+    pub rtl: bool,
+}
+
+/// Lines of glyphs, see [`layout_text`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLayout {
+    pub lines: Vec<Line>,
+    pub font_size: Float,
+    /// of the widest line, or the maximum width if the lines are aligned to it
+    pub width: Float,
+    pub height: Float,
+}
+
+impl TextLayout {
+    pub fn size(&self) -> Vec2F {
+        Vec2F::new(self.width, self.height)
+    }
+
+    pub fn glyphs(&self) -> impl Iterator<Item = &LaidOutGlyph> {
+        self.lines.iter().flat_map(|line| &line.glyphs)
+    }
+}
+
+/// A part of a paragraph that isn't broken, the glyphs it consists of and its width with
+/// and without the spaces at its end
+struct Segment {
+    glyphs: Range<usize>,
+    width: Float,
+    visible: Float,
+}
+
+/// Shape the runs of one direction of a paragraph. The glyphs are in the order of the text
+/// with the byte index of their cluster in the whole text.
+fn shape_runs(
+    text: &str,
+    paragraph: &Range<usize>,
+    bidi: &BidiParagraph,
+    font: &Font,
+    features: &Features,
+) -> Vec<(PositionedGlyph, usize)> {
+    let mut glyphs = Vec::new();
+    let mut start = 0;
+    let levels = &bidi.levels;
+    while start < levels.len() {
+        let end = (start..levels.len())
+            .find(|&i| levels[i] != levels[start])
+            .unwrap_or(levels.len());
+        let run = &text[paragraph.start + start..paragraph.start + end];
+        let shaped = if levels[start] % 2 == 1 {
+            // mirrored characters of the same length keep the clusters in place
+            let mirrored: String = run
+                .chars()
+                .map(|c| {
+                    mirrored(c)
+                        .filter(|m| m.len_utf8() == c.len_utf8())
+                        .unwrap_or(c)
+                })
+                .collect();
+            ttflib::shape(&mirrored, font, features)
+        } else {
+            ttflib::shape(run, font, features)
+        };
+        let offset = paragraph.start + start;
+        glyphs.extend(
+            shaped
+                .into_iter()
+                .map(|glyph| (glyph, offset + glyph.cluster)),
+        );
+        start = end;
+    }
+    glyphs
+}
+
+/// Shape the text and break it into lines that are at most `max_width` wide, unless a
+/// single grapheme cluster is wider. Every paragraph has at least one line, also an empty
+/// one.
+pub fn layout_text(text: &str, font: &Font, options: &LayoutOptions) -> TextLayout {
+    let scale = font.scale(options.font_size);
+    let metrics = font.metrics();
+    let line_height = metrics.line_height() as Float * scale;
+    let ascender = metrics.ascender as Float * scale;
+
+    let mut lines = Vec::new();
+    for paragraph in paragraphs(text) {
+        let paragraph_text = &text[paragraph.clone()];
+        let bidi = BidiParagraph::new(paragraph_text, options.direction);
+        let rtl = bidi.direction() == Direction::RightToLeft;
+        let glyphs = shape_runs(text, &paragraph, &bidi, font, &options.features);
+        let clusters: Vec<usize> = grapheme_indices(paragraph_text)
+            .map(|(i, _)| paragraph.start + i)
+            .collect();
+        let advance = |i: usize| glyphs[i].0.x_advance as Float * scale;
+        let is_space = |i: usize| text[glyphs[i].1..].starts_with(char::is_whitespace);
+
+        // the glyphs between two break opportunities
+        let mut segments = Vec::new();
+        let mut start = 0;
+        for end in break_opportunities(paragraph_text)
+            .into_iter()
+            .map(|i| paragraph.start + i)
+            .chain(Some(paragraph.end))
+        {
+            let mut glyph_end = start;
+            while glyph_end < glyphs.len() && glyphs[glyph_end].1 < end {
+                glyph_end += 1;
+            }
+            if glyph_end == start {
+                continue;
+            }
+            let width = (start..glyph_end).map(advance).sum::<Float>();
+            let trailing = (start..glyph_end)
+                .rev()
+                .take_while(|&i| is_space(i))
+                .map(advance)
+                .sum::<Float>();
+            let segment = Segment {
+                glyphs: start..glyph_end,
+                width,
+                visible: width - trailing,
+            };
+            if segment.visible > options.max_width {
+                // too wide for any line, every grapheme cluster may start a line
+                let mut cluster_start = start;
+                for i in start + 1..=glyph_end {
+                    let boundary = i == glyph_end
+                        || glyphs[i].1 != glyphs[i - 1].1
+                            && clusters.binary_search(&glyphs[i].1).is_ok();
+                    if !boundary {
+                        continue;
+                    }
+                    let width = (cluster_start..i).map(advance).sum::<Float>();
+                    segments.push(Segment {
+                        glyphs: cluster_start..i,
+                        width,
+                        visible: if is_space(cluster_start) { 0.0 } else { width },
+                    });
+                    cluster_start = i;
+                }
+            } else {
+                segments.push(segment);
+            }
+            start = glyph_end;
+        }
+
+        // as many segments on a line as fit
+        let mut line_glyphs = 0..0;
+        let (mut width, mut visible) = (0.0, 0.0);
+        let mut breaks = Vec::new();
+        for segment in segments {
+            if !line_glyphs.is_empty() && width + segment.visible > options.max_width {
+                breaks.push((line_glyphs.clone(), visible));
+                line_glyphs = segment.glyphs.start..segment.glyphs.start;
+                width = 0.0;
+            }
+            line_glyphs.end = segment.glyphs.end;
+            visible = width + segment.visible;
+            width += segment.width;
+        }
+        breaks.push((line_glyphs, visible));
+
+        let num_breaks = breaks.len();
+        for (n, (range, width)) in breaks.into_iter().enumerate() {
+            let text_start = glyphs.get(range.start).map_or(paragraph.start, |g| g.1);
+            let text_end = glyphs.get(range.end).map_or(paragraph.end, |g| g.1);
+            let last = n + 1 == num_breaks;
+
+            // the extra space every space between words gets when the line is justified
+            let content_end = range
+                .clone()
+                .rev()
+                .find(|&i| !is_space(i))
+                .map_or(range.start, |i| i + 1);
+            let spaces = (range.start..content_end).filter(|&i| is_space(i)).count();
+            let stretch = match options.align {
+                TextAlign::Justify if !last && spaces > 0 && options.max_width.is_finite() => {
+                    (options.max_width - width).max(0.0) / spaces as Float
+                }
+                _ => 0.0,
+            };
+
+            let baseline = ascender + lines.len() as Float * line_height;
+            // the spaces at the end of a right to left line are left of it
+            let trailing = (content_end..range.end).map(advance).sum::<Float>();
+            let mut pen = if rtl { -trailing } else { 0.0 };
+            let mut line = Line {
+                glyphs: Vec::with_capacity(range.len()),
+                text: text_start..text_end,
+                x: 0.0,
+                baseline,
+                width: if stretch > 0.0 {
+                    options.max_width
+                } else {
+                    width
+                },
+                rtl,
+            };
+            let line_range = text_start - paragraph.start..text_end - paragraph.start;
+            let runs = bidi.visual_runs(paragraph_text, line_range);
+            let order = runs.iter().flat_map(|run| {
+                let in_run = range
+                    .clone()
+                    .filter(|&i| run.range.contains(&(glyphs[i].1 - paragraph.start)));
+                let mut in_run: Vec<usize> = in_run.collect();
+                if run.is_rtl() {
+                    in_run.reverse();
+                }
+                in_run
+            });
+            for i in order {
+                let (glyph, cluster) = glyphs[i];
+                let mut advance = advance(i);
+                if is_space(i) && i < content_end {
+                    advance += stretch;
+                }
+                line.glyphs.push(LaidOutGlyph {
+                    id: glyph.id,
+                    cluster,
+                    pos: Vec2F::new(
+                        pen + glyph.x_offset as Float * scale,
+                        baseline - glyph.y_offset as Float * scale,
+                    ),
+                    advance,
+                });
+                pen += advance;
+            }
+            lines.push(line);
+        }
+    }
+
+    let widest = lines.iter().map(|l| l.width).fold(0.0, Float::max);
+    let width = match options.align {
+        TextAlign::Left => widest,
+        _ if options.max_width.is_finite() => options.max_width.max(widest),
+        _ => widest,
+    };
+    for line in &mut lines {
+        let x = match options.align {
+            TextAlign::Left => 0.0,
+            TextAlign::Justify if line.rtl => width - line.width,
+            TextAlign::Justify => 0.0,
+            TextAlign::Center => (width - line.width) / 2.0,
+            TextAlign::Right => width - line.width,
+        };
+        line.x = x;
+        for glyph in &mut line.glyphs {
+            glyph.pos.x += x;
+        }
+    }
+
+    TextLayout {
+        font_size: options.font_size,
+        height: lines.len() as Float * line_height,
+        width,
+        lines,
+    }
+}
+
+/// The byte ranges of the text between mandatory line breaks, without the breaks
+fn paragraphs(text: &str) -> Vec<Range<usize>> {
+    let mut paragraphs = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let is_break = matches!(
+            c,
+            '\n' | '\r' | '\u{b}' | '\u{c}' | '\u{85}' | '\u{2028}' | '\u{2029}'
+        );
+        if !is_break {
+            continue;
+        }
+        paragraphs.push(start..i);
+        start = i + c.len_utf8();
+        // CR LF is one break
+        if c == '\r' && chars.next_if(|&(_, c)| c == '\n').is_some() {
+            start += 1;
+        }
+    }
+    paragraphs.push(start..text.len());
+    paragraphs
+}
+
+/// How a character takes part in line breaking, a small subset of the UAX #14 classes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BreakClass {
+    Space,
+    /// a zero width space, allows a break after it
+    ZeroWidthSpace,
+    /// no-break spaces and word joiners prohibit breaks on both sides
+    Glue,
+    /// hyphens and dashes, allow a break after them
+    Hyphen,
+    /// opening brackets and quotes, prohibit a break after them
+    Open,
+    /// closing brackets and punctuation that mustn't start a line
+    Close,
+    /// ideographs and kana, which allow breaks on both sides
+    Ideographic,
+    Other,
+}
+
+fn break_class(c: char) -> BreakClass {
+    match c {
+        '\u{200b}' => BreakClass::ZeroWidthSpace,
+        '\u{a0}' | '\u{2007}' | '\u{202f}' | '\u{2060}' | '\u{feff}' => BreakClass::Glue,
+        c if c.is_whitespace() => BreakClass::Space,
+        '-' | '\u{ad}' | '\u{2010}' | '\u{2012}' | '\u{2013}' => BreakClass::Hyphen,
+        '(' | '[' | '{' | '\u{ab}' | '\u{2018}' | '\u{201c}' | '\u{3008}' | '\u{300c}'
+        | '\u{ff08}' => BreakClass::Open,
+        ')' | ']' | '}' | '!' | '?' | ',' | '.' | ':' | ';' | '/' | '\u{bb}' | '\u{2019}'
+        | '\u{201d}' | '\u{3001}' | '\u{3002}' | '\u{3009}' | '\u{300d}' | '\u{ff09}'
+        | '\u{ff0c}' | '\u{ff01}' | '\u{ff1f}' => BreakClass::Close,
+        '\u{2e80}'..='\u{2fff}'
+        | '\u{3040}'..='\u{30ff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{4e00}'..='\u{9fff}'
+        | '\u{ac00}'..='\u{d7af}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{ff00}'..='\u{ff60}'
+        | '\u{20000}'..='\u{3fffd}' => BreakClass::Ideographic,
+        _ => BreakClass::Other,
+    }
+}
+
+/// The byte indices of the characters a line may start with, apart from the first one
+fn break_opportunities(text: &str) -> Vec<usize> {
+    use BreakClass::*;
+
+    let mut breaks = Vec::new();
+    let mut chars = text.char_indices();
+    let Some((_, first)) = chars.next() else {
+        return breaks;
+    };
+    let mut before = break_class(first);
+    // the class before a run of spaces, a break after the spaces depends on it
+    let mut before_spaces = before;
+    // the class before `before`, the start of the text counts as a space
+    let mut earlier = Space;
+    for (i, c) in chars {
+        // marks and joiners belong to the character before them
+        if matches!(
+            grapheme_break(c),
+            GraphemeBreak::Extend | GraphemeBreak::ZWJ | GraphemeBreak::SpacingMark
+        ) {
+            continue;
+        }
+        let class = break_class(c);
+        let allowed = match (before, class) {
+            (_, Space | Glue | Close | ZeroWidthSpace) => false,
+            (ZeroWidthSpace, _) => true,
+            (Glue | Open, _) => false,
+            (Space, _) => before_spaces != Open && before_spaces != Glue,
+            // not after a hyphen that starts a word or before a number like in "-5"
+            (Hyphen, _) => earlier == Other && !c.is_numeric() && class != Hyphen,
+            (Ideographic, _) | (_, Ideographic) => true,
+            _ => false,
+        };
+        if allowed {
+            breaks.push(i);
+        }
+        if class != Space {
+            before_spaces = class;
+        }
+        earlier = before;
+        before = class;
+    }
+    breaks
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roboto() -> Vec<u8> {
+        std::fs::read("../../test-data/Roboto-Regular.ttf").unwrap()
+    }
+
+    #[test]
+    fn breaks() {
+        assert_eq!(break_opportunities("Hello world"), [6]);
+        assert_eq!(break_opportunities("Hello   world"), [8]);
+        assert_eq!(break_opportunities("well-known -5 --"), [5, 11, 14]);
+        assert_eq!(break_opportunities("a (b), c!"), [2, 7]);
+        assert_eq!(break_opportunities("x\u{a0}y z"), [5]);
+        assert_eq!(break_opportunities("日本語。"), [3, 6]);
+        assert_eq!(break_opportunities("a\u{200b}b"), [4]);
+        // not before a mark
+        assert_eq!(break_opportunities("日\u{301}本"), [5]);
+        assert_eq!(break_opportunities(""), []);
+
+        assert_eq!(paragraphs("a\nb\r\nc\r"), [0..1, 2..3, 5..6, 7..7]);
+        assert_eq!(paragraphs("").len(), 1);
+    }
+
+    #[test]
+    fn lines_and_alignment() {
+        let data = roboto();
+        let font = ttflib::load_ttf(&data).unwrap();
+        let options = LayoutOptions {
+            font_size: 20.0,
+            ..Default::default()
+        };
+        let metrics = font.metrics();
+        let line_height = metrics.line_height() as Float * font.scale(20.0);
+
+        let single = layout_text("Hello world", &font, &options);
+        assert_eq!(single.lines.len(), 1);
+        assert_eq!(single.height, line_height);
+        let advances: Float = single.glyphs().map(|g| g.advance).sum();
+        assert!((single.width - advances).abs() < 0.01);
+        let hello = layout_text("Hello", &font, &options).width;
+
+        // the space at the end of the first line doesn't count
+        let narrow = LayoutOptions {
+            max_width: single.width - 1.0,
+            ..options.clone()
+        };
+        let wrapped = layout_text("Hello world", &font, &narrow);
+        assert_eq!(wrapped.lines.len(), 2);
+        assert_eq!(wrapped.lines[0].text, 0..6);
+        assert_eq!(wrapped.lines[1].text, 6..11);
+        assert!((wrapped.lines[0].width - hello).abs() < 0.01);
+        assert_eq!(wrapped.lines[1].glyphs[0].pos.x, 0.0);
+        assert_eq!(
+            wrapped.lines[1].baseline - wrapped.lines[0].baseline,
+            line_height
+        );
+        assert_eq!(wrapped.height, 2.0 * line_height);
+        assert_eq!(wrapped.width, wrapped.lines[1].width.max(hello));
+
+        // line breaks in the text, also at the end
+        let paragraphs = layout_text("Hello\n\nworld\n", &font, &options);
+        assert_eq!(paragraphs.lines.len(), 4);
+        assert!(paragraphs.lines[1].glyphs.is_empty());
+        assert_eq!(paragraphs.lines[2].text, 7..12);
+
+        let aligned = |align| {
+            let options = LayoutOptions {
+                max_width: 200.0,
+                align,
+                ..options.clone()
+            };
+            layout_text("Hello", &font, &options)
+        };
+        assert_eq!(aligned(TextAlign::Left).width, hello);
+        let right = aligned(TextAlign::Right);
+        assert_eq!(right.width, 200.0);
+        assert!((right.lines[0].x - (200.0 - hello)).abs() < 0.01);
+        let center = aligned(TextAlign::Center);
+        assert!((center.lines[0].glyphs[0].pos.x - (100.0 - hello / 2.0)).abs() < 0.01);
+    }
+
+    #[test]
+    fn justified_and_long_words() {
+        let data = roboto();
+        let font = ttflib::load_ttf(&data).unwrap();
+        let options = LayoutOptions {
+            font_size: 20.0,
+            max_width: 100.0,
+            align: TextAlign::Justify,
+            ..Default::default()
+        };
+
+        let text = "aa bb cc dd ee ff gg";
+        let layout = layout_text(text, &font, &options);
+        assert!(layout.lines.len() > 1);
+        for line in &layout.lines[..layout.lines.len() - 1] {
+            // the last letter ends at the right edge
+            let last = line
+                .glyphs
+                .iter()
+                .rfind(|g| !text[g.cluster..].starts_with(' '));
+            let end = last.map(|g| g.pos.x + g.advance).unwrap();
+            assert!((end - 100.0).abs() < 0.01, "the line ends at {end}");
+        }
+        let last = layout.lines.last().unwrap();
+        assert!(last.width < 100.0);
+
+        // a word wider than a line is broken between its glyphs
+        let options = LayoutOptions {
+            max_width: 50.0,
+            align: TextAlign::Left,
+            ..options
+        };
+        let layout = layout_text("Supercalifragilistic", &font, &options);
+        assert!(layout.lines.len() > 2);
+        assert!(layout
+            .lines
+            .iter()
+            .all(|l| l.width <= 50.0 && !l.glyphs.is_empty()));
+        let glyphs = layout.glyphs().count();
+        assert_eq!(glyphs, "Supercalifragilistic".len());
+
+        // marks stay with their letter
+        let accents = "e\u{301}".repeat(20);
+        let layout = layout_text(&accents, &font, &options);
+        assert!(layout.lines.len() > 2);
+        assert!(layout.lines.iter().all(|l| l.text.start % 3 == 0));
+
+        let empty = layout_text("", &font, &options);
+        assert_eq!(empty.lines.len(), 1);
+        assert_eq!(empty.width, 0.0);
+    }
+
+    #[test]
+    fn bidirectional() {
+        let data = roboto();
+        let font = ttflib::load_ttf(&data).unwrap();
+        let options = LayoutOptions {
+            font_size: 20.0,
+            ..Default::default()
+        };
+        let clusters = |layout: &TextLayout| -> Vec<usize> {
+            let glyphs: Vec<_> = layout.glyphs().collect();
+            assert!(glyphs.windows(2).all(|g| g[0].pos.x < g[1].pos.x));
+            glyphs.iter().map(|g| g.cluster).collect()
+        };
+
+        let layout = layout_text("abc \u{5d0}\u{5d1}\u{5d2}", &font, &options);
+        assert!(!layout.lines[0].rtl);
+        assert_eq!(clusters(&layout), [0, 1, 2, 3, 8, 6, 4]);
+
+        let layout = layout_text("\u{5d0}\u{5d1} cd", &font, &options);
+        assert!(layout.lines[0].rtl);
+        assert_eq!(clusters(&layout), [5, 6, 4, 2, 0]);
+
+        // brackets are mirrored in right to left text
+        let layout = layout_text("\u{5d0}(\u{5d1})", &font, &options);
+        let opening = layout.glyphs().find(|g| g.cluster == 2).unwrap();
+        assert_eq!(Some(opening.id), font.glyph_id(')'));
+
+        // the last line of a justified right to left paragraph is on the right
+        let options = LayoutOptions {
+            max_width: 200.0,
+            align: TextAlign::Justify,
+            ..options
+        };
+        let layout = layout_text("\u{5d0}\u{5d1} cd ", &font, &options);
+        let line = &layout.lines[0];
+        assert!((line.x + line.width - 200.0).abs() < 0.01);
+        // the space at the end hangs over the left edge of the line
+        let space = line.glyphs.iter().find(|g| g.cluster == 7).unwrap();
+        assert!(space.pos.x < line.x);
+    }
+}
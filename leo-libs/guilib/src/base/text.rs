@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
 use corelib::types::Float;
+use drawlib::text_layout::{LayoutOptions, TextAlign, TextLayout, layout_text};
+use ttflib::Font;
 
 use crate::{IntoWidgetInt, SizeUnit, UiBox, WidgetInt};
 
@@ -8,6 +10,23 @@ use crate::{IntoWidgetInt, SizeUnit, UiBox, WidgetInt};
 pub struct Text {
     pub content: String,
     pub font_size: Float,
+    /// text without a font takes up no space
+    pub font: Option<Arc<Font<'static>>>,
+    pub align: TextAlign,
+}
+
+impl Text {
+    /// The lines of the text when they are at most `max_width` wide, None without a font
+    pub fn layout(&self, max_width: Float) -> Option<TextLayout> {
+        let options = LayoutOptions {
+            font_size: self.font_size,
+            max_width,
+            align: self.align,
+            ..Default::default()
+        };
+        let font = self.font.as_ref()?;
+        Some(layout_text(&self.content, font, &options))
+    }
 }
 
 impl IntoWidgetInt<()> for Text {
@@ -25,32 +44,21 @@ impl IntoWidgetInt<()> for Text {
         }
     }
 
+    /// As wide as the text without breaks other than its own line breaks
     fn ui_box(&self) -> crate::UiBox {
         let mut ui_box = UiBox::default();
-
-        // FIXME: this is obviously not correct and just a dummy implementation
-
-        ui_box.sizing.width = SizeUnit::Fixed(self.content.len() as Float * self.font_size);
-
-        ui_box.sizing.height = SizeUnit::Fixed(self.font_size);
-
+        let size = self
+            .layout(Float::INFINITY)
+            .map_or((0.0, 0.0), |layout| (layout.width, layout.height));
+        ui_box.sizing.width = SizeUnit::Fixed(size.0);
+        ui_box.sizing.height = SizeUnit::Fixed(size.1);
         ui_box
     }
 
     fn produce(self, _ctx: &mut crate::UiContext, _: ()) {}
 
+    /// The height of the text when its lines are broken to fit the width
     fn shrink(&self, new_width: Float) -> Float {
-        let mut stride = 0.0;
-        let mut line_cnt = 0;
-        for word in self.content.split_whitespace() {
-            let word_len = word.len() as Float * self.font_size;
-            stride += word_len;
-            if stride >= new_width {
-                line_cnt += 1;
-                stride = word_len;
-            }
-        }
-
-        line_cnt as Float * self.font_size
+        self.layout(new_width).map_or(0.0, |layout| layout.height)
     }
 }
@@ -3,7 +3,7 @@ use std::{fmt::Debug, sync::Arc};
 
 use corelib::types::Float;
 use drawlib::path::Path;
-use mathlib::vectors::Vec2F;
+use mathlib::{color::ColA, vectors::Vec2F};
 use widgets::button::Button;
 
 pub mod base;
@@ -16,43 +16,8 @@ pub struct Node {
     pub size: Vec2F,
 }
 
-#[derive(Debug, Default, Clone, Copy)]
-pub struct UiColor {
-    pub r: Float,
-    pub g: Float,
-    pub b: Float,
-    pub a: Float,
-}
-
-impl UiColor {
-    pub const PINK: UiColor = UiColor {
-        r: 1.0,
-        g: 0.7529411765,
-        b: 0.7960784314,
-        a: 1.0,
-    };
-
-    pub const BLUE: UiColor = UiColor {
-        r: 0.0,
-        g: 0.0,
-        b: 1.0,
-        a: 1.0,
-    };
-
-    pub const LIGHT_BLUE: UiColor = UiColor {
-        r: 0.678,
-        g: 0.847,
-        b: 0.902,
-        a: 1.0,
-    };
-
-    pub const YELLOW: UiColor = UiColor {
-        r: 1.0,
-        g: 1.0,
-        b: 0.0,
-        a: 1.0,
-    };
-}
+/// Colors in the gui are plain css style sRGB colors
+pub type UiColor = ColA;
 
 pub trait Widget<I>: Debug + Sized + Clone {
     fn container(&self) -> UiBox;
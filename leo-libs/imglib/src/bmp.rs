@@ -0,0 +1,499 @@
+//! The BMP format, uncompressed and with bit fields:
+//! https://learn.microsoft.com/en-us/windows/win32/gdi/bitmap-storage
+
+use std::{
+    fmt::Display,
+    io::{self, Write},
+};
+
+use corelib::reader::Reader;
+
+use crate::{Rgba, RgbaImage};
+
+pub const MAGIC: [u8; 2] = *b"BM";
+
+/// The same limit as for qoi, protects against absurd allocations
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: u32 = 40;
+const V4_HEADER_SIZE: u32 = 108;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BmpError {
+    InvalidMagic,
+    UnexpectedEof,
+    /// a header size that does not belong to a known version of the format
+    InvalidHeaderSize(u32),
+    InvalidDimensions {
+        width: i32,
+        height: i32,
+    },
+    InvalidBitsPerPixel(u16),
+    /// compressed images are not supported
+    UnsupportedCompression(u32),
+    InvalidPaletteIndex(u8),
+}
+
+impl Display for BmpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "not a bmp file"),
+            Self::UnexpectedEof => write!(f, "unexpected end of data"),
+            Self::InvalidHeaderSize(size) => write!(f, "unknown bmp header size {size}"),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Self::InvalidBitsPerPixel(bpp) => write!(f, "unsupported bits per pixel {bpp}"),
+            Self::UnsupportedCompression(c) => write!(f, "unsupported compression method {c}"),
+            Self::InvalidPaletteIndex(i) => write!(f, "palette index {i} out of range"),
+        }
+    }
+}
+
+/// A channel stored in the bits of a mask
+#[derive(Debug, Clone, Copy)]
+struct Channel {
+    mask: u32,
+    shift: u32,
+    max: u64,
+}
+
+impl Channel {
+    fn new(mask: u32) -> Self {
+        Self {
+            mask,
+            shift: if mask == 0 { 0 } else { mask.trailing_zeros() },
+            max: (1u64 << mask.count_ones()) - 1,
+        }
+    }
+
+    /// extract the channel scaled to 8 bits, or `default` if the channel is not stored
+    fn get(&self, pixel: u32, default: u8) -> u8 {
+        if self.mask == 0 {
+            return default;
+        }
+        let v = ((pixel & self.mask) >> self.shift) as u64;
+        ((v * 255 + self.max / 2) / self.max) as u8
+    }
+}
+
+/// Decode a bmp file. Supported are palette images with 1, 4 or 8 bits per pixel, 24 bit
+/// images and 16 or 32 bit images with or without bit fields.
+pub fn decode(data: &[u8]) -> Result<RgbaImage, BmpError> {
+    if !data.starts_with(&MAGIC) {
+        return Err(BmpError::InvalidMagic);
+    }
+    if data.len() < FILE_HEADER_SIZE + 4 {
+        return Err(BmpError::UnexpectedEof);
+    }
+    let mut reader = Reader::new_little_endian(data, 10);
+    let data_offset: u32 = reader.read();
+
+    let header_size: u32 = reader.read();
+    if !matches!(header_size, 12 | 40 | 52 | 56 | 108 | 124) {
+        return Err(BmpError::InvalidHeaderSize(header_size));
+    }
+    if reader.remaining() < header_size as usize - 4 {
+        return Err(BmpError::UnexpectedEof);
+    }
+
+    let (width, height, bpp, compression) = if header_size == 12 {
+        // the old OS/2 header with 16 bit dimensions
+        let width: u16 = reader.read();
+        let height: u16 = reader.read();
+        let _planes: u16 = reader.read();
+        let bpp: u16 = reader.read();
+        (width as i32, height as i32, bpp, BI_RGB)
+    } else {
+        let width: i32 = reader.read();
+        let height: i32 = reader.read();
+        let _planes: u16 = reader.read();
+        let bpp: u16 = reader.read();
+        let compression: u32 = reader.read();
+        (width, height, bpp, compression)
+    };
+
+    if width <= 0
+        || height == 0
+        || height == i32::MIN
+        || width as u64 * height.unsigned_abs() as u64 > MAX_PIXELS
+    {
+        return Err(BmpError::InvalidDimensions { width, height });
+    }
+    // negative heights mean the rows are stored from top to bottom
+    let top_down = height < 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+
+    if !matches!(bpp, 1 | 4 | 8 | 16 | 24 | 32) {
+        return Err(BmpError::InvalidBitsPerPixel(bpp));
+    }
+
+    let masks = match compression {
+        BI_RGB => match bpp {
+            16 => Some([0x7c00, 0x03e0, 0x001f, 0]),
+            // the fourth byte is unused
+            32 => Some([0xff0000, 0xff00, 0xff, 0]),
+            _ => None,
+        },
+        BI_BITFIELDS | BI_ALPHABITFIELDS if matches!(bpp, 16 | 32) => {
+            // the masks follow the info header or are part of the newer headers
+            let mask_count = if compression == BI_ALPHABITFIELDS || header_size >= 56 {
+                4
+            } else {
+                3
+            };
+            reader.set_pos(FILE_HEADER_SIZE + INFO_HEADER_SIZE as usize);
+            if reader.remaining() < mask_count * 4 {
+                return Err(BmpError::UnexpectedEof);
+            }
+            let mut masks = [0u32; 4];
+            for mask in &mut masks[..mask_count] {
+                *mask = reader.read();
+            }
+            Some(masks)
+        }
+        c => return Err(BmpError::UnsupportedCompression(c)),
+    };
+
+    let palette = if bpp <= 8 {
+        let (start, entry_size, count) = if header_size == 12 {
+            (FILE_HEADER_SIZE + 12, 3, 1 << bpp)
+        } else {
+            reader.set_pos(FILE_HEADER_SIZE + 32);
+            let colors_used: u32 = reader.read();
+            let count = if colors_used == 0 || colors_used > 1 << bpp {
+                1 << bpp
+            } else {
+                colors_used as usize
+            };
+            (FILE_HEADER_SIZE + header_size as usize, 4, count)
+        };
+        // the palette may be cut short by the image data
+        let end = (start + count * entry_size).min(data_offset as usize);
+        if end > data.len() {
+            return Err(BmpError::UnexpectedEof);
+        }
+        data[start.min(end)..end]
+            .chunks_exact(entry_size)
+            .map(|c| Rgba {
+                r: c[2],
+                g: c[1],
+                b: c[0],
+                a: 255,
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let stride = (width * bpp as usize).div_ceil(32) * 4;
+    let start = data_offset as usize;
+    if start > data.len() || (data.len() - start) / stride < height {
+        return Err(BmpError::UnexpectedEof);
+    }
+
+    let channels = masks.map(|m| m.map(Channel::new));
+    let mut img = RgbaImage::new(width, height, Rgba::BLACK);
+    let mut reader = Reader::new_little_endian(data, start);
+    for row in 0..height {
+        let y = if top_down { row } else { height - 1 - row };
+        reader.set_pos(start + row * stride);
+
+        let mut x = 0;
+        while x < width {
+            match bpp {
+                1 | 4 | 8 => {
+                    let byte: u8 = reader.read();
+                    let per_byte = 8 / bpp as usize;
+                    for i in 0..per_byte.min(width - x) {
+                        let shift = 8 - bpp as usize * (i + 1);
+                        let index = (byte >> shift) & ((1 << bpp) - 1) as u8;
+                        let pixel = *palette
+                            .get(index as usize)
+                            .ok_or(BmpError::InvalidPaletteIndex(index))?;
+                        img.put_pixel(x + i, y, pixel);
+                    }
+                    x += per_byte;
+                }
+                24 => {
+                    let [b, g, r]: [u8; 3] = reader.read();
+                    img.put_pixel(x, y, Rgba { r, g, b, a: 255 });
+                    x += 1;
+                }
+                _ => {
+                    let pixel = if bpp == 16 {
+                        reader.read::<u16>() as u32
+                    } else {
+                        reader.read::<u32>()
+                    };
+                    let [r, g, b, a] = channels.unwrap();
+                    let pixel = Rgba {
+                        r: r.get(pixel, 0),
+                        g: g.get(pixel, 0),
+                        b: b.get(pixel, 0),
+                        a: a.get(pixel, 255),
+                    };
+                    img.put_pixel(x, y, pixel);
+                    x += 1;
+                }
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+/// Encode an image as bmp. Opaque images are stored with 24 bits per pixel, images with
+/// transparency with 32 bits and an alpha mask.
+pub fn encode(img: &RgbaImage, out: &mut impl Write) -> io::Result<()> {
+    let (width, height) = img.dimensions();
+    if width == 0
+        || height == 0
+        || width > i32::MAX as usize
+        || height > i32::MAX as usize
+        || width as u64 * height as u64 > MAX_PIXELS
+    {
+        let err = BmpError::InvalidDimensions {
+            width: width as i32,
+            height: height as i32,
+        };
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err.to_string()));
+    }
+
+    let alpha = img.data.iter().any(|p| p.a != 255);
+    let (bpp, header_size) = if alpha {
+        (32, V4_HEADER_SIZE)
+    } else {
+        (24, INFO_HEADER_SIZE)
+    };
+    let stride = (width * bpp).div_ceil(32) * 4;
+    let data_offset = FILE_HEADER_SIZE + header_size as usize;
+    let file_size = data_offset + stride * height;
+    let file_size = u32::try_from(file_size)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "image too large for bmp"))?;
+
+    let mut header = Vec::with_capacity(data_offset);
+    header.extend(MAGIC);
+    header.extend(file_size.to_le_bytes());
+    header.extend([0; 4]);
+    header.extend((data_offset as u32).to_le_bytes());
+
+    header.extend(header_size.to_le_bytes());
+    header.extend((width as i32).to_le_bytes());
+    // positive height: bottom up rows like most writers
+    header.extend((height as i32).to_le_bytes());
+    header.extend(1u16.to_le_bytes());
+    header.extend((bpp as u16).to_le_bytes());
+    let compression = if alpha { BI_BITFIELDS } else { BI_RGB };
+    header.extend(compression.to_le_bytes());
+    header.extend(((stride * height) as u32).to_le_bytes());
+    // 72 dpi, the colors used and important colors
+    header.extend(2835u32.to_le_bytes());
+    header.extend(2835u32.to_le_bytes());
+    header.extend([0; 8]);
+    if alpha {
+        for mask in [0xff0000u32, 0xff00, 0xff, 0xff000000] {
+            header.extend(mask.to_le_bytes());
+        }
+        // the "Win " color space (sRGB), the endpoints and gamma are unused
+        header.extend(0x57696e20u32.to_le_bytes());
+        header.extend([0; 48]);
+    }
+    out.write_all(&header)?;
+
+    let mut row_data = Vec::with_capacity(stride);
+    for row in img.data.chunks_exact(width).rev() {
+        row_data.clear();
+        for pix in row {
+            row_data.extend([pix.b, pix.g, pix.r]);
+            if alpha {
+                row_data.push(pix.a);
+            }
+        }
+        row_data.resize(stride, 0);
+        out.write_all(&row_data)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_image(width: usize, height: usize, alpha: bool) -> RgbaImage {
+        let mut img = RgbaImage::new(width, height, Rgba::BLACK);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = Rgba {
+                    r: (x * 20) as u8,
+                    g: (y * 30) as u8,
+                    b: (x * y) as u8,
+                    a: if alpha { (x * 50 + y) as u8 } else { 255 },
+                };
+                img.put_pixel(x, y, pixel);
+            }
+        }
+        img
+    }
+
+    /// a bmp file with an info header, the given pixel data and optional masks or palette
+    fn file(
+        width: i32,
+        height: i32,
+        bpp: u16,
+        compression: u32,
+        extra: &[u8],
+        pixels: &[u8],
+    ) -> Vec<u8> {
+        let offset = (FILE_HEADER_SIZE + 40 + extra.len()) as u32;
+        let mut res = MAGIC.to_vec();
+        res.extend((offset + pixels.len() as u32).to_le_bytes());
+        res.extend([0; 4]);
+        res.extend(offset.to_le_bytes());
+        res.extend(40u32.to_le_bytes());
+        res.extend(width.to_le_bytes());
+        res.extend(height.to_le_bytes());
+        res.extend(1u16.to_le_bytes());
+        res.extend(bpp.to_le_bytes());
+        res.extend(compression.to_le_bytes());
+        res.extend([0; 20]);
+        res.extend_from_slice(extra);
+        res.extend_from_slice(pixels);
+        res
+    }
+
+    #[test]
+    fn roundtrip() {
+        for (width, height) in [(1, 1), (3, 5), (17, 4)] {
+            for alpha in [false, true] {
+                let img = test_image(width, height, alpha);
+                let mut data = Vec::new();
+                encode(&img, &mut data).unwrap();
+                assert_eq!(data[28], if alpha { 32 } else { 24 });
+                assert_eq!(decode(&data).as_ref(), Ok(&img));
+            }
+        }
+    }
+
+    #[test]
+    fn top_down_and_palette() {
+        let red = Rgba {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let blue = Rgba {
+            r: 0,
+            g: 0,
+            b: 255,
+            a: 255,
+        };
+        // two palette entries in BGRX order
+        let palette = [0, 0, 255, 0, 255, 0, 0, 0];
+
+        // 1 bit per pixel, 3 pixels in every row padded to 4 bytes
+        let rows = [0b1010_0000, 0, 0, 0, 0b0100_0000, 0, 0, 0];
+        let bottom_up = decode(&file(3, 2, 1, BI_RGB, &palette, &rows)).unwrap();
+        assert_eq!(bottom_up.data, [red, blue, red, blue, red, blue]);
+
+        let top_down = decode(&file(3, -2, 1, BI_RGB, &palette, &rows)).unwrap();
+        assert_eq!(top_down.data, [blue, red, blue, red, blue, red]);
+
+        let rows = [0x10, 0, 0, 0];
+        let four_bit = decode(&file(2, 1, 4, BI_RGB, &palette, &rows)).unwrap();
+        assert_eq!(four_bit.data, [blue, red]);
+
+        let rows = [2, 0, 0, 0];
+        assert_eq!(
+            decode(&file(1, 1, 8, BI_RGB, &palette, &rows)),
+            Err(BmpError::InvalidPaletteIndex(2))
+        );
+    }
+
+    #[test]
+    fn bit_fields() {
+        // 16 bit 565
+        let masks: Vec<u8> = [0xf800u32, 0x07e0, 0x001f]
+            .iter()
+            .flat_map(|m| m.to_le_bytes())
+            .collect();
+        let pixels = [0x1f, 0xf8, 0xe0, 0x07];
+        let img = decode(&file(2, 1, 16, BI_BITFIELDS, &masks, &pixels)).unwrap();
+        assert_eq!(
+            img.data,
+            [
+                Rgba {
+                    r: 255,
+                    g: 0,
+                    b: 255,
+                    a: 255
+                },
+                Rgba {
+                    r: 0,
+                    g: 255,
+                    b: 0,
+                    a: 255
+                }
+            ]
+        );
+
+        // 32 bit with alpha in the lowest byte
+        let masks: Vec<u8> = [0xff000000u32, 0xff0000, 0xff00, 0xff]
+            .iter()
+            .flat_map(|m| m.to_le_bytes())
+            .collect();
+        let pixels = [0x80, 3, 2, 1];
+        let img = decode(&file(1, 1, 32, BI_ALPHABITFIELDS, &masks, &pixels)).unwrap();
+        assert_eq!(
+            img.data,
+            [Rgba {
+                r: 1,
+                g: 2,
+                b: 3,
+                a: 0x80
+            }]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut valid = Vec::new();
+        encode(&test_image(3, 3, false), &mut valid).unwrap();
+
+        assert_eq!(decode(&valid[1..]), Err(BmpError::InvalidMagic));
+        for len in 2..valid.len() {
+            assert!(decode(&valid[..len]).is_err(), "truncated to {len} bytes");
+        }
+
+        assert_eq!(
+            decode(&file(1, 1, 8, 1, &[], &[0; 4])),
+            Err(BmpError::UnsupportedCompression(1))
+        );
+        assert_eq!(
+            decode(&file(1, 1, 7, BI_RGB, &[], &[0; 4])),
+            Err(BmpError::InvalidBitsPerPixel(7))
+        );
+        assert_eq!(
+            decode(&file(0, 1, 24, BI_RGB, &[], &[0; 4])),
+            Err(BmpError::InvalidDimensions {
+                width: 0,
+                height: 1
+            })
+        );
+        // a small file claiming a huge image
+        assert_eq!(
+            decode(&file(20_000, 20_000, 24, BI_RGB, &[], &[0; 16])),
+            Err(BmpError::UnexpectedEof)
+        );
+
+        let mut out = Vec::new();
+        assert!(encode(&RgbaImage::new(0, 0, Rgba::BLACK), &mut out).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! Blurring and convolution. Like the resampling in [`crate::resize`], filters work on
+//! premultiplied colors in linear light, so that transparent pixels do not darken their
+//! neighbours.
+
+use mathlib::color::LinColA;
+
+use crate::{Image, Pixel};
+
+/// The pixels of an image as premultiplied linear colors
+pub(crate) fn to_linear<Pix: Pixel, Cont: AsRef<[Pix]>>(img: &Image<Pix, Cont>) -> Vec<LinColA> {
+    img.rows()
+        .flat_map(|row| row.iter().map(|pix| pix.to_linear().premultiplied()))
+        .collect()
+}
+
+pub(crate) fn from_linear<Pix: Pixel>(width: usize, height: usize, data: &[LinColA]) -> Image<Pix> {
+    Image::from_vec(
+        width,
+        height,
+        data.iter()
+            .map(|col| {
+                // sharpening kernels can over- and undershoot
+                let a = col.a.clamp(0.0, 1.0);
+                let col = LinColA::new(
+                    col.r.clamp(0.0, a),
+                    col.g.clamp(0.0, a),
+                    col.b.clamp(0.0, a),
+                    a,
+                );
+                Pix::from_linear(col.unpremultiplied())
+            })
+            .collect(),
+    )
+}
+
+/// The weights of the input samples that make up one output sample
+pub(crate) struct Taps {
+    pub(crate) start: usize,
+    pub(crate) weights: Vec<f32>,
+}
+
+impl Taps {
+    /// Collect the weights for the input samples from `first` on. Samples outside of the
+    /// input are replaced with the nearest edge sample. The weights are normalized if
+    /// `normalize` is set.
+    pub(crate) fn new(len: usize, first: isize, weights: &[f32], normalize: bool) -> Self {
+        let clamp = |i: isize| i.clamp(0, len as isize - 1) as usize;
+        let start = clamp(first);
+        let end = clamp(first + weights.len() as isize - 1);
+        let mut taps = vec![0.0; end - start + 1];
+        for (i, &w) in weights.iter().enumerate() {
+            taps[clamp(first + i as isize) - start] += w;
+        }
+        if normalize {
+            let sum: f32 = taps.iter().sum();
+            if sum != 0.0 {
+                taps.iter_mut().for_each(|w| *w /= sum);
+            }
+        }
+        Self {
+            start,
+            weights: taps,
+        }
+    }
+
+    /// The same symmetric kernel around every sample
+    pub(crate) fn uniform(len: usize, kernel: &[f32]) -> Vec<Self> {
+        let radius = (kernel.len() / 2) as isize;
+        (0..len)
+            .map(|i| Self::new(len, i as isize - radius, kernel, false))
+            .collect()
+    }
+}
+
+/// Filter every row, returning an image with one sample per tap
+pub(crate) fn filter_rows(data: &[LinColA], width: usize, taps: &[Taps]) -> Vec<LinColA> {
+    let mut out = Vec::with_capacity(data.len() / width.max(1) * taps.len());
+    for row in data.chunks_exact(width.max(1)) {
+        out.extend(taps.iter().map(|tap| {
+            let mut sum = LinColA::TRANSPARENT;
+            for (&col, &w) in row[tap.start..].iter().zip(&tap.weights) {
+                sum += col * w;
+            }
+            sum
+        }));
+    }
+    out
+}
+
+/// Filter every column, returning an image with one row per tap
+pub(crate) fn filter_columns(data: &[LinColA], width: usize, taps: &[Taps]) -> Vec<LinColA> {
+    let mut out = vec![LinColA::TRANSPARENT; width * taps.len()];
+    for (out_row, tap) in out.chunks_exact_mut(width.max(1)).zip(taps) {
+        for (i, &w) in tap.weights.iter().enumerate() {
+            let row = &data[(tap.start + i) * width..][..width];
+            for (out, &col) in out_row.iter_mut().zip(row) {
+                *out += col * w;
+            }
+        }
+    }
+    out
+}
+
+/// A convolution kernel with its center at `(width / 2, height / 2)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Kernel {
+    pub width: usize,
+    pub height: usize,
+    /// the weights in row major order
+    pub weights: Vec<f32>,
+}
+
+impl Kernel {
+    pub fn new(width: usize, height: usize, weights: Vec<f32>) -> Self {
+        assert_eq!(weights.len(), width * height, "kernel has the wrong size");
+        Self {
+            width,
+            height,
+            weights,
+        }
+    }
+
+    pub fn sharpen() -> Self {
+        Self::new(3, 3, vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0])
+    }
+
+    /// The weights of a one dimensional gaussian, cut off at 3 sigma
+    pub fn gaussian_1d(sigma: f32) -> Vec<f32> {
+        let radius = (sigma * 3.0).ceil().max(0.0) as isize;
+        let weights: Vec<f32> = (-radius..=radius)
+            .map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let sum: f32 = weights.iter().sum();
+        weights.into_iter().map(|w| w / sum).collect()
+    }
+}
+
+impl<Pix: Pixel, Cont: AsRef<[Pix]>> Image<Pix, Cont> {
+    /// Apply a convolution kernel. The edge pixels are repeated outside of the image.
+    pub fn convolve(&self, kernel: &Kernel) -> Image<Pix> {
+        let (width, height) = self.dimensions();
+        let data = to_linear(self);
+        let (cx, cy) = ((kernel.width / 2) as isize, (kernel.height / 2) as isize);
+        let clamp = |v: isize, len: usize| v.clamp(0, len as isize - 1) as usize;
+
+        let mut out = Vec::with_capacity(data.len());
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut sum = LinColA::TRANSPARENT;
+                for (ky, weights) in kernel.weights.chunks_exact(kernel.width).enumerate() {
+                    let sy = clamp(y + ky as isize - cy, height);
+                    for (kx, &w) in weights.iter().enumerate() {
+                        let sx = clamp(x + kx as isize - cx, width);
+                        sum += data[sy * width + sx] * w;
+                    }
+                }
+                out.push(sum);
+            }
+        }
+        from_linear(width, height, &out)
+    }
+
+    /// Apply a separable kernel, first to the rows and then to the columns
+    pub fn convolve_separable(&self, horizontal: &[f32], vertical: &[f32]) -> Image<Pix> {
+        let (width, height) = self.dimensions();
+        if width == 0 || height == 0 {
+            return Image::from_vec(width, height, Vec::new());
+        }
+        let data = filter_rows(&to_linear(self), width, &Taps::uniform(width, horizontal));
+        let data = filter_columns(&data, width, &Taps::uniform(height, vertical));
+        from_linear(width, height, &data)
+    }
+
+    /// Average every pixel with the pixels at most `radius` pixels away on both axes
+    pub fn box_blur(&self, radius: usize) -> Image<Pix> {
+        let kernel = vec![1.0 / (2 * radius + 1) as f32; 2 * radius + 1];
+        self.convolve_separable(&kernel, &kernel)
+    }
+
+    pub fn gaussian_blur(&self, sigma: f32) -> Image<Pix> {
+        let kernel = Kernel::gaussian_1d(sigma);
+        self.convolve_separable(&kernel, &kernel)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Gray8, Rgba, RgbaImage};
+
+    #[test]
+    fn taps_at_edges() {
+        let taps = Taps::new(4, -2, &[1.0, 2.0, 3.0, 4.0], false);
+        assert_eq!((taps.start, taps.weights), (0, vec![6.0, 4.0]));
+        let taps = Taps::new(4, 2, &[1.0, 1.0, 2.0, 4.0], true);
+        assert_eq!((taps.start, taps.weights), (2, vec![0.125, 0.875]));
+    }
+
+    #[test]
+    fn blur() {
+        // blurring a constant image does not change it
+        let img = RgbaImage::new(
+            7,
+            5,
+            Rgba {
+                r: 200,
+                g: 10,
+                b: 90,
+                a: 255,
+            },
+        );
+        assert_eq!(img.box_blur(2), img);
+        assert_eq!(img.gaussian_blur(1.5), img);
+        assert_eq!(img.convolve(&Kernel::sharpen()), img);
+
+        // a single dot is spread out evenly
+        let mut dot = Image::new(5, 5, Gray8(0));
+        dot.put_pixel(2, 2, Gray8(255));
+        let blurred = dot.box_blur(1);
+        assert_eq!(blurred.get_pixel(0, 0), Gray8(0));
+        assert!(blurred.get_pixel(1, 1).0 > 0);
+        assert_eq!(blurred.get_pixel(1, 1), blurred.get_pixel(3, 2));
+        let gauss = dot.gaussian_blur(1.0);
+        assert!(gauss.get_pixel(2, 2).0 > gauss.get_pixel(2, 1).0);
+        assert!(gauss.get_pixel(2, 1).0 > gauss.get_pixel(1, 1).0);
+        assert_eq!(gauss.get_pixel(2, 1), gauss.get_pixel(1, 2));
+        assert_eq!(
+            dot.convolve(&Kernel::new(3, 3, vec![1.0 / 9.0; 9])),
+            blurred
+        );
+    }
+
+    #[test]
+    fn blur_keeps_transparent_colors_out() {
+        // a red dot on transparent black does not become darker when blurred
+        let mut img = RgbaImage::new(5, 1, Rgba::TRANSPARENT);
+        img.put_pixel(
+            2,
+            0,
+            Rgba {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            },
+        );
+        let blurred = img.box_blur(1);
+        let side = blurred.get_pixel(1, 0);
+        assert_eq!((side.r, side.g, side.b), (255, 0, 0));
+        assert!(side.a > 0 && side.a < 255);
+    }
+}
@@ -0,0 +1,245 @@
+//! The variable code width LZW compression of gif image data
+
+use super::GifError;
+
+const MAX_CODES: usize = 4096;
+const MAX_WIDTH: u32 = 12;
+
+/// Reads codes of `width` bits, least significant bit first
+struct CodeReader<'data> {
+    data: &'data [u8],
+    pos: usize,
+    bits: u32,
+    count: u32,
+}
+
+impl CodeReader<'_> {
+    fn read(&mut self, width: u32) -> Option<u16> {
+        while self.count < width {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            self.bits |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+        let code = self.bits & ((1 << width) - 1);
+        self.bits >>= width;
+        self.count -= width;
+        Some(code as u16)
+    }
+}
+
+/// Decompress `data` into `out` and return the amount of decoded bytes. Decoding stops at
+/// the end of information code, when `out` is full or when the data ends.
+pub(crate) fn decode(data: &[u8], min_code_size: u8, out: &mut [u8]) -> Result<usize, GifError> {
+    if !(2..=8).contains(&min_code_size) {
+        return Err(GifError::InvalidCodeSize(min_code_size));
+    }
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+
+    // every code is a previous code followed by one byte
+    let mut prefix = [0u16; MAX_CODES];
+    let mut suffix = [0u8; MAX_CODES];
+    let mut first = [0u8; MAX_CODES];
+    let mut len = [0u16; MAX_CODES];
+    for code in 0..clear {
+        suffix[code as usize] = code as u8;
+        first[code as usize] = code as u8;
+        len[code as usize] = 1;
+    }
+
+    let mut reader = CodeReader {
+        data,
+        pos: 0,
+        bits: 0,
+        count: 0,
+    };
+    let mut width = min_code_size as u32 + 1;
+    let mut next = end + 1;
+    let mut prev: Option<u16> = None;
+    let mut pos = 0;
+
+    while pos < out.len() {
+        let Some(code) = reader.read(width) else {
+            break;
+        };
+        if code == clear {
+            width = min_code_size as u32 + 1;
+            next = end + 1;
+            prev = None;
+            continue;
+        }
+        if code == end {
+            break;
+        }
+
+        let (string, first_byte) = match prev {
+            _ if code < next && (code < clear || code > end) => (code, first[code as usize]),
+            // the code that is about to be added: the previous string and its first byte
+            Some(prev) if code == next => (prev, first[prev as usize]),
+            _ => return Err(GifError::InvalidCode),
+        };
+
+        // write the string backwards, cut off at the end of the output
+        let string_len = len[string as usize] as usize;
+        let total = string_len + (string != code) as usize;
+        if string != code
+            && let Some(last) = out.get_mut(pos + string_len)
+        {
+            *last = first_byte;
+        }
+        let mut c = string;
+        for i in (0..string_len).rev() {
+            if let Some(byte) = out.get_mut(pos + i) {
+                *byte = suffix[c as usize];
+            }
+            c = prefix[c as usize];
+        }
+        pos = (pos + total).min(out.len());
+
+        if let Some(prev) = prev
+            && (next as usize) < MAX_CODES
+        {
+            prefix[next as usize] = prev;
+            suffix[next as usize] = first_byte;
+            first[next as usize] = first[prev as usize];
+            len[next as usize] = len[prev as usize] + 1;
+            next += 1;
+            if next == 1 << width && width < MAX_WIDTH {
+                width += 1;
+            }
+        }
+        prev = Some(code);
+    }
+
+    Ok(pos)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// A simple LZW encoder that does not reset the dictionary when it is full
+    pub(crate) fn encode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1u32 << min_code_size;
+        let mut out = Vec::new();
+        let (mut bits, mut count) = (0u32, 0u32);
+        let mut put = |code: u32, width: u32, out: &mut Vec<u8>| {
+            bits |= code << count;
+            count += width;
+            while count >= 8 {
+                out.push(bits as u8);
+                bits >>= 8;
+                count -= 8;
+            }
+        };
+
+        let mut dict = std::collections::HashMap::new();
+        let mut width = min_code_size as u32 + 1;
+        let mut next = clear + 2;
+        put(clear, width, &mut out);
+
+        let mut current: Option<u32> = None;
+        let mut emitted = false;
+        for &byte in data {
+            let Some(code) = current else {
+                current = Some(byte as u32);
+                continue;
+            };
+            if let Some(&found) = dict.get(&(code, byte)) {
+                current = Some(found);
+                continue;
+            }
+            put(code, width, &mut out);
+            emitted = true;
+            if (next as usize) < MAX_CODES {
+                dict.insert((code, byte), next);
+                next += 1;
+                // the decoder adds its entry one code later, so it switches one code later
+                if next == (1 << width) + 1 && width < MAX_WIDTH {
+                    width += 1;
+                }
+            }
+            current = Some(byte as u32);
+        }
+        if let Some(code) = current {
+            put(code, width, &mut out);
+            // the decoder adds an entry for the last code as well
+            if emitted && (next as usize) < MAX_CODES && next == 1 << width && width < MAX_WIDTH {
+                width += 1;
+            }
+        }
+        put(clear + 1, width, &mut out);
+        put(0, 7, &mut out);
+        out
+    }
+
+    fn roundtrip(data: &[u8], min_code_size: u8) {
+        let compressed = encode(data, min_code_size);
+        let mut out = vec![0; data.len()];
+        assert_eq!(decode(&compressed, min_code_size, &mut out), Ok(data.len()));
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn roundtrips() {
+        roundtrip(&[], 2);
+        roundtrip(&[1], 2);
+        roundtrip(&[0, 1, 0, 1, 0, 1, 0, 1, 0, 1, 3, 3, 3, 3, 3, 3, 3], 2);
+        // many codes, which reach the largest code width and fill the dictionary
+        let mut x: u32 = 1;
+        let noisy: Vec<u8> = (0..20000)
+            .map(|i| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                if i % 7 < 3 {
+                    (x >> 16) as u8
+                } else {
+                    (i / 50) as u8
+                }
+            })
+            .collect();
+        roundtrip(&noisy, 8);
+        roundtrip(&vec![5; 100_000], 4);
+        let bits: Vec<u8> = noisy.iter().map(|b| b & 1).collect();
+        roundtrip(&bits, 2);
+    }
+
+    #[test]
+    fn known_data() {
+        // a 10x10 image with 4 colors from the gif specification examples, compressed
+        // with a minimum code size of 2
+        let data = [
+            0x8c, 0x2d, 0x99, 0x87, 0x2a, 0x1c, 0xdc, 0x33, 0xa0, 0x02, 0x75, 0xec, 0x95, 0xfa,
+            0xa8, 0xde, 0x60, 0x8c, 0x04, 0x91, 0x4c, 0x01,
+        ];
+        let mut out = [0; 100];
+        assert_eq!(decode(&data, 2, &mut out), Ok(100));
+        let expected = [
+            "1111122222",
+            "1111122222",
+            "1111122222",
+            "1110000222",
+            "1110000222",
+            "2220000111",
+            "2220000111",
+            "2222211111",
+            "2222211111",
+            "2222211111",
+        ];
+        let expected: Vec<u8> = expected.concat().bytes().map(|b| b - b'0').collect();
+        assert_eq!(out.to_vec(), expected);
+    }
+
+    #[test]
+    fn invalid_data() {
+        let mut out = [0; 10];
+        assert_eq!(decode(&[0], 1, &mut out), Err(GifError::InvalidCodeSize(1)));
+        assert_eq!(decode(&[0], 9, &mut out), Err(GifError::InvalidCodeSize(9)));
+        // the code 7 is not defined yet
+        assert_eq!(decode(&[0x3c], 2, &mut out), Err(GifError::InvalidCode));
+        // data that ends early and data that is longer than the output
+        assert_eq!(decode(&encode(&[1; 5], 2)[..1], 2, &mut out), Ok(1));
+        assert_eq!(decode(&encode(&[1; 50], 2), 2, &mut out), Ok(10));
+        assert_eq!(out, [1; 10]);
+    }
+}
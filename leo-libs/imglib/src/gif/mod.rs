@@ -0,0 +1,798 @@
+//! The GIF format (87a and 89a) including animations:
+//! https://www.w3.org/Graphics/GIF/spec-gif89a.txt
+//!
+//! Frames are composited onto the logical screen like browsers do: the screen starts out
+//! transparent, the background color is ignored and "restore to background" clears the
+//! area of a frame to transparent.
+
+mod lzw;
+
+use std::{fmt::Display, time::Duration};
+
+use corelib::reader::Reader;
+
+use crate::{Rgba, RgbaImage};
+
+pub const MAGIC_87A: [u8; 6] = *b"GIF87a";
+pub const MAGIC_89A: [u8; 6] = *b"GIF89a";
+
+/// The same limit as for qoi, protects against absurd allocations
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+const IMAGE_DESCRIPTOR: u8 = 0x2c;
+const EXTENSION: u8 = 0x21;
+const TRAILER: u8 = 0x3b;
+
+const GRAPHIC_CONTROL: u8 = 0xf9;
+const APPLICATION: u8 = 0xff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GifError {
+    InvalidSignature,
+    UnexpectedEof,
+    InvalidDimensions {
+        width: u16,
+        height: u16,
+    },
+    /// a block that is not an image, an extension or the trailer
+    InvalidBlock(u8),
+    InvalidCodeSize(u8),
+    InvalidCode,
+    /// an image without a local or global color table
+    MissingPalette,
+    /// the file does not contain any images
+    NoImage,
+}
+
+impl Display for GifError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidSignature => write!(f, "not a gif file"),
+            Self::UnexpectedEof => write!(f, "unexpected end of data"),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Self::InvalidBlock(b) => write!(f, "invalid block type {b:#04x}"),
+            Self::InvalidCodeSize(s) => write!(f, "invalid lzw code size {s}"),
+            Self::InvalidCode => write!(f, "invalid lzw code"),
+            Self::MissingPalette => write!(f, "image without a color table"),
+            Self::NoImage => write!(f, "no image in the file"),
+        }
+    }
+}
+
+/// What happens to the area of a frame before the next frame is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposal {
+    /// leave the frame in place
+    Keep,
+    /// clear the area of the frame
+    Background,
+    /// restore the area to what it was before the frame was drawn
+    Previous,
+}
+
+impl Disposal {
+    fn from_u8(v: u8) -> Self {
+        match v {
+            2 => Self::Background,
+            3 => Self::Previous,
+            // 0 means unspecified, the other values are reserved
+            _ => Self::Keep,
+        }
+    }
+}
+
+/// How often an animation is played
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopCount {
+    Infinite,
+    Times(u32),
+}
+
+/// The graphic control extension, which applies to the next image
+#[derive(Debug, Clone, Copy)]
+struct GraphicControl {
+    disposal: Disposal,
+    /// in hundredths of a second
+    delay: u16,
+    transparent: Option<u8>,
+}
+
+impl Default for GraphicControl {
+    fn default() -> Self {
+        Self {
+            disposal: Disposal::Keep,
+            delay: 0,
+            transparent: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// the whole logical screen after drawing the frame
+    pub image: RgbaImage,
+    /// how long the frame is shown as stored in the file, see [`Player`] for how small
+    /// delays are treated
+    pub delay: Duration,
+}
+
+/// The rows of an interlaced image in the order they are stored
+fn interlaced_rows(height: usize) -> impl Iterator<Item = usize> {
+    [(0, 8), (4, 8), (2, 4), (1, 2)]
+        .into_iter()
+        .flat_map(move |(start, step)| (start..height).step_by(step))
+}
+
+/// A palette of `2^(size + 1)` colors at `pos`
+fn read_palette(data: &[u8], pos: usize, size: u8) -> Result<Vec<Rgba>, GifError> {
+    let len = 3 << (size + 1);
+    let bytes = data.get(pos..pos + len).ok_or(GifError::UnexpectedEof)?;
+    Ok(bytes
+        .chunks_exact(3)
+        .map(|c| Rgba {
+            r: c[0],
+            g: c[1],
+            b: c[2],
+            a: 255,
+        })
+        .collect())
+}
+
+/// Decodes the frames of a gif file one at a time
+pub struct Frames<'data> {
+    data: &'data [u8],
+    pos: usize,
+    width: usize,
+    height: usize,
+    global_palette: Option<Vec<Rgba>>,
+    canvas: Option<RgbaImage>,
+    /// how to dispose the last frame and its area
+    dispose: Option<(Disposal, [usize; 4])>,
+    /// the canvas before the last frame, for `Disposal::Previous`
+    saved: Option<RgbaImage>,
+    control: Option<GraphicControl>,
+    loop_count: LoopCount,
+    done: bool,
+}
+
+impl<'data> Frames<'data> {
+    pub fn new(data: &'data [u8]) -> Result<Self, GifError> {
+        if !data.starts_with(&MAGIC_87A) && !data.starts_with(&MAGIC_89A) {
+            return Err(GifError::InvalidSignature);
+        }
+        if data.len() < 13 {
+            return Err(GifError::UnexpectedEof);
+        }
+        let mut reader = Reader::new_little_endian(data, 6);
+        let width: u16 = reader.read();
+        let height: u16 = reader.read();
+        let flags: u8 = reader.read();
+        if width == 0 || height == 0 || width as u64 * height as u64 > MAX_PIXELS {
+            return Err(GifError::InvalidDimensions { width, height });
+        }
+        // codes are at least 3 bits and produce at most 4096 pixels each, so a file this
+        // short can't fill the screen. Checking this up front avoids huge canvases.
+        let max_pixels = (data.len() * 8 / 3 + 1) * 4096;
+        if width as usize * height as usize > max_pixels {
+            return Err(GifError::UnexpectedEof);
+        }
+
+        let mut pos = 13;
+        let global_palette = if flags & 0x80 != 0 {
+            let palette = read_palette(data, pos, flags & 7)?;
+            pos += palette.len() * 3;
+            Some(palette)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            data,
+            pos,
+            width: width as usize,
+            height: height as usize,
+            global_palette,
+            canvas: None,
+            dispose: None,
+            saved: None,
+            control: None,
+            loop_count: LoopCount::Times(1),
+            done: false,
+        })
+    }
+
+    /// The size of the logical screen
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    /// The loop count of the animation, which is only known once the frames are decoded
+    /// because the extension that holds it can come anywhere in the file.
+    pub fn loop_count(&self) -> LoopCount {
+        self.loop_count
+    }
+
+    /// The data of the sub-blocks at `pos` and the position after them, which is `None`
+    /// when the data ends before the terminating empty block
+    fn sub_blocks(&self, mut pos: usize) -> (Vec<u8>, Option<usize>) {
+        let mut out = Vec::new();
+        loop {
+            let Some(&len) = self.data.get(pos) else {
+                return (out, None);
+            };
+            if len == 0 {
+                return (out, Some(pos + 1));
+            }
+            let Some(block) = self.data.get(pos + 1..pos + 1 + len as usize) else {
+                out.extend_from_slice(&self.data[(pos + 1).min(self.data.len())..]);
+                return (out, None);
+            };
+            out.extend_from_slice(block);
+            pos += 1 + len as usize;
+        }
+    }
+
+    fn read_extension(&mut self) -> Result<(), GifError> {
+        let label = *self.data.get(self.pos + 1).ok_or(GifError::UnexpectedEof)?;
+        let first_block = self.pos + 2;
+        let (contents, end) = self.sub_blocks(first_block);
+        self.pos = end.ok_or(GifError::UnexpectedEof)?;
+
+        match label {
+            GRAPHIC_CONTROL if contents.len() >= 4 => {
+                self.control = Some(GraphicControl {
+                    disposal: Disposal::from_u8((contents[0] >> 2) & 7),
+                    delay: u16::from_le_bytes([contents[1], contents[2]]),
+                    transparent: (contents[0] & 1 != 0).then_some(contents[3]),
+                });
+            }
+            APPLICATION => {
+                // the looping extension is a sub-block of 1, followed by the loop count
+                let id = &self.data[first_block..];
+                let is_loop =
+                    id.starts_with(b"\x0bNETSCAPE2.0") || id.starts_with(b"\x0bANIMEXTS1.0");
+                if is_loop && contents.len() >= 14 && contents[11] == 1 {
+                    self.loop_count = match u16::from_le_bytes([contents[12], contents[13]]) {
+                        0 => LoopCount::Infinite,
+                        // the count is the amount of repetitions after the first time
+                        n => LoopCount::Times(n as u32 + 1),
+                    };
+                }
+            }
+            // comments, plain text and unknown extensions
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn read_frame(&mut self) -> Result<Frame, GifError> {
+        let data = self.data;
+        if data.len() < self.pos + 11 {
+            return Err(GifError::UnexpectedEof);
+        }
+        let mut reader = Reader::new_little_endian(data, self.pos + 1);
+        let left: u16 = reader.read();
+        let top: u16 = reader.read();
+        let width: u16 = reader.read();
+        let height: u16 = reader.read();
+        let flags: u8 = reader.read();
+        let mut pos = self.pos + 10;
+
+        let local_palette = if flags & 0x80 != 0 {
+            let palette = read_palette(data, pos, flags & 7)?;
+            pos += palette.len() * 3;
+            Some(palette)
+        } else {
+            None
+        };
+        let min_code_size = *data.get(pos).ok_or(GifError::UnexpectedEof)?;
+        let (compressed, end) = self.sub_blocks(pos + 1);
+        // an image that ends early is shown as far as it goes, like browsers do
+        match end {
+            Some(end) => self.pos = end,
+            None => self.done = true,
+        }
+
+        let control = self.control.take().unwrap_or_default();
+        let palette = local_palette
+            .as_ref()
+            .or(self.global_palette.as_ref())
+            .ok_or(GifError::MissingPalette)?;
+
+        // every code produces at most 4096 bytes, which limits the pixels of small files
+        let (width, height) = (width as usize, height as usize);
+        let max_codes = compressed.len() * 8 / (min_code_size as usize + 1) + 1;
+        let mut indices = vec![0; (width * height).min(max_codes * 4096)];
+        let decoded = lzw::decode(&compressed, min_code_size, &mut indices)?;
+
+        let (screen_w, screen_h) = (self.width, self.height);
+        let canvas = self
+            .canvas
+            .get_or_insert_with(|| RgbaImage::new(screen_w, screen_h, Rgba::TRANSPARENT));
+
+        // dispose the previous frame
+        match self.dispose.take() {
+            Some((Disposal::Background, [x, y, w, h])) => {
+                canvas.view_mut(x, y, w, h).fill(Rgba::TRANSPARENT);
+            }
+            Some((Disposal::Previous, _)) => {
+                if let Some(saved) = self.saved.take() {
+                    *canvas = saved;
+                }
+            }
+            _ => {}
+        }
+        if control.disposal == Disposal::Previous {
+            self.saved = Some(canvas.clone());
+        }
+
+        // the area of the frame on the screen
+        let (left, top) = (left as usize, top as usize);
+        let area_x = left.min(screen_w);
+        let area_y = top.min(screen_h);
+        let area = [
+            area_x,
+            area_y,
+            (left + width).min(screen_w) - area_x,
+            (top + height).min(screen_h) - area_y,
+        ];
+        self.dispose = Some((control.disposal, area));
+
+        let rows: Box<dyn Iterator<Item = usize>> = if flags & 0x40 != 0 {
+            Box::new(interlaced_rows(height))
+        } else {
+            Box::new(0..height)
+        };
+        for (row, y) in indices[..decoded].chunks(width.max(1)).zip(rows) {
+            let y = top + y;
+            if y >= screen_h {
+                continue;
+            }
+            for (x, &index) in row.iter().enumerate() {
+                let x = left + x;
+                if x >= screen_w || control.transparent == Some(index) {
+                    continue;
+                }
+                if let Some(&color) = palette.get(index as usize) {
+                    canvas.put_pixel(x, y, color);
+                }
+            }
+        }
+
+        Ok(Frame {
+            image: canvas.clone(),
+            delay: Duration::from_millis(control.delay as u64 * 10),
+        })
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Frame, GifError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let result = match self.data.get(self.pos) {
+                // files without a trailer end after the last complete block
+                None | Some(&TRAILER) => {
+                    self.done = true;
+                    return None;
+                }
+                Some(&EXTENSION) => self.read_extension(),
+                Some(&IMAGE_DESCRIPTOR) => {
+                    let frame = self.read_frame();
+                    if frame.is_err() {
+                        self.done = true;
+                    }
+                    return Some(frame);
+                }
+                Some(&block) => Err(GifError::InvalidBlock(block)),
+            };
+            if let Err(e) = result {
+                self.done = true;
+                return Some(Err(e));
+            }
+        }
+        None
+    }
+}
+
+/// All frames of a gif file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Animation {
+    pub frames: Vec<Frame>,
+    pub loop_count: LoopCount,
+}
+
+/// Decode all frames of a gif file. A file that ends early after the first frame is
+/// decoded as far as it goes.
+pub fn decode_animation(data: &[u8]) -> Result<Animation, GifError> {
+    let mut decoder = Frames::new(data)?;
+    let mut frames = Vec::new();
+    for frame in decoder.by_ref() {
+        match frame {
+            Ok(frame) => frames.push(frame),
+            Err(GifError::UnexpectedEof) if !frames.is_empty() => break,
+            Err(e) => return Err(e),
+        }
+    }
+    if frames.is_empty() {
+        return Err(GifError::NoImage);
+    }
+    Ok(Animation {
+        frames,
+        loop_count: decoder.loop_count(),
+    })
+}
+
+/// Decode the first frame of a gif file
+pub fn decode(data: &[u8]) -> Result<RgbaImage, GifError> {
+    match Frames::new(data)?.next() {
+        Some(frame) => Ok(frame?.image),
+        None => Err(GifError::NoImage),
+    }
+}
+
+/// Plays an animation in real time. The GUI calls [`Player::advance`] with the time that
+/// passed and redraws when the frame changed.
+#[derive(Debug, Clone)]
+pub struct Player {
+    animation: Animation,
+    index: usize,
+    /// the time the current frame has been shown
+    elapsed: Duration,
+    plays: u32,
+}
+
+impl Player {
+    pub fn new(animation: Animation) -> Self {
+        assert!(!animation.frames.is_empty(), "animation without frames");
+        Self {
+            animation,
+            index: 0,
+            elapsed: Duration::ZERO,
+            plays: 0,
+        }
+    }
+
+    /// How long a frame is shown. Like in browsers, delays of 10ms and less are shown for
+    /// 100ms, because many files have a delay of 0 and expect it to be slowed down.
+    pub fn frame_delay(frame: &Frame) -> Duration {
+        if frame.delay <= Duration::from_millis(10) {
+            Duration::from_millis(100)
+        } else {
+            frame.delay
+        }
+    }
+
+    pub fn image(&self) -> &RgbaImage {
+        &self.animation.frames[self.index].image
+    }
+
+    pub fn frame_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn is_finished(&self) -> bool {
+        match self.animation.loop_count {
+            _ if self.animation.frames.len() == 1 => true,
+            LoopCount::Infinite => false,
+            LoopCount::Times(n) => self.plays >= n,
+        }
+    }
+
+    /// The time until the frame changes, `None` if it will not change anymore
+    pub fn time_to_next_frame(&self) -> Option<Duration> {
+        if self.is_finished() {
+            return None;
+        }
+        let delay = Self::frame_delay(&self.animation.frames[self.index]);
+        Some(delay.saturating_sub(self.elapsed))
+    }
+
+    /// Let `dt` pass and return whether the frame changed
+    pub fn advance(&mut self, dt: Duration) -> bool {
+        let start = self.index;
+        let mut wrapped = false;
+        self.elapsed += dt;
+        if self.animation.loop_count == LoopCount::Infinite && !self.is_finished() {
+            // skip whole loops
+            let total: Duration = self.animation.frames.iter().map(Self::frame_delay).sum();
+            if self.elapsed > total {
+                self.elapsed =
+                    Duration::from_nanos((self.elapsed.as_nanos() % total.as_nanos()) as u64);
+                wrapped = true;
+            }
+        }
+
+        while !self.is_finished() {
+            let delay = Self::frame_delay(&self.animation.frames[self.index]);
+            if self.elapsed < delay {
+                break;
+            }
+            self.elapsed -= delay;
+            if self.index + 1 < self.animation.frames.len() {
+                self.index += 1;
+                continue;
+            }
+            self.plays += 1;
+            wrapped = true;
+            if self.is_finished() {
+                // the last frame stays
+                self.elapsed = Duration::ZERO;
+            } else {
+                self.index = 0;
+            }
+        }
+        self.index != start || (wrapped && self.animation.frames.len() > 1)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const RED: Rgba = Rgba {
+        r: 255,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    const GREEN: Rgba = Rgba {
+        r: 0,
+        g: 255,
+        b: 0,
+        a: 255,
+    };
+    const BLUE: Rgba = Rgba {
+        r: 0,
+        g: 0,
+        b: 255,
+        a: 255,
+    };
+    const T: Rgba = Rgba::TRANSPARENT;
+
+    fn palette_bytes(colors: &[Rgba]) -> Vec<u8> {
+        colors.iter().flat_map(|c| [c.r, c.g, c.b]).collect()
+    }
+
+    fn sub_blocks(out: &mut Vec<u8>, data: &[u8]) {
+        for chunk in data.chunks(255) {
+            out.push(chunk.len() as u8);
+            out.extend_from_slice(chunk);
+        }
+        out.push(0);
+    }
+
+    struct TestFrame {
+        rect: [u16; 4],
+        indices: Vec<u8>,
+        local_palette: Option<Vec<Rgba>>,
+        disposal: u8,
+        delay: u16,
+        transparent: Option<u8>,
+        interlaced: bool,
+    }
+
+    fn frame(rect: [u16; 4], indices: &[u8]) -> TestFrame {
+        TestFrame {
+            rect,
+            indices: indices.to_vec(),
+            local_palette: None,
+            disposal: 0,
+            delay: 0,
+            transparent: None,
+            interlaced: false,
+        }
+    }
+
+    /// a gif with a global palette of red, green, blue and black
+    fn encode(size: (u16, u16), loops: Option<u16>, frames: &[TestFrame]) -> Vec<u8> {
+        let mut out = MAGIC_89A.to_vec();
+        out.extend(size.0.to_le_bytes());
+        out.extend(size.1.to_le_bytes());
+        out.extend([0x81, 0, 0]);
+        out.extend(palette_bytes(&[RED, GREEN, BLUE, Rgba::BLACK]));
+        if let Some(loops) = loops {
+            out.extend([0x21, 0xff, 11]);
+            out.extend(b"NETSCAPE2.0");
+            out.extend([3, 1]);
+            out.extend(loops.to_le_bytes());
+            out.push(0);
+        }
+        for frame in frames {
+            out.extend([
+                0x21,
+                0xf9,
+                4,
+                frame.disposal << 2 | frame.transparent.is_some() as u8,
+            ]);
+            out.extend(frame.delay.to_le_bytes());
+            out.extend([frame.transparent.unwrap_or(0), 0]);
+            // a comment, which is skipped
+            out.extend([0x21, 0xfe]);
+            sub_blocks(&mut out, b"comment");
+
+            out.push(0x2c);
+            for v in frame.rect {
+                out.extend(v.to_le_bytes());
+            }
+            let mut flags = if frame.interlaced { 0x40 } else { 0 };
+            if frame.local_palette.is_some() {
+                flags |= 0x80;
+            }
+            out.push(flags);
+            if let Some(palette) = &frame.local_palette {
+                out.extend(palette_bytes(palette));
+            }
+            out.push(2);
+            sub_blocks(&mut out, &lzw::test::encode(&frame.indices, 2));
+        }
+        out.push(0x3b);
+        out
+    }
+
+    #[test]
+    fn single_image() {
+        let data = encode((3, 2), None, &[frame([0, 0, 3, 2], &[0, 1, 2, 3, 2, 1])]);
+        let img = decode(&data).unwrap();
+        assert_eq!(img.data, [RED, GREEN, BLUE, Rgba::BLACK, BLUE, GREEN]);
+
+        let animation = decode_animation(&data).unwrap();
+        assert_eq!(animation.frames.len(), 1);
+        assert_eq!(animation.loop_count, LoopCount::Times(1));
+        assert_eq!(crate::load(&data).unwrap(), img);
+    }
+
+    #[test]
+    fn local_palette_and_clipping() {
+        let mut f = frame([1, 1, 3, 2], &[0, 1, 0, 1, 0, 1]);
+        f.local_palette = Some(vec![BLUE, GREEN]);
+        let img = decode(&encode((3, 2), None, &[f])).unwrap();
+        assert_eq!(img.data, [T, T, T, T, BLUE, GREEN]);
+    }
+
+    #[test]
+    fn interlacing() {
+        let rows: Vec<usize> = interlaced_rows(10).collect();
+        assert_eq!(rows, [0, 8, 4, 2, 6, 1, 3, 5, 7, 9]);
+
+        // rows stored in the order 0, 2, 1, 3
+        let mut f = frame([0, 0, 1, 4], &[0, 2, 1, 3]);
+        f.interlaced = true;
+        let img = decode(&encode((1, 4), None, &[f])).unwrap();
+        assert_eq!(img.data, [RED, GREEN, BLUE, Rgba::BLACK]);
+    }
+
+    #[test]
+    fn animation() {
+        let mut background = frame([0, 0, 2, 2], &[0, 0, 0, 0]);
+        background.delay = 5;
+        // a transparent pixel shows the frame below
+        let mut second = frame([1, 0, 1, 2], &[3, 1]);
+        second.transparent = Some(3);
+        second.disposal = 2;
+        second.delay = 20;
+        let mut third = frame([0, 1, 1, 1], &[2]);
+        third.disposal = 3;
+        let fourth = frame([0, 0, 1, 1], &[1]);
+
+        let data = encode((2, 2), Some(0), &[background, second, third, fourth]);
+        let animation = decode_animation(&data).unwrap();
+        assert_eq!(animation.loop_count, LoopCount::Infinite);
+        let images: Vec<_> = animation
+            .frames
+            .iter()
+            .map(|f| f.image.data.clone())
+            .collect();
+        assert_eq!(
+            images,
+            [
+                [RED, RED, RED, RED],
+                [RED, RED, RED, GREEN],
+                // the second frame was cleared
+                [RED, T, BLUE, T],
+                // the third frame was undone
+                [GREEN, T, RED, T],
+            ]
+        );
+        assert_eq!(animation.frames[0].delay, Duration::from_millis(50));
+        assert_eq!(animation.frames[1].delay, Duration::from_millis(200));
+
+        // the frames iterator gives the same frames
+        let frames: Vec<_> = Frames::new(&data).unwrap().map(Result::unwrap).collect();
+        assert_eq!(frames, animation.frames);
+    }
+
+    #[test]
+    fn player() {
+        let frames: Vec<_> = [5, 20, 0]
+            .into_iter()
+            .map(|delay| TestFrame {
+                delay,
+                ..frame([0, 0, 1, 1], &[delay as u8 % 4])
+            })
+            .collect();
+        let ms = Duration::from_millis;
+
+        let mut player = Player::new(decode_animation(&encode((1, 1), Some(1), &frames)).unwrap());
+        assert_eq!(player.time_to_next_frame(), Some(ms(50)));
+        assert!(!player.advance(ms(30)));
+        assert_eq!(player.time_to_next_frame(), Some(ms(20)));
+        assert!(player.advance(ms(30)));
+        assert_eq!(player.frame_index(), 1);
+        // the delay of 0 is shown for 100ms
+        assert!(player.advance(ms(200)));
+        assert_eq!(player.frame_index(), 2);
+        assert_eq!(player.time_to_next_frame(), Some(ms(90)));
+        assert!(player.advance(ms(90)));
+        assert_eq!(player.frame_index(), 0);
+        // the second time through is the last
+        assert!(player.advance(ms(10_000)));
+        assert_eq!(player.frame_index(), 2);
+        assert!(player.is_finished());
+        assert_eq!(player.time_to_next_frame(), None);
+        assert!(!player.advance(ms(500)));
+        assert_eq!(player.image().data, [RED]);
+
+        // infinite loops skip whole cycles
+        let mut player = Player::new(decode_animation(&encode((1, 1), Some(0), &frames)).unwrap());
+        assert!(player.advance(ms(350 * 1000 + 60)));
+        assert_eq!(player.frame_index(), 1);
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn errors() {
+        let data = encode(
+            (2, 1),
+            None,
+            &[frame([0, 0, 2, 1], &[0, 1]), frame([0, 0, 1, 1], &[2])],
+        );
+        assert_eq!(decode(b"GIF90a"), Err(GifError::InvalidSignature));
+        assert_eq!(decode(&data[..10]), Err(GifError::UnexpectedEof));
+        assert_eq!(decode(&data[..16]), Err(GifError::UnexpectedEof));
+        // no images
+        assert_eq!(decode(&data[..25]), Err(GifError::NoImage));
+        assert_eq!(decode_animation(&data[..25]), Err(GifError::NoImage));
+
+        let mut zero = data.clone();
+        zero[6] = 0;
+        assert_eq!(
+            decode(&zero),
+            Err(GifError::InvalidDimensions {
+                width: 0,
+                height: 1
+            })
+        );
+
+        // a small file claiming a huge screen
+        let mut huge = data.clone();
+        huge[6..10].copy_from_slice(&[0x20, 0x4e, 0x20, 0x4e]);
+        assert_eq!(decode(&huge), Err(GifError::UnexpectedEof));
+
+        let first = data.iter().position(|&b| b == 0x2c).unwrap();
+        let mut code_size = data.clone();
+        code_size[first + 10] = 12;
+        assert_eq!(decode(&code_size), Err(GifError::InvalidCodeSize(12)));
+
+        let mut no_palette = data.clone();
+        no_palette[10] = 0;
+        no_palette.drain(13..25);
+        assert_eq!(decode(&no_palette), Err(GifError::MissingPalette));
+
+        let mut block = data.clone();
+        block[first] = 0x99;
+        assert_eq!(decode(&block), Err(GifError::InvalidBlock(0x99)));
+
+        // a file that ends in the second frame still has its first frame, and the second
+        // frame as far as it goes
+        let second = data.iter().rposition(|&b| b == 0x2c).unwrap();
+        for len in second..data.len() {
+            let animation = decode_animation(&data[..len]).unwrap();
+            assert!(!animation.frames.is_empty(), "truncated to {len}");
+            assert_eq!(animation.frames[0].image.data, [RED, GREEN]);
+        }
+    }
+}
@@ -0,0 +1,766 @@
+use corelib::reader::Reader;
+
+use crate::{Rgba, RgbaImage};
+
+use super::{
+    JpegError, JpegHeader, MAGIC, MAX_PIXELS, ZIGZAG,
+    huffman::{BitReader, HuffmanTable},
+    idct::idct_block,
+};
+
+const SOF0: u8 = 0xc0;
+const SOF1: u8 = 0xc1;
+const SOF2: u8 = 0xc2;
+const DHT: u8 = 0xc4;
+const RST0: u8 = 0xd0;
+const RST7: u8 = 0xd7;
+const SOI: u8 = 0xd8;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+const DQT: u8 = 0xdb;
+const DRI: u8 = 0xdd;
+const APP14: u8 = 0xee;
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant_table: usize,
+    /// the blocks that contain image data
+    blocks_w: usize,
+    blocks_h: usize,
+    /// the blocks per row of `coefs`, which cover all mcus
+    stride: usize,
+    /// the quantized coefficients of every block in natural order
+    coefs: Vec<[i16; 64]>,
+    dc_table: usize,
+    ac_table: usize,
+    dc_pred: i32,
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    progressive: bool,
+    components: Vec<Component>,
+    max_h: usize,
+    max_v: usize,
+    mcus_x: usize,
+    mcus_y: usize,
+}
+
+struct Scan {
+    /// indices into the components of the frame
+    components: Vec<usize>,
+    /// the first and last coefficient in zigzag order
+    ss: usize,
+    se: usize,
+    /// the previous and current bit position for successive approximation
+    ah: u8,
+    al: u8,
+}
+
+#[derive(Default)]
+struct Decoder {
+    /// quantization tables in natural order
+    quant: [Option<[u16; 64]>; 4],
+    dc_tables: [Option<HuffmanTable>; 4],
+    ac_tables: [Option<HuffmanTable>; 4],
+    restart_interval: usize,
+    frame: Option<Frame>,
+    /// the color transform flag of an Adobe APP14 segment
+    adobe_transform: Option<u8>,
+    scans: usize,
+}
+
+/// The marker starting at `pos` and the position after it. Fill bytes are skipped.
+fn next_marker(data: &[u8], pos: usize) -> Result<(u8, usize), JpegError> {
+    match data.get(pos) {
+        None => return Err(JpegError::UnexpectedEof),
+        Some(0xff) => {}
+        Some(_) => return Err(JpegError::ExpectedMarker),
+    }
+    let mut pos = pos + 1;
+    while data.get(pos) == Some(&0xff) {
+        pos += 1;
+    }
+    match data.get(pos) {
+        None => Err(JpegError::UnexpectedEof),
+        Some(0) => Err(JpegError::ExpectedMarker),
+        Some(&marker) => Ok((marker, pos + 1)),
+    }
+}
+
+/// The contents of the segment whose length is at `pos`
+fn segment(data: &[u8], pos: usize, marker: u8) -> Result<&[u8], JpegError> {
+    let mut reader = Reader::new_big_endian(data, pos);
+    if reader.remaining() < 2 {
+        return Err(JpegError::UnexpectedEof);
+    }
+    let len = reader.read::<u16>() as usize;
+    if len < 2 {
+        return Err(JpegError::InvalidSegment(marker));
+    }
+    if reader.remaining() < len - 2 {
+        return Err(JpegError::UnexpectedEof);
+    }
+    Ok(&data[pos + 2..pos + len])
+}
+
+fn is_unsupported_frame(marker: u8) -> bool {
+    matches!(marker, 0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf)
+}
+
+fn parse_header(marker: u8, seg: &[u8]) -> Result<JpegHeader, JpegError> {
+    if seg.len() < 6 {
+        return Err(JpegError::InvalidSegment(marker));
+    }
+    let mut reader = Reader::new_big_endian(seg, 0);
+    let precision: u8 = reader.read();
+    let height: u16 = reader.read();
+    let width: u16 = reader.read();
+    let components: u8 = reader.read();
+
+    if precision != 8 {
+        return Err(JpegError::UnsupportedProcess(marker));
+    }
+    // a height of 0 means it is defined later with a DNL marker, which is not supported
+    if width == 0 || height == 0 || width as u64 * height as u64 > MAX_PIXELS {
+        return Err(JpegError::InvalidDimensions {
+            width: width as u32,
+            height: height as u32,
+        });
+    }
+    if !matches!(components, 1 | 3 | 4) {
+        return Err(JpegError::UnsupportedComponents(components));
+    }
+    if seg.len() != 6 + 3 * components as usize {
+        return Err(JpegError::InvalidSegment(marker));
+    }
+
+    Ok(JpegHeader {
+        width: width as u32,
+        height: height as u32,
+        components,
+        progressive: marker == SOF2,
+    })
+}
+
+/// Read only the frame header of a jpeg file
+pub fn read_header(data: &[u8]) -> Result<JpegHeader, JpegError> {
+    if !data.starts_with(&MAGIC) {
+        return Err(JpegError::InvalidMagic);
+    }
+    let mut pos = MAGIC.len();
+    loop {
+        let (marker, after) = next_marker(data, pos)?;
+        if (RST0..=RST7).contains(&marker) {
+            pos = after;
+            continue;
+        }
+        if marker == SOS || marker == EOI {
+            return Err(JpegError::MissingFrame);
+        }
+        let seg = segment(data, after, marker)?;
+        match marker {
+            SOF0 | SOF1 | SOF2 => return parse_header(marker, seg),
+            m if is_unsupported_frame(m) => return Err(JpegError::UnsupportedProcess(m)),
+            _ => pos = after + 2 + seg.len(),
+        }
+    }
+}
+
+/// Decode a baseline or progressive jpeg file. Files that end early are decoded as far as
+/// the data goes, like browsers do.
+pub fn decode(data: &[u8]) -> Result<RgbaImage, JpegError> {
+    if !data.starts_with(&MAGIC) {
+        return Err(JpegError::InvalidMagic);
+    }
+    let mut decoder = Decoder::default();
+
+    let mut pos = MAGIC.len();
+    loop {
+        if pos >= data.len() && decoder.scans > 0 {
+            break;
+        }
+        let (marker, after) = next_marker(data, pos)?;
+        match marker {
+            EOI => break,
+            // restart markers without data do not matter
+            RST0..=RST7 => {
+                pos = after;
+                continue;
+            }
+            SOI => return Err(JpegError::InvalidSegment(SOI)),
+            _ => {}
+        }
+
+        let seg = segment(data, after, marker)?;
+        pos = after + 2 + seg.len();
+        match marker {
+            SOF0 | SOF1 | SOF2 => decoder.read_frame(marker, seg, data.len())?,
+            m if is_unsupported_frame(m) => return Err(JpegError::UnsupportedProcess(m)),
+            DHT => decoder.read_huffman_tables(seg)?,
+            DQT => decoder.read_quant_tables(seg)?,
+            DRI => {
+                if seg.len() != 2 {
+                    return Err(JpegError::InvalidSegment(DRI));
+                }
+                decoder.restart_interval = u16::from_be_bytes([seg[0], seg[1]]) as usize;
+            }
+            APP14 if seg.starts_with(b"Adobe") && seg.len() >= 12 => {
+                decoder.adobe_transform = Some(seg[11]);
+            }
+            SOS => {
+                let scan = decoder.read_scan_header(seg)?;
+                pos = decoder.decode_scan(data, pos, &scan)?;
+                decoder.scans += 1;
+
+                // skip what is left of the entropy coded data
+                while pos + 1 < data.len() && !(data[pos] == 0xff && data[pos + 1] != 0) {
+                    pos += 1;
+                }
+                if pos + 1 >= data.len() {
+                    pos = data.len();
+                }
+            }
+            // application data, comments and unknown segments
+            _ => {}
+        }
+    }
+
+    decoder.finish()
+}
+
+impl Decoder {
+    fn read_frame(&mut self, marker: u8, seg: &[u8], data_len: usize) -> Result<(), JpegError> {
+        if self.frame.is_some() {
+            return Err(JpegError::InvalidSegment(marker));
+        }
+        let header = parse_header(marker, seg)?;
+        let (width, height) = (header.width as usize, header.height as usize);
+
+        let mut reader = Reader::new_big_endian(seg, 6);
+        let mut components = Vec::new();
+        for _ in 0..header.components {
+            let id: u8 = reader.read();
+            let sampling: u8 = reader.read();
+            let quant_table: u8 = reader.read();
+            let (h, v) = ((sampling >> 4) as usize, (sampling & 15) as usize);
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || quant_table > 3 {
+                return Err(JpegError::InvalidSegment(marker));
+            }
+            components.push((id, h, v, quant_table as usize));
+        }
+
+        let max_h = components.iter().map(|c| c.1).max().unwrap();
+        let max_v = components.iter().map(|c| c.2).max().unwrap();
+        let mcus_x = width.div_ceil(8 * max_h);
+        let mcus_y = height.div_ceil(8 * max_v);
+
+        // every block takes at least one bit, so a small file can not claim a huge image
+        let total_blocks: usize =
+            components.iter().map(|c| c.1 * c.2).sum::<usize>() * mcus_x * mcus_y;
+        if total_blocks > data_len.saturating_mul(8) {
+            return Err(JpegError::UnexpectedEof);
+        }
+
+        let components = components
+            .into_iter()
+            .map(|(id, h, v, quant_table)| Component {
+                id,
+                h,
+                v,
+                quant_table,
+                blocks_w: (width * h).div_ceil(max_h).div_ceil(8),
+                blocks_h: (height * v).div_ceil(max_v).div_ceil(8),
+                stride: mcus_x * h,
+                coefs: vec![[0; 64]; mcus_x * h * mcus_y * v],
+                dc_table: 0,
+                ac_table: 0,
+                dc_pred: 0,
+            })
+            .collect();
+
+        self.frame = Some(Frame {
+            width,
+            height,
+            progressive: header.progressive,
+            components,
+            max_h,
+            max_v,
+            mcus_x,
+            mcus_y,
+        });
+        Ok(())
+    }
+
+    fn read_huffman_tables(&mut self, seg: &[u8]) -> Result<(), JpegError> {
+        let mut reader = Reader::new_big_endian(seg, 0);
+        while reader.remaining() > 0 {
+            if reader.remaining() < 17 {
+                return Err(JpegError::InvalidSegment(DHT));
+            }
+            let info: u8 = reader.read();
+            let (class, id) = (info >> 4, (info & 15) as usize);
+            if class > 1 || id > 3 {
+                return Err(JpegError::InvalidSegment(DHT));
+            }
+
+            let counts: [u8; 16] = reader.read();
+            let total: usize = counts.iter().map(|&c| c as usize).sum();
+            if reader.remaining() < total {
+                return Err(JpegError::InvalidSegment(DHT));
+            }
+            let start = reader.get_pos();
+            let table = HuffmanTable::new(&counts, &seg[start..start + total])?;
+            reader.set_pos(start + total);
+
+            if class == 0 {
+                self.dc_tables[id] = Some(table);
+            } else {
+                self.ac_tables[id] = Some(table);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_quant_tables(&mut self, seg: &[u8]) -> Result<(), JpegError> {
+        let mut reader = Reader::new_big_endian(seg, 0);
+        while reader.remaining() > 0 {
+            let info: u8 = reader.read();
+            let (precision, id) = (info >> 4, (info & 15) as usize);
+            if precision > 1 || id > 3 || reader.remaining() < 64 * (precision as usize + 1) {
+                return Err(JpegError::InvalidSegment(DQT));
+            }
+
+            let mut table = [0u16; 64];
+            for &natural in &ZIGZAG {
+                table[natural] = if precision == 0 {
+                    reader.read::<u8>() as u16
+                } else {
+                    reader.read::<u16>()
+                };
+            }
+            self.quant[id] = Some(table);
+        }
+        Ok(())
+    }
+
+    fn read_scan_header(&mut self, seg: &[u8]) -> Result<Scan, JpegError> {
+        let invalid = JpegError::InvalidSegment(SOS);
+        let frame = self.frame.as_mut().ok_or(JpegError::MissingFrame)?;
+
+        let count = *seg.first().ok_or(invalid.clone())? as usize;
+        if count == 0 || count > 4 || seg.len() != 1 + 2 * count + 3 {
+            return Err(invalid);
+        }
+
+        let mut components = Vec::with_capacity(count);
+        for c in seg[1..1 + 2 * count].chunks_exact(2) {
+            let index = frame
+                .components
+                .iter()
+                .position(|comp| comp.id == c[0])
+                .ok_or(invalid.clone())?;
+            let comp = &mut frame.components[index];
+            comp.dc_table = (c[1] >> 4) as usize;
+            comp.ac_table = (c[1] & 15) as usize;
+            if comp.dc_table > 3 || comp.ac_table > 3 || components.contains(&index) {
+                return Err(invalid);
+            }
+            components.push(index);
+        }
+        // an mcu may have at most 10 blocks
+        if count > 1
+            && components
+                .iter()
+                .map(|&i| frame.components[i].h * frame.components[i].v)
+                .sum::<usize>()
+                > 10
+        {
+            return Err(invalid);
+        }
+
+        let params = &seg[1 + 2 * count..];
+        let mut scan = Scan {
+            components,
+            ss: params[0] as usize,
+            se: params[1] as usize,
+            ah: params[2] >> 4,
+            al: params[2] & 15,
+        };
+
+        if frame.progressive {
+            let valid_range = if scan.ss == 0 {
+                scan.se == 0
+            } else {
+                count == 1 && scan.ss <= scan.se && scan.se <= 63
+            };
+            if !valid_range || scan.al > 13 || scan.ah > 13 {
+                return Err(invalid);
+            }
+        } else {
+            // the values are fixed for sequential images
+            (scan.ss, scan.se, scan.ah, scan.al) = (0, 63, 0, 0);
+        }
+
+        Ok(scan)
+    }
+
+    /// Decode the entropy coded data of a scan starting at `pos` and return the position
+    /// after it
+    fn decode_scan(&mut self, data: &[u8], pos: usize, scan: &Scan) -> Result<usize, JpegError> {
+        let Self {
+            frame,
+            dc_tables,
+            ac_tables,
+            restart_interval,
+            ..
+        } = self;
+        let frame = frame.as_mut().unwrap();
+        let progressive = frame.progressive;
+
+        for &i in &scan.components {
+            frame.components[i].dc_pred = 0;
+        }
+
+        // a scan with a single component has mcus of one block
+        let single = scan.components.len() == 1;
+        let (mcus_x, mcus_y) = if single {
+            let comp = &frame.components[scan.components[0]];
+            (comp.blocks_w, comp.blocks_h)
+        } else {
+            (frame.mcus_x, frame.mcus_y)
+        };
+
+        let mut reader = BitReader::new(data, pos);
+        let mut eobrun = 0;
+        for mcu in 0..mcus_x * mcus_y {
+            if *restart_interval > 0 && mcu > 0 && mcu % *restart_interval == 0 {
+                reader.restart();
+                eobrun = 0;
+                for &i in &scan.components {
+                    frame.components[i].dc_pred = 0;
+                }
+            }
+
+            let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+            for &i in &scan.components {
+                let comp = &mut frame.components[i];
+                let dc = dc_tables[comp.dc_table].as_ref();
+                let ac = ac_tables[comp.ac_table].as_ref();
+                let (bw, bh) = if single { (1, 1) } else { (comp.h, comp.v) };
+
+                for by in 0..bh {
+                    for bx in 0..bw {
+                        let index = (my * bh + by) * comp.stride + mx * bw + bx;
+                        let block = &mut comp.coefs[index];
+                        let r = &mut reader;
+                        match (progressive, scan.ss, scan.ah) {
+                            (false, ..) => decode_baseline(r, dc, ac, &mut comp.dc_pred, block)?,
+                            (true, 0, 0) => {
+                                decode_dc_first(r, dc, &mut comp.dc_pred, block, scan.al)?
+                            }
+                            (true, 0, _) => decode_dc_refine(r, block, scan.al),
+                            (true, _, 0) => decode_ac_first(r, ac, scan, block, &mut eobrun)?,
+                            (true, _, _) => decode_ac_refine(r, ac, scan, block, &mut eobrun)?,
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(reader.position())
+    }
+
+    fn finish(self) -> Result<RgbaImage, JpegError> {
+        let frame = self.frame.ok_or(JpegError::MissingFrame)?;
+        if self.scans == 0 {
+            return Err(JpegError::MissingFrame);
+        }
+        let (width, height) = (frame.width, frame.height);
+
+        let mut planes = Vec::with_capacity(frame.components.len());
+        for comp in &frame.components {
+            let quant = self.quant[comp.quant_table].ok_or(JpegError::MissingTable)?;
+            let plane_w = comp.blocks_w * 8;
+            let mut plane = vec![0u8; plane_w * comp.blocks_h * 8];
+
+            for by in 0..comp.blocks_h {
+                for bx in 0..comp.blocks_w {
+                    let block = &comp.coefs[by * comp.stride + bx];
+                    let coefs: [i32; 64] =
+                        std::array::from_fn(|i| block[i] as i32 * quant[i] as i32);
+                    idct_block(&coefs, &mut plane[by * 8 * plane_w + bx * 8..], plane_w);
+                }
+            }
+
+            planes.push(upsample(&plane, plane_w, comp, &frame));
+        }
+
+        let transform = match (planes.len(), self.adobe_transform) {
+            (_, Some(transform)) => transform,
+            // without the Adobe segment the component ids are a hint for rgb images
+            (3, None) if frame.components.iter().map(|c| c.id).eq(*b"RGB") => 0,
+            (3, None) => 1,
+            _ => 0,
+        };
+
+        let data = (0..width * height)
+            .map(|i| match planes.len() {
+                1 => {
+                    let v = planes[0][i];
+                    Rgba {
+                        r: v,
+                        g: v,
+                        b: v,
+                        a: 255,
+                    }
+                }
+                3 => {
+                    let (a, b, c) = (planes[0][i], planes[1][i], planes[2][i]);
+                    let [r, g, b] = if transform == 0 {
+                        [a, b, c]
+                    } else {
+                        ycbcr_to_rgb(a, b, c)
+                    };
+                    Rgba { r, g, b, a: 255 }
+                }
+                _ => {
+                    let (a, b, c, k) = (planes[0][i], planes[1][i], planes[2][i], planes[3][i]);
+                    // Adobe stores inverted cmyk, so the inverted cyan is the red amount.
+                    // YCCK is the ycbcr of the cyan, magenta and yellow that aren't inverted.
+                    let [r, g, b] = if transform == 2 {
+                        ycbcr_to_rgb(a, b, c).map(|v| 255 - v)
+                    } else {
+                        [a, b, c]
+                    };
+                    let mul = |v: u8| ((v as u32 * k as u32 + 127) / 255) as u8;
+                    Rgba {
+                        r: mul(r),
+                        g: mul(g),
+                        b: mul(b),
+                        a: 255,
+                    }
+                }
+            })
+            .collect();
+
+        Ok(RgbaImage::from_vec(width, height, data))
+    }
+}
+
+fn decode_baseline(
+    reader: &mut BitReader,
+    dc: Option<&HuffmanTable>,
+    ac: Option<&HuffmanTable>,
+    dc_pred: &mut i32,
+    block: &mut [i16; 64],
+) -> Result<(), JpegError> {
+    let (dc, ac) = (
+        dc.ok_or(JpegError::MissingTable)?,
+        ac.ok_or(JpegError::MissingTable)?,
+    );
+
+    let s = reader.decode(dc)?;
+    *dc_pred = dc_pred.wrapping_add(reader.receive_extend(s)?);
+    block[0] = *dc_pred as i16;
+
+    let mut k = 1;
+    while k < 64 {
+        let rs = reader.decode(ac)?;
+        let (run, size) = ((rs >> 4) as usize, rs & 15);
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > 63 {
+            return Err(JpegError::InvalidCode);
+        }
+        block[ZIGZAG[k]] = reader.receive_extend(size)? as i16;
+        k += 1;
+    }
+    Ok(())
+}
+
+fn decode_dc_first(
+    reader: &mut BitReader,
+    dc: Option<&HuffmanTable>,
+    dc_pred: &mut i32,
+    block: &mut [i16; 64],
+    al: u8,
+) -> Result<(), JpegError> {
+    let s = reader.decode(dc.ok_or(JpegError::MissingTable)?)?;
+    *dc_pred = dc_pred.wrapping_add(reader.receive_extend(s)?);
+    block[0] = (*dc_pred << al) as i16;
+    Ok(())
+}
+
+fn decode_dc_refine(reader: &mut BitReader, block: &mut [i16; 64], al: u8) {
+    if reader.bit() {
+        block[0] |= 1 << al;
+    }
+}
+
+fn decode_ac_first(
+    reader: &mut BitReader,
+    ac: Option<&HuffmanTable>,
+    scan: &Scan,
+    block: &mut [i16; 64],
+    eobrun: &mut u32,
+) -> Result<(), JpegError> {
+    if *eobrun > 0 {
+        *eobrun -= 1;
+        return Ok(());
+    }
+    let ac = ac.ok_or(JpegError::MissingTable)?;
+
+    let mut k = scan.ss;
+    while k <= scan.se {
+        let rs = reader.decode(ac)?;
+        let (run, size) = ((rs >> 4) as u32, rs & 15);
+        if size == 0 {
+            if run < 15 {
+                // this and the next blocks end here
+                *eobrun = (1 << run) - 1 + reader.bits(run);
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run as usize;
+        if k > scan.se {
+            return Err(JpegError::InvalidCode);
+        }
+        block[ZIGZAG[k]] = (reader.receive_extend(size)? << scan.al) as i16;
+        k += 1;
+    }
+    Ok(())
+}
+
+/// Refine a nonzero coefficient with the next bit, which is 1 if its absolute value grows
+fn refine(reader: &mut BitReader, coef: &mut i16, bit: i16) {
+    if reader.bit() && *coef & bit == 0 {
+        *coef = if *coef >= 0 {
+            coef.wrapping_add(bit)
+        } else {
+            coef.wrapping_sub(bit)
+        };
+    }
+}
+
+fn decode_ac_refine(
+    reader: &mut BitReader,
+    ac: Option<&HuffmanTable>,
+    scan: &Scan,
+    block: &mut [i16; 64],
+    eobrun: &mut u32,
+) -> Result<(), JpegError> {
+    let bit = 1i16 << scan.al;
+    let mut k = scan.ss;
+
+    if *eobrun == 0 {
+        let ac = ac.ok_or(JpegError::MissingTable)?;
+        while k <= scan.se {
+            let rs = reader.decode(ac)?;
+            let (mut run, size) = ((rs >> 4) as i32, rs & 15);
+            let mut value = 0;
+            if size != 0 {
+                // newly nonzero coefficients always have a size of 1
+                value = if reader.bit() { bit } else { -bit };
+            } else if run != 15 {
+                *eobrun = (1 << run) + reader.bits(run as u32);
+                break;
+            }
+
+            // skip `run` zero coefficients, refining the nonzero ones on the way
+            while k <= scan.se {
+                let coef = &mut block[ZIGZAG[k]];
+                if *coef != 0 {
+                    refine(reader, coef, bit);
+                } else {
+                    if run == 0 {
+                        break;
+                    }
+                    run -= 1;
+                }
+                k += 1;
+            }
+
+            if value != 0 && k <= scan.se {
+                block[ZIGZAG[k]] = value;
+            }
+            k += 1;
+        }
+    }
+
+    if *eobrun > 0 {
+        // the rest of the block only has refinement bits
+        while k <= scan.se {
+            let coef = &mut block[ZIGZAG[k]];
+            if *coef != 0 {
+                refine(reader, coef, bit);
+            }
+            k += 1;
+        }
+        *eobrun -= 1;
+    }
+    Ok(())
+}
+
+/// Scale a component plane to the size of the image with bilinear interpolation between
+/// the sample centers
+fn upsample(plane: &[u8], plane_w: usize, comp: &Component, frame: &Frame) -> Vec<u8> {
+    let (width, height) = (frame.width, frame.height);
+    if comp.h == frame.max_h && comp.v == frame.max_v {
+        return plane
+            .chunks_exact(plane_w)
+            .take(height)
+            .flat_map(|row| &row[..width])
+            .copied()
+            .collect();
+    }
+
+    let comp_w = (width * comp.h).div_ceil(frame.max_h);
+    let comp_h = (height * comp.v).div_ceil(frame.max_v);
+    // the source sample and the weight of the next one in 1/256 for every output position
+    let positions = |len: usize, factor: usize, max: usize, comp_len: usize| -> Vec<(usize, u32)> {
+        (0..len)
+            .map(|x| {
+                let src = ((2 * x + 1) * factor * 256 / (2 * max)) as i64 - 128;
+                let src = src.clamp(0, (comp_len as i64 - 1) * 256) as usize;
+                (src / 256, (src % 256) as u32)
+            })
+            .collect()
+    };
+    let xs = positions(width, comp.h, frame.max_h, comp_w);
+    let ys = positions(height, comp.v, frame.max_v, comp_h);
+
+    let mut out = Vec::with_capacity(width * height);
+    for &(y0, fy) in &ys {
+        let row0 = &plane[y0 * plane_w..];
+        let row1 = &plane[(y0 + 1).min(comp_h - 1) * plane_w..];
+        for &(x0, fx) in &xs {
+            let x1 = (x0 + 1).min(comp_w - 1);
+            let top = row0[x0] as u32 * (256 - fx) + row0[x1] as u32 * fx;
+            let bottom = row1[x0] as u32 * (256 - fx) + row1[x1] as u32 * fx;
+            out.push(((top * (256 - fy) + bottom * fy + (1 << 15)) >> 16) as u8);
+        }
+    }
+    out
+}
+
+/// The JFIF conversion with 16 bits of fixed point precision
+fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> [u8; 3] {
+    let y = ((y as i32) << 16) + (1 << 15);
+    let (cb, cr) = (cb as i32 - 128, cr as i32 - 128);
+    let r = y + 91881 * cr;
+    let g = y - 22554 * cb - 46802 * cr;
+    let b = y + 116130 * cb;
+    [r, g, b].map(|v| (v >> 16).clamp(0, 255) as u8)
+}
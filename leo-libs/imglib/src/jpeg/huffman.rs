@@ -0,0 +1,207 @@
+//! Huffman tables and the bit reader for entropy coded segments
+
+use super::JpegError;
+
+const LOOKUP_BITS: u32 = 9;
+
+pub(crate) struct HuffmanTable {
+    /// `(length << 8) | value` for codes of at most `LOOKUP_BITS` bits indexed by the next
+    /// bits of the data, 0 if the code is longer
+    lookup: Box<[u16; 1 << LOOKUP_BITS]>,
+    /// the largest code of each length, -1 if there is none
+    max_code: [i32; 17],
+    /// added to a code of a length to get the index of its value
+    offset: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    /// Build a table from the amount of codes of every length from 1 to 16 and the values
+    /// in order of their codes
+    pub(crate) fn new(counts: &[u8; 16], values: &[u8]) -> Result<Self, JpegError> {
+        let total: usize = counts.iter().map(|&c| c as usize).sum();
+        if total != values.len() || total > 256 {
+            return Err(JpegError::InvalidHuffmanTable);
+        }
+
+        let mut lookup = Box::new([0u16; 1 << LOOKUP_BITS]);
+        let mut max_code = [-1; 17];
+        let mut offset = [0; 17];
+
+        let mut code: u32 = 0;
+        let mut index = 0;
+        for len in 1..=16 {
+            let count = counts[len - 1] as usize;
+            offset[len] = index as i32 - code as i32;
+            // more codes than fit in `len` bits
+            if code + count as u32 > 1 << len {
+                return Err(JpegError::InvalidHuffmanTable);
+            }
+            for _ in 0..count {
+                if len as u32 <= LOOKUP_BITS {
+                    let shift = LOOKUP_BITS - len as u32;
+                    let start = (code << shift) as usize;
+                    let entry = ((len as u16) << 8) | values[index] as u16;
+                    lookup[start..start + (1 << shift)].fill(entry);
+                }
+                code += 1;
+                index += 1;
+            }
+            if count > 0 {
+                max_code[len] = code as i32 - 1;
+            }
+            code <<= 1;
+        }
+
+        Ok(Self {
+            lookup,
+            max_code,
+            offset,
+            values: values.to_vec(),
+        })
+    }
+}
+
+/// Reads the entropy coded data of a scan, most significant bit first. Stuffed zero bytes
+/// after 0xff are removed. Once a marker is reached only zero bits are returned.
+pub(crate) struct BitReader<'data> {
+    data: &'data [u8],
+    pos: usize,
+    /// the buffered bits, aligned to the most significant bit
+    bits: u64,
+    count: u32,
+    marker_reached: bool,
+}
+
+impl<'data> BitReader<'data> {
+    pub(crate) fn new(data: &'data [u8], pos: usize) -> Self {
+        Self {
+            data,
+            pos,
+            bits: 0,
+            count: 0,
+            marker_reached: false,
+        }
+    }
+
+    /// The position after the entropy coded data, which is the start of the next marker
+    /// if the data was read entirely
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    fn fill(&mut self) {
+        while self.count <= 56 {
+            let byte = if self.marker_reached || self.pos >= self.data.len() {
+                0
+            } else if self.data[self.pos] != 0xff {
+                self.pos += 1;
+                self.data[self.pos - 1]
+            } else {
+                // any amount of 0xff bytes may come before a marker
+                let mut next = self.pos + 1;
+                while self.data.get(next) == Some(&0xff) {
+                    next += 1;
+                }
+                if self.data.get(next) == Some(&0) {
+                    self.pos = next + 1;
+                    0xff
+                } else {
+                    self.pos = next - 1;
+                    self.marker_reached = true;
+                    0
+                }
+            };
+            self.bits |= (byte as u64) << (56 - self.count);
+            self.count += 8;
+        }
+    }
+
+    #[inline]
+    fn peek(&mut self, n: u32) -> u32 {
+        if self.count < n {
+            self.fill();
+        }
+        (self.bits >> (64 - n)) as u32
+    }
+
+    #[inline]
+    fn consume(&mut self, n: u32) {
+        self.bits <<= n;
+        self.count -= n;
+    }
+
+    /// read `n` bits, at most 16
+    #[inline]
+    pub(crate) fn bits(&mut self, n: u32) -> u32 {
+        if n == 0 {
+            return 0;
+        }
+        let v = self.peek(n);
+        self.consume(n);
+        v
+    }
+
+    #[inline]
+    pub(crate) fn bit(&mut self) -> bool {
+        self.bits(1) == 1
+    }
+
+    pub(crate) fn decode(&mut self, table: &HuffmanTable) -> Result<u8, JpegError> {
+        let entry = table.lookup[self.peek(LOOKUP_BITS) as usize];
+        if entry != 0 {
+            self.consume((entry >> 8) as u32);
+            return Ok(entry as u8);
+        }
+
+        for len in LOOKUP_BITS + 1..=16 {
+            let code = self.peek(len) as i32;
+            if code <= table.max_code[len as usize] {
+                self.consume(len);
+                let index = code + table.offset[len as usize];
+                return table
+                    .values
+                    .get(index as usize)
+                    .copied()
+                    .ok_or(JpegError::InvalidCode);
+            }
+        }
+        Err(JpegError::InvalidCode)
+    }
+
+    /// read an `s` bit number, where the values starting with a 0 bit are negative
+    #[inline]
+    pub(crate) fn receive_extend(&mut self, s: u8) -> Result<i32, JpegError> {
+        if s == 0 {
+            return Ok(0);
+        }
+        if s > 16 {
+            return Err(JpegError::InvalidCode);
+        }
+        let v = self.bits(s as u32) as i32;
+        Ok(if v < 1 << (s - 1) {
+            v - (1 << s) + 1
+        } else {
+            v
+        })
+    }
+
+    /// Skip to the data after the next restart marker, discarding the remaining bits
+    pub(crate) fn restart(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+        self.marker_reached = false;
+
+        // the padding bits of the last byte were already read, look for the marker
+        while self.pos + 1 < self.data.len() {
+            if self.data[self.pos] == 0xff && self.data[self.pos + 1] != 0xff {
+                if (0xd0..=0xd7).contains(&self.data[self.pos + 1]) {
+                    self.pos += 2;
+                }
+                // another marker ends the scan, it is found again by the next read
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+}
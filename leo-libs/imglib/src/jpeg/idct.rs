@@ -0,0 +1,134 @@
+//! The integer inverse DCT, the same algorithm as the "islow" IDCT of libjpeg
+//! (Loeffler, Ligtenberg and Moschytz) with 12 bits of fixed point precision.
+
+const PRECISION: u32 = 12;
+
+/// a constant in fixed point
+const fn fix(x: f64) -> i64 {
+    (x * (1 << PRECISION) as f64 + 0.5) as i64
+}
+
+/// The one dimensional IDCT of 8 values, returns the even and odd parts that are combined
+/// into the outputs as `even[i] + odd[i]` and `even[i] - odd[i]`.
+#[inline]
+fn idct_1d(s: [i64; 8]) -> ([i64; 4], [i64; 4]) {
+    // even part
+    let p1 = (s[2] + s[6]) * fix(0.541196100);
+    let t2 = p1 + s[6] * fix(-1.847759065);
+    let t3 = p1 + s[2] * fix(0.765366865);
+    let t0 = (s[0] + s[4]) << PRECISION;
+    let t1 = (s[0] - s[4]) << PRECISION;
+    let even = [t0 + t3, t1 + t2, t1 - t2, t0 - t3];
+
+    // odd part
+    let (t0, t1, t2, t3) = (s[7], s[5], s[3], s[1]);
+    let p1 = t0 + t3;
+    let p2 = t1 + t2;
+    let p3 = t0 + t2;
+    let p4 = t1 + t3;
+    let p5 = (p3 + p4) * fix(1.175875602);
+
+    let p1 = p5 + p1 * fix(-0.899976223);
+    let p2 = p5 + p2 * fix(-2.562915447);
+    let p3 = p3 * fix(-1.961570560);
+    let p4 = p4 * fix(-0.390180644);
+
+    let t0 = t0 * fix(0.298631336) + p1 + p3;
+    let t1 = t1 * fix(2.053119869) + p2 + p4;
+    let t2 = t2 * fix(3.072711026) + p2 + p3;
+    let t3 = t3 * fix(1.501321110) + p1 + p4;
+
+    (even, [t3, t2, t1, t0])
+}
+
+/// Transform dequantized coefficients in natural order into samples, which are written
+/// to `out` with `stride` bytes per row.
+pub(crate) fn idct_block(coefs: &[i32; 64], out: &mut [u8], stride: usize) {
+    let mut tmp = [0i64; 64];
+
+    // columns, keeping 2 extra bits of precision
+    const PASS1_BITS: u32 = 2;
+    for x in 0..8 {
+        let col: [i64; 8] = std::array::from_fn(|y| coefs[y * 8 + x] as i64);
+        if col[1..].iter().all(|&c| c == 0) {
+            for y in 0..8 {
+                tmp[y * 8 + x] = col[0] << PASS1_BITS;
+            }
+            continue;
+        }
+
+        let (even, odd) = idct_1d(col);
+        let round = 1 << (PRECISION - PASS1_BITS - 1);
+        for i in 0..4 {
+            tmp[i * 8 + x] = (even[i] + odd[i] + round) >> (PRECISION - PASS1_BITS);
+            tmp[(7 - i) * 8 + x] = (even[i] - odd[i] + round) >> (PRECISION - PASS1_BITS);
+        }
+    }
+
+    // rows, removing the precision, the extra bits and the factor of 8 of the two passes,
+    // then shifting the samples from -128..127 to 0..255
+    let shift = PRECISION + PASS1_BITS + 3;
+    let round = (1 << (shift - 1)) + (128 << shift);
+    for y in 0..8 {
+        let row: [i64; 8] = tmp[y * 8..y * 8 + 8].try_into().unwrap();
+        let (even, odd) = idct_1d(row);
+        let out_row = &mut out[y * stride..y * stride + 8];
+        for i in 0..4 {
+            out_row[i] = ((even[i] + odd[i] + round) >> shift).clamp(0, 255) as u8;
+            out_row[7 - i] = ((even[i] - odd[i] + round) >> shift).clamp(0, 255) as u8;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// the textbook definition of the IDCT
+    fn reference(coefs: &[i32; 64]) -> [f64; 64] {
+        let c = |u: usize| if u == 0 { 1.0 / 2f64.sqrt() } else { 1.0 };
+        std::array::from_fn(|i| {
+            let (x, y) = ((i % 8) as f64, (i / 8) as f64);
+            let mut sum = 0.0;
+            for v in 0..8 {
+                for u in 0..8 {
+                    sum += c(u)
+                        * c(v)
+                        * coefs[v * 8 + u] as f64
+                        * ((2.0 * x + 1.0) * u as f64 * std::f64::consts::PI / 16.0).cos()
+                        * ((2.0 * y + 1.0) * v as f64 * std::f64::consts::PI / 16.0).cos();
+                }
+            }
+            (sum / 4.0 + 128.0).clamp(0.0, 255.0)
+        })
+    }
+
+    #[test]
+    fn matches_reference() {
+        let mut x: u32 = 5;
+        for round in 0..200 {
+            let coefs: [i32; 64] = std::array::from_fn(|i| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                // larger values for the low frequencies like in real images
+                let range = 1024 >> (i / 8 + i % 8).min(8);
+                if round % 4 == 0 && i > 0 {
+                    0
+                } else {
+                    ((x >> 8) % (2 * range as u32 + 1)) as i32 - range
+                }
+            });
+
+            let mut out = [0u8; 64];
+            idct_block(&coefs, &mut out, 8);
+            let expected = reference(&coefs);
+            for i in 0..64 {
+                assert!(
+                    (out[i] as f64 - expected[i]).abs() <= 1.0,
+                    "{} vs {} for {coefs:?}",
+                    out[i],
+                    expected[i]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,596 @@
+//! The JPEG format (ITU T.81): https://www.w3.org/Graphics/JPEG/itu-t81.pdf
+//!
+//! Baseline and progressive images with huffman coding and 8 bit samples are supported.
+//! Arithmetic coding, lossless and hierarchical images are not.
+
+use std::fmt::Display;
+
+pub mod decoder;
+mod huffman;
+mod idct;
+
+pub use decoder::{decode, read_header};
+
+pub const MAGIC: [u8; 2] = [0xff, 0xd8];
+
+/// The same limit as for qoi, protects against absurd allocations
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+/// The natural (row major) index of every coefficient in zigzag order
+pub(crate) const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// The information from the frame header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JpegHeader {
+    pub width: u32,
+    pub height: u32,
+    pub components: u8,
+    pub progressive: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JpegError {
+    InvalidMagic,
+    UnexpectedEof,
+    /// a byte where a marker was expected
+    ExpectedMarker,
+    /// a segment that has the wrong size, invalid values or is at the wrong position
+    InvalidSegment(u8),
+    /// arithmetic coding, lossless or hierarchical jpeg, or a sample precision other than 8
+    UnsupportedProcess(u8),
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+    /// 2 or more than 4 components
+    UnsupportedComponents(u8),
+    InvalidHuffmanTable,
+    /// a scan uses a huffman or quantization table that was not defined
+    MissingTable,
+    /// a huffman code that is not part of the table or a coefficient outside the block
+    InvalidCode,
+    /// the image ends before a frame and a scan were read
+    MissingFrame,
+}
+
+impl Display for JpegError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "not a jpeg file"),
+            Self::UnexpectedEof => write!(f, "unexpected end of data"),
+            Self::ExpectedMarker => write!(f, "expected a marker"),
+            Self::InvalidSegment(m) => write!(f, "invalid segment with marker {m:#04x}"),
+            Self::UnsupportedProcess(m) => write!(f, "unsupported jpeg process {m:#04x}"),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Self::UnsupportedComponents(n) => write!(f, "unsupported component count {n}"),
+            Self::InvalidHuffmanTable => write!(f, "invalid huffman table"),
+            Self::MissingTable => write!(f, "scan uses an undefined table"),
+            Self::InvalidCode => write!(f, "invalid entropy coded data"),
+            Self::MissingFrame => write!(f, "no image data"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::{Rgba, RgbaImage};
+
+    /// Writes entropy coded data, stuffing a zero byte after every 0xff
+    #[derive(Default)]
+    struct BitWriter {
+        out: Vec<u8>,
+        acc: u32,
+        count: u32,
+    }
+
+    impl BitWriter {
+        fn put(&mut self, bits: u32, len: u32) {
+            for i in (0..len).rev() {
+                self.acc = (self.acc << 1) | ((bits >> i) & 1);
+                self.count += 1;
+                if self.count == 8 {
+                    self.out.push(self.acc as u8);
+                    if self.acc == 0xff {
+                        self.out.push(0);
+                    }
+                    self.acc = 0;
+                    self.count = 0;
+                }
+            }
+        }
+
+        /// pad the last byte with 1 bits
+        fn flush(&mut self) {
+            if self.count > 0 {
+                self.put(0x7f, 8 - self.count);
+            }
+        }
+
+        /// the category and the extra bits of a value
+        fn value(&mut self, v: i32) -> (u32, u32) {
+            let size = 32 - v.unsigned_abs().leading_zeros();
+            let extra = if v < 0 { v + (1 << size) - 1 } else { v };
+            (size, extra as u32)
+        }
+    }
+
+    /// The ac symbols of the test huffman table, every symbol has an 8 bit code which is its
+    /// index. The dc table has 4 bit codes that are the same as the symbols.
+    fn ac_symbols() -> Vec<u8> {
+        let mut symbols = vec![0x00, 0xf0];
+        symbols.extend((1..15).map(|run| run << 4));
+        for run in 0..16 {
+            symbols.extend((1..=10).map(|size| run << 4 | size));
+        }
+        symbols
+    }
+
+    struct Options {
+        gray: bool,
+        /// the sampling factors of the luma component
+        sampling: (usize, usize),
+        quant: u8,
+        restart_interval: u16,
+        progressive: bool,
+    }
+
+    const BASELINE: Options = Options {
+        gray: false,
+        sampling: (1, 1),
+        quant: 1,
+        restart_interval: 0,
+        progressive: false,
+    };
+
+    fn segment(out: &mut Vec<u8>, marker: u8, data: &[u8]) {
+        out.extend([0xff, marker]);
+        out.extend((data.len() as u16 + 2).to_be_bytes());
+        out.extend_from_slice(data);
+    }
+
+    /// A minimal jpeg encoder with a floating point DCT
+    fn encode(img: &RgbaImage, options: &Options) -> Vec<u8> {
+        let (width, height) = img.dimensions();
+        let (max_h, max_v) = options.sampling;
+        let sampling: Vec<(usize, usize)> = if options.gray {
+            vec![(max_h, max_v)]
+        } else {
+            vec![(max_h, max_v), (1, 1), (1, 1)]
+        };
+        let mcus_x = width.div_ceil(8 * max_h);
+        let mcus_y = height.div_ceil(8 * max_v);
+
+        // the quantized coefficients in zigzag order of every block of every component
+        let mut components = Vec::new();
+        for (c, &(h, v)) in sampling.iter().enumerate() {
+            let sample = |x: usize, y: usize| -> f64 {
+                let p = img.data[y.min(height - 1) * width + x.min(width - 1)];
+                let (r, g, b) = (p.r as f64, p.g as f64, p.b as f64);
+                match c {
+                    0 => 0.299 * r + 0.587 * g + 0.114 * b,
+                    1 => -0.168736 * r - 0.331264 * g + 0.5 * b + 128.0,
+                    _ => 0.5 * r - 0.418688 * g - 0.081312 * b + 128.0,
+                }
+            };
+            // subsampled components are averaged over the covered pixels
+            let (sx, sy) = (max_h / h, max_v / v);
+            let comp_sample = |x: usize, y: usize| -> f64 {
+                let mut sum = 0.0;
+                for dy in 0..sy {
+                    for dx in 0..sx {
+                        sum += sample(x * sx + dx, y * sy + dy);
+                    }
+                }
+                sum / (sx * sy) as f64
+            };
+
+            let (stride, rows) = (mcus_x * h, mcus_y * v);
+            let mut blocks = Vec::new();
+            for by in 0..rows {
+                for bx in 0..stride {
+                    let block: [i32; 64] = std::array::from_fn(|zz| {
+                        let (u, w) = (ZIGZAG[zz] % 8, ZIGZAG[zz] / 8);
+                        let cu = if u == 0 { 1.0 / 2f64.sqrt() } else { 1.0 };
+                        let cw = if w == 0 { 1.0 / 2f64.sqrt() } else { 1.0 };
+                        let mut sum = 0.0;
+                        for y in 0..8 {
+                            for x in 0..8 {
+                                let s = comp_sample(bx * 8 + x, by * 8 + y) - 128.0;
+                                sum += s
+                                    * ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / 16.0)
+                                        .cos()
+                                    * ((2 * y + 1) as f64 * w as f64 * std::f64::consts::PI / 16.0)
+                                        .cos();
+                            }
+                        }
+                        (sum * cu * cw / 4.0 / options.quant as f64).round() as i32
+                    });
+                    blocks.push(block);
+                }
+            }
+            components.push((h, v, stride, blocks));
+        }
+
+        let mut out = vec![0xff, 0xd8];
+        let mut dqt = vec![0];
+        dqt.extend([options.quant; 64]);
+        segment(&mut out, 0xdb, &dqt);
+
+        let mut sof = vec![8];
+        sof.extend((height as u16).to_be_bytes());
+        sof.extend((width as u16).to_be_bytes());
+        sof.push(sampling.len() as u8);
+        for (i, &(h, v)) in sampling.iter().enumerate() {
+            sof.extend([i as u8 + 1, (h << 4 | v) as u8, 0]);
+        }
+        segment(
+            &mut out,
+            if options.progressive { 0xc2 } else { 0xc0 },
+            &sof,
+        );
+
+        let symbols = ac_symbols();
+        let mut dht = vec![0x00, 0, 0, 0, 12];
+        dht.extend([0; 12]);
+        dht.extend(0..12);
+        dht.extend([0x10, 0, 0, 0, 0, 0, 0, 0, symbols.len() as u8]);
+        dht.extend([0; 8]);
+        dht.extend(&symbols);
+        segment(&mut out, 0xc4, &dht);
+        if options.restart_interval > 0 {
+            segment(&mut out, 0xdd, &options.restart_interval.to_be_bytes());
+        }
+
+        let ac_code = |symbol: u8| symbols.iter().position(|&s| s == symbol).unwrap() as u32;
+        let sos = |out: &mut Vec<u8>, comps: &[usize], ss: u8, se: u8, ah_al: u8| {
+            let mut data = vec![comps.len() as u8];
+            for &c in comps {
+                data.extend([c as u8 + 1, 0x00]);
+            }
+            data.extend([ss, se, ah_al]);
+            segment(out, 0xda, &data);
+        };
+
+        if !options.progressive {
+            let all: Vec<usize> = (0..components.len()).collect();
+            sos(&mut out, &all, 0, 63, 0);
+            let mut writer = BitWriter::default();
+            let mut preds = vec![0; components.len()];
+            let interval = options.restart_interval as usize;
+            for mcu in 0..mcus_x * mcus_y {
+                if interval > 0 && mcu > 0 && mcu % interval == 0 {
+                    writer.flush();
+                    writer
+                        .out
+                        .extend([0xff, 0xd0 + ((mcu / interval - 1) % 8) as u8]);
+                    preds.fill(0);
+                }
+                let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+                for (c, (h, v, stride, blocks)) in components.iter().enumerate() {
+                    for by in 0..*v {
+                        for bx in 0..*h {
+                            let block = &blocks[(my * v + by) * stride + mx * h + bx];
+                            let (size, extra) = writer.value(block[0] - preds[c]);
+                            preds[c] = block[0];
+                            writer.put(size, 4);
+                            writer.put(extra, size);
+
+                            let mut run = 0;
+                            for &coef in &block[1..] {
+                                if coef == 0 {
+                                    run += 1;
+                                    continue;
+                                }
+                                while run >= 16 {
+                                    writer.put(ac_code(0xf0), 8);
+                                    run -= 16;
+                                }
+                                let (size, extra) = writer.value(coef);
+                                writer.put(ac_code((run << 4 | size) as u8), 8);
+                                writer.put(extra, size);
+                                run = 0;
+                            }
+                            if run > 0 {
+                                writer.put(ac_code(0), 8);
+                            }
+                        }
+                    }
+                }
+            }
+            writer.flush();
+            out.extend(writer.out);
+        } else {
+            // the dc coefficients with successive approximation, first without the lowest bit
+            let all: Vec<usize> = (0..components.len()).collect();
+            for refine in [false, true] {
+                sos(&mut out, &all, 0, 0, if refine { 0x10 } else { 0x01 });
+                let mut writer = BitWriter::default();
+                let mut preds = vec![0; components.len()];
+                for mcu in 0..mcus_x * mcus_y {
+                    let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+                    for (c, (h, v, stride, blocks)) in components.iter().enumerate() {
+                        for by in 0..*v {
+                            for bx in 0..*h {
+                                let dc = blocks[(my * v + by) * stride + mx * h + bx][0];
+                                if refine {
+                                    writer.put(dc as u32 & 1, 1);
+                                } else {
+                                    let (size, extra) = writer.value((dc >> 1) - preds[c]);
+                                    preds[c] = dc >> 1;
+                                    writer.put(size, 4);
+                                    writer.put(extra, size);
+                                }
+                            }
+                        }
+                    }
+                }
+                writer.flush();
+                out.extend(writer.out);
+            }
+
+            // the ac coefficients in two bands, each component on its own
+            for (c, (h, v, stride, blocks)) in components.iter().enumerate() {
+                let blocks_w = (width * h).div_ceil(max_h).div_ceil(8);
+                let blocks_h = (height * v).div_ceil(max_v).div_ceil(8);
+                for (ss, se) in [(1, 5), (6, 63)] {
+                    sos(&mut out, &[c], ss as u8, se as u8, 0);
+                    let mut writer = BitWriter::default();
+                    for by in 0..blocks_h {
+                        for bx in 0..blocks_w {
+                            let block = &blocks[by * stride + bx];
+                            let mut run = 0;
+                            for &coef in &block[ss..=se] {
+                                if coef == 0 {
+                                    run += 1;
+                                    continue;
+                                }
+                                while run >= 16 {
+                                    writer.put(ac_code(0xf0), 8);
+                                    run -= 16;
+                                }
+                                let (size, extra) = writer.value(coef);
+                                writer.put(ac_code((run << 4 | size) as u8), 8);
+                                writer.put(extra, size);
+                                run = 0;
+                            }
+                            if run > 0 {
+                                // an end of band run of one block
+                                writer.put(ac_code(0), 8);
+                            }
+                        }
+                    }
+                    writer.flush();
+                    out.extend(writer.out);
+                }
+            }
+        }
+
+        out.extend([0xff, 0xd9]);
+        out
+    }
+
+    fn test_image(width: usize, height: usize, pixel: impl Fn(usize, usize) -> Rgba) -> RgbaImage {
+        let mut img = RgbaImage::new(width, height, Rgba::BLACK);
+        for y in 0..height {
+            for x in 0..width {
+                img.put_pixel(x, y, pixel(x, y));
+            }
+        }
+        img
+    }
+
+    fn gradient(x: usize, y: usize) -> Rgba {
+        Rgba {
+            r: (x * 9 + 20) as u8,
+            g: (y * 7 + 30) as u8,
+            b: (x * 3 + y * 4) as u8,
+            a: 255,
+        }
+    }
+
+    /// a gray gradient with a constant color, which is not changed by chroma subsampling
+    fn tinted(x: usize, y: usize) -> Rgba {
+        let v = (x * 5 + y * 3) as u8 / 2;
+        Rgba {
+            r: v + 40,
+            g: v + 20,
+            b: v,
+            a: 255,
+        }
+    }
+
+    fn assert_close(a: &RgbaImage, b: &RgbaImage, tolerance: u8) {
+        assert_eq!(a.dimensions(), b.dimensions());
+        for (i, (p, q)) in a.data.iter().zip(&b.data).enumerate() {
+            let diff =
+                p.r.abs_diff(q.r)
+                    .max(p.g.abs_diff(q.g))
+                    .max(p.b.abs_diff(q.b));
+            assert!(diff <= tolerance, "pixel {i}: {p:?} vs {q:?}");
+        }
+    }
+
+    #[test]
+    fn grayscale() {
+        let img = test_image(19, 13, |x, y| {
+            let v = (x * 13 + y * 7) as u8;
+            Rgba {
+                r: v,
+                g: v,
+                b: v,
+                a: 255,
+            }
+        });
+        let options = Options {
+            gray: true,
+            ..BASELINE
+        };
+        let data = encode(&img, &options);
+        assert_eq!(
+            read_header(&data),
+            Ok(JpegHeader {
+                width: 19,
+                height: 13,
+                components: 1,
+                progressive: false
+            })
+        );
+        assert_close(&decode(&data).unwrap(), &img, 1);
+    }
+
+    #[test]
+    fn color() {
+        let img = test_image(21, 11, gradient);
+        assert_close(&decode(&encode(&img, &BASELINE)).unwrap(), &img, 2);
+
+        // coarse quantization still gives a similar image
+        let options = Options {
+            quant: 8,
+            ..BASELINE
+        };
+        assert_close(&decode(&encode(&img, &options)).unwrap(), &img, 12);
+    }
+
+    #[test]
+    fn subsampling() {
+        let img = test_image(37, 21, tinted);
+        for sampling in [(2, 2), (2, 1), (1, 2), (4, 1)] {
+            let options = Options {
+                sampling,
+                ..BASELINE
+            };
+            assert_close(&decode(&encode(&img, &options)).unwrap(), &img, 2);
+        }
+    }
+
+    #[test]
+    fn restart_intervals() {
+        let img = test_image(40, 40, gradient);
+        for sampling in [(1, 1), (2, 2)] {
+            let plain = Options {
+                sampling,
+                ..BASELINE
+            };
+            let expected = decode(&encode(&img, &plain)).unwrap();
+            for restart_interval in [1, 2, 5] {
+                let options = Options {
+                    sampling,
+                    restart_interval,
+                    ..BASELINE
+                };
+                assert_eq!(decode(&encode(&img, &options)).unwrap(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn progressive() {
+        let img = test_image(45, 27, gradient);
+        for (gray, sampling) in [(true, (1, 1)), (false, (1, 1)), (false, (2, 2))] {
+            let baseline = Options {
+                gray,
+                sampling,
+                quant: 3,
+                ..BASELINE
+            };
+            let progressive = Options {
+                progressive: true,
+                ..baseline
+            };
+            let data = encode(&img, &progressive);
+            assert!(read_header(&data).unwrap().progressive);
+            // the same coefficients give the same image
+            assert_eq!(
+                decode(&data).unwrap(),
+                decode(&encode(&img, &baseline)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let valid = encode(&test_image(9, 9, gradient), &BASELINE);
+        assert_eq!(decode(&valid[1..]), Err(JpegError::InvalidMagic));
+        assert_eq!(decode(&valid[..2]), Err(JpegError::UnexpectedEof));
+        // truncated headers are errors, truncated image data is not
+        let sos = valid.windows(2).position(|w| w == [0xff, 0xda]).unwrap();
+        for len in 2..sos + 14 {
+            assert!(decode(&valid[..len]).is_err(), "truncated to {len} bytes");
+        }
+        assert!(decode(&valid[..valid.len() - 10]).is_ok());
+
+        let sof = valid.windows(2).position(|w| w == [0xff, 0xc0]).unwrap();
+        let mut lossless = valid.clone();
+        lossless[sof + 1] = 0xc3;
+        assert_eq!(decode(&lossless), Err(JpegError::UnsupportedProcess(0xc3)));
+
+        let mut precision = valid.clone();
+        precision[sof + 4] = 12;
+        assert_eq!(decode(&precision), Err(JpegError::UnsupportedProcess(0xc0)));
+
+        let mut components = valid.clone();
+        components[sof + 9] = 2;
+        assert_eq!(
+            decode(&components),
+            Err(JpegError::UnsupportedComponents(2))
+        );
+
+        // the scan uses huffman table 1, which is not defined
+        let mut table = valid.clone();
+        table[sos + 6] = 0x11;
+        assert_eq!(decode(&table), Err(JpegError::MissingTable));
+
+        // three codes of length 1 with the same amount of values in total
+        let dht = valid.windows(2).position(|w| w == [0xff, 0xc4]).unwrap();
+        let mut oversubscribed = valid.clone();
+        let counts = &mut oversubscribed[dht + 5..dht + 21];
+        let longer = counts.iter().rposition(|&c| c >= 3).unwrap();
+        counts[longer] -= 3;
+        counts[0] += 3;
+        assert_eq!(decode(&oversubscribed), Err(JpegError::InvalidHuffmanTable));
+
+        let mut no_frame = valid[..sof].to_vec();
+        no_frame.extend([0xff, 0xd9]);
+        assert_eq!(decode(&no_frame), Err(JpegError::MissingFrame));
+
+        // a small file claiming a huge image
+        let mut huge = valid.clone();
+        huge[sof + 5..sof + 9].copy_from_slice(&[0x40, 0, 0x40, 0]);
+        assert_eq!(decode(&huge), Err(JpegError::UnexpectedEof));
+    }
+
+    /// The files in `test-data/jpeg`, written by libjpeg with `make_fixtures.c` there, each
+    /// with the image libjpeg decodes it to in the PNG with its name. The chroma upsampling
+    /// and rounding differ slightly from the ones of libjpeg.
+    #[test]
+    fn reference_images() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test-data/jpeg");
+        let entries = std::fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("jpeg reference images in {}: {e}", dir.display()));
+
+        let mut count = 0;
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "jpg") {
+                continue;
+            }
+            let data = std::fs::read(&path).unwrap();
+            let png = std::fs::read(path.with_extension("png"))
+                .unwrap_or_else(|e| panic!("expected output of {}: {e}", path.display()));
+            let img = decode(&data).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+            let expected = crate::load(&png).unwrap();
+            assert_close(&img, &expected, 3);
+            count += 1;
+        }
+        assert!(count > 0, "no jpeg reference images in {}", dir.display());
+    }
+}
@@ -1,76 +1,327 @@
-use std::{io::Write, marker::PhantomData};
+use std::{fmt::Display, io::Write, marker::PhantomData, ops::Range, path::Path};
 
+pub mod bmp;
+pub mod filter;
+pub mod gif;
+pub mod jpeg;
+pub mod ops;
+pub mod pixel;
+pub mod png;
+pub mod pnm;
 pub mod qoi;
+pub mod resize;
+pub mod zlib;
 
+pub use pixel::{Gray8, GrayA, Pixel, Rgb, Rgba, Rgba32F};
+
+/// The image formats that can be loaded
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Rgba {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
-    pub a: u8,
+pub enum ImageFormat {
+    Qoi,
+    Png,
+    Bmp,
+    Pnm(pnm::PnmFormat),
+    /// only decoding is supported
+    Jpeg,
+    /// only decoding is supported, use [`gif::decode_animation`] for all frames
+    Gif,
 }
 
-impl Rgba {
-    pub const BLACK: Self = Rgba {
-        r: 0,
-        g: 0,
-        b: 0,
-        a: 255,
-    };
+impl ImageFormat {
+    /// Detect the format from the magic bytes at the start of the file
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&qoi::MAGIC) {
+            Some(Self::Qoi)
+        } else if data.starts_with(&png::SIGNATURE) {
+            Some(Self::Png)
+        } else if data.starts_with(&bmp::MAGIC) {
+            Some(Self::Bmp)
+        } else if data.starts_with(&jpeg::MAGIC) {
+            Some(Self::Jpeg)
+        } else if data.starts_with(&gif::MAGIC_87A) || data.starts_with(&gif::MAGIC_89A) {
+            Some(Self::Gif)
+        } else {
+            let [a, b, ..] = *data else {
+                return None;
+            };
+            pnm::PnmFormat::from_magic([a, b]).map(Self::Pnm)
+        }
+    }
+
+    /// The format belonging to a file extension, used when saving images
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Some(match ext.to_ascii_lowercase().as_str() {
+            "qoi" => Self::Qoi,
+            "png" => Self::Png,
+            "bmp" => Self::Bmp,
+            "pbm" => Self::Pnm(pnm::PnmFormat::Pbm),
+            "pgm" => Self::Pnm(pnm::PnmFormat::Pgm),
+            "ppm" => Self::Pnm(pnm::PnmFormat::Ppm),
+            "pam" => Self::Pnm(pnm::PnmFormat::Pam),
+            "jpg" | "jpeg" => Self::Jpeg,
+            "gif" => Self::Gif,
+            _ => return None,
+        })
+    }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct Rgb {
-    pub r: u8,
-    pub g: u8,
-    pub b: u8,
+#[derive(Debug)]
+pub enum LoadError {
+    UnknownFormat,
+    Qoi(qoi::QoiError),
+    Png(png::PngError),
+    Bmp(bmp::BmpError),
+    Pnm(pnm::PnmError),
+    Jpeg(jpeg::JpegError),
+    Gif(gif::GifError),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(f, "unknown image format"),
+            Self::Qoi(e) => write!(f, "{e}"),
+            Self::Png(e) => write!(f, "{e}"),
+            Self::Bmp(e) => write!(f, "{e}"),
+            Self::Pnm(e) => write!(f, "{e}"),
+            Self::Jpeg(e) => write!(f, "{e}"),
+            Self::Gif(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Decode an image in any of the supported formats, which is detected from the magic bytes
+pub fn load(data: &[u8]) -> Result<RgbaImage, LoadError> {
+    match ImageFormat::detect(data).ok_or(LoadError::UnknownFormat)? {
+        ImageFormat::Qoi => qoi::decode(data).map_err(LoadError::Qoi),
+        ImageFormat::Png => png::decode(data).map_err(LoadError::Png),
+        ImageFormat::Bmp => bmp::decode(data).map_err(LoadError::Bmp),
+        ImageFormat::Pnm(_) => pnm::decode(data).map_err(LoadError::Pnm),
+        ImageFormat::Jpeg => jpeg::decode(data).map_err(LoadError::Jpeg),
+        ImageFormat::Gif => gif::decode(data).map_err(LoadError::Gif),
+    }
 }
 
 pub type RgbaImage = Image<Rgba>;
 
+/// An image with pixels in row major order. The container is a `Vec` for owned images and
+/// a slice for views into other images, see [`Image::view`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Image<Pix, Cont = Vec<Pix>> {
     pub data: Cont,
     pub width: usize,
     pub height: usize,
+    /// the distance between the starts of two rows in `data`, the width for owned images
+    pub stride: usize,
     _phant: PhantomData<Pix>,
 }
 
 impl<Pix: Copy> Image<Pix> {
     pub fn new(width: usize, height: usize, fill: Pix) -> Self {
+        Self::from_vec(width, height, vec![fill; width * height])
+    }
+
+    /// Create an image from pixels in row major order
+    pub fn from_vec(width: usize, height: usize, data: Vec<Pix>) -> Self {
+        assert_eq!(data.len(), width * height, "image data has the wrong size");
         Self {
-            data: vec![fill; width * height],
+            data,
             width,
             height,
+            stride: width,
             _phant: PhantomData,
         }
     }
 
+    /// Create an image from a function that returns the pixel at a position
+    pub fn from_fn(width: usize, height: usize, mut f: impl FnMut(usize, usize) -> Pix) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            data.extend((0..width).map(|x| f(x, y)));
+        }
+        Self::from_vec(width, height, data)
+    }
+}
+
+/// The range of `data` used by an area of an image
+fn view_range(stride: usize, x: usize, y: usize, width: usize, height: usize) -> Range<usize> {
+    if width == 0 || height == 0 {
+        return 0..0;
+    }
+    let start = y * stride + x;
+    start..start + (height - 1) * stride + width
+}
+
+impl<Pix: Copy, Cont: AsRef<[Pix]>> Image<Pix, Cont> {
     pub fn dimensions(&self) -> (usize, usize) {
         (self.width, self.height)
     }
 
+    pub fn get_pixel(&self, x: usize, y: usize) -> Pix {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        self.data.as_ref()[y * self.stride + x]
+    }
+
+    pub fn row(&self, y: usize) -> &[Pix] {
+        assert!(y < self.height, "row out of bounds");
+        &self.data.as_ref()[y * self.stride..y * self.stride + self.width]
+    }
+
+    pub fn rows(&self) -> impl DoubleEndedIterator<Item = &[Pix]> + ExactSizeIterator {
+        (0..self.height).map(|y| self.row(y))
+    }
+
+    /// All pixels with their positions in row major order
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize, Pix)> {
+        self.rows()
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, &pix)| (x, y, pix)))
+    }
+
+    /// A view of an area of the image, which has to be inside the image
+    pub fn view(&self, x: usize, y: usize, width: usize, height: usize) -> Image<Pix, &[Pix]> {
+        assert!(
+            x + width <= self.width && y + height <= self.height,
+            "view out of bounds"
+        );
+        Image {
+            data: &self.data.as_ref()[view_range(self.stride, x, y, width, height)],
+            width,
+            height,
+            stride: self.stride,
+            _phant: PhantomData,
+        }
+    }
+
+    /// Copy the pixels into an owned image
+    pub fn to_image(&self) -> Image<Pix> {
+        let mut data = Vec::with_capacity(self.width * self.height);
+        for row in self.rows() {
+            data.extend_from_slice(row);
+        }
+        Image::from_vec(self.width, self.height, data)
+    }
+}
+
+impl<Pix: Copy, Cont: AsRef<[Pix]> + AsMut<[Pix]>> Image<Pix, Cont> {
     pub fn put_pixel(&mut self, x: usize, y: usize, pixel: Pix) {
-        self.data[y * self.width + x] = pixel;
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        self.data.as_mut()[y * self.stride + x] = pixel;
+    }
+
+    pub fn row_mut(&mut self, y: usize) -> &mut [Pix] {
+        assert!(y < self.height, "row out of bounds");
+        &mut self.data.as_mut()[y * self.stride..y * self.stride + self.width]
+    }
+
+    pub fn rows_mut(&mut self) -> impl Iterator<Item = &mut [Pix]> {
+        let (width, height) = (self.width, self.height);
+        // the last row may be shorter than the stride in views
+        self.data
+            .as_mut()
+            .chunks_mut(self.stride.max(1))
+            .take(height)
+            .map(move |row| &mut row[..width])
+    }
+
+    /// A mutable view of an area of the image, which has to be inside the image
+    pub fn view_mut(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    ) -> Image<Pix, &mut [Pix]> {
+        assert!(
+            x + width <= self.width && y + height <= self.height,
+            "view out of bounds"
+        );
+        Image {
+            data: &mut self.data.as_mut()[view_range(self.stride, x, y, width, height)],
+            width,
+            height,
+            stride: self.stride,
+            _phant: PhantomData,
+        }
+    }
+
+    pub fn fill(&mut self, pixel: Pix) {
+        for row in self.rows_mut() {
+            row.fill(pixel);
+        }
     }
 }
 
 impl Image<Rgba> {
-    pub fn save(self, target: &str) -> std::io::Result<()> {
-        let mut file = std::fs::File::create("test.qoi").unwrap();
-        let mut writer = qoi::writer::QoiWriter::new(
-            qoi::writer::QoiHeader {
-                width: self.width as u32,
-                height: self.height as u32,
-                channels: 4,
-                colorspace: 1,
-            },
-            &self.data,
-            &mut file,
-        );
-        writer.write();
+    /// Save the image in the format belonging to the file extension, qoi if it is unknown
+    pub fn save(&self, target: impl AsRef<Path>) -> std::io::Result<()> {
+        let target = target.as_ref();
+        let format = target
+            .extension()
+            .and_then(|ext| ImageFormat::from_extension(&ext.to_string_lossy()))
+            .unwrap_or(ImageFormat::Qoi);
+        if matches!(format, ImageFormat::Jpeg | ImageFormat::Gif) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("{format:?} images can not be saved"),
+            ));
+        }
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(target)?);
+        match format {
+            ImageFormat::Qoi => qoi::encode(self, &mut file)?,
+            ImageFormat::Png => png::encode(self, &mut file)?,
+            ImageFormat::Bmp => bmp::encode(self, &mut file)?,
+            ImageFormat::Pnm(format) => pnm::encode(self, &mut file, format)?,
+            ImageFormat::Jpeg | ImageFormat::Gif => unreachable!(),
+        }
         file.flush()?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn load_detects_format() {
+        let mut img = RgbaImage::new(5, 3, Rgba::BLACK);
+        img.put_pixel(
+            1,
+            2,
+            Rgba {
+                r: 10,
+                g: 20,
+                b: 30,
+                a: 40,
+            },
+        );
+
+        let mut qoi = Vec::new();
+        qoi::encode(&img, &mut qoi).unwrap();
+        let mut png = Vec::new();
+        png::encode(&img, &mut png).unwrap();
+        let mut bmp = Vec::new();
+        bmp::encode(&img, &mut bmp).unwrap();
+        let mut pam = Vec::new();
+        pnm::encode(&img, &mut pam, pnm::PnmFormat::Pam).unwrap();
+
+        for (data, format) in [
+            (qoi, ImageFormat::Qoi),
+            (png, ImageFormat::Png),
+            (bmp, ImageFormat::Bmp),
+            (pam, ImageFormat::Pnm(pnm::PnmFormat::Pam)),
+        ] {
+            assert_eq!(ImageFormat::detect(&data), Some(format));
+            assert_eq!(load(&data).unwrap(), img, "{format:?}");
+        }
+
+        assert!(matches!(load(b"II*\0"), Err(LoadError::UnknownFormat)));
+        assert!(matches!(load(b"GIF89a"), Err(LoadError::Gif(_))));
+        assert!(matches!(load(b"BM"), Err(LoadError::Bmp(_))));
+        assert!(matches!(load(&[0xff, 0xd8]), Err(LoadError::Jpeg(_))));
+        assert_eq!(ImageFormat::from_extension("PNG"), Some(ImageFormat::Png));
+    }
+}
@@ -0,0 +1,221 @@
+//! Image transformations and copying images onto each other
+
+use crate::{Image, Pixel};
+
+impl<Pix: Copy, Cont: AsRef<[Pix]>> Image<Pix, Cont> {
+    /// Copy an area of the image, which is clipped to the image
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> Image<Pix> {
+        let x = x.min(self.width);
+        let y = y.min(self.height);
+        let width = width.min(self.width - x);
+        let height = height.min(self.height - y);
+        self.view(x, y, width, height).to_image()
+    }
+
+    /// Mirror the image left to right
+    pub fn flip_horizontal(&self) -> Image<Pix> {
+        Image::from_fn(self.width, self.height, |x, y| {
+            self.get_pixel(self.width - 1 - x, y)
+        })
+    }
+
+    /// Mirror the image top to bottom
+    pub fn flip_vertical(&self) -> Image<Pix> {
+        let mut data = Vec::with_capacity(self.width * self.height);
+        for row in self.rows().rev() {
+            data.extend_from_slice(row);
+        }
+        Image::from_vec(self.width, self.height, data)
+    }
+
+    /// Rotate the image clockwise by 90 degrees
+    pub fn rotate90(&self) -> Image<Pix> {
+        Image::from_fn(self.height, self.width, |x, y| {
+            self.get_pixel(y, self.height - 1 - x)
+        })
+    }
+
+    pub fn rotate180(&self) -> Image<Pix> {
+        Image::from_fn(self.width, self.height, |x, y| {
+            self.get_pixel(self.width - 1 - x, self.height - 1 - y)
+        })
+    }
+
+    /// Rotate the image counterclockwise by 90 degrees
+    pub fn rotate270(&self) -> Image<Pix> {
+        Image::from_fn(self.height, self.width, |x, y| {
+            self.get_pixel(self.width - 1 - y, x)
+        })
+    }
+
+    /// Convert every pixel to another format
+    pub fn convert<P: Pixel>(&self) -> Image<P>
+    where
+        Pix: Pixel,
+    {
+        Image::from_fn(self.width, self.height, |x, y| {
+            self.get_pixel(x, y).convert()
+        })
+    }
+}
+
+/// The overlapping area when placing an image of size `src` at `pos` in an image of size
+/// `dst`: the offset into the source, the offset into the destination and the size
+fn overlap(src: (usize, usize), dst: (usize, usize), pos: (isize, isize)) -> Option<[usize; 6]> {
+    let axis = |src: usize, dst: usize, pos: isize| {
+        let src_start = pos.min(0).unsigned_abs();
+        let dst_start = pos.max(0) as usize;
+        let len = src
+            .saturating_sub(src_start)
+            .min(dst.saturating_sub(dst_start));
+        (len > 0).then_some((src_start, dst_start, len))
+    };
+    let (sx, dx, width) = axis(src.0, dst.0, pos.0)?;
+    let (sy, dy, height) = axis(src.1, dst.1, pos.1)?;
+    Some([sx, sy, dx, dy, width, height])
+}
+
+impl<Pix: Copy, Cont: AsRef<[Pix]> + AsMut<[Pix]>> Image<Pix, Cont> {
+    /// Copy `src` into the image with its top left corner at `x`, `y`. The parts outside the
+    /// image are clipped.
+    pub fn blit<C: AsRef<[Pix]>>(&mut self, src: &Image<Pix, C>, x: isize, y: isize) {
+        let Some([sx, sy, dx, dy, width, height]) =
+            overlap(src.dimensions(), self.dimensions(), (x, y))
+        else {
+            return;
+        };
+        let src = src.view(sx, sy, width, height);
+        let mut dst = self.view_mut(dx, dy, width, height);
+        for (dst_row, src_row) in dst.rows_mut().zip(src.rows()) {
+            dst_row.copy_from_slice(src_row);
+        }
+    }
+
+    /// Like [`Image::blit`], but blends the pixels of `src` on top of the image
+    pub fn composite<C: AsRef<[Pix]>>(&mut self, src: &Image<Pix, C>, x: isize, y: isize)
+    where
+        Pix: Pixel,
+    {
+        let Some([sx, sy, dx, dy, width, height]) =
+            overlap(src.dimensions(), self.dimensions(), (x, y))
+        else {
+            return;
+        };
+        let src = src.view(sx, sy, width, height);
+        let mut dst = self.view_mut(dx, dy, width, height);
+        for (dst_row, src_row) in dst.rows_mut().zip(src.rows()) {
+            for (dst, &src) in dst_row.iter_mut().zip(src_row) {
+                *dst = src.over(*dst);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Gray8, GrayA, Image, Rgba, RgbaImage};
+
+    /// every pixel has a different value
+    fn numbered(width: usize, height: usize) -> Image<Gray8> {
+        Image::from_fn(width, height, |x, y| Gray8((y * width + x) as u8))
+    }
+
+    fn values(img: &Image<Gray8>) -> Vec<u8> {
+        img.data.iter().map(|p| p.0).collect()
+    }
+
+    #[test]
+    fn views() {
+        let mut img = numbered(5, 4);
+        let view = img.view(1, 2, 3, 2);
+        assert_eq!(view.dimensions(), (3, 2));
+        assert_eq!(view.get_pixel(0, 0), Gray8(11));
+        assert_eq!(view.row(1), [Gray8(16), Gray8(17), Gray8(18)]);
+        assert_eq!(values(&view.to_image()), [11, 12, 13, 16, 17, 18]);
+        // views of views
+        assert_eq!(
+            view.view(1, 1, 2, 1).to_image().data,
+            [Gray8(17), Gray8(18)]
+        );
+        let pixels: Vec<_> = view.pixels().map(|(x, y, p)| (x, y, p.0)).collect();
+        assert_eq!(pixels[4], (1, 1, 17));
+
+        let mut view = img.view_mut(3, 0, 2, 4);
+        view.fill(Gray8(0));
+        view.put_pixel(1, 3, Gray8(99));
+        assert_eq!(view.rows_mut().count(), 4);
+        assert_eq!(
+            values(&img),
+            [
+                0, 1, 2, 0, 0, 5, 6, 7, 0, 0, 10, 11, 12, 0, 0, 15, 16, 17, 0, 99
+            ]
+        );
+        assert_eq!(img.view(2, 4, 3, 0).to_image().dimensions(), (3, 0));
+    }
+
+    #[test]
+    fn transforms() {
+        let img = numbered(3, 2);
+        assert_eq!(values(&img.crop(1, 0, 5, 5)), [1, 2, 4, 5]);
+        assert_eq!(img.crop(7, 1, 2, 2).dimensions(), (0, 1));
+        assert_eq!(values(&img.flip_horizontal()), [2, 1, 0, 5, 4, 3]);
+        assert_eq!(values(&img.flip_vertical()), [3, 4, 5, 0, 1, 2]);
+
+        let rotated = img.rotate90();
+        assert_eq!(rotated.dimensions(), (2, 3));
+        assert_eq!(values(&rotated), [3, 0, 4, 1, 5, 2]);
+        assert_eq!(values(&img.rotate270()), [2, 5, 1, 4, 0, 3]);
+        assert_eq!(values(&img.rotate180()), [5, 4, 3, 2, 1, 0]);
+        assert_eq!(rotated.rotate90().rotate90().rotate90(), img);
+        // transforms of views
+        assert_eq!(values(&img.view(1, 0, 2, 2).rotate90()), [4, 1, 5, 2]);
+
+        let gray = img.convert::<GrayA>().convert::<Rgba>();
+        assert_eq!(
+            gray.get_pixel(2, 1),
+            Rgba {
+                r: 5,
+                g: 5,
+                b: 5,
+                a: 255
+            }
+        );
+    }
+
+    #[test]
+    fn blit() {
+        let mut img = Image::new(4, 3, Gray8(0));
+        let src = Image::new(2, 2, Gray8(1));
+        img.blit(&src, 3, -1);
+        img.blit(&src, -1, 2);
+        img.blit(&src, 4, 0);
+        img.blit(&src, 1, -2);
+        assert_eq!(values(&img), [0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0]);
+
+        let mut img = numbered(4, 3);
+        let src = numbered(5, 5);
+        img.blit(&src.view(1, 1, 2, 2), 1, 1);
+        assert_eq!(values(&img), [0, 1, 2, 3, 4, 6, 7, 7, 8, 11, 12, 11]);
+    }
+
+    #[test]
+    fn composite() {
+        let red = Rgba {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let mut img = RgbaImage::new(3, 1, Rgba::WHITE);
+        let mut src = RgbaImage::new(2, 1, Rgba::TRANSPARENT);
+        src.put_pixel(1, 0, red);
+        img.composite(&src, 1, 0);
+        assert_eq!(img.data, [Rgba::WHITE, Rgba::WHITE, red]);
+
+        let half = RgbaImage::new(1, 1, Rgba { a: 128, ..red });
+        img.composite(&half, 0, 0);
+        let blended = img.get_pixel(0, 0);
+        assert_eq!((blended.r, blended.a), (255, 255));
+        assert!(blended.g > 128 && blended.g < 255 && blended.g == blended.b);
+    }
+}
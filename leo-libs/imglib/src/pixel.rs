@@ -0,0 +1,324 @@
+//! Pixel formats and the conversions between them
+
+use mathlib::color::{ColA, LinColA};
+
+/// A pixel format. All formats convert to and from [`ColA`], which is also the floating
+/// point rgba format. Components are sRGB encoded with straight (not premultiplied) alpha.
+pub trait Pixel: Copy {
+    /// the amount of components, including alpha
+    const CHANNELS: usize;
+
+    fn to_color(self) -> ColA;
+
+    /// Formats without color use the luma of the color, formats without alpha drop it
+    fn from_color(col: ColA) -> Self;
+
+    fn to_rgba(self) -> Rgba {
+        self.to_color().into()
+    }
+
+    fn from_rgba(pix: Rgba) -> Self {
+        Self::from_color(pix.into())
+    }
+
+    fn convert<P: Pixel>(self) -> P {
+        P::from_color(self.to_color())
+    }
+
+    /// The alpha component from 0 to 1
+    fn alpha(self) -> f32 {
+        self.to_color().a
+    }
+
+    /// Decode the sRGB transfer function for doing math on the color
+    fn to_linear(self) -> LinColA {
+        self.to_color().to_linear()
+    }
+
+    fn from_linear(col: LinColA) -> Self {
+        Self::from_color(col.to_srgb())
+    }
+
+    /// Composite self on top of `dst` (porter duff source over) in linear light
+    fn over(self, dst: Self) -> Self {
+        let alpha = self.alpha();
+        if alpha >= 1.0 {
+            return self;
+        }
+        if alpha <= 0.0 {
+            return dst;
+        }
+        Self::from_linear(self.to_linear().over(dst.to_linear()))
+    }
+}
+
+/// The rgba pixel with 32 bit float components
+pub type Rgba32F = ColA;
+
+/// the luma of sRGB encoded components with the Rec. 601 weights, like jpeg
+fn luma(col: ColA) -> f32 {
+    0.299 * col.r + 0.587 * col.g + 0.114 * col.b
+}
+
+fn unit_to_u8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl Rgba {
+    pub const BLACK: Self = Rgba {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+    pub const WHITE: Self = Rgba {
+        r: 255,
+        g: 255,
+        b: 255,
+        a: 255,
+    };
+    pub const TRANSPARENT: Self = Rgba {
+        r: 0,
+        g: 0,
+        b: 0,
+        a: 0,
+    };
+}
+
+impl From<ColA> for Rgba {
+    fn from(col: ColA) -> Self {
+        let [r, g, b, a] = col.to_rgba_arr();
+        Self { r, g, b, a }
+    }
+}
+
+impl From<Rgba> for ColA {
+    fn from(pix: Rgba) -> Self {
+        ColA::from_rgba_arr([pix.r, pix.g, pix.b, pix.a])
+    }
+}
+
+impl Pixel for Rgba {
+    const CHANNELS: usize = 4;
+
+    fn to_color(self) -> ColA {
+        self.into()
+    }
+
+    fn from_color(col: ColA) -> Self {
+        col.into()
+    }
+
+    fn to_rgba(self) -> Rgba {
+        self
+    }
+
+    fn from_rgba(pix: Rgba) -> Self {
+        pix
+    }
+
+    fn alpha(self) -> f32 {
+        self.a as f32 / 255.0
+    }
+
+    fn to_linear(self) -> LinColA {
+        LinColA::from_srgb8([self.r, self.g, self.b, self.a])
+    }
+
+    fn from_linear(col: LinColA) -> Self {
+        let [r, g, b, a] = col.to_srgb8();
+        Self { r, g, b, a }
+    }
+}
+
+impl Pixel for ColA {
+    const CHANNELS: usize = 4;
+
+    fn to_color(self) -> ColA {
+        self
+    }
+
+    fn from_color(col: ColA) -> Self {
+        col
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Pixel for Rgb {
+    const CHANNELS: usize = 3;
+
+    fn to_color(self) -> ColA {
+        ColA::from_rgba_arr([self.r, self.g, self.b, 255])
+    }
+
+    fn from_color(col: ColA) -> Self {
+        let [r, g, b, _] = col.to_rgba_arr();
+        Self { r, g, b }
+    }
+
+    fn to_rgba(self) -> Rgba {
+        Rgba {
+            r: self.r,
+            g: self.g,
+            b: self.b,
+            a: 255,
+        }
+    }
+
+    fn from_rgba(pix: Rgba) -> Self {
+        Self {
+            r: pix.r,
+            g: pix.g,
+            b: pix.b,
+        }
+    }
+
+    fn alpha(self) -> f32 {
+        1.0
+    }
+
+    fn to_linear(self) -> LinColA {
+        LinColA::from_srgb8([self.r, self.g, self.b, 255])
+    }
+}
+
+/// An 8 bit gray value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gray8(pub u8);
+
+impl Pixel for Gray8 {
+    const CHANNELS: usize = 1;
+
+    fn to_color(self) -> ColA {
+        ColA::from_rgba_arr([self.0, self.0, self.0, 255])
+    }
+
+    fn from_color(col: ColA) -> Self {
+        Self(unit_to_u8(luma(col)))
+    }
+
+    fn alpha(self) -> f32 {
+        1.0
+    }
+
+    fn to_linear(self) -> LinColA {
+        LinColA::from_srgb8([self.0, self.0, self.0, 255])
+    }
+}
+
+/// An 8 bit gray value with alpha
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GrayA {
+    pub v: u8,
+    pub a: u8,
+}
+
+impl Pixel for GrayA {
+    const CHANNELS: usize = 2;
+
+    fn to_color(self) -> ColA {
+        ColA::from_rgba_arr([self.v, self.v, self.v, self.a])
+    }
+
+    fn from_color(col: ColA) -> Self {
+        Self {
+            v: unit_to_u8(luma(col)),
+            a: unit_to_u8(col.a),
+        }
+    }
+
+    fn alpha(self) -> f32 {
+        self.a as f32 / 255.0
+    }
+
+    fn to_linear(self) -> LinColA {
+        LinColA::from_srgb8([self.v, self.v, self.v, self.a])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let pix = Rgba {
+            r: 200,
+            g: 100,
+            b: 50,
+            a: 128,
+        };
+        assert_eq!(pix.convert::<Rgba32F>().convert::<Rgba>(), pix);
+        assert_eq!(
+            pix.convert::<Rgb>(),
+            Rgb {
+                r: 200,
+                g: 100,
+                b: 50
+            }
+        );
+        assert_eq!(pix.convert::<Gray8>(), Gray8(124));
+        assert_eq!(pix.convert::<GrayA>(), GrayA { v: 124, a: 128 });
+        assert_eq!(
+            Gray8(77).to_rgba(),
+            Rgba {
+                r: 77,
+                g: 77,
+                b: 77,
+                a: 255
+            }
+        );
+        assert_eq!(
+            GrayA { v: 9, a: 0 }.convert::<Rgb>(),
+            Rgb { r: 9, g: 9, b: 9 }
+        );
+
+        // every 8 bit value survives the float format
+        for v in 0..=255 {
+            assert_eq!(Gray8(v).convert::<Rgba32F>().convert::<Gray8>(), Gray8(v));
+        }
+    }
+
+    #[test]
+    fn over() {
+        let red = Rgba {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        assert_eq!(Rgba::TRANSPARENT.over(red), red);
+        assert_eq!(red.over(Rgba::WHITE), red);
+
+        // half transparent white on black is brighter than 128 because blending is linear
+        let half = Rgba {
+            a: 128,
+            ..Rgba::WHITE
+        };
+        let res = half.over(Rgba::BLACK);
+        assert_eq!(res.a, 255);
+        assert!(res.r > 180 && res.r == res.g && res.g == res.b, "{res:?}");
+
+        // the generic version gives the same result
+        let float = half.convert::<Rgba32F>().over(Rgba::BLACK.convert());
+        assert_eq!(float.convert::<Rgba>(), res);
+        assert_eq!(
+            GrayA { v: 255, a: 128 }.over(GrayA { v: 0, a: 255 }).v,
+            res.r
+        );
+    }
+}
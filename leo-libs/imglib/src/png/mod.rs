@@ -0,0 +1,677 @@
+//! The PNG image format: https://www.w3.org/TR/png/
+
+use std::fmt::Display;
+
+use crate::zlib::InflateError;
+
+pub mod reader;
+pub mod writer;
+
+pub use reader::decode;
+pub use writer::{Filter, PngOptions, encode, encode_with};
+
+pub const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// The same limit as for qoi, protects against absurd allocations
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Gray = 0,
+    Rgb = 2,
+    Indexed = 3,
+    GrayAlpha = 4,
+    Rgba = 6,
+}
+
+impl ColorType {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        Some(match v {
+            0 => Self::Gray,
+            2 => Self::Rgb,
+            3 => Self::Indexed,
+            4 => Self::GrayAlpha,
+            6 => Self::Rgba,
+            _ => return None,
+        })
+    }
+
+    pub fn channels(self) -> usize {
+        match self {
+            Self::Gray | Self::Indexed => 1,
+            Self::GrayAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+
+    /// whether the bit depth is allowed for this color type
+    pub fn allows_bit_depth(self, bit_depth: u8) -> bool {
+        match self {
+            Self::Gray => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+            Self::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+            Self::Rgb | Self::GrayAlpha | Self::Rgba => matches!(bit_depth, 8 | 16),
+        }
+    }
+}
+
+/// The contents of the IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngHeader {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: ColorType,
+    pub interlaced: bool,
+}
+
+impl PngHeader {
+    pub fn bits_per_pixel(&self) -> usize {
+        self.color_type.channels() * self.bit_depth as usize
+    }
+
+    /// the size of a filtered row in bytes, without the filter type byte
+    pub fn stride(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel()).div_ceil(8)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PngError {
+    InvalidSignature,
+    UnexpectedEof,
+    CrcMismatch([u8; 4]),
+    /// a chunk that has to be present is missing
+    MissingChunk([u8; 4]),
+    /// a critical chunk that is not known, which means the image can not be decoded
+    UnknownCriticalChunk([u8; 4]),
+    /// a chunk that is not allowed at its position or has the wrong size
+    InvalidChunk([u8; 4]),
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+    InvalidColorFormat {
+        color_type: u8,
+        bit_depth: u8,
+    },
+    /// the compression, filter or interlace method is unknown
+    UnknownMethod,
+    InvalidFilter(u8),
+    /// a palette index that is not part of the palette
+    InvalidPaletteIndex(u8),
+    /// the decompressed image data has the wrong size
+    InvalidDataSize,
+    Inflate(InflateError),
+}
+
+impl Display for PngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = |n: &[u8; 4]| String::from_utf8_lossy(n).into_owned();
+        match self {
+            Self::InvalidSignature => write!(f, "not a png file"),
+            Self::UnexpectedEof => write!(f, "unexpected end of data"),
+            Self::CrcMismatch(n) => write!(f, "crc mismatch in chunk {}", name(n)),
+            Self::MissingChunk(n) => write!(f, "missing chunk {}", name(n)),
+            Self::UnknownCriticalChunk(n) => write!(f, "unknown critical chunk {}", name(n)),
+            Self::InvalidChunk(n) => write!(f, "invalid chunk {}", name(n)),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Self::InvalidColorFormat {
+                color_type,
+                bit_depth,
+            } => write!(
+                f,
+                "invalid color type {color_type} with bit depth {bit_depth}"
+            ),
+            Self::UnknownMethod => write!(f, "unknown compression, filter or interlace method"),
+            Self::InvalidFilter(t) => write!(f, "invalid filter type {t}"),
+            Self::InvalidPaletteIndex(i) => write!(f, "palette index {i} out of range"),
+            Self::InvalidDataSize => write!(f, "image data has the wrong size"),
+            Self::Inflate(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<InflateError> for PngError {
+    fn from(e: InflateError) -> Self {
+        Self::Inflate(e)
+    }
+}
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+/// The crc32 used by png chunks (and zip, gzip, ...)
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The Adam7 passes as (x offset, y offset, x step, y step)
+pub(crate) const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The sub images an image is stored as: (x offset, y offset, x step, y step, width, height)
+pub(crate) fn passes(
+    header: &PngHeader,
+) -> impl Iterator<Item = (usize, usize, usize, usize, usize, usize)> {
+    let (width, height) = (header.width as usize, header.height as usize);
+    let passes: &[_] = if header.interlaced {
+        &ADAM7
+    } else {
+        &[(0, 0, 1, 1)]
+    };
+    passes
+        .iter()
+        .map(move |&(x0, y0, dx, dy)| {
+            let w = (width + dx - 1 - x0) / dx;
+            let h = (height + dy - 1 - y0) / dy;
+            (x0, y0, dx, dy, w, h)
+        })
+        .filter(|p| p.4 > 0 && p.5 > 0)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Rgba, RgbaImage, zlib};
+
+    use super::{
+        reader::{paeth, read_header},
+        *,
+    };
+
+    fn chunk(name: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut res = (data.len() as u32).to_be_bytes().to_vec();
+        res.extend_from_slice(name);
+        res.extend_from_slice(data);
+        res.extend(crc32(&res[4..]).to_be_bytes());
+        res
+    }
+
+    fn ihdr(width: u32, height: u32, bit_depth: u8, color_type: u8, interlace: u8) -> Vec<u8> {
+        let mut data = width.to_be_bytes().to_vec();
+        data.extend(height.to_be_bytes());
+        data.extend([bit_depth, color_type, 0, 0, interlace]);
+        chunk(b"IHDR", &data)
+    }
+
+    fn file(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut res = SIGNATURE.to_vec();
+        for c in chunks {
+            res.extend_from_slice(c);
+        }
+        res.extend(chunk(b"IEND", &[]));
+        res
+    }
+
+    /// filter every row, cycling through the filter types
+    fn filter(rows: &[Vec<u8>], bpp: usize) -> Vec<u8> {
+        let mut res = Vec::new();
+        let empty = vec![0; rows.first().map_or(0, |r| r.len())];
+        for (y, row) in rows.iter().enumerate() {
+            let up = if y == 0 { &empty } else { &rows[y - 1] };
+            let filter_type = (y % 5) as u8;
+            res.push(filter_type);
+            for i in 0..row.len() {
+                let left = if i >= bpp { row[i - bpp] } else { 0 };
+                let upper_left = if i >= bpp { up[i - bpp] } else { 0 };
+                let predicted = match filter_type {
+                    0 => 0,
+                    1 => left,
+                    2 => up[i],
+                    3 => ((left as u16 + up[i] as u16) / 2) as u8,
+                    _ => paeth(left, up[i], upper_left),
+                };
+                res.push(row[i].wrapping_sub(predicted));
+            }
+        }
+        res
+    }
+
+    /// pack samples of the given bit depth into rows
+    fn pack(samples: &[u16], width: usize, channels: usize, depth: u8) -> Vec<Vec<u8>> {
+        samples
+            .chunks(width * channels)
+            .map(|row| {
+                let mut res = vec![0u8; (row.len() * depth as usize).div_ceil(8)];
+                for (i, &s) in row.iter().enumerate() {
+                    match depth {
+                        16 => res[i * 2..i * 2 + 2].copy_from_slice(&s.to_be_bytes()),
+                        8 => res[i] = s as u8,
+                        _ => {
+                            let bit = i * depth as usize;
+                            res[bit / 8] |= (s as u8) << (8 - depth as usize - bit % 8);
+                        }
+                    }
+                }
+                res
+            })
+            .collect()
+    }
+
+    fn samples(count: usize, depth: u8) -> Vec<u16> {
+        let max = if depth == 16 {
+            u16::MAX as u32
+        } else {
+            (1 << depth) - 1
+        };
+        (0..count as u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 7) % (max + 1))
+            .map(|v| v as u16)
+            .collect()
+    }
+
+    fn scale(v: u16, depth: u8) -> u8 {
+        let max = if depth == 16 {
+            u16::MAX as f32
+        } else {
+            ((1 << depth) - 1) as f32
+        };
+        (v as f32 / max * 255.0).round() as u8
+    }
+
+    fn encode(
+        width: usize,
+        height: usize,
+        depth: u8,
+        color_type: ColorType,
+        samples: &[u16],
+        extra: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let channels = color_type.channels();
+        let rows = pack(samples, width, channels, depth);
+        let bpp = (channels * depth as usize).div_ceil(8);
+        let mut chunks = vec![ihdr(
+            width as u32,
+            height as u32,
+            depth,
+            color_type as u8,
+            0,
+        )];
+        chunks.extend_from_slice(extra);
+        let data = zlib::stored(&filter(&rows, bpp));
+        // split the image data into several chunks
+        for part in data.chunks(100) {
+            chunks.push(chunk(b"IDAT", part));
+        }
+        file(&chunks)
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+    }
+
+    #[test]
+    fn all_color_types_and_depths() {
+        let (width, height) = (13, 7);
+        for color_type in [
+            ColorType::Gray,
+            ColorType::Rgb,
+            ColorType::Indexed,
+            ColorType::GrayAlpha,
+            ColorType::Rgba,
+        ] {
+            for depth in [1, 2, 4, 8, 16] {
+                if !color_type.allows_bit_depth(depth) {
+                    continue;
+                }
+                let channels = color_type.channels();
+                let samples = samples(width * height * channels, depth);
+
+                let palette: Vec<u8> = (0..(1usize << depth) * 3).map(|i| (i * 37) as u8).collect();
+                let mut extra = Vec::new();
+                if color_type == ColorType::Indexed {
+                    extra.push(chunk(b"PLTE", &palette));
+                }
+                let img = decode(&encode(width, height, depth, color_type, &samples, &extra))
+                    .unwrap_or_else(|e| panic!("{color_type:?} {depth}: {e}"));
+
+                for (i, pix) in img.data.iter().enumerate() {
+                    let s = &samples[i * channels..(i + 1) * channels];
+                    let v = |c: usize| scale(s[c], depth);
+                    let expected = match color_type {
+                        ColorType::Gray => Rgba {
+                            r: v(0),
+                            g: v(0),
+                            b: v(0),
+                            a: 255,
+                        },
+                        ColorType::Rgb => Rgba {
+                            r: v(0),
+                            g: v(1),
+                            b: v(2),
+                            a: 255,
+                        },
+                        ColorType::Indexed => {
+                            let p = &palette[s[0] as usize * 3..];
+                            Rgba {
+                                r: p[0],
+                                g: p[1],
+                                b: p[2],
+                                a: 255,
+                            }
+                        }
+                        ColorType::GrayAlpha => Rgba {
+                            r: v(0),
+                            g: v(0),
+                            b: v(0),
+                            a: v(1),
+                        },
+                        ColorType::Rgba => Rgba {
+                            r: v(0),
+                            g: v(1),
+                            b: v(2),
+                            a: v(3),
+                        },
+                    };
+                    assert_eq!(*pix, expected, "{color_type:?} {depth} pixel {i}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transparency() {
+        // gray with a transparent value
+        let samples = [0, 5, 9, 5];
+        let trns = chunk(b"tRNS", &5u16.to_be_bytes());
+        let img = decode(&encode(2, 2, 4, ColorType::Gray, &samples, &[trns])).unwrap();
+        let alpha: Vec<u8> = img.data.iter().map(|p| p.a).collect();
+        assert_eq!(alpha, [255, 0, 255, 0]);
+
+        // 16 bit rgb, only the exact color is transparent
+        let samples = [1000, 2000, 3000, 1000, 2000, 3001];
+        let trns = chunk(b"tRNS", &[0x03, 0xe8, 0x07, 0xd0, 0x0b, 0xb8]);
+        let img = decode(&encode(2, 1, 16, ColorType::Rgb, &samples, &[trns])).unwrap();
+        let alpha: Vec<u8> = img.data.iter().map(|p| p.a).collect();
+        assert_eq!(alpha, [0, 255]);
+
+        // palette alpha, entries without an alpha value are opaque
+        let plte = chunk(b"PLTE", &[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let trns = chunk(b"tRNS", &[10, 20]);
+        let img = decode(&encode(
+            3,
+            1,
+            8,
+            ColorType::Indexed,
+            &[0, 1, 2],
+            &[plte, trns],
+        ))
+        .unwrap();
+        assert_eq!(
+            img.data,
+            [
+                Rgba {
+                    r: 1,
+                    g: 2,
+                    b: 3,
+                    a: 10
+                },
+                Rgba {
+                    r: 4,
+                    g: 5,
+                    b: 6,
+                    a: 20
+                },
+                Rgba {
+                    r: 7,
+                    g: 8,
+                    b: 9,
+                    a: 255
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn adam7() {
+        // sizes where some passes are empty
+        for (width, height) in [(1, 1), (3, 2), (9, 11), (16, 16)] {
+            let samples = samples(width * height * 3, 8);
+            let header = PngHeader {
+                width: width as u32,
+                height: height as u32,
+                bit_depth: 8,
+                color_type: ColorType::Rgb,
+                interlaced: true,
+            };
+
+            let mut raw = Vec::new();
+            for (x0, y0, dx, dy, w, h) in passes(&header) {
+                let rows: Vec<Vec<u8>> = (0..h)
+                    .map(|y| {
+                        (0..w)
+                            .flat_map(|x| {
+                                let i = (y0 + y * dy) * width + x0 + x * dx;
+                                samples[i * 3..i * 3 + 3].iter().map(|&s| s as u8)
+                            })
+                            .collect()
+                    })
+                    .collect();
+                raw.extend(filter(&rows, 3));
+            }
+
+            let data = file(&[
+                ihdr(width as u32, height as u32, 8, 2, 1),
+                chunk(b"IDAT", &zlib::stored(&raw)),
+            ]);
+            let img = decode(&data).unwrap();
+            for (i, pix) in img.data.iter().enumerate() {
+                let s = &samples[i * 3..];
+                assert_eq!(
+                    *pix,
+                    Rgba {
+                        r: s[0] as u8,
+                        g: s[1] as u8,
+                        b: s[2] as u8,
+                        a: 255
+                    }
+                );
+            }
+        }
+    }
+
+    fn test_image(
+        width: usize,
+        height: usize,
+        mut pixel: impl FnMut(usize, usize) -> Rgba,
+    ) -> RgbaImage {
+        let mut img = RgbaImage::new(width, height, Rgba::BLACK);
+        for y in 0..height {
+            for x in 0..width {
+                img.put_pixel(x, y, pixel(x, y));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        let gradient = |x: usize, y: usize| Rgba {
+            r: (x * 5) as u8,
+            g: (y * 3) as u8,
+            b: (x * y) as u8,
+            a: 255,
+        };
+        let mut noise = 1u32;
+        let images = [
+            (test_image(1, 1, |_, _| Rgba::BLACK), ColorType::Gray),
+            (
+                test_image(40, 30, |x, y| {
+                    let v = (x ^ y) as u8;
+                    Rgba {
+                        r: v,
+                        g: v,
+                        b: v,
+                        a: (x * 6) as u8,
+                    }
+                }),
+                ColorType::GrayAlpha,
+            ),
+            (test_image(97, 61, gradient), ColorType::Rgb),
+            (
+                test_image(64, 64, |x, y| Rgba {
+                    a: (y * 4) as u8,
+                    ..gradient(x, y)
+                }),
+                ColorType::Rgba,
+            ),
+            (
+                test_image(50, 70, |_, _| {
+                    noise = noise.wrapping_mul(1103515245).wrapping_add(12345);
+                    let [r, g, b, a] = noise.to_le_bytes();
+                    Rgba { r, g, b, a }
+                }),
+                ColorType::Rgba,
+            ),
+        ];
+
+        let filters = [
+            Filter::None,
+            Filter::Sub,
+            Filter::Up,
+            Filter::Average,
+            Filter::Paeth,
+            Filter::Adaptive,
+        ];
+        for (img, color_type) in &images {
+            for filter in filters {
+                for compression in [0, 1, 6, 9] {
+                    let mut file = Vec::new();
+                    let options = PngOptions {
+                        compression,
+                        filter,
+                    };
+                    super::encode_with(img, &mut file, &options).unwrap();
+
+                    assert_eq!(read_header(&file).unwrap().color_type, *color_type);
+                    assert_eq!(
+                        decode(&file).as_ref(),
+                        Ok(img),
+                        "{filter:?} at level {compression}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn encode_compresses() {
+        let img = test_image(256, 256, |x, y| Rgba {
+            r: x as u8,
+            g: y as u8,
+            b: 128,
+            a: 255,
+        });
+        let size = |options: PngOptions| {
+            let mut file = Vec::new();
+            super::encode_with(&img, &mut file, &options).unwrap();
+            file.len()
+        };
+
+        let raw = 256 * 256 * 3;
+        let stored = size(PngOptions {
+            compression: 0,
+            filter: Filter::None,
+        });
+        assert!(stored > raw);
+        // the filters turn the gradient into a constant
+        let adaptive = size(PngOptions::default());
+        assert!(adaptive < raw / 100, "{adaptive} bytes");
+        assert!(size(PngOptions::best()) <= adaptive);
+        assert!(size(PngOptions::fast()) < raw / 50);
+
+        let mut file = Vec::new();
+        let empty = RgbaImage::new(0, 5, Rgba::BLACK);
+        assert!(super::encode(&empty, &mut file).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let valid = encode(2, 2, 8, ColorType::Gray, &[1, 2, 3, 4], &[]);
+        assert!(decode(&valid).is_ok());
+
+        assert_eq!(decode(&valid[1..]), Err(PngError::InvalidSignature));
+
+        for len in SIGNATURE.len()..valid.len() {
+            assert!(decode(&valid[..len]).is_err(), "truncated to {len} bytes");
+        }
+
+        let mut crc = valid.clone();
+        crc[SIGNATURE.len() + 10] ^= 1;
+        assert_eq!(decode(&crc), Err(PngError::CrcMismatch(*b"IHDR")));
+
+        let critical = file(&[ihdr(1, 1, 8, 0, 0), chunk(b"ABCD", &[])]);
+        assert_eq!(
+            decode(&critical),
+            Err(PngError::UnknownCriticalChunk(*b"ABCD"))
+        );
+
+        let format = file(&[ihdr(1, 1, 3, 2, 0)]);
+        assert!(matches!(
+            decode(&format),
+            Err(PngError::InvalidColorFormat { .. })
+        ));
+
+        let empty = file(&[ihdr(0, 1, 8, 0, 0)]);
+        assert!(matches!(
+            decode(&empty),
+            Err(PngError::InvalidDimensions { .. })
+        ));
+
+        let no_data = file(&[ihdr(1, 1, 8, 0, 0), chunk(b"teXt", b"ignored")]);
+        assert_eq!(decode(&no_data), Err(PngError::MissingChunk(*b"IDAT")));
+
+        let filter = file(&[ihdr(1, 1, 8, 0, 0), chunk(b"IDAT", &zlib::stored(&[5, 0]))]);
+        assert_eq!(decode(&filter), Err(PngError::InvalidFilter(5)));
+
+        let short = file(&[ihdr(2, 1, 8, 0, 0), chunk(b"IDAT", &zlib::stored(&[0, 0]))]);
+        assert_eq!(decode(&short), Err(PngError::InvalidDataSize));
+
+        let index = file(&[
+            ihdr(1, 1, 8, 3, 0),
+            chunk(b"PLTE", &[0, 0, 0]),
+            chunk(b"IDAT", &zlib::stored(&[0, 1])),
+        ]);
+        assert_eq!(decode(&index), Err(PngError::InvalidPaletteIndex(1)));
+
+        // a small file claiming a huge image must not allocate the image
+        let huge = file(&[
+            ihdr(20_000, 20_000, 8, 6, 0),
+            chunk(b"IDAT", &zlib::stored(&[0; 100])),
+        ]);
+        assert_eq!(decode(&huge), Err(PngError::InvalidDataSize));
+    }
+}
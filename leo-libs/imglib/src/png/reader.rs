@@ -0,0 +1,346 @@
+use corelib::reader::{BigEndianReader, Reader};
+
+use crate::{Rgba, RgbaImage, zlib};
+
+use super::{ColorType, MAX_PIXELS, PngError, PngHeader, SIGNATURE, crc32, passes};
+
+struct Chunk<'data> {
+    name: [u8; 4],
+    data: &'data [u8],
+}
+
+fn next_chunk<'data>(
+    reader: &mut Reader<'data, BigEndianReader>,
+) -> Result<Chunk<'data>, PngError> {
+    if reader.remaining() < 12 {
+        return Err(PngError::UnexpectedEof);
+    }
+    let len = reader.read::<u32>() as usize;
+    if reader.remaining() < len + 8 {
+        return Err(PngError::UnexpectedEof);
+    }
+
+    let start = reader.get_pos();
+    let name: [u8; 4] = reader.read();
+    let data = &reader.data[start + 4..start + 4 + len];
+    // the crc covers the name and the data
+    let crc = crc32(&reader.data[start..start + 4 + len]);
+
+    reader.set_pos(start + 4 + len);
+    if reader.read::<u32>() != crc {
+        return Err(PngError::CrcMismatch(name));
+    }
+
+    Ok(Chunk { name, data })
+}
+
+fn parse_header(chunk: &Chunk) -> Result<PngHeader, PngError> {
+    if &chunk.name != b"IHDR" {
+        return Err(PngError::MissingChunk(*b"IHDR"));
+    }
+    if chunk.data.len() != 13 {
+        return Err(PngError::InvalidChunk(chunk.name));
+    }
+
+    let mut reader = Reader::new_big_endian(chunk.data, 0);
+    let width: u32 = reader.read();
+    let height: u32 = reader.read();
+    let bit_depth: u8 = reader.read();
+    let color_type: u8 = reader.read();
+    let [compression, filter, interlace]: [u8; 3] = reader.read();
+
+    if width == 0
+        || height == 0
+        || width > i32::MAX as u32
+        || height > i32::MAX as u32
+        || width as u64 * height as u64 > MAX_PIXELS
+    {
+        return Err(PngError::InvalidDimensions { width, height });
+    }
+
+    let invalid_format = PngError::InvalidColorFormat {
+        color_type,
+        bit_depth,
+    };
+    let Some(color_type) = ColorType::from_u8(color_type) else {
+        return Err(invalid_format);
+    };
+    if !color_type.allows_bit_depth(bit_depth) {
+        return Err(invalid_format);
+    }
+
+    if compression != 0 || filter != 0 || interlace > 1 {
+        return Err(PngError::UnknownMethod);
+    }
+
+    Ok(PngHeader {
+        width,
+        height,
+        bit_depth,
+        color_type,
+        interlaced: interlace == 1,
+    })
+}
+
+/// Read only the header of a png file
+pub fn read_header(data: &[u8]) -> Result<PngHeader, PngError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(PngError::InvalidSignature);
+    }
+    let mut reader = Reader::new_big_endian(data, SIGNATURE.len());
+    parse_header(&next_chunk(&mut reader)?)
+}
+
+/// The transparency information from the tRNS chunk
+enum Transparency {
+    None,
+    /// the raw sample value of a gray pixel that is fully transparent
+    Gray(u16),
+    /// the raw sample values of a color that is fully transparent
+    Rgb(u16, u16, u16),
+}
+
+/// Decode a png file. Gamma and color profile information is ignored, the pixels are
+/// assumed to be sRGB.
+pub fn decode(data: &[u8]) -> Result<RgbaImage, PngError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(PngError::InvalidSignature);
+    }
+    let mut reader = Reader::new_big_endian(data, SIGNATURE.len());
+    let header = parse_header(&next_chunk(&mut reader)?)?;
+
+    let mut palette: Vec<Rgba> = Vec::new();
+    let mut transparency = Transparency::None;
+    let mut idat = Vec::new();
+
+    loop {
+        let chunk = next_chunk(&mut reader)?;
+        let invalid = PngError::InvalidChunk(chunk.name);
+        match &chunk.name {
+            b"IHDR" => return Err(invalid),
+            b"PLTE" => {
+                let len = chunk.data.len();
+                if !idat.is_empty()
+                    || !palette.is_empty()
+                    || !len.is_multiple_of(3)
+                    || !(1..=256 * 3).contains(&len)
+                    || matches!(header.color_type, ColorType::Gray | ColorType::GrayAlpha)
+                {
+                    return Err(invalid);
+                }
+                palette = chunk
+                    .data
+                    .chunks_exact(3)
+                    .map(|c| Rgba {
+                        r: c[0],
+                        g: c[1],
+                        b: c[2],
+                        a: 255,
+                    })
+                    .collect();
+            }
+            b"tRNS" => {
+                if !idat.is_empty() {
+                    return Err(invalid);
+                }
+                let sample = |i: usize| u16::from_be_bytes([chunk.data[i], chunk.data[i + 1]]);
+                match header.color_type {
+                    ColorType::Indexed => {
+                        if chunk.data.len() > palette.len() {
+                            return Err(invalid);
+                        }
+                        for (entry, &alpha) in palette.iter_mut().zip(chunk.data) {
+                            entry.a = alpha;
+                        }
+                    }
+                    ColorType::Gray if chunk.data.len() == 2 => {
+                        transparency = Transparency::Gray(sample(0));
+                    }
+                    ColorType::Rgb if chunk.data.len() == 6 => {
+                        transparency = Transparency::Rgb(sample(0), sample(2), sample(4));
+                    }
+                    _ => return Err(invalid),
+                }
+            }
+            b"IDAT" => idat.extend_from_slice(chunk.data),
+            b"IEND" => break,
+            // bit 5 of the first byte is set for ancillary chunks, which can be ignored
+            name if name[0] & 0x20 == 0 => return Err(PngError::UnknownCriticalChunk(*name)),
+            _ => {}
+        }
+    }
+
+    if idat.is_empty() {
+        return Err(PngError::MissingChunk(*b"IDAT"));
+    }
+    if header.color_type == ColorType::Indexed && palette.is_empty() {
+        return Err(PngError::MissingChunk(*b"PLTE"));
+    }
+
+    let expected_len: usize = passes(&header)
+        .map(|(.., w, h)| h * (header.stride(w) + 1))
+        .sum();
+    let mut raw = zlib::decompress(&idat, expected_len)?;
+    if raw.len() != expected_len {
+        return Err(PngError::InvalidDataSize);
+    }
+
+    let mut img = RgbaImage::new(header.width as usize, header.height as usize, Rgba::BLACK);
+    let bytes_per_pixel = header.bits_per_pixel().div_ceil(8);
+
+    let mut pass_data = &mut raw[..];
+    for (x0, y0, dx, dy, w, h) in passes(&header) {
+        let stride = header.stride(w);
+        let (this_pass, rest) = pass_data.split_at_mut(h * (stride + 1));
+        pass_data = rest;
+
+        unfilter(this_pass, stride, bytes_per_pixel)?;
+
+        for (y, row) in this_pass.chunks_exact(stride + 1).enumerate() {
+            let row = &row[1..];
+            for x in 0..w {
+                let pixel = convert_pixel(&header, row, x, &palette, &transparency)?;
+                img.put_pixel(x0 + x * dx, y0 + y * dy, pixel);
+            }
+        }
+    }
+
+    Ok(img)
+}
+
+/// Undo the filtering in place. Every row starts with its filter type.
+fn unfilter(data: &mut [u8], stride: usize, bpp: usize) -> Result<(), PngError> {
+    let mut previous: Option<&[u8]> = None;
+    for row in data.chunks_exact_mut(stride + 1) {
+        let (filter, row) = row.split_first_mut().unwrap();
+        let up = |i: usize| previous.map_or(0, |p| p[i]);
+
+        match *filter {
+            0 => {}
+            1 => {
+                for i in bpp..stride {
+                    row[i] = row[i].wrapping_add(row[i - bpp]);
+                }
+            }
+            2 => {
+                for (i, v) in row.iter_mut().enumerate() {
+                    *v = v.wrapping_add(up(i));
+                }
+            }
+            3 => {
+                for i in 0..stride {
+                    let left = if i >= bpp { row[i - bpp] } else { 0 };
+                    row[i] = row[i].wrapping_add(((left as u16 + up(i) as u16) / 2) as u8);
+                }
+            }
+            4 => {
+                for i in 0..stride {
+                    let (left, upper_left) = if i >= bpp {
+                        (row[i - bpp], up(i - bpp))
+                    } else {
+                        (0, 0)
+                    };
+                    row[i] = row[i].wrapping_add(paeth(left, up(i), upper_left));
+                }
+            }
+            t => return Err(PngError::InvalidFilter(t)),
+        }
+
+        previous = Some(row);
+    }
+    Ok(())
+}
+
+pub(crate) fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// the `index`th sample of a row
+fn sample(row: &[u8], index: usize, bit_depth: u8) -> u16 {
+    match bit_depth {
+        16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bit = index * bit_depth as usize;
+            let shift = 8 - bit_depth as usize - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << bit_depth) - 1)) as u16
+        }
+    }
+}
+
+/// scale a sample to 8 bits
+fn to_u8(v: u16, bit_depth: u8) -> u8 {
+    match bit_depth {
+        16 => ((v as u32 * 255 + 32767) / 65535) as u8,
+        8 => v as u8,
+        _ => (v as u32 * 255 / ((1 << bit_depth) - 1)) as u8,
+    }
+}
+
+fn convert_pixel(
+    header: &PngHeader,
+    row: &[u8],
+    x: usize,
+    palette: &[Rgba],
+    transparency: &Transparency,
+) -> Result<Rgba, PngError> {
+    let depth = header.bit_depth;
+    let channels = header.color_type.channels();
+    let s = |c: usize| sample(row, x * channels + c, depth);
+
+    Ok(match header.color_type {
+        ColorType::Gray => {
+            let v = s(0);
+            let g = to_u8(v, depth);
+            let a = match transparency {
+                Transparency::Gray(t) if *t == v => 0,
+                _ => 255,
+            };
+            Rgba { r: g, g, b: g, a }
+        }
+        ColorType::Rgb => {
+            let (r, g, b) = (s(0), s(1), s(2));
+            let a = match transparency {
+                Transparency::Rgb(tr, tg, tb) if (*tr, *tg, *tb) == (r, g, b) => 0,
+                _ => 255,
+            };
+            Rgba {
+                r: to_u8(r, depth),
+                g: to_u8(g, depth),
+                b: to_u8(b, depth),
+                a,
+            }
+        }
+        ColorType::Indexed => {
+            let index = s(0) as u8;
+            *palette
+                .get(index as usize)
+                .ok_or(PngError::InvalidPaletteIndex(index))?
+        }
+        ColorType::GrayAlpha => {
+            let g = to_u8(s(0), depth);
+            Rgba {
+                r: g,
+                g,
+                b: g,
+                a: to_u8(s(1), depth),
+            }
+        }
+        ColorType::Rgba => Rgba {
+            r: to_u8(s(0), depth),
+            g: to_u8(s(1), depth),
+            b: to_u8(s(2), depth),
+            a: to_u8(s(3), depth),
+        },
+    })
+}
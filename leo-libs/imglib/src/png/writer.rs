@@ -0,0 +1,171 @@
+use std::io::{self, Write};
+
+use crate::{Rgba, RgbaImage, zlib};
+
+use super::{ColorType, MAX_PIXELS, PngError, SIGNATURE, crc32, reader::paeth};
+
+/// The filter that is applied to every row before compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+    /// choose the filter per row, the one with the smallest sum of absolute differences
+    Adaptive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PngOptions {
+    /// 0 (no compression) to 9 (best compression)
+    pub compression: u8,
+    pub filter: Filter,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        Self {
+            compression: 6,
+            filter: Filter::Adaptive,
+        }
+    }
+}
+
+impl PngOptions {
+    /// Fast compression for images that are written often, like screenshots
+    pub fn fast() -> Self {
+        Self {
+            compression: 1,
+            filter: Filter::Sub,
+        }
+    }
+
+    pub fn best() -> Self {
+        Self {
+            compression: 9,
+            filter: Filter::Adaptive,
+        }
+    }
+}
+
+/// Encode an image as 8 bit png with the default options
+pub fn encode(img: &RgbaImage, out: &mut impl Write) -> io::Result<()> {
+    encode_with(img, out, &PngOptions::default())
+}
+
+/// Encode an image as 8 bit png. The color type is the smallest one that can store the image
+/// without loss: gray, gray with alpha, rgb or rgba.
+pub fn encode_with(img: &RgbaImage, out: &mut impl Write, options: &PngOptions) -> io::Result<()> {
+    let (width, height) = img.dimensions();
+    if width == 0
+        || height == 0
+        || width > i32::MAX as usize
+        || height > i32::MAX as usize
+        || width as u64 * height as u64 > MAX_PIXELS
+    {
+        let err = PngError::InvalidDimensions {
+            width: width as u32,
+            height: height as u32,
+        };
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err.to_string()));
+    }
+
+    let color_type = smallest_color_type(&img.data);
+    let channels = color_type.channels();
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend((width as u32).to_be_bytes());
+    ihdr.extend((height as u32).to_be_bytes());
+    // bit depth, color type, compression, filter and interlace method
+    ihdr.extend([8, color_type as u8, 0, 0, 0]);
+
+    let stride = width * channels;
+    let mut filtered = Vec::with_capacity(height * (stride + 1));
+    let mut previous = vec![0; stride];
+    let mut row = Vec::with_capacity(stride);
+    let mut candidate = Vec::with_capacity(stride);
+    for pixels in img.data.chunks_exact(width) {
+        row.clear();
+        for pix in pixels {
+            match color_type {
+                ColorType::Gray => row.push(pix.r),
+                ColorType::GrayAlpha => row.extend([pix.r, pix.a]),
+                ColorType::Rgb => row.extend([pix.r, pix.g, pix.b]),
+                _ => row.extend([pix.r, pix.g, pix.b, pix.a]),
+            }
+        }
+
+        let filter = match options.filter {
+            Filter::Adaptive if options.compression == 0 => Filter::None,
+            Filter::Adaptive => [
+                Filter::None,
+                Filter::Sub,
+                Filter::Up,
+                Filter::Average,
+                Filter::Paeth,
+            ]
+            .into_iter()
+            .min_by_key(|&f| {
+                candidate.clear();
+                apply_filter(f, &row, &previous, channels, &mut candidate);
+                // interpret the bytes as signed, small differences in both directions are good
+                candidate
+                    .iter()
+                    .map(|&b| (b as i8).unsigned_abs() as u64)
+                    .sum::<u64>()
+            })
+            .unwrap(),
+            f => f,
+        };
+
+        filtered.push(filter as u8);
+        apply_filter(filter, &row, &previous, channels, &mut filtered);
+        std::mem::swap(&mut previous, &mut row);
+    }
+
+    let compressed = zlib::compress(&filtered, options.compression);
+
+    out.write_all(&SIGNATURE)?;
+    write_chunk(out, b"IHDR", &ihdr)?;
+    // large images are split into several chunks so readers do not need a huge buffer
+    for part in compressed.chunks(1 << 20) {
+        write_chunk(out, b"IDAT", part)?;
+    }
+    write_chunk(out, b"IEND", &[])
+}
+
+fn smallest_color_type(pixels: &[Rgba]) -> ColorType {
+    let opaque = pixels.iter().all(|p| p.a == 255);
+    let gray = pixels.iter().all(|p| p.r == p.g && p.g == p.b);
+    match (gray, opaque) {
+        (true, true) => ColorType::Gray,
+        (true, false) => ColorType::GrayAlpha,
+        (false, true) => ColorType::Rgb,
+        (false, false) => ColorType::Rgba,
+    }
+}
+
+/// Filter a row and append it to `out`. `bpp` is the amount of bytes per pixel.
+fn apply_filter(filter: Filter, row: &[u8], previous: &[u8], bpp: usize, out: &mut Vec<u8>) {
+    let left = |i: usize| if i >= bpp { row[i - bpp] } else { 0 };
+    let upper_left = |i: usize| if i >= bpp { previous[i - bpp] } else { 0 };
+    let filtered = row.iter().enumerate().map(|(i, &v)| match filter {
+        Filter::None | Filter::Adaptive => v,
+        Filter::Sub => v.wrapping_sub(left(i)),
+        Filter::Up => v.wrapping_sub(previous[i]),
+        Filter::Average => v.wrapping_sub(((left(i) as u16 + previous[i] as u16) / 2) as u8),
+        Filter::Paeth => v.wrapping_sub(paeth(left(i), previous[i], upper_left(i))),
+    });
+    out.extend(filtered);
+}
+
+fn write_chunk(out: &mut impl Write, name: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let mut crc_data = Vec::with_capacity(data.len() + 4);
+    crc_data.extend_from_slice(name);
+    crc_data.extend_from_slice(data);
+
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(&crc_data)?;
+    out.write_all(&crc32(&crc_data).to_be_bytes())
+}
@@ -0,0 +1,656 @@
+//! The Netpbm formats PBM, PGM and PPM (P1-P6) and PAM (P7): https://netpbm.sourceforge.net/doc/
+
+use std::{
+    fmt::Display,
+    io::{self, Write},
+};
+
+use corelib::reader::{BigEndianReader, Reader};
+
+use crate::{Rgba, RgbaImage};
+
+/// The same limit as for qoi, protects against absurd allocations
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+/// The variants of the format, named after the magic number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PnmFormat {
+    /// P1, black and white as text
+    PbmAscii = 1,
+    /// P2, grayscale as text
+    PgmAscii = 2,
+    /// P3, rgb as text
+    PpmAscii = 3,
+    /// P4, black and white with 8 pixels per byte
+    Pbm = 4,
+    /// P5, binary grayscale
+    Pgm = 5,
+    /// P6, binary rgb
+    Ppm = 6,
+    /// P7, binary with any amount of channels, used for images with alpha
+    Pam = 7,
+}
+
+impl PnmFormat {
+    pub fn from_magic(magic: [u8; 2]) -> Option<Self> {
+        Some(match &magic {
+            b"P1" => Self::PbmAscii,
+            b"P2" => Self::PgmAscii,
+            b"P3" => Self::PpmAscii,
+            b"P4" => Self::Pbm,
+            b"P5" => Self::Pgm,
+            b"P6" => Self::Ppm,
+            b"P7" => Self::Pam,
+            _ => return None,
+        })
+    }
+
+    pub fn magic(self) -> [u8; 2] {
+        [b'P', b'0' + self as u8]
+    }
+
+    pub fn is_ascii(self) -> bool {
+        matches!(self, Self::PbmAscii | Self::PgmAscii | Self::PpmAscii)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PnmError {
+    InvalidMagic,
+    UnexpectedEof,
+    /// a header field or a sample that is not a valid number
+    InvalidNumber,
+    /// an unknown or missing field in the header of a pam file
+    InvalidHeader,
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+    InvalidMaxValue(u32),
+    /// a pam file with a channel count or tuple type that is not supported
+    UnsupportedTupleType,
+    /// a sample larger than the maximum value
+    InvalidSample,
+}
+
+impl Display for PnmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic => write!(f, "not a netpbm file"),
+            Self::UnexpectedEof => write!(f, "unexpected end of data"),
+            Self::InvalidNumber => write!(f, "invalid number"),
+            Self::InvalidHeader => write!(f, "invalid pam header"),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Self::InvalidMaxValue(v) => write!(f, "invalid maximum sample value {v}"),
+            Self::UnsupportedTupleType => write!(f, "unsupported pam tuple type"),
+            Self::InvalidSample => write!(f, "sample larger than the maximum value"),
+        }
+    }
+}
+
+/// How the samples of a pixel are turned into a color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Layout {
+    /// one sample, 1 is black
+    BlackAndWhite,
+    Gray,
+    GrayAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl Layout {
+    fn channels(self) -> usize {
+        match self {
+            Self::BlackAndWhite | Self::Gray => 1,
+            Self::GrayAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+}
+
+struct Header {
+    format: PnmFormat,
+    width: usize,
+    height: usize,
+    max_value: u32,
+    layout: Layout,
+}
+
+fn is_whitespace(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b'\r' | 0x0b | 0x0c)
+}
+
+/// Skips whitespace and comments, which go from `#` to the end of the line
+fn skip_whitespace(reader: &mut Reader<BigEndianReader>) {
+    while reader.remaining() > 0 {
+        let pos = reader.get_pos();
+        match reader.read_byte() {
+            b'#' => while reader.remaining() > 0 && reader.read_byte() != b'\n' {},
+            b if is_whitespace(b) => {}
+            _ => {
+                reader.set_pos(pos);
+                return;
+            }
+        }
+    }
+}
+
+/// A decimal number after optional whitespace
+fn read_number(reader: &mut Reader<BigEndianReader>) -> Result<u32, PnmError> {
+    skip_whitespace(reader);
+    if reader.remaining() == 0 {
+        return Err(PnmError::UnexpectedEof);
+    }
+
+    let mut value: u32 = 0;
+    let mut digits = 0;
+    while reader.remaining() > 0 {
+        let pos = reader.get_pos();
+        let b = reader.read_byte();
+        if !b.is_ascii_digit() {
+            reader.set_pos(pos);
+            break;
+        }
+        value = value
+            .checked_mul(10)
+            .and_then(|v| v.checked_add((b - b'0') as u32))
+            .ok_or(PnmError::InvalidNumber)?;
+        digits += 1;
+    }
+
+    if digits == 0 {
+        return Err(PnmError::InvalidNumber);
+    }
+    Ok(value)
+}
+
+/// The rest of the current line of a pam header
+fn read_line<'data>(reader: &mut Reader<'data, BigEndianReader>) -> &'data [u8] {
+    let start = reader.get_pos();
+    while reader.remaining() > 0 && reader.read_byte() != b'\n' {}
+    reader.data[start..reader.get_pos()].trim_ascii()
+}
+
+fn parse_pam_header(reader: &mut Reader<BigEndianReader>) -> Result<Header, PnmError> {
+    let (mut width, mut height, mut depth, mut max_value) = (None, None, None, None);
+    let mut tuple_type = Vec::new();
+
+    loop {
+        if reader.remaining() == 0 {
+            return Err(PnmError::UnexpectedEof);
+        }
+        let line = read_line(reader);
+        if line.is_empty() || line[0] == b'#' {
+            continue;
+        }
+
+        let split = line
+            .iter()
+            .position(|&b| is_whitespace(b))
+            .unwrap_or(line.len());
+        let (key, value) = (&line[..split], line[split..].trim_ascii());
+        let number = || -> Result<u32, PnmError> {
+            let mut value_reader = Reader::new_big_endian(value, 0);
+            let n = read_number(&mut value_reader)?;
+            if value_reader.remaining() != 0 {
+                return Err(PnmError::InvalidNumber);
+            }
+            Ok(n)
+        };
+
+        match key {
+            b"WIDTH" => width = Some(number()?),
+            b"HEIGHT" => height = Some(number()?),
+            b"DEPTH" => depth = Some(number()?),
+            b"MAXVAL" => max_value = Some(number()?),
+            // the tuple type may be split over several lines
+            b"TUPLTYPE" => {
+                if !tuple_type.is_empty() {
+                    tuple_type.push(b' ');
+                }
+                tuple_type.extend_from_slice(value);
+            }
+            b"ENDHDR" => break,
+            _ => return Err(PnmError::InvalidHeader),
+        }
+    }
+
+    let (Some(width), Some(height), Some(depth), Some(max_value)) =
+        (width, height, depth, max_value)
+    else {
+        return Err(PnmError::InvalidHeader);
+    };
+
+    // the tuple type is only a hint, the depth decides how the samples are interpreted
+    let layout = match depth {
+        1 => Layout::Gray,
+        2 => Layout::GrayAlpha,
+        3 => Layout::Rgb,
+        4 => Layout::Rgba,
+        _ => return Err(PnmError::UnsupportedTupleType),
+    };
+    // black and white pam images use 1 for white, unlike pbm
+    if tuple_type.starts_with(b"BLACKANDWHITE") && max_value != 1 {
+        return Err(PnmError::InvalidMaxValue(max_value));
+    }
+
+    Ok(Header {
+        format: PnmFormat::Pam,
+        width: width as usize,
+        height: height as usize,
+        max_value,
+        layout,
+    })
+}
+
+fn parse_header(data: &[u8], reader: &mut Reader<BigEndianReader>) -> Result<Header, PnmError> {
+    let [a, b, ..] = *data else {
+        return Err(PnmError::InvalidMagic);
+    };
+    let format = PnmFormat::from_magic([a, b]).ok_or(PnmError::InvalidMagic)?;
+    reader.set_pos(2);
+
+    let header = if format == PnmFormat::Pam {
+        parse_pam_header(reader)?
+    } else {
+        let width = read_number(reader)?;
+        let height = read_number(reader)?;
+        let max_value = match format {
+            PnmFormat::PbmAscii | PnmFormat::Pbm => 1,
+            _ => read_number(reader)?,
+        };
+        let layout = match format {
+            PnmFormat::PbmAscii | PnmFormat::Pbm => Layout::BlackAndWhite,
+            PnmFormat::PgmAscii | PnmFormat::Pgm => Layout::Gray,
+            _ => Layout::Rgb,
+        };
+        // binary data starts after a single whitespace character
+        if !format.is_ascii() {
+            if reader.remaining() == 0 {
+                return Err(PnmError::UnexpectedEof);
+            }
+            if !is_whitespace(reader.read_byte()) {
+                return Err(PnmError::InvalidNumber);
+            }
+        }
+        Header {
+            format,
+            width: width as usize,
+            height: height as usize,
+            max_value,
+            layout,
+        }
+    };
+
+    if header.width == 0
+        || header.height == 0
+        || header.width as u64 * header.height as u64 > MAX_PIXELS
+    {
+        return Err(PnmError::InvalidDimensions {
+            width: header.width as u32,
+            height: header.height as u32,
+        });
+    }
+    if !(1..=u16::MAX as u32).contains(&header.max_value) {
+        return Err(PnmError::InvalidMaxValue(header.max_value));
+    }
+
+    Ok(header)
+}
+
+/// Decode any of the netpbm formats
+pub fn decode(data: &[u8]) -> Result<RgbaImage, PnmError> {
+    let mut reader = Reader::new_big_endian(data, 0);
+    let header = parse_header(data, &mut reader)?;
+    let channels = header.layout.channels();
+    let sample_count = header.width * header.height * channels;
+
+    // check the size up front so a small file can not claim a huge image
+    let min_size = match header.format {
+        PnmFormat::Pbm => header.width.div_ceil(8) * header.height,
+        // at least a digit and a separator for every sample, pbm may leave out the separator
+        PnmFormat::PbmAscii => sample_count,
+        PnmFormat::PgmAscii | PnmFormat::PpmAscii => sample_count * 2 - 1,
+        _ if header.max_value > 255 => sample_count * 2,
+        _ => sample_count,
+    };
+    if reader.remaining() < min_size {
+        return Err(PnmError::UnexpectedEof);
+    }
+
+    let mut samples = Vec::with_capacity(sample_count);
+    match header.format {
+        PnmFormat::Pbm => {
+            let stride = header.width.div_ceil(8);
+            for _ in 0..header.height {
+                let pos = reader.get_pos();
+                let row = &reader.data[pos..pos + stride];
+                for x in 0..header.width {
+                    samples.push(((row[x / 8] >> (7 - x % 8)) & 1) as u16);
+                }
+                reader.set_pos(pos + stride);
+            }
+        }
+        PnmFormat::PbmAscii => {
+            // the samples do not have to be separated
+            while samples.len() < sample_count {
+                skip_whitespace(&mut reader);
+                if reader.remaining() == 0 {
+                    return Err(PnmError::UnexpectedEof);
+                }
+                match reader.read_byte() {
+                    b'0' => samples.push(0),
+                    b'1' => samples.push(1),
+                    _ => return Err(PnmError::InvalidNumber),
+                }
+            }
+        }
+        PnmFormat::PgmAscii | PnmFormat::PpmAscii => {
+            for _ in 0..sample_count {
+                let v = read_number(&mut reader)?;
+                if v > header.max_value {
+                    return Err(PnmError::InvalidSample);
+                }
+                samples.push(v as u16);
+            }
+        }
+        _ => {
+            let wide = header.max_value > 255;
+            for _ in 0..sample_count {
+                let v = if wide {
+                    reader.read::<u16>()
+                } else {
+                    reader.read::<u8>() as u16
+                };
+                if v as u32 > header.max_value {
+                    return Err(PnmError::InvalidSample);
+                }
+                samples.push(v);
+            }
+        }
+    }
+
+    let max = header.max_value;
+    let scale = |v: u16| ((v as u32 * 255 + max / 2) / max) as u8;
+    let data = samples
+        .chunks_exact(channels)
+        .map(|s| match header.layout {
+            Layout::BlackAndWhite => {
+                let v = if s[0] == 1 { 0 } else { 255 };
+                Rgba {
+                    r: v,
+                    g: v,
+                    b: v,
+                    a: 255,
+                }
+            }
+            Layout::Gray | Layout::GrayAlpha => {
+                let v = scale(s[0]);
+                Rgba {
+                    r: v,
+                    g: v,
+                    b: v,
+                    a: s.get(1).map_or(255, |&a| scale(a)),
+                }
+            }
+            Layout::Rgb | Layout::Rgba => Rgba {
+                r: scale(s[0]),
+                g: scale(s[1]),
+                b: scale(s[2]),
+                a: s.get(3).map_or(255, |&a| scale(a)),
+            },
+        })
+        .collect();
+
+    Ok(RgbaImage::from_vec(header.width, header.height, data))
+}
+
+/// Encode the image in the given format with 8 bit samples. Formats without alpha drop it,
+/// pbm and pgm store the luma of the color. Pixels darker than half the range are black in pbm.
+pub fn encode(img: &RgbaImage, out: &mut impl Write, format: PnmFormat) -> io::Result<()> {
+    let (width, height) = img.dimensions();
+    if width == 0 || height == 0 {
+        let err = PnmError::InvalidDimensions {
+            width: width as u32,
+            height: height as u32,
+        };
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, err.to_string()));
+    }
+
+    let luma = |p: &Rgba| ((p.r as u32 * 77 + p.g as u32 * 150 + p.b as u32 * 29) >> 8) as u8;
+    let black = |p: &Rgba| luma(p) < 128;
+    let mut buf = Vec::new();
+
+    let [m1, m2] = format.magic();
+    match format {
+        PnmFormat::Pam => {
+            write!(
+                buf,
+                "P7\nWIDTH {width}\nHEIGHT {height}\nDEPTH 4\nMAXVAL 255\nTUPLTYPE RGB_ALPHA\nENDHDR\n"
+            )?;
+        }
+        PnmFormat::PbmAscii | PnmFormat::Pbm => {
+            writeln!(buf, "{}{}\n{width} {height}", m1 as char, m2 as char)?
+        }
+        _ => writeln!(buf, "{}{}\n{width} {height}\n255", m1 as char, m2 as char)?,
+    }
+
+    for row in img.data.chunks_exact(width) {
+        match format {
+            PnmFormat::PbmAscii => {
+                let line: Vec<u8> = row.iter().map(|p| b'0' + black(p) as u8).collect();
+                // lines should not be longer than 70 characters
+                for part in line.chunks(70) {
+                    buf.extend_from_slice(part);
+                    buf.push(b'\n');
+                }
+            }
+            PnmFormat::PgmAscii | PnmFormat::PpmAscii => {
+                let samples: Vec<u8> = if format == PnmFormat::PgmAscii {
+                    row.iter().map(luma).collect()
+                } else {
+                    row.iter().flat_map(|p| [p.r, p.g, p.b]).collect()
+                };
+                // 17 samples with 3 digits and a space fit into 70 characters
+                for part in samples.chunks(17) {
+                    let line: Vec<String> = part.iter().map(|s| s.to_string()).collect();
+                    writeln!(buf, "{}", line.join(" "))?;
+                }
+            }
+            PnmFormat::Pbm => {
+                let mut bytes = vec![0u8; width.div_ceil(8)];
+                for (x, p) in row.iter().enumerate() {
+                    bytes[x / 8] |= (black(p) as u8) << (7 - x % 8);
+                }
+                buf.extend(bytes);
+            }
+            PnmFormat::Pgm => buf.extend(row.iter().map(luma)),
+            PnmFormat::Ppm => buf.extend(row.iter().flat_map(|p| [p.r, p.g, p.b])),
+            PnmFormat::Pam => buf.extend(row.iter().flat_map(|p| [p.r, p.g, p.b, p.a])),
+        }
+        out.write_all(&buf)?;
+        buf.clear();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn gray(v: u8) -> Rgba {
+        Rgba {
+            r: v,
+            g: v,
+            b: v,
+            a: 255,
+        }
+    }
+
+    fn test_image() -> RgbaImage {
+        let mut img = RgbaImage::new(23, 5, Rgba::BLACK);
+        for y in 0..5 {
+            for x in 0..23 {
+                let pixel = Rgba {
+                    r: (x * 11) as u8,
+                    g: (y * 60) as u8,
+                    b: (x * y) as u8,
+                    a: (x * 10 + y) as u8,
+                };
+                img.put_pixel(x, y, pixel);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn roundtrip() {
+        let img = test_image();
+        let opaque = RgbaImage::from_vec(
+            23,
+            5,
+            img.data.iter().map(|p| Rgba { a: 255, ..*p }).collect(),
+        );
+
+        for format in [PnmFormat::Pam, PnmFormat::Ppm, PnmFormat::PpmAscii] {
+            let mut data = Vec::new();
+            encode(&img, &mut data, format).unwrap();
+            let expected = if format == PnmFormat::Pam {
+                &img
+            } else {
+                &opaque
+            };
+            assert_eq!(decode(&data).as_ref(), Ok(expected), "{format:?}");
+        }
+
+        let grays = RgbaImage::from_vec(4, 1, vec![gray(0), gray(1), gray(128), gray(255)]);
+        for format in [PnmFormat::Pgm, PnmFormat::PgmAscii] {
+            let mut data = Vec::new();
+            encode(&grays, &mut data, format).unwrap();
+            assert_eq!(decode(&data).as_ref(), Ok(&grays), "{format:?}");
+        }
+
+        let bits = RgbaImage::from_vec(
+            10,
+            2,
+            (0..20)
+                .map(|i| gray(if i % 3 == 0 { 0 } else { 255 }))
+                .collect(),
+        );
+        for format in [PnmFormat::Pbm, PnmFormat::PbmAscii] {
+            let mut data = Vec::new();
+            encode(&bits, &mut data, format).unwrap();
+            assert_eq!(decode(&data).as_ref(), Ok(&bits), "{format:?}");
+        }
+    }
+
+    #[test]
+    fn text_formats() {
+        // comments, unseparated bits and any whitespace
+        let pbm = b"P1 # a comment\n3 2\n010\n1 1\t0";
+        let img = decode(pbm).unwrap();
+        assert_eq!(
+            img.data,
+            [gray(255), gray(0), gray(255), gray(0), gray(0), gray(255)]
+        );
+
+        let pgm = b"P2\n2 1\n# comment\n4\n0 2\n";
+        assert_eq!(decode(pgm).unwrap().data, [gray(0), gray(128)]);
+
+        let ppm = b"P3 1 1 65535 65535 0 32768";
+        assert_eq!(
+            decode(ppm).unwrap().data,
+            [Rgba {
+                r: 255,
+                g: 0,
+                b: 128,
+                a: 255
+            }]
+        );
+    }
+
+    #[test]
+    fn binary_formats() {
+        // 16 bit samples are big endian
+        let mut ppm = b"P6 1 1 1000\n".to_vec();
+        ppm.extend([0x03, 0xe8, 0x00, 0x00, 0x01, 0xf4]);
+        assert_eq!(
+            decode(&ppm).unwrap().data,
+            [Rgba {
+                r: 255,
+                g: 0,
+                b: 128,
+                a: 255
+            }]
+        );
+
+        let mut pbm = b"P4\n9 1\n".to_vec();
+        pbm.extend([0b1000_0000, 0b1000_0000]);
+        let img = decode(&pbm).unwrap();
+        assert_eq!(img.data[0], gray(0));
+        assert_eq!(img.data[1..8], [gray(255); 7]);
+        assert_eq!(img.data[8], gray(0));
+
+        let mut pam = b"P7\nWIDTH 2\nHEIGHT 1\nDEPTH 2\nMAXVAL 3\n# comment\nTUPLTYPE GRAYSCALE_ALPHA\nENDHDR\n".to_vec();
+        pam.extend([3, 0, 0, 3]);
+        let img = decode(&pam).unwrap();
+        assert_eq!(
+            img.data,
+            [
+                Rgba {
+                    r: 255,
+                    g: 255,
+                    b: 255,
+                    a: 0
+                },
+                gray(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(decode(b"P8 1 1 255 0"), Err(PnmError::InvalidMagic));
+        assert_eq!(decode(b"P"), Err(PnmError::InvalidMagic));
+        assert_eq!(decode(b"P5 1"), Err(PnmError::UnexpectedEof));
+        assert_eq!(decode(b"P5 a 1 255 0"), Err(PnmError::InvalidNumber));
+        assert_eq!(
+            decode(b"P5 0 1 255 "),
+            Err(PnmError::InvalidDimensions {
+                width: 0,
+                height: 1
+            })
+        );
+        assert_eq!(
+            decode(b"P5 1 1 70000 ab"),
+            Err(PnmError::InvalidMaxValue(70000))
+        );
+        assert_eq!(decode(b"P5 1 1 99999999999"), Err(PnmError::InvalidNumber));
+        assert_eq!(decode(b"P2 1 1 5 6"), Err(PnmError::InvalidSample));
+        assert_eq!(decode(b"P5 1 1 5 \x06"), Err(PnmError::InvalidSample));
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nENDHDR\n\0"),
+            Err(PnmError::InvalidHeader)
+        );
+        assert_eq!(
+            decode(b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 5\nMAXVAL 1\nENDHDR\n\0\0\0\0\0"),
+            Err(PnmError::UnsupportedTupleType)
+        );
+        // a small file claiming a huge image
+        assert_eq!(
+            decode(b"P6 20000 20000 255\n\0\0\0"),
+            Err(PnmError::UnexpectedEof)
+        );
+
+        let mut valid = Vec::new();
+        encode(&test_image(), &mut valid, PnmFormat::Pam).unwrap();
+        for len in 0..valid.len() {
+            assert!(decode(&valid[..len]).is_err(), "truncated to {len} bytes");
+        }
+    }
+}
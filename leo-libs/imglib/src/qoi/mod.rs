@@ -1,2 +1,527 @@
+//! The QOI image format: https://qoiformat.org/qoi-specification.pdf
+
+use std::{fmt::Display, io::Write};
+
+use corelib::reader::{ByteReader, Reader};
+use mathlib::color::{linear_to_srgb8, srgb8_to_linear};
+
+use crate::{Rgba, RgbaImage};
+
 pub mod reader;
 pub mod writer;
+
+/// `QoiHeader::colorspace` for sRGB color channels with a linear alpha channel
+pub const COLORSPACE_SRGB: u8 = 0;
+/// `QoiHeader::colorspace` for all channels being linear
+pub const COLORSPACE_LINEAR: u8 = 1;
+
+pub const MAGIC: [u8; 4] = *b"qoif";
+pub const HEADER_SIZE: usize = 14;
+pub const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+/// The same limit as the reference implementation, protects against absurd allocations
+pub const MAX_PIXELS: u64 = 400_000_000;
+
+pub(crate) const OP_RGB: u8 = 0b11111110;
+pub(crate) const OP_RGBA: u8 = 0b11111111;
+pub(crate) const OP_INDEX: u8 = 0b00;
+pub(crate) const OP_DIFF: u8 = 0b01;
+pub(crate) const OP_LUMA: u8 = 0b10;
+pub(crate) const OP_RUN: u8 = 0b11;
+
+/// the longest run a single chunk can encode
+pub(crate) const MAX_RUN: usize = 62;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QoiHeader {
+    pub width: u32,   // image width in pixels (BE)
+    pub height: u32,  // image height in pixels (BE)
+    pub channels: u8, // 3 = RGB, 4 = RGBA
+    pub colorspace: u8, // 0 = sRGB with linear alpha
+                      // 1 = all channels linear
+}
+
+impl QoiHeader {
+    pub fn parse(reader: &mut Reader<impl ByteReader>) -> Result<Self, QoiError> {
+        if reader.remaining() < HEADER_SIZE {
+            return Err(QoiError::UnexpectedEof);
+        }
+
+        let magic: [u8; 4] = reader.read();
+        if magic != MAGIC {
+            return Err(QoiError::InvalidMagic(magic));
+        }
+
+        let header = QoiHeader {
+            width: reader.read(),
+            height: reader.read(),
+            channels: reader.read(),
+            colorspace: reader.read(),
+        };
+        header.validate()?;
+
+        Ok(header)
+    }
+
+    pub fn validate(&self) -> Result<(), QoiError> {
+        if self.channels != 3 && self.channels != 4 {
+            return Err(QoiError::InvalidChannels(self.channels));
+        }
+        if self.colorspace != COLORSPACE_SRGB && self.colorspace != COLORSPACE_LINEAR {
+            return Err(QoiError::InvalidColorspace(self.colorspace));
+        }
+        if self.width == 0 || self.height == 0 || self.pixel_count() as u64 > MAX_PIXELS {
+            return Err(QoiError::InvalidDimensions {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut res = [0; HEADER_SIZE];
+        res[0..4].copy_from_slice(&MAGIC);
+        res[4..8].copy_from_slice(&self.width.to_be_bytes());
+        res[8..12].copy_from_slice(&self.height.to_be_bytes());
+        res[12] = self.channels;
+        res[13] = self.colorspace;
+        res
+    }
+}
+
+#[derive(Debug)]
+pub enum QoiError {
+    InvalidMagic([u8; 4]),
+    InvalidChannels(u8),
+    InvalidColorspace(u8),
+    /// the image is empty or has more than [`MAX_PIXELS`] pixels
+    InvalidDimensions {
+        width: u32,
+        height: u32,
+    },
+    /// the data ended in the middle of the image
+    UnexpectedEof,
+    /// the pixel data is not followed by the end marker
+    MissingEndMarker,
+    /// the amount of pixels given to the encoder or the size of a row buffer does not match
+    /// the header
+    PixelCountMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    Io(std::io::Error),
+}
+
+impl Display for QoiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidMagic(magic) => write!(f, "not a qoi file (magic {magic:?})"),
+            Self::InvalidChannels(c) => write!(f, "invalid channel count {c}"),
+            Self::InvalidColorspace(c) => write!(f, "invalid colorspace {c}"),
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "invalid image dimensions {width}x{height}")
+            }
+            Self::UnexpectedEof => write!(f, "unexpected end of data"),
+            Self::MissingEndMarker => write!(f, "missing end marker"),
+            Self::PixelCountMismatch { expected, actual } => {
+                write!(f, "expected {expected} pixels but got {actual}")
+            }
+            Self::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl From<std::io::Error> for QoiError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<QoiError> for std::io::Error {
+    fn from(e: QoiError) -> Self {
+        match e {
+            QoiError::Io(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()),
+        }
+    }
+}
+
+#[inline]
+pub(crate) fn index_position(pix: Rgba) -> usize {
+    (pix.r as usize * 3 + pix.g as usize * 5 + pix.b as usize * 7 + pix.a as usize * 11) % 64
+}
+
+/// Decode a qoi file. The returned pixels are always sRGB encoded.
+pub fn decode(data: &[u8]) -> Result<RgbaImage, QoiError> {
+    let mut reader = Reader::new_big_endian(data, 0);
+    let (header, pixels) = reader::QoiReader::new(&mut reader)?.read_entire_image()?;
+    Ok(RgbaImage::from_vec(
+        header.width as usize,
+        header.height as usize,
+        pixels,
+    ))
+}
+
+/// Encode an sRGB image with all four channels.
+pub fn encode(img: &RgbaImage, out: &mut impl Write) -> Result<(), QoiError> {
+    let header = QoiHeader {
+        width: img.width as u32,
+        height: img.height as u32,
+        channels: 4,
+        colorspace: COLORSPACE_SRGB,
+    };
+    writer::QoiWriter::new(header, &img.data, out).write()
+}
+
+/// encode a pixel with linear color channels to sRGB
+pub fn linear_to_srgb_pixel(pix: Rgba) -> Rgba {
+    let encode = |v: u8| linear_to_srgb8(v as f32 / 255.0);
+    Rgba {
+        r: encode(pix.r),
+        g: encode(pix.g),
+        b: encode(pix.b),
+        a: pix.a,
+    }
+}
+
+/// decode a pixel with sRGB color channels to linear values
+pub fn srgb_to_linear_pixel(pix: Rgba) -> Rgba {
+    let decode = |v: u8| (srgb8_to_linear(v) * 255.0).round() as u8;
+    Rgba {
+        r: decode(pix.r),
+        g: decode(pix.g),
+        b: decode(pix.b),
+        a: pix.a,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{
+        reader::QoiReader,
+        writer::{QoiEncoder, QoiWriter},
+        *,
+    };
+
+    fn pix(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba { r, g, b, a }
+    }
+
+    fn file(width: u32, height: u32, channels: u8, chunks: &[u8]) -> Vec<u8> {
+        let header = QoiHeader {
+            width,
+            height,
+            channels,
+            colorspace: COLORSPACE_SRGB,
+        };
+        let mut res = header.to_bytes().to_vec();
+        res.extend_from_slice(chunks);
+        res.extend_from_slice(&END_MARKER);
+        res
+    }
+
+    fn encode_with(header: QoiHeader, pixels: &[Rgba]) -> Vec<u8> {
+        let mut out = Vec::new();
+        QoiWriter::new(header, pixels, &mut out).write().unwrap();
+        out
+    }
+
+    /// an image that exercises every chunk type
+    fn test_image() -> RgbaImage {
+        let mut img = RgbaImage::new(37, 23, Rgba::BLACK);
+        for y in 0..img.height {
+            for x in 0..img.width {
+                let p = match (x / 8 + y / 5) % 4 {
+                    0 => pix(x as u8 * 7, y as u8 * 11, 40, 255),
+                    1 => pix(x as u8, y as u8, (x + y) as u8, 255),
+                    2 => pix(200, 10, 10, (x * 30) as u8),
+                    _ => pix(5, 5, 5, 255),
+                };
+                img.put_pixel(x, y, p);
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn decode_every_chunk_type() {
+        let data = file(
+            8,
+            1,
+            4,
+            &[
+                OP_RGB,
+                10,
+                20,
+                30,
+                OP_RGBA,
+                1,
+                2,
+                3,
+                4,
+                // r + 1, g + 0, b - 1
+                OP_DIFF << 6 | 0b11_10_01,
+                // g + 5, r - g = -1, b - g = 1
+                OP_LUMA << 6 | (32 + 5),
+                0x79,
+                OP_RUN << 6 | 1,
+                OP_INDEX << 6 | index_position(pix(10, 20, 30, 255)) as u8,
+                OP_RUN << 6,
+            ],
+        );
+
+        let img = decode(&data).unwrap();
+        assert_eq!(
+            img.data,
+            [
+                pix(10, 20, 30, 255),
+                pix(1, 2, 3, 4),
+                pix(2, 2, 2, 4),
+                pix(6, 7, 8, 4),
+                pix(6, 7, 8, 4),
+                pix(6, 7, 8, 4),
+                pix(10, 20, 30, 255),
+                pix(10, 20, 30, 255),
+            ]
+        );
+    }
+
+    #[test]
+    fn first_chunk_run_registers_pixel() {
+        // the implicit previous pixel only ends up in the index through the run
+        let black = pix(0, 0, 0, 255);
+        let data = file(
+            3,
+            1,
+            4,
+            &[
+                OP_RUN << 6,
+                OP_RGB,
+                9,
+                9,
+                9,
+                OP_INDEX << 6 | index_position(black) as u8,
+            ],
+        );
+        assert_eq!(
+            decode(&data).unwrap().data,
+            [black, pix(9, 9, 9, 255), black]
+        );
+    }
+
+    #[test]
+    fn roundtrip() {
+        let img = test_image();
+        let mut out = Vec::new();
+        encode(&img, &mut out).unwrap();
+        assert_eq!(decode(&out).unwrap(), img);
+    }
+
+    #[test]
+    fn roundtrip_rgb() {
+        let img = test_image();
+        let header = QoiHeader {
+            width: img.width as u32,
+            height: img.height as u32,
+            channels: 3,
+            colorspace: COLORSPACE_SRGB,
+        };
+        let decoded = decode(&encode_with(header, &img.data)).unwrap();
+
+        for (a, b) in img.data.iter().zip(&decoded.data) {
+            assert_eq!(*b, pix(a.r, a.g, a.b, 255));
+        }
+    }
+
+    #[test]
+    fn long_runs_are_split() {
+        let img = RgbaImage::new(200, 1, pix(1, 2, 3, 255));
+        let mut out = Vec::new();
+        encode(&img, &mut out).unwrap();
+        assert_eq!(decode(&out).unwrap(), img);
+    }
+
+    #[test]
+    fn large_channel_differences() {
+        // r - g does not fit into an i8
+        let img = RgbaImage::from_vec(2, 1, vec![pix(0, 0, 0, 255), pix(128, 20, 0, 255)]);
+        let mut out = Vec::new();
+        encode(&img, &mut out).unwrap();
+        assert_eq!(decode(&out).unwrap(), img);
+    }
+
+    #[test]
+    fn errors() {
+        let valid = file(1, 1, 4, &[OP_RGB, 1, 2, 3]);
+        assert!(decode(&valid).is_ok());
+
+        let mut magic = valid.clone();
+        magic[0] = b'x';
+        assert!(matches!(decode(&magic), Err(QoiError::InvalidMagic(_))));
+
+        let mut channels = valid.clone();
+        channels[12] = 2;
+        assert!(matches!(
+            decode(&channels),
+            Err(QoiError::InvalidChannels(2))
+        ));
+
+        let mut colorspace = valid.clone();
+        colorspace[13] = 7;
+        assert!(matches!(
+            decode(&colorspace),
+            Err(QoiError::InvalidColorspace(7))
+        ));
+
+        let mut end = valid.clone();
+        *end.last_mut().unwrap() = 0;
+        assert!(matches!(decode(&end), Err(QoiError::MissingEndMarker)));
+
+        for len in 0..valid.len() - END_MARKER.len() {
+            assert!(decode(&valid[..len]).is_err(), "truncated to {len} bytes");
+        }
+
+        let huge = file(u32::MAX, u32::MAX, 4, &[]);
+        assert!(matches!(
+            decode(&huge),
+            Err(QoiError::InvalidDimensions { .. })
+        ));
+
+        // a header promising far more pixels than the data could contain
+        let big = file(10_000, 10_000, 4, &[OP_RUN << 6 | 61]);
+        assert!(matches!(decode(&big), Err(QoiError::UnexpectedEof)));
+
+        let header = QoiHeader {
+            width: 2,
+            height: 2,
+            channels: 4,
+            colorspace: COLORSPACE_SRGB,
+        };
+        let mut out = Vec::new();
+        assert!(matches!(
+            QoiWriter::new(header, &[Rgba::BLACK], &mut out).write(),
+            Err(QoiError::PixelCountMismatch {
+                expected: 4,
+                actual: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn stream_rows() {
+        let img = test_image();
+        let mut data = Vec::new();
+        encode(&img, &mut data).unwrap();
+
+        let mut reader = Reader::new_big_endian(&data, 0);
+        let mut qoi = QoiReader::new(&mut reader).unwrap();
+        let mut row = vec![Rgba::BLACK; img.width];
+        let mut y = 0;
+        while qoi.next_row(&mut row).unwrap() {
+            assert_eq!(row, img.data[y * img.width..(y + 1) * img.width]);
+            y += 1;
+        }
+        assert_eq!(y, img.height);
+        assert_eq!(qoi.rows_left(), 0);
+
+        let mut too_short = vec![Rgba::BLACK; img.width - 1];
+        let mut reader = Reader::new_big_endian(&data, 0);
+        let mut qoi = QoiReader::new(&mut reader).unwrap();
+        assert!(matches!(
+            qoi.next_row(&mut too_short),
+            Err(QoiError::PixelCountMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn push_encoder() {
+        let img = test_image();
+        let header = QoiHeader {
+            width: img.width as u32,
+            height: img.height as u32,
+            channels: 4,
+            colorspace: COLORSPACE_LINEAR,
+        };
+        let expected = encode_with(header, &img.data);
+
+        // runs crossing the pieces have to be continued
+        let mut out = Vec::new();
+        let mut encoder = QoiEncoder::new(header, &mut out).unwrap();
+        for row in img.data.chunks(img.width) {
+            encoder.push_row(row).unwrap();
+        }
+        encoder.finish().unwrap();
+        assert!(out == expected);
+
+        let mut out = Vec::new();
+        let mut encoder = QoiEncoder::new(header, &mut out).unwrap();
+        for piece in img.data.chunks(7) {
+            encoder.push_pixels(piece).unwrap();
+        }
+        encoder.finish().unwrap();
+        assert!(out == expected);
+
+        let mut out = Vec::new();
+        let mut encoder = QoiEncoder::new(header, &mut out).unwrap();
+        encoder.push_pixels(&img.data[..10]).unwrap();
+        assert!(matches!(
+            encoder.finish(),
+            Err(QoiError::PixelCountMismatch { .. })
+        ));
+
+        let mut out = Vec::new();
+        let mut encoder = QoiEncoder::new(header, &mut out).unwrap();
+        encoder.push_pixels(&img.data).unwrap();
+        assert!(encoder.push_pixels(&img.data[..1]).is_err());
+    }
+
+    /// The reference images in `test-data/qoi_test_images`, written by the reference
+    /// encoder with `make_reference.c` there. Each decodes to the pixels of the PNG with its
+    /// name, and as the reference encoder is deterministic, re-encoding has to reproduce the
+    /// file exactly.
+    #[test]
+    fn reference_images() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test-data/qoi_test_images");
+        let entries = std::fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("qoi reference images in {}: {e}", dir.display()));
+
+        let mut count = 0;
+        for entry in entries {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|e| e != "qoi") {
+                continue;
+            }
+            let data = std::fs::read(&path).unwrap();
+            let png = std::fs::read(path.with_extension("png"))
+                .unwrap_or_else(|e| panic!("expected output of {}: {e}", path.display()));
+
+            let mut reader = Reader::new_big_endian(&data, 0);
+            let qoi = QoiReader::new(&mut reader).unwrap();
+            let header = *qoi.header();
+            let pixels = qoi.read_raw_image().unwrap();
+            let expected = crate::load(&png).unwrap();
+            assert_eq!(
+                (header.width as usize, header.height as usize),
+                expected.dimensions(),
+                "{}",
+                path.display()
+            );
+            assert!(pixels == expected.data, "{} decodes wrong", path.display());
+
+            let mut out = Vec::new();
+            QoiWriter::new_raw(header, &pixels, &mut out)
+                .write()
+                .unwrap();
+            assert!(out == data, "{} does not roundtrip", path.display());
+            count += 1;
+        }
+        assert!(count > 0, "no qoi reference images in {}", dir.display());
+    }
+}
@@ -2,22 +2,35 @@ use corelib::reader::{BigEndianReader, Reader};
 
 use crate::Rgba;
 
-use super::writer::QoiHeader;
+use super::{
+    COLORSPACE_LINEAR, END_MARKER, MAX_RUN, OP_DIFF, OP_INDEX, OP_LUMA, OP_RGB, OP_RGBA, QoiError,
+    QoiHeader, index_position, linear_to_srgb_pixel,
+};
 
 pub struct QoiReader<'data> {
     reader: &'data mut Reader<'data, BigEndianReader>,
     header: QoiHeader,
     pix_arr: [Rgba; 64],
-    pub result: Vec<Rgba>,
     previous_pixel: Rgba,
+    /// how many more times the previous pixel is repeated
+    run: usize,
+    rows_left: usize,
 }
 
 impl<'data> QoiReader<'data> {
-    pub fn new(reader: &'data mut Reader<'data, BigEndianReader>) -> Self {
-        let header: QoiHeader = reader.read();
-        Self {
+    /// Read and validate the header.
+    pub fn new(reader: &'data mut Reader<'data, BigEndianReader>) -> Result<Self, QoiError> {
+        let header = QoiHeader::parse(reader)?;
+
+        // every chunk byte produces at most `MAX_RUN` pixels, so a file this short can never
+        // contain the image. Checking this up front avoids huge allocations for broken files.
+        let max_pixels = reader.remaining().saturating_sub(END_MARKER.len()) * MAX_RUN;
+        if header.pixel_count() > max_pixels {
+            return Err(QoiError::UnexpectedEof);
+        }
+
+        Ok(Self {
             reader,
-            result: Vec::with_capacity((header.height * header.width) as usize),
             header,
             pix_arr: [Rgba {
                 r: 0,
@@ -31,73 +44,132 @@ impl<'data> QoiReader<'data> {
                 b: 0,
                 a: 255,
             },
+            run: 0,
+            rows_left: header.height as usize,
+        })
+    }
+
+    pub fn header(&self) -> &QoiHeader {
+        &self.header
+    }
+
+    /// Decode the whole image. The returned pixels are always sRGB encoded,
+    /// images stored with linear channels are converted.
+    pub fn read_entire_image(self) -> Result<(QoiHeader, Vec<Rgba>), QoiError> {
+        let header = self.header;
+        let mut result = self.read_raw_image()?;
+
+        if header.colorspace == COLORSPACE_LINEAR {
+            result
+                .iter_mut()
+                .for_each(|p| *p = linear_to_srgb_pixel(*p));
         }
+
+        Ok((header, result))
     }
 
-    pub fn read_entire_image(mut self) -> (QoiHeader, Vec<Rgba>) {
-        let pic_size = self.header.width * self.header.height;
+    /// Decode the whole image without any colorspace conversion and check the end marker.
+    pub fn read_raw_image(mut self) -> Result<Vec<Rgba>, QoiError> {
+        let mut result = vec![Rgba::BLACK; self.header.pixel_count()];
 
-        while self.result.len() < pic_size as usize {
-            self.read_chunk();
+        for row in result.chunks_mut(self.header.width as usize) {
+            self.next_raw_row(row)?;
         }
 
-        (self.header, self.result)
+        Ok(result)
+    }
+
+    /// The amount of rows that were not yet decoded
+    pub fn rows_left(&self) -> usize {
+        self.rows_left
     }
 
-    fn reg_new_pix(&mut self, pix: Rgba) {
-        let index_position =
-            (pix.r as usize * 3 + pix.g as usize * 5 + pix.b as usize * 7 + pix.a as usize * 11)
-                % 64;
+    /// Decode the next row into `row`, which has to be exactly as wide as the image. Like
+    /// [`Self::read_entire_image`] the pixels are always sRGB encoded.
+    ///
+    /// Returns `false` once all rows are read. The end marker is checked after the last row.
+    pub fn next_row(&mut self, row: &mut [Rgba]) -> Result<bool, QoiError> {
+        let res = self.next_raw_row(row)?;
 
-        self.pix_arr[index_position] = pix;
+        if res && self.header.colorspace == COLORSPACE_LINEAR {
+            row.iter_mut().for_each(|p| *p = linear_to_srgb_pixel(*p));
+        }
+
+        Ok(res)
     }
 
-    pub fn read_chunk(&mut self) {
-        let first_byte = self.reader.read_byte();
+    /// [`Self::next_row`] without any colorspace conversion
+    pub fn next_raw_row(&mut self, row: &mut [Rgba]) -> Result<bool, QoiError> {
+        if self.rows_left == 0 {
+            return Ok(false);
+        }
+        if row.len() != self.header.width as usize {
+            return Err(QoiError::PixelCountMismatch {
+                expected: self.header.width as usize,
+                actual: row.len(),
+            });
+        }
 
-        match first_byte {
-            0b11111110 => {
-                let red = self.reader.read_byte();
-                let green = self.reader.read_byte();
-                let blue = self.reader.read_byte();
+        for pix in row.iter_mut() {
+            *pix = self.next_pixel()?;
+        }
 
-                self.previous_pixel = Rgba {
-                    r: red,
-                    g: green,
-                    b: blue,
-                    a: self.previous_pixel.a,
-                };
+        self.rows_left -= 1;
+        if self.rows_left == 0 {
+            self.read_end_marker()?;
+        }
 
-                self.reg_new_pix(self.previous_pixel);
-                self.result.push(self.previous_pixel);
-            }
-            0b11111111 => {
-                let red = self.reader.read_byte();
-                let green = self.reader.read_byte();
-                let blue = self.reader.read_byte();
-                let alpha = self.reader.read_byte();
+        Ok(true)
+    }
+
+    fn read_end_marker(&mut self) -> Result<(), QoiError> {
+        if self.reader.remaining() < END_MARKER.len() {
+            return Err(QoiError::MissingEndMarker);
+        }
+        let marker: [u8; 8] = self.reader.read();
+        if marker != END_MARKER {
+            return Err(QoiError::MissingEndMarker);
+        }
+        Ok(())
+    }
+
+    fn read_bytes<const N: usize>(&mut self) -> Result<[u8; N], QoiError> {
+        if self.reader.remaining() < N {
+            return Err(QoiError::UnexpectedEof);
+        }
+        Ok(self.reader.read())
+    }
+
+    fn next_pixel(&mut self) -> Result<Rgba, QoiError> {
+        if self.run > 0 {
+            self.run -= 1;
+            return Ok(self.previous_pixel);
+        }
+
+        let [first_byte] = self.read_bytes()?;
 
+        match first_byte {
+            OP_RGB => {
+                let [r, g, b] = self.read_bytes()?;
                 self.previous_pixel = Rgba {
-                    r: red,
-                    g: green,
-                    b: blue,
-                    a: alpha,
+                    r,
+                    g,
+                    b,
+                    a: self.previous_pixel.a,
                 };
-                self.reg_new_pix(self.previous_pixel);
-                self.result.push(self.previous_pixel);
+            }
+            OP_RGBA => {
+                let [r, g, b, a] = self.read_bytes()?;
+                self.previous_pixel = Rgba { r, g, b, a };
             }
             _ => match first_byte >> 6 {
-                0b00 => {
-                    let index = first_byte & 0b111111;
-
-                    self.previous_pixel = self.pix_arr[index as usize];
-                    self.reg_new_pix(self.previous_pixel);
-                    self.result.push(self.previous_pixel);
+                OP_INDEX => {
+                    self.previous_pixel = self.pix_arr[(first_byte & 0b111111) as usize];
                 }
-                0b01 => {
+                OP_DIFF => {
                     let new_r = (first_byte >> 4) & 0b11;
                     let new_g = (first_byte >> 2) & 0b11;
-                    let new_b = (first_byte >> 0) & 0b11;
+                    let new_b = first_byte & 0b11;
 
                     self.previous_pixel.r =
                         (self.previous_pixel.r.wrapping_add(new_r)).wrapping_sub(2);
@@ -105,16 +177,13 @@ impl<'data> QoiReader<'data> {
                         (self.previous_pixel.g.wrapping_add(new_g)).wrapping_sub(2);
                     self.previous_pixel.b =
                         (self.previous_pixel.b.wrapping_add(new_b)).wrapping_sub(2);
-
-                    self.reg_new_pix(self.previous_pixel);
-                    self.result.push(self.previous_pixel);
                 }
-                0b10 => {
-                    let next_byte = self.reader.read_byte();
+                OP_LUMA => {
+                    let [next_byte] = self.read_bytes()?;
                     let diff_green = (first_byte & 0b111111).wrapping_sub(32);
 
                     let dr_dg = (next_byte >> 4) & 0b1111;
-                    let db_dg = (next_byte >> 0) & 0b1111;
+                    let db_dg = next_byte & 0b1111;
 
                     let diff_red = diff_green.wrapping_add(dr_dg).wrapping_sub(8);
                     let diff_blue = diff_green.wrapping_add(db_dg).wrapping_sub(8);
@@ -122,25 +191,18 @@ impl<'data> QoiReader<'data> {
                     self.previous_pixel.r = self.previous_pixel.r.wrapping_add(diff_red);
                     self.previous_pixel.g = self.previous_pixel.g.wrapping_add(diff_green);
                     self.previous_pixel.b = self.previous_pixel.b.wrapping_add(diff_blue);
-
-                    self.reg_new_pix(self.previous_pixel);
-                    self.result.push(self.previous_pixel);
                 }
-                0b11 => {
-                    let run_length = first_byte & 0b111111;
-                    assert!(run_length != 0b111111 && run_length != 0b111110);
-
-                    // This handles the edge case of the first chunk being a run
-                    if self.reader.get_pos() == 15 {
-                        self.reg_new_pix(self.previous_pixel);
-                    }
-
-                    for _ in 0..(run_length + 1) {
-                        self.result.push(self.previous_pixel);
-                    }
+                // OP_RUN, the run lengths 63 and 64 are taken by OP_RGB and OP_RGBA
+                _ => {
+                    self.run = (first_byte & 0b111111) as usize;
                 }
-                _ => panic!("invalid qoi file"),
             },
         }
+
+        // Like the reference decoder every pixel is registered, including runs. This
+        // matters when the image starts with a run of the implicit previous pixel.
+        self.pix_arr[index_position(self.previous_pixel)] = self.previous_pixel;
+
+        Ok(self.previous_pixel)
     }
 }
@@ -1,48 +1,90 @@
 use std::io::Write;
 
-use corelib::reader::{ByteReader, Readable, Reader};
-
 use crate::Rgba;
 
-#[derive(Debug)]
-pub struct QoiHeader {
-    pub width: u32,   // image width in pixels (BE)
-    pub height: u32,  // image height in pixels (BE)
-    pub channels: u8, // 3 = RGB, 4 = RGBA
-    pub colorspace: u8, // 0 = sRGB with linear alpha
-                      // 1 = all channels linear
+use super::{
+    COLORSPACE_LINEAR, END_MARKER, MAX_RUN, OP_DIFF, OP_INDEX, OP_LUMA, OP_RGB, OP_RGBA, OP_RUN,
+    QoiError, QoiHeader, index_position, srgb_to_linear_pixel,
+};
+
+/// Encodes a whole image at once
+pub struct QoiWriter<'out, 'src, W: Write> {
+    out: &'out mut W,
+    image: &'src [Rgba],
+    header: QoiHeader,
+    raw: bool,
 }
 
-impl Readable for QoiHeader {
-    fn read(reader: &mut Reader<impl ByteReader>) -> Self {
-        let magic: [u8; 4] = reader.read();
+impl<'out, 'src, W: Write> QoiWriter<'out, 'src, W> {
+    /// The image is expected to be sRGB encoded. If the header specifies linear
+    /// channels the pixels are converted before writing.
+    pub fn new(header: QoiHeader, image: &'src [Rgba], out: &'out mut W) -> Self {
+        Self {
+            out,
+            image,
+            header,
+            raw: false,
+        }
+    }
 
-        assert_eq!(&magic, b"qoif", "This is not a qoi file.");
+    /// Write the pixels as they are, without any colorspace conversion.
+    pub fn new_raw(header: QoiHeader, image: &'src [Rgba], out: &'out mut W) -> Self {
+        Self {
+            out,
+            image,
+            header,
+            raw: true,
+        }
+    }
 
-        QoiHeader {
-            width: reader.read(),
-            height: reader.read(),
-            channels: reader.read(),
-            colorspace: reader.read(),
+    pub fn write(&mut self) -> Result<(), QoiError> {
+        if self.image.len() != self.header.pixel_count() {
+            return Err(QoiError::PixelCountMismatch {
+                expected: self.header.pixel_count(),
+                actual: self.image.len(),
+            });
         }
+
+        let mut encoder = if self.raw {
+            QoiEncoder::new_raw(self.header, &mut *self.out)?
+        } else {
+            QoiEncoder::new(self.header, &mut *self.out)?
+        };
+        encoder.push_pixels(self.image)?;
+        encoder.finish()
     }
 }
 
-pub struct QoiWriter<'out, 'src, W: Write> {
+/// A push style encoder: the pixels can be handed over in pieces, for example row by row,
+/// and are written out directly. Only a constant amount of memory is used.
+pub struct QoiEncoder<'out, W: Write> {
     out: &'out mut W,
-    image: &'src [Rgba],
     header: QoiHeader,
+    to_linear: bool,
     pix_arr: [Rgba; 64],
     previous_pixel: Rgba,
-    index: usize,
+    run: usize,
+    pixels_written: usize,
 }
 
-impl<'out, 'src, W: Write> QoiWriter<'out, 'src, W> {
-    pub fn new(header: QoiHeader, image: &'src [Rgba], out: &'out mut W) -> Self {
-        Self {
+impl<'out, W: Write> QoiEncoder<'out, W> {
+    /// Validate and write the header. The pixels are expected to be sRGB encoded and
+    /// are converted if the header specifies linear channels.
+    pub fn new(header: QoiHeader, out: &'out mut W) -> Result<Self, QoiError> {
+        let mut encoder = Self::new_raw(header, out)?;
+        encoder.to_linear = header.colorspace == COLORSPACE_LINEAR;
+        Ok(encoder)
+    }
+
+    /// Like [`Self::new`] but the pixels are written without any colorspace conversion.
+    pub fn new_raw(header: QoiHeader, out: &'out mut W) -> Result<Self, QoiError> {
+        header.validate()?;
+        out.write_all(&header.to_bytes())?;
+
+        Ok(Self {
             out,
-            image,
             header,
+            to_linear: false,
             pix_arr: [Rgba {
                 r: 0,
                 g: 0,
@@ -55,230 +97,139 @@ impl<'out, 'src, W: Write> QoiWriter<'out, 'src, W> {
                 b: 0,
                 a: 255,
             },
-            index: 0,
-        }
+            run: 0,
+            pixels_written: 0,
+        })
     }
 
-    pub fn write(&mut self) {
-        self.out.write_all(b"qoif").unwrap();
-        self.out
-            .write_all(&self.header.width.to_be_bytes())
-            .unwrap();
-        self.out
-            .write_all(&self.header.height.to_be_bytes())
-            .unwrap();
-        self.out
-            .write_all(&self.header.channels.to_be_bytes())
-            .unwrap();
-        self.out
-            .write_all(&self.header.colorspace.to_be_bytes())
-            .unwrap();
-
-        if self.header.channels == 3 {
-            while self.index < self.image.len() {
-                self.write_next_chunk_rgb();
-            }
-        } else if self.header.channels == 4 {
-            while self.index < self.image.len() {
-                self.write_next_chunk_rgba();
-            }
-        } else {
-            panic!("invalid channel amount");
+    /// Encode the next row, which has to be exactly as wide as the image.
+    pub fn push_row(&mut self, row: &[Rgba]) -> Result<(), QoiError> {
+        if row.len() != self.header.width as usize {
+            return Err(QoiError::PixelCountMismatch {
+                expected: self.header.width as usize,
+                actual: row.len(),
+            });
         }
-
-        self.out.write_all(&[0, 0, 0, 0, 0, 0, 0, 0x01]).unwrap();
+        self.push_pixels(row)
     }
 
-    pub fn write_next_chunk_rgb(&mut self) {
-        let current_pix = self.image[self.index];
-        if current_pix == self.previous_pixel {
-            let mut cnt = 0;
+    /// Encode the next pixels in row major order. With three channels the alpha values
+    /// are ignored.
+    pub fn push_pixels(&mut self, pixels: &[Rgba]) -> Result<(), QoiError> {
+        let total = self.header.pixel_count();
+        if self.pixels_written + pixels.len() > total {
+            return Err(QoiError::PixelCountMismatch {
+                expected: total,
+                actual: self.pixels_written + pixels.len(),
+            });
+        }
 
-            while self.index + cnt < self.image.len()
-                && self.image[self.index + cnt] == self.previous_pixel
-                && cnt < 62
-            {
-                cnt += 1;
+        for &pix in pixels {
+            let mut pix = if self.to_linear {
+                srgb_to_linear_pixel(pix)
+            } else {
+                pix
+            };
+            if self.header.channels == 3 {
+                pix.a = 255;
             }
-
-            self.index += cnt;
-            self.out
-                .write_all(&[(0b11 << 6) | (cnt - 1) as u8])
-                .unwrap();
-
-            return;
+            self.push_pixel(pix)?;
         }
+        self.pixels_written += pixels.len();
 
-        if self.color_is_registered(current_pix) {
-            let index_position = (current_pix.r as usize * 3
-                + current_pix.g as usize * 5
-                + current_pix.b as usize * 7
-                + current_pix.a as usize * 11)
-                % 64;
+        Ok(())
+    }
 
-            self.previous_pixel = current_pix;
-            self.out
-                .write_all(&[(0b00 << 6) | index_position as u8])
-                .unwrap();
-            self.index += 1;
-            return;
+    /// Write the end of the image. Fails if fewer pixels were pushed than the header specifies.
+    pub fn finish(mut self) -> Result<(), QoiError> {
+        if self.pixels_written != self.header.pixel_count() {
+            return Err(QoiError::PixelCountMismatch {
+                expected: self.header.pixel_count(),
+                actual: self.pixels_written,
+            });
         }
 
-        if current_pix.a == self.previous_pixel.a {
-            let r_diff = current_pix.r.wrapping_sub(self.previous_pixel.r) as i8;
-            let g_diff = current_pix.g.wrapping_sub(self.previous_pixel.g) as i8;
-            let b_diff = current_pix.b.wrapping_sub(self.previous_pixel.b) as i8;
+        self.flush_run()?;
+        self.out.write_all(&END_MARKER)?;
 
-            if (r_diff <= 1 && r_diff >= -2)
-                && (g_diff <= 1 && g_diff >= -2)
-                && (b_diff <= 1 && b_diff >= -2)
-            {
-                self.previous_pixel = current_pix;
-                self.reg_pixel(self.previous_pixel);
-
-                self.out
-                    .write_all(&[(0b01 << 6)
-                        | (((r_diff + 2) as u8) << 4)
-                        | (((g_diff + 2) as u8) << 2)
-                        | (((b_diff + 2) as u8) << 0)])
-                    .unwrap();
-                self.index += 1;
-                return;
-            }
-
-            if g_diff <= 31 && g_diff >= -32 {
-                let rg_diff = r_diff - g_diff;
-                let bg_diff = b_diff - g_diff;
+        Ok(())
+    }
 
-                if (rg_diff <= 7 && rg_diff >= -8) && (bg_diff <= 7 && bg_diff >= -8) {
-                    self.previous_pixel = current_pix;
-                    self.reg_pixel(self.previous_pixel);
-                    self.out
-                        .write_all(&[
-                            (0b10 << 6) | (g_diff + 32) as u8,
-                            ((rg_diff + 8) << 4) as u8 | ((bg_diff + 8) << 0) as u8,
-                        ])
-                        .unwrap();
-                    self.index += 1;
-                    return;
-                }
-            }
+    fn flush_run(&mut self) -> Result<(), QoiError> {
+        if self.run > 0 {
+            self.out
+                .write_all(&[(OP_RUN << 6) | (self.run - 1) as u8])?;
+            self.run = 0;
         }
-
-        self.previous_pixel = current_pix;
-        self.reg_pixel(self.previous_pixel);
-        self.out
-            .write_all(&[0b11111110, current_pix.r, current_pix.g, current_pix.b])
-            .unwrap();
-
-        self.index += 1;
+        Ok(())
     }
 
-    pub fn write_next_chunk_rgba(&mut self) {
-        let current_pix = self.image[self.index];
+    fn push_pixel(&mut self, current_pix: Rgba) -> Result<(), QoiError> {
         if current_pix == self.previous_pixel {
-            let mut cnt = 0;
-
-            while self.index + cnt < self.image.len()
-                && self.image[self.index + cnt] == self.previous_pixel
-                && cnt < 62
-            {
-                cnt += 1;
+            self.run += 1;
+            if self.run == MAX_RUN {
+                self.flush_run()?;
             }
-
-            self.index += cnt;
-            self.out
-                .write_all(&[(0b11 << 6) | (cnt - 1) as u8])
-                .unwrap();
-
-            return;
+            return Ok(());
         }
 
-        if self.color_is_registered(current_pix) {
-            let index_position = (current_pix.r as usize * 3
-                + current_pix.g as usize * 5
-                + current_pix.b as usize * 7
-                + current_pix.a as usize * 11)
-                % 64;
+        self.flush_run()?;
 
+        let index_position = index_position(current_pix);
+        if self.pix_arr[index_position] == current_pix {
             self.previous_pixel = current_pix;
             self.out
-                .write_all(&[(0b00 << 6) | index_position as u8])
-                .unwrap();
-            self.index += 1;
-            return;
+                .write_all(&[(OP_INDEX << 6) | index_position as u8])?;
+            return Ok(());
         }
 
-        if current_pix.a == self.previous_pixel.a {
-            let r_diff = current_pix.r.wrapping_sub(self.previous_pixel.r) as i8;
-            let g_diff = current_pix.g.wrapping_sub(self.previous_pixel.g) as i8;
-            let b_diff = current_pix.b.wrapping_sub(self.previous_pixel.b) as i8;
+        let previous_pixel = self.previous_pixel;
+        self.previous_pixel = current_pix;
+        self.pix_arr[index_position] = current_pix;
 
-            if (r_diff <= 1 && r_diff >= -2)
-                && (g_diff <= 1 && g_diff >= -2)
-                && (b_diff <= 1 && b_diff >= -2)
-            {
-                self.previous_pixel = current_pix;
-                self.reg_pixel(self.previous_pixel);
+        if current_pix.a == previous_pixel.a {
+            let r_diff = current_pix.r.wrapping_sub(previous_pixel.r) as i8 as i16;
+            let g_diff = current_pix.g.wrapping_sub(previous_pixel.g) as i8 as i16;
+            let b_diff = current_pix.b.wrapping_sub(previous_pixel.b) as i8 as i16;
 
-                self.out
-                    .write_all(&[(0b01 << 6)
-                        | (((r_diff + 2) as u8) << 4)
-                        | (((g_diff + 2) as u8) << 2)
-                        | (((b_diff + 2) as u8) << 0)])
-                    .unwrap();
-                self.index += 1;
-                return;
+            if (-2..=1).contains(&r_diff)
+                && (-2..=1).contains(&g_diff)
+                && (-2..=1).contains(&b_diff)
+            {
+                self.out.write_all(&[(OP_DIFF << 6)
+                    | (((r_diff + 2) as u8) << 4)
+                    | (((g_diff + 2) as u8) << 2)
+                    | ((b_diff + 2) as u8)])?;
+                return Ok(());
             }
 
-            if g_diff <= 31 && g_diff >= -32 {
-                let rg_diff = r_diff - g_diff;
-                let bg_diff = b_diff - g_diff;
+            let rg_diff = r_diff - g_diff;
+            let bg_diff = b_diff - g_diff;
 
-                if (rg_diff <= 7 && rg_diff >= -8) && (bg_diff <= 7 && bg_diff >= -8) {
-                    self.previous_pixel = current_pix;
-                    self.reg_pixel(self.previous_pixel);
-                    self.out
-                        .write_all(&[
-                            (0b10 << 6) | (g_diff + 32) as u8,
-                            ((rg_diff + 8) << 4) as u8 | ((bg_diff + 8) << 0) as u8,
-                        ])
-                        .unwrap();
-                    self.index += 1;
-                    return;
-                }
+            if (-32..=31).contains(&g_diff)
+                && (-8..=7).contains(&rg_diff)
+                && (-8..=7).contains(&bg_diff)
+            {
+                self.out.write_all(&[
+                    (OP_LUMA << 6) | (g_diff + 32) as u8,
+                    (((rg_diff + 8) as u8) << 4) | (bg_diff + 8) as u8,
+                ])?;
+                return Ok(());
             }
-        }
-
-        self.previous_pixel = current_pix;
-        self.reg_pixel(self.previous_pixel);
-        self.out
-            .write_all(&[
-                0b11111111,
-                current_pix.r,
-                current_pix.g,
-                current_pix.b,
-                current_pix.a,
-            ])
-            .unwrap();
-
-        self.index += 1;
-    }
 
-    pub fn reg_pixel(&mut self, pix: Rgba) {
-        let index_position =
-            (pix.r as usize * 3 + pix.g as usize * 5 + pix.b as usize * 7 + pix.a as usize * 11)
-                % 64;
-
-        self.pix_arr[index_position] = pix;
-    }
+            self.out
+                .write_all(&[OP_RGB, current_pix.r, current_pix.g, current_pix.b])?;
+            return Ok(());
+        }
 
-    #[inline]
-    pub fn color_is_registered(&mut self, pix: Rgba) -> bool {
-        let index_position =
-            (pix.r as usize * 3 + pix.g as usize * 5 + pix.b as usize * 7 + pix.a as usize * 11)
-                % 64;
+        self.out.write_all(&[
+            OP_RGBA,
+            current_pix.r,
+            current_pix.g,
+            current_pix.b,
+            current_pix.a,
+        ])?;
 
-        self.pix_arr[index_position] == pix
+        Ok(())
     }
 }
@@ -0,0 +1,254 @@
+//! Resampling images to other sizes with separable filters
+
+use std::f32::consts::PI;
+
+use crate::{
+    Image, Pixel, Rgba,
+    filter::{Taps, filter_columns, filter_rows, from_linear, to_linear},
+};
+
+/// The filter used for resampling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// Pick the closest pixel, which keeps hard edges
+    Nearest,
+    /// Average the covered pixels, good for shrinking by whole factors
+    Box,
+    Bilinear,
+    /// Catmull-Rom, sharper than bilinear
+    Bicubic,
+    /// Lanczos with 3 lobes, the sharpest but can ring at hard edges
+    Lanczos3,
+}
+
+impl Filter {
+    /// the distance from the center at which the kernel becomes 0
+    fn radius(self) -> f32 {
+        match self {
+            Self::Nearest | Self::Box => 0.5,
+            Self::Bilinear => 1.0,
+            Self::Bicubic => 2.0,
+            Self::Lanczos3 => 3.0,
+        }
+    }
+
+    fn kernel(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            Self::Nearest | Self::Box => (x <= 0.5) as u8 as f32,
+            Self::Bilinear => (1.0 - x).max(0.0),
+            Self::Bicubic => {
+                if x < 1.0 {
+                    1.5 * x * x * x - 2.5 * x * x + 1.0
+                } else if x < 2.0 {
+                    -0.5 * x * x * x + 2.5 * x * x - 4.0 * x + 2.0
+                } else {
+                    0.0
+                }
+            }
+            Self::Lanczos3 => {
+                if x < 1e-6 {
+                    1.0
+                } else if x < 3.0 {
+                    let px = PI * x;
+                    3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    /// The taps for every output sample when scaling `src` samples to `dst` samples
+    fn taps(self, src: usize, dst: usize) -> Vec<Taps> {
+        let scale = src as f32 / dst as f32;
+        // when shrinking the kernel is widened to cover all input samples
+        let filter_scale = scale.max(1.0);
+        let support = self.radius() * filter_scale;
+
+        (0..dst)
+            .map(|i| {
+                let center = (i as f32 + 0.5) * scale - 0.5;
+                let first = (center - support).ceil() as isize;
+                let last = (center + support).floor() as isize;
+                let weights: Vec<f32> = (first..=last)
+                    .map(|j| self.kernel((j as f32 - center) / filter_scale))
+                    .collect();
+                Taps::new(src, first, &weights, true)
+            })
+            .collect()
+    }
+}
+
+impl<Pix: Pixel, Cont: AsRef<[Pix]>> Image<Pix, Cont> {
+    /// Resample the image to a new size
+    pub fn resize(&self, width: usize, height: usize, filter: Filter) -> Image<Pix> {
+        let (src_w, src_h) = self.dimensions();
+        if width == 0 || height == 0 || src_w == 0 || src_h == 0 {
+            return Image::new(width, height, Pix::from_rgba(Rgba::TRANSPARENT));
+        }
+        if (width, height) == (src_w, src_h) {
+            return self.to_image();
+        }
+
+        if filter == Filter::Nearest {
+            let index =
+                |i: usize, src: usize, dst: usize| ((i * 2 + 1) * src / (dst * 2)).min(src - 1);
+            return Image::from_fn(width, height, |x, y| {
+                self.get_pixel(index(x, src_w, width), index(y, src_h, height))
+            });
+        }
+
+        let data = to_linear(self);
+        let data = if width != src_w {
+            filter_rows(&data, src_w, &filter.taps(src_w, width))
+        } else {
+            data
+        };
+        let data = if height != src_h {
+            filter_columns(&data, width, &filter.taps(src_h, height))
+        } else {
+            data
+        };
+        from_linear(width, height, &data)
+    }
+
+    /// Resample the image by a factor, like the scaling factor of a screen
+    pub fn scale(&self, factor: f32, filter: Filter) -> Image<Pix> {
+        let size = |v: usize| ((v as f32 * factor).round() as usize).max(1);
+        self.resize(size(self.width), size(self.height), filter)
+    }
+
+    /// The smaller versions of the image for mipmapping, each half the size of the one
+    /// before, down to 1x1. The image itself is not included.
+    pub fn mipmaps(&self) -> Vec<Image<Pix>> {
+        let mut levels: Vec<Image<Pix>> = Vec::new();
+        let (mut width, mut height) = self.dimensions();
+        while width > 1 || height > 1 {
+            (width, height) = ((width / 2).max(1), (height / 2).max(1));
+            let level = match levels.last() {
+                Some(prev) => prev.resize(width, height, Filter::Box),
+                None => self.resize(width, height, Filter::Box),
+            };
+            levels.push(level);
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Gray8, RgbaImage};
+
+    const FILTERS: [Filter; 5] = [
+        Filter::Nearest,
+        Filter::Box,
+        Filter::Bilinear,
+        Filter::Bicubic,
+        Filter::Lanczos3,
+    ];
+
+    #[test]
+    fn constant_images() {
+        let color = Rgba {
+            r: 30,
+            g: 140,
+            b: 250,
+            a: 200,
+        };
+        let img = RgbaImage::new(9, 7, color);
+        for filter in FILTERS {
+            for (w, h) in [(3, 2), (20, 13), (9, 30), (1, 1)] {
+                let resized = img.resize(w, h, filter);
+                assert_eq!(resized.dimensions(), (w, h));
+                assert!(resized.data.iter().all(|&p| p == color), "{filter:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn nearest() {
+        let img = Image::from_fn(4, 2, |x, y| Gray8((y * 4 + x) as u8));
+        let big = img.resize(8, 4, Filter::Nearest);
+        assert_eq!(big.get_pixel(7, 3), Gray8(7));
+        assert_eq!(big.get_pixel(2, 1), Gray8(1));
+        assert_eq!(big.resize(4, 2, Filter::Nearest), img);
+        assert_eq!(img.resize(2, 1, Filter::Nearest).data, [Gray8(5), Gray8(7)]);
+    }
+
+    #[test]
+    fn shrinking_averages() {
+        // black and white stripes become gray, which is brighter than 128 in sRGB
+        let stripes = Image::from_fn(24, 4, |x, _| Gray8(if x % 2 == 0 { 0 } else { 255 }));
+        for filter in [
+            Filter::Box,
+            Filter::Bilinear,
+            Filter::Bicubic,
+            Filter::Lanczos3,
+        ] {
+            let small = stripes.resize(12, 2, filter);
+            // away from the edges, which are repeated outside of the image
+            for x in 3..9 {
+                let pix = small.get_pixel(x, 1);
+                assert!((pix.0 as i32 - 188).abs() <= 1, "{filter:?} {pix:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn premultiplied_alpha() {
+        // the color of a transparent pixel does not bleed into its neighbours
+        let red = Rgba {
+            r: 255,
+            g: 0,
+            b: 0,
+            a: 255,
+        };
+        let transparent_green = Rgba {
+            r: 0,
+            g: 255,
+            b: 0,
+            a: 0,
+        };
+        let img = Image::from_fn(2, 1, |x, _| if x == 0 { red } else { transparent_green });
+        for filter in [
+            Filter::Box,
+            Filter::Bilinear,
+            Filter::Bicubic,
+            Filter::Lanczos3,
+        ] {
+            let pix = img.resize(1, 1, filter).get_pixel(0, 0);
+            assert_eq!((pix.r, pix.g, pix.b, pix.a), (255, 0, 0, 128), "{filter:?}");
+        }
+    }
+
+    #[test]
+    fn upscaling_is_smooth() {
+        let img = Image::from_fn(2, 1, |x, _| Gray8(x as u8 * 255));
+        for filter in [Filter::Bilinear, Filter::Bicubic, Filter::Lanczos3] {
+            let big = img.resize(8, 1, filter);
+            let values: Vec<u8> = big.data.iter().map(|p| p.0).collect();
+            assert!(
+                values.windows(2).all(|w| w[0] <= w[1]),
+                "{filter:?} {values:?}"
+            );
+            assert_eq!((values[0], values[7]), (0, 255));
+        }
+    }
+
+    #[test]
+    fn mipmaps() {
+        let img = RgbaImage::new(13, 4, Rgba::WHITE);
+        let sizes: Vec<_> = img.mipmaps().iter().map(|m| m.dimensions()).collect();
+        assert_eq!(sizes, [(6, 2), (3, 1), (1, 1)]);
+        assert!(
+            img.mipmaps()
+                .iter()
+                .all(|m| m.data.iter().all(|&p| p == Rgba::WHITE))
+        );
+        assert!(RgbaImage::new(1, 1, Rgba::WHITE).mipmaps().is_empty());
+
+        assert_eq!(img.scale(1.5, Filter::Bilinear).dimensions(), (20, 6));
+    }
+}
@@ -0,0 +1,590 @@
+//! DEFLATE compression: LZ77 with hash chains and dynamic huffman codes.
+
+use super::inflate::fixed_lengths;
+
+/// Writes bits least significant bit first
+struct BitWriter {
+    out: Vec<u8>,
+    bit_buf: u64,
+    bit_cnt: u32,
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> Self {
+        Self {
+            out,
+            bit_buf: 0,
+            bit_cnt: 0,
+        }
+    }
+
+    /// write the lowest `n` bits of `bits` (at most 32)
+    #[inline]
+    fn bits(&mut self, bits: u32, n: u32) {
+        self.bit_buf |= (bits as u64) << self.bit_cnt;
+        self.bit_cnt += n;
+        while self.bit_cnt >= 8 {
+            self.out.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_cnt -= 8;
+        }
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.bit_cnt > 0 {
+            self.bits(0, 8 - self.bit_cnt);
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.out
+    }
+}
+
+const WINDOW_SIZE: usize = 1 << 15;
+const WINDOW_MASK: usize = WINDOW_SIZE - 1;
+const HASH_BITS: u32 = 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// the amount of symbols per block, a new set of huffman codes is chosen for every block
+const BLOCK_SYMBOLS: usize = 1 << 15;
+
+#[derive(Debug, Clone, Copy)]
+enum Symbol {
+    Literal(u8),
+    /// a back reference, `len` in 3..=258 and `dist` in 1..=32768
+    Match {
+        len: u16,
+        dist: u16,
+    },
+}
+
+/// How hard the compressor tries to find matches
+struct Effort {
+    /// how many positions of a hash chain are compared at most
+    max_chain: usize,
+    /// stop searching once a match is at least this long
+    nice_len: usize,
+    /// whether to check if the next position has a longer match before using a match
+    lazy: bool,
+}
+
+impl Effort {
+    fn for_level(level: u8) -> Self {
+        let (max_chain, nice_len, lazy) = match level {
+            0 => (0, 0, false),
+            1 => (4, 8, false),
+            2 => (8, 16, false),
+            3 => (16, 32, false),
+            4 => (16, 32, true),
+            5 => (32, 64, true),
+            6 => (128, 128, true),
+            7 => (256, 192, true),
+            8 => (1024, 258, true),
+            _ => (4096, 258, true),
+        };
+        Self {
+            max_chain,
+            nice_len,
+            lazy,
+        }
+    }
+}
+
+/// Finds back references with hash chains
+struct Matcher<'data> {
+    data: &'data [u8],
+    /// the last position + 1 of each hash, 0 for none
+    head: Vec<u32>,
+    /// the previous position + 1 with the same hash for every position in the window
+    prev: Vec<u32>,
+    effort: Effort,
+}
+
+impl<'data> Matcher<'data> {
+    fn new(data: &'data [u8], effort: Effort) -> Self {
+        Self {
+            data,
+            head: vec![0; 1 << HASH_BITS],
+            prev: vec![0; WINDOW_SIZE],
+            effort,
+        }
+    }
+
+    #[inline]
+    fn hash(&self, pos: usize) -> usize {
+        let v = u32::from_le_bytes([self.data[pos], self.data[pos + 1], self.data[pos + 2], 0]);
+        (v.wrapping_mul(0x9e3779b1) >> (32 - HASH_BITS)) as usize
+    }
+
+    /// register the position so later positions can reference it
+    #[inline]
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let hash = self.hash(pos);
+        self.prev[pos & WINDOW_MASK] = self.head[hash];
+        self.head[hash] = pos as u32 + 1;
+    }
+
+    /// the longest match for the data at `pos` as (length, distance)
+    fn find(&self, pos: usize) -> Option<(usize, usize)> {
+        if pos + MIN_MATCH > self.data.len() {
+            return None;
+        }
+        let max_len = MAX_MATCH.min(self.data.len() - pos);
+        let target = &self.data[pos..pos + max_len];
+
+        let mut best: Option<(usize, usize)> = None;
+        let mut candidate = self.head[self.hash(pos)] as usize;
+        for _ in 0..self.effort.max_chain {
+            if candidate == 0 {
+                break;
+            }
+            let cand_pos = candidate - 1;
+            if cand_pos >= pos || pos - cand_pos > WINDOW_SIZE {
+                break;
+            }
+
+            let best_len = best.map_or(MIN_MATCH - 1, |b| b.0);
+            // a quick check of the byte that would make this match longer than the best one
+            if self.data[cand_pos + best_len] == target[best_len] {
+                let len = self.data[cand_pos..]
+                    .iter()
+                    .zip(target)
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if len > best_len {
+                    best = Some((len, pos - cand_pos));
+                    if len >= self.effort.nice_len || len == max_len {
+                        break;
+                    }
+                }
+            }
+
+            let next = self.prev[cand_pos & WINDOW_MASK] as usize;
+            // the chain entry was overwritten by a newer position
+            if next >= candidate {
+                break;
+            }
+            candidate = next;
+        }
+
+        best
+    }
+}
+
+/// Turn the data into literals and back references
+fn lz77(data: &[u8], effort: Effort) -> Vec<Symbol> {
+    let lazy = effort.lazy;
+    let mut matcher = Matcher::new(data, effort);
+    let mut symbols = Vec::with_capacity(data.len() / 2);
+
+    let mut pos = 0;
+    while pos < data.len() {
+        let found = matcher.find(pos);
+        matcher.insert(pos);
+
+        let Some((mut len, mut dist)) = found else {
+            symbols.push(Symbol::Literal(data[pos]));
+            pos += 1;
+            continue;
+        };
+
+        // a longer match at the next position is better than the current one
+        if lazy
+            && len < MAX_MATCH
+            && let Some((next_len, next_dist)) = matcher.find(pos + 1)
+            && next_len > len
+        {
+            symbols.push(Symbol::Literal(data[pos]));
+            pos += 1;
+            matcher.insert(pos);
+            (len, dist) = (next_len, next_dist);
+        }
+
+        symbols.push(Symbol::Match {
+            len: len as u16,
+            dist: dist as u16,
+        });
+        for p in pos + 1..pos + len {
+            matcher.insert(p);
+        }
+        pos += len;
+    }
+
+    symbols
+}
+
+/// the length symbol (257..=285) and the extra bits with their amount
+fn length_code(len: usize) -> (usize, u32, u32) {
+    const BASE: [u16; 29] = [
+        3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+        131, 163, 195, 227, 258,
+    ];
+    const EXTRA: [u32; 29] = [
+        0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+    ];
+    let idx = BASE.partition_point(|&b| b as usize <= len) - 1;
+    (257 + idx, (len - BASE[idx] as usize) as u32, EXTRA[idx])
+}
+
+/// the distance symbol (0..=29) and the extra bits with their amount
+fn dist_code(dist: usize) -> (usize, u32, u32) {
+    const BASE: [u16; 30] = [
+        1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+        2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+    ];
+    let idx = BASE.partition_point(|&b| b as usize <= dist) - 1;
+    let extra = if idx < 4 { 0 } else { (idx as u32 - 2) / 2 };
+    (idx, (dist - BASE[idx] as usize) as u32, extra)
+}
+
+/// Huffman code lengths for the frequencies, no longer than `max_len`
+fn code_lengths(freqs: &[u32], max_len: u8) -> Vec<u8> {
+    let mut freqs = freqs.to_vec();
+    loop {
+        let lengths = huffman_lengths(&freqs);
+        if lengths.iter().all(|&l| l <= max_len) {
+            return lengths;
+        }
+        // flatten the distribution until the tree is shallow enough
+        for f in freqs.iter_mut().filter(|f| **f > 0) {
+            *f = (*f).div_ceil(2);
+        }
+    }
+}
+
+/// Standard huffman code lengths. Symbols with a frequency of 0 get no code.
+fn huffman_lengths(freqs: &[u32]) -> Vec<u8> {
+    // nodes: (frequency, parent). The leaves come first.
+    let mut nodes: Vec<(u64, usize)> = Vec::new();
+    let mut leaves = Vec::new();
+    for (symbol, &f) in freqs.iter().enumerate() {
+        if f > 0 {
+            leaves.push(symbol);
+            nodes.push((f as u64, usize::MAX));
+        }
+    }
+
+    let mut lengths = vec![0u8; freqs.len()];
+    match leaves.len() {
+        0 => return lengths,
+        1 => {
+            lengths[leaves[0]] = 1;
+            return lengths;
+        }
+        _ => {}
+    }
+
+    // the nodes that are not part of a subtree yet, sorted by descending frequency
+    let mut open: Vec<usize> = (0..nodes.len()).collect();
+    open.sort_by_key(|&n| std::cmp::Reverse(nodes[n].0));
+    while open.len() > 1 {
+        let a = open.pop().unwrap();
+        let b = open.pop().unwrap();
+        let parent = nodes.len();
+        nodes.push((nodes[a].0 + nodes[b].0, usize::MAX));
+        nodes[a].1 = parent;
+        nodes[b].1 = parent;
+        let freq = nodes[parent].0;
+        let at = open.partition_point(|&n| nodes[n].0 > freq);
+        open.insert(at, parent);
+    }
+
+    // the depth of a node is one more than the depth of its parent, parents come later
+    let mut depth = vec![0u8; nodes.len()];
+    for n in (0..nodes.len() - 1).rev() {
+        depth[n] = depth[nodes[n].1] + 1;
+    }
+    for (leaf, &symbol) in leaves.iter().enumerate() {
+        lengths[symbol] = depth[leaf];
+    }
+    lengths
+}
+
+/// canonical codes for the lengths, already bit reversed for writing
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut counts = [0u16; 16];
+    for &l in lengths {
+        counts[l as usize] += 1;
+    }
+    counts[0] = 0;
+
+    let mut next = [0u16; 16];
+    let mut code = 0;
+    for len in 1..16 {
+        code = (code + counts[len - 1]) << 1;
+        next[len] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            code.reverse_bits() >> (16 - len)
+        })
+        .collect()
+}
+
+/// Run length encode code lengths with the symbols 16, 17 and 18 as (symbol, extra bits)
+fn rle_lengths(lengths: &[u8]) -> Vec<(u8, u8)> {
+    let mut res = Vec::new();
+    let mut i = 0;
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..].iter().take_while(|&&l| l == len).count();
+
+        if len == 0 && run >= 3 {
+            let run = run.min(138);
+            if run >= 11 {
+                res.push((18, (run - 11) as u8));
+            } else {
+                res.push((17, (run - 3) as u8));
+            }
+            i += run;
+        } else if len != 0 && run >= 4 {
+            res.push((len, 0));
+            let run = (run - 1).min(6);
+            res.push((16, (run - 3) as u8));
+            i += run + 1;
+        } else {
+            res.push((len, 0));
+            i += 1;
+        }
+    }
+    res
+}
+
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// The dynamic huffman codes of a block and how to write them
+struct DynamicCodes {
+    lit_len: Vec<u8>,
+    dist: Vec<u8>,
+    code_length_lengths: Vec<u8>,
+    rle: Vec<(u8, u8)>,
+    hclen: usize,
+}
+
+impl DynamicCodes {
+    fn new(lit_freqs: &[u32; 286], dist_freqs: &[u32; 30]) -> Self {
+        let mut lit_freqs = *lit_freqs;
+        let mut dist_freqs = *dist_freqs;
+        // Some decoders do not accept incomplete codes, so make sure there are at least two
+        // symbols which results in a complete code.
+        for freqs in [&mut lit_freqs[..], &mut dist_freqs[..]] {
+            for i in 0..2 {
+                if freqs.iter().filter(|&&f| f > 0).count() < 2 && freqs[i] == 0 {
+                    freqs[i] = 1;
+                }
+            }
+        }
+
+        let lit_len = code_lengths(&lit_freqs, 15);
+        let dist = code_lengths(&dist_freqs, 15);
+
+        let hlit = 257.max(lit_len.iter().rposition(|&l| l != 0).unwrap() + 1);
+        let hdist = 1.max(dist.iter().rposition(|&l| l != 0).unwrap_or(0) + 1);
+
+        let mut all = lit_len[..hlit].to_vec();
+        all.extend_from_slice(&dist[..hdist]);
+        let rle = rle_lengths(&all);
+
+        let mut cl_freqs = [0u32; 19];
+        for &(symbol, _) in &rle {
+            cl_freqs[symbol as usize] += 1;
+        }
+        let code_length_lengths = code_lengths(&cl_freqs, 7);
+        let hclen = 4.max(
+            CODE_LENGTH_ORDER
+                .iter()
+                .rposition(|&i| code_length_lengths[i] != 0)
+                .unwrap()
+                + 1,
+        );
+
+        Self {
+            lit_len: lit_len[..hlit].to_vec(),
+            dist: dist[..hdist].to_vec(),
+            code_length_lengths,
+            rle,
+            hclen,
+        }
+    }
+
+    /// the size of the code description in bits
+    fn header_bits(&self) -> usize {
+        let extra = |s: u8| match s {
+            16 => 2,
+            17 => 3,
+            18 => 7,
+            _ => 0,
+        };
+        5 + 5
+            + 4
+            + self.hclen * 3
+            + self
+                .rle
+                .iter()
+                .map(|&(s, _)| self.code_length_lengths[s as usize] as usize + extra(s))
+                .sum::<usize>()
+    }
+
+    fn write_header(&self, writer: &mut BitWriter) {
+        writer.bits(self.lit_len.len() as u32 - 257, 5);
+        writer.bits(self.dist.len() as u32 - 1, 5);
+        writer.bits(self.hclen as u32 - 4, 4);
+        for &i in &CODE_LENGTH_ORDER[..self.hclen] {
+            writer.bits(self.code_length_lengths[i] as u32, 3);
+        }
+
+        let codes = canonical_codes(&self.code_length_lengths);
+        for &(symbol, extra) in &self.rle {
+            let s = symbol as usize;
+            writer.bits(codes[s] as u32, self.code_length_lengths[s] as u32);
+            match symbol {
+                16 => writer.bits(extra as u32, 2),
+                17 => writer.bits(extra as u32, 3),
+                18 => writer.bits(extra as u32, 7),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// the size of the symbols in bits with the given code lengths
+fn symbols_bits(symbols: &[Symbol], lit_len: &[u8], dist: &[u8]) -> usize {
+    let mut bits = lit_len[256] as usize;
+    for symbol in symbols {
+        bits += match *symbol {
+            Symbol::Literal(b) => lit_len[b as usize] as usize,
+            Symbol::Match { len, dist: d } => {
+                let (l, _, l_extra) = length_code(len as usize);
+                let (ds, _, d_extra) = dist_code(d as usize);
+                (lit_len[l] + dist[ds]) as usize + (l_extra + d_extra) as usize
+            }
+        };
+    }
+    bits
+}
+
+fn write_symbols(writer: &mut BitWriter, symbols: &[Symbol], lit_len: &[u8], dist: &[u8]) {
+    let lit_codes = canonical_codes(lit_len);
+    let dist_codes = canonical_codes(dist);
+
+    for symbol in symbols {
+        match *symbol {
+            Symbol::Literal(b) => {
+                writer.bits(lit_codes[b as usize] as u32, lit_len[b as usize] as u32)
+            }
+            Symbol::Match { len, dist: d } => {
+                let (l, l_bits, l_extra) = length_code(len as usize);
+                writer.bits(lit_codes[l] as u32, lit_len[l] as u32);
+                writer.bits(l_bits, l_extra);
+                let (ds, d_bits, d_extra) = dist_code(d as usize);
+                writer.bits(dist_codes[ds] as u32, dist[ds] as u32);
+                writer.bits(d_bits, d_extra);
+            }
+        }
+    }
+    writer.bits(lit_codes[256] as u32, lit_len[256] as u32);
+}
+
+fn write_stored(writer: &mut BitWriter, data: &[u8], last: bool) {
+    let chunks: Vec<&[u8]> = if data.is_empty() {
+        vec![&[]]
+    } else {
+        data.chunks(u16::MAX as usize).collect()
+    };
+    for (i, chunk) in chunks.iter().enumerate() {
+        writer.bits((last && i == chunks.len() - 1) as u32, 1);
+        writer.bits(0, 2);
+        writer.align_to_byte();
+        writer.bits(chunk.len() as u32, 16);
+        writer.bits(!(chunk.len() as u16) as u32, 16);
+        writer.out.extend_from_slice(chunk);
+    }
+}
+
+/// Compress to raw DEFLATE data. `level` goes from 0 (no compression) to 9 (best compression).
+pub fn deflate(data: &[u8], level: u8) -> Vec<u8> {
+    deflate_into(Vec::with_capacity(data.len() / 2 + 64), data, level)
+}
+
+/// [`deflate`] appending to `out`
+pub(crate) fn deflate_into(out: Vec<u8>, data: &[u8], level: u8) -> Vec<u8> {
+    let mut writer = BitWriter::new(out);
+
+    if level == 0 {
+        write_stored(&mut writer, data, true);
+        return writer.finish();
+    }
+
+    let symbols = lz77(data, Effort::for_level(level));
+    if symbols.is_empty() {
+        // an empty fixed block
+        writer.bits(1, 1);
+        writer.bits(1, 2);
+        writer.bits(0, 7);
+        return writer.finish();
+    }
+
+    let (fixed_lit, fixed_dist) = fixed_lengths();
+    let mut data_pos = 0;
+    let blocks: Vec<&[Symbol]> = symbols.chunks(BLOCK_SYMBOLS).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        let last = i == blocks.len() - 1;
+
+        let mut lit_freqs = [0u32; 286];
+        let mut dist_freqs = [0u32; 30];
+        let mut block_len = 0;
+        for symbol in block.iter() {
+            match *symbol {
+                Symbol::Literal(b) => {
+                    lit_freqs[b as usize] += 1;
+                    block_len += 1;
+                }
+                Symbol::Match { len, dist } => {
+                    lit_freqs[length_code(len as usize).0] += 1;
+                    dist_freqs[dist_code(dist as usize).0] += 1;
+                    block_len += len as usize;
+                }
+            }
+        }
+        lit_freqs[256] += 1;
+
+        let dynamic = DynamicCodes::new(&lit_freqs, &dist_freqs);
+        let dynamic_bits =
+            dynamic.header_bits() + symbols_bits(block, &dynamic.lit_len, &dynamic.dist);
+        let fixed_bits = symbols_bits(block, &fixed_lit, &fixed_dist);
+        // the header and the alignment of every stored block
+        let stored_bits = (block_len + block_len.div_ceil(u16::MAX as usize).max(1) * 5) * 8;
+
+        let block_data = &data[data_pos..data_pos + block_len];
+        data_pos += block_len;
+
+        if stored_bits <= dynamic_bits.min(fixed_bits) {
+            write_stored(&mut writer, block_data, last);
+        } else if fixed_bits <= dynamic_bits {
+            writer.bits(last as u32, 1);
+            writer.bits(1, 2);
+            write_symbols(&mut writer, block, &fixed_lit, &fixed_dist);
+        } else {
+            writer.bits(last as u32, 1);
+            writer.bits(2, 2);
+            dynamic.write_header(&mut writer);
+            write_symbols(&mut writer, block, &dynamic.lit_len, &dynamic.dist);
+        }
+    }
+
+    writer.finish()
+}
@@ -0,0 +1,388 @@
+//! DEFLATE decompression: https://www.rfc-editor.org/rfc/rfc1951
+
+use std::sync::LazyLock;
+
+use super::InflateError;
+
+/// Reads the bits of a byte slice, least significant bit first
+pub(crate) struct BitReader<'data> {
+    data: &'data [u8],
+    pos: usize,
+    bit_buf: u64,
+    bit_cnt: u32,
+}
+
+impl<'data> BitReader<'data> {
+    pub fn new(data: &'data [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            bit_buf: 0,
+            bit_cnt: 0,
+        }
+    }
+
+    #[inline]
+    fn refill(&mut self) {
+        while self.bit_cnt <= 56 && self.pos < self.data.len() {
+            self.bit_buf |= (self.data[self.pos] as u64) << self.bit_cnt;
+            self.pos += 1;
+            self.bit_cnt += 8;
+        }
+    }
+
+    /// read `n` bits (at most 32)
+    #[inline]
+    pub fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        if self.bit_cnt < n {
+            self.refill();
+            if self.bit_cnt < n {
+                return Err(InflateError::UnexpectedEof);
+            }
+        }
+        let res = (self.bit_buf & ((1u64 << n) - 1)) as u32;
+        self.bit_buf >>= n;
+        self.bit_cnt -= n;
+        Ok(res)
+    }
+
+    /// the next bits without consuming them, together with how many of them are valid
+    #[inline]
+    fn peek(&mut self, n: u32) -> (u32, u32) {
+        if self.bit_cnt < n {
+            self.refill();
+        }
+        (
+            (self.bit_buf & ((1u64 << n) - 1)) as u32,
+            self.bit_cnt.min(n),
+        )
+    }
+
+    #[inline]
+    fn consume(&mut self, n: u32) {
+        self.bit_buf >>= n;
+        self.bit_cnt -= n;
+    }
+
+    /// skip to the next byte boundary and give back the buffered bytes
+    pub fn align_to_byte(&mut self) {
+        let whole_bytes = self.bit_cnt / 8;
+        self.pos -= whole_bytes as usize;
+        self.bit_buf = 0;
+        self.bit_cnt = 0;
+    }
+
+    /// Read bytes directly, only valid after [`Self::align_to_byte`].
+    pub fn bytes(&mut self, n: usize) -> Result<&'data [u8], InflateError> {
+        debug_assert_eq!(self.bit_cnt, 0);
+        let res = self
+            .data
+            .get(self.pos..self.pos + n)
+            .ok_or(InflateError::UnexpectedEof)?;
+        self.pos += n;
+        Ok(res)
+    }
+
+    /// the amount of bytes that were read so far, including partially read bytes
+    pub fn bytes_consumed(&self) -> usize {
+        self.pos - (self.bit_cnt / 8) as usize
+    }
+}
+
+const MAX_BITS: usize = 15;
+/// codes up to this length are decoded with a single table lookup
+const FAST_BITS: u32 = 10;
+
+/// A canonical huffman code
+struct Huffman {
+    /// the amount of codes of each length
+    counts: [u16; MAX_BITS + 1],
+    /// the symbols ordered by their code
+    symbols: Vec<u16>,
+    /// indexed with the next `FAST_BITS` bits, `(length << 9) | symbol` or 0 for longer codes
+    fast: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, InflateError> {
+        let mut counts = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // check for an over-subscribed code. Incomplete codes are allowed, invalid codes
+        // produce an error when they are encountered.
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(InflateError::InvalidCodeLengths);
+            }
+        }
+
+        let mut offsets = [0u16; MAX_BITS + 2];
+        for len in 1..=MAX_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; offsets[MAX_BITS + 1] as usize];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        let mut fast = vec![0u16; 1 << FAST_BITS];
+        let mut code = 0u32;
+        let mut index = 0;
+        for len in 1..=FAST_BITS {
+            for _ in 0..counts[len as usize] {
+                // huffman codes are stored starting with the most significant bit
+                let reversed = code.reverse_bits() >> (32 - len);
+                let entry = ((len as u16) << 9) | symbols[index];
+                for fill in 0..(1 << (FAST_BITS - len)) {
+                    fast[(reversed | (fill << len)) as usize] = entry;
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+
+        Ok(Self {
+            counts,
+            symbols,
+            fast,
+        })
+    }
+
+    #[inline]
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, InflateError> {
+        let (bits, available) = reader.peek(FAST_BITS);
+        let entry = self.fast[bits as usize];
+        let len = (entry >> 9) as u32;
+        if entry != 0 && len <= available {
+            reader.consume(len);
+            return Ok(entry & 0x1ff);
+        }
+
+        self.decode_slow(reader)
+    }
+
+    /// decode bit by bit, see puff.c from zlib
+    fn decode_slow(&self, reader: &mut BitReader) -> Result<u16, InflateError> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err(InflateError::InvalidCode)
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// the order in which the code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+pub(crate) struct Inflater<'data> {
+    reader: BitReader<'data>,
+    out: Vec<u8>,
+    max_len: usize,
+}
+
+impl<'data> Inflater<'data> {
+    /// `max_len` limits the size of the output
+    pub fn new(data: &'data [u8], max_len: usize) -> Self {
+        Self {
+            reader: BitReader::new(data),
+            out: Vec::new(),
+            max_len,
+        }
+    }
+
+    /// Decompress all blocks. Returns the output and the amount of input bytes used.
+    pub fn inflate(mut self) -> Result<(Vec<u8>, usize), InflateError> {
+        loop {
+            let last = self.reader.bits(1)? == 1;
+            match self.reader.bits(2)? {
+                0 => self.stored_block()?,
+                1 => {
+                    let (lit_len, dist) = &*FIXED_CODES;
+                    self.compressed_block(lit_len, dist)?
+                }
+                2 => {
+                    let (lit_len, dist) = self.dynamic_codes()?;
+                    self.compressed_block(&lit_len, &dist)?
+                }
+                _ => return Err(InflateError::InvalidBlockType),
+            }
+            if last {
+                break;
+            }
+        }
+
+        let consumed = self.reader.bytes_consumed();
+        Ok((self.out, consumed))
+    }
+
+    fn reserve(&mut self, additional: usize) -> Result<(), InflateError> {
+        if self.out.len() + additional > self.max_len {
+            return Err(InflateError::OutputTooLarge);
+        }
+        Ok(())
+    }
+
+    fn stored_block(&mut self) -> Result<(), InflateError> {
+        self.reader.align_to_byte();
+        let header = self.reader.bytes(4)?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+        let nlen = u16::from_le_bytes([header[2], header[3]]);
+        if len != !nlen {
+            return Err(InflateError::InvalidStoredLength);
+        }
+
+        self.reserve(len as usize)?;
+        let data = self.reader.bytes(len as usize)?;
+        self.out.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn dynamic_codes(&mut self) -> Result<(Huffman, Huffman), InflateError> {
+        let hlit = self.reader.bits(5)? as usize + 257;
+        let hdist = self.reader.bits(5)? as usize + 1;
+        let hclen = self.reader.bits(4)? as usize + 4;
+        if hlit > 286 || hdist > 30 {
+            return Err(InflateError::InvalidCodeLengths);
+        }
+
+        let mut code_length_lengths = [0u8; 19];
+        for &i in &CODE_LENGTH_ORDER[..hclen] {
+            code_length_lengths[i] = self.reader.bits(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_length_lengths)?;
+
+        let mut lengths = vec![0u8; hlit + hdist];
+        let mut i = 0;
+        while i < lengths.len() {
+            let symbol = code_length_code.decode(&mut self.reader)?;
+            let (value, repeat) = match symbol {
+                0..=15 => (symbol as u8, 1),
+                16 => {
+                    let Some(&previous) = i.checked_sub(1).map(|p| &lengths[p]) else {
+                        return Err(InflateError::InvalidCodeLengths);
+                    };
+                    (previous, 3 + self.reader.bits(2)? as usize)
+                }
+                17 => (0, 3 + self.reader.bits(3)? as usize),
+                _ => (0, 11 + self.reader.bits(7)? as usize),
+            };
+            if i + repeat > lengths.len() {
+                return Err(InflateError::InvalidCodeLengths);
+            }
+            lengths[i..i + repeat].fill(value);
+            i += repeat;
+        }
+
+        // without an end of block code the block could never end
+        if lengths[256] == 0 {
+            return Err(InflateError::InvalidCodeLengths);
+        }
+
+        Ok((
+            Huffman::new(&lengths[..hlit])?,
+            Huffman::new(&lengths[hlit..])?,
+        ))
+    }
+
+    fn compressed_block(&mut self, lit_len: &Huffman, dist: &Huffman) -> Result<(), InflateError> {
+        loop {
+            let symbol = lit_len.decode(&mut self.reader)? as usize;
+            match symbol {
+                0..=255 => {
+                    self.reserve(1)?;
+                    self.out.push(symbol as u8);
+                }
+                256 => return Ok(()),
+                257..=285 => {
+                    let idx = symbol - 257;
+                    let len = LENGTH_BASE[idx] as usize
+                        + self.reader.bits(LENGTH_EXTRA[idx] as u32)? as usize;
+
+                    let dist_symbol = dist.decode(&mut self.reader)? as usize;
+                    if dist_symbol >= 30 {
+                        return Err(InflateError::InvalidCode);
+                    }
+                    let distance = DIST_BASE[dist_symbol] as usize
+                        + self.reader.bits(DIST_EXTRA[dist_symbol] as u32)? as usize;
+
+                    if distance > self.out.len() {
+                        return Err(InflateError::InvalidDistance);
+                    }
+                    self.reserve(len)?;
+
+                    let start = self.out.len() - distance;
+                    if distance >= len {
+                        self.out.extend_from_within(start..start + len);
+                    } else {
+                        // the copy overlaps with itself
+                        for i in 0..len {
+                            self.out.push(self.out[start + i]);
+                        }
+                    }
+                }
+                _ => return Err(InflateError::InvalidCode),
+            }
+        }
+    }
+}
+
+/// the code lengths of the fixed huffman codes
+pub(crate) fn fixed_lengths() -> ([u8; 288], [u8; 30]) {
+    let mut lit_len = [0u8; 288];
+    lit_len[..144].fill(8);
+    lit_len[144..256].fill(9);
+    lit_len[256..280].fill(7);
+    lit_len[280..].fill(8);
+    (lit_len, [5; 30])
+}
+
+static FIXED_CODES: LazyLock<(Huffman, Huffman)> = LazyLock::new(|| {
+    let (lit_len, dist) = fixed_lengths();
+    (
+        Huffman::new(&lit_len).unwrap(),
+        Huffman::new(&dist).unwrap(),
+    )
+});
+
+/// Decompress raw DEFLATE data. The output may not grow beyond `max_len` bytes.
+pub fn inflate(data: &[u8], max_len: usize) -> Result<Vec<u8>, InflateError> {
+    Inflater::new(data, max_len).inflate().map(|(out, _)| out)
+}
@@ -0,0 +1,291 @@
+//! The zlib format: https://www.rfc-editor.org/rfc/rfc1950
+
+use std::fmt::Display;
+
+pub mod deflate;
+pub mod inflate;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InflateError {
+    UnexpectedEof,
+    InvalidZlibHeader,
+    /// a preset dictionary is required, which is not supported (and not allowed in png)
+    PresetDictionary,
+    InvalidBlockType,
+    /// the length of a stored block does not match its complement
+    InvalidStoredLength,
+    InvalidCodeLengths,
+    /// a huffman code or symbol that is not part of the alphabet
+    InvalidCode,
+    /// a back reference to before the start of the output
+    InvalidDistance,
+    ChecksumMismatch,
+    /// the output is larger than the allowed maximum
+    OutputTooLarge,
+}
+
+impl Display for InflateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of compressed data"),
+            Self::InvalidZlibHeader => write!(f, "invalid zlib header"),
+            Self::PresetDictionary => write!(f, "preset dictionaries are not supported"),
+            Self::InvalidBlockType => write!(f, "invalid deflate block type"),
+            Self::InvalidStoredLength => write!(f, "invalid stored block length"),
+            Self::InvalidCodeLengths => write!(f, "invalid huffman code lengths"),
+            Self::InvalidCode => write!(f, "invalid huffman code"),
+            Self::InvalidDistance => write!(f, "back reference distance too far"),
+            Self::ChecksumMismatch => write!(f, "adler32 checksum mismatch"),
+            Self::OutputTooLarge => write!(f, "decompressed data is too large"),
+        }
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    // the largest amount of bytes that can be summed up before b overflows
+    const CHUNK: usize = 5552;
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(CHUNK) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD;
+        b %= MOD;
+    }
+    (b << 16) | a
+}
+
+/// Decompress a zlib stream and verify its checksum. The output may not grow beyond
+/// `max_len` bytes.
+pub fn decompress(data: &[u8], max_len: usize) -> Result<Vec<u8>, InflateError> {
+    let [cmf, flg, ..] = *data else {
+        return Err(InflateError::UnexpectedEof);
+    };
+    // compression method 8 (deflate) with a window of at most 32k
+    if cmf & 0x0f != 8 || cmf >> 4 > 7 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err(InflateError::InvalidZlibHeader);
+    }
+    if flg & 0x20 != 0 {
+        return Err(InflateError::PresetDictionary);
+    }
+
+    let (out, consumed) = inflate::Inflater::new(&data[2..], max_len).inflate()?;
+
+    let checksum = data
+        .get(2 + consumed..2 + consumed + 4)
+        .ok_or(InflateError::UnexpectedEof)?;
+    if u32::from_be_bytes(checksum.try_into().unwrap()) != adler32(&out) {
+        return Err(InflateError::ChecksumMismatch);
+    }
+
+    Ok(out)
+}
+
+/// Compress to a zlib stream. `level` goes from 0 (no compression) to 9 (best compression).
+pub fn compress(data: &[u8], level: u8) -> Vec<u8> {
+    let level = level.min(9);
+    // the window size is always 32k, the level hint is informational only
+    let cmf = 0x78u8;
+    let flevel = match level {
+        0..=1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let flg = flevel << 6;
+    let flg = flg + (31 - (cmf as u16 * 256 + flg as u16) % 31) as u8;
+
+    let mut out = deflate::deflate_into(vec![cmf, flg], data, level);
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+/// A zlib stream with uncompressed blocks, for testing decoders
+#[cfg(test)]
+pub(crate) fn stored(data: &[u8]) -> Vec<u8> {
+    let mut res = vec![0x78, 0x01];
+    let chunks: Vec<_> = data.chunks(u16::MAX as usize).collect();
+    if chunks.is_empty() {
+        res.extend([1, 0, 0, 0xff, 0xff]);
+    }
+    for (i, chunk) in chunks.iter().enumerate() {
+        res.push((i == chunks.len() - 1) as u8);
+        res.extend((chunk.len() as u16).to_le_bytes());
+        res.extend((!(chunk.len() as u16)).to_le_bytes());
+        res.extend_from_slice(chunk);
+    }
+    res.extend(adler32(data).to_be_bytes());
+    res
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// `zlib.compress(b"hello hello hello hello")`, a fixed huffman block with a back reference
+    const FIXED: [u8; 16] = [
+        0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x27, 0x01, 0x68, 0x03, 0x08,
+        0xb1,
+    ];
+
+    #[test]
+    fn adler() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        let big = vec![0xffu8; 100_000];
+        let naive = big.iter().fold((1u64, 0u64), |(a, b), &x| {
+            let a = (a + x as u64) % 65521;
+            (a, (b + a) % 65521)
+        });
+        assert_eq!(adler32(&big), ((naive.1 << 16) | naive.0) as u32);
+    }
+
+    #[test]
+    fn stored_blocks() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        assert_eq!(decompress(&stored(&data), usize::MAX).unwrap(), data);
+        assert_eq!(decompress(&stored(&[]), usize::MAX).unwrap(), []);
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(
+            decompress(&FIXED, usize::MAX).unwrap(),
+            b"hello hello hello hello"
+        );
+    }
+
+    #[test]
+    fn dynamic_block() {
+        // 300 bytes of a skewed distribution without repetitions, compressed with python's
+        // `zlib.compress(data, 9)`
+        let mut x: u32 = 1;
+        let expected: Vec<u8> = (0..300)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fffffff;
+                b"aaaaaaaabbbbccde"[(x >> 16) as usize % 16]
+            })
+            .collect();
+
+        let data = [
+            0x78, 0xda, 0x3d, 0x8f, 0xdb, 0x11, 0x44, 0x31, 0x08, 0x42, 0x6b, 0xe5, 0x61, 0xff,
+            0x2d, 0x2c, 0x68, 0xee, 0xfa, 0xe1, 0x18, 0x81, 0xe3, 0x04, 0x06, 0x49, 0x10, 0xd0,
+            0x48, 0x00, 0x06, 0x6e, 0x4b, 0x65, 0xaf, 0x48, 0xd5, 0x40, 0x9d, 0x52, 0x6b, 0x66,
+            0xc2, 0x43, 0xce, 0xec, 0x4a, 0xa2, 0x6b, 0xb2, 0x28, 0x5f, 0x72, 0xbe, 0x50, 0x00,
+            0x45, 0x40, 0x18, 0x1e, 0xb3, 0xbe, 0xa8, 0x3d, 0xb5, 0x77, 0x4a, 0xe3, 0x79, 0xdb,
+            0xbc, 0xd1, 0x00, 0xd4, 0x50, 0xd0, 0x90, 0x3d, 0xcb, 0x3f, 0xc0, 0x31, 0xf0, 0x78,
+            0x5f, 0x71, 0xec, 0xec, 0x13, 0xa3, 0x9f, 0x5e, 0x42, 0x7f, 0x00, 0xfe, 0x93, 0x5b,
+            0xfa, 0xc6, 0x0e, 0x6b, 0x8e, 0x27, 0x69, 0x2d, 0xbb, 0x97, 0xc4, 0x67, 0xef, 0x17,
+            0xcf, 0xb8, 0xef, 0x1f, 0x8e, 0x3d, 0x72, 0xc8,
+        ];
+        assert_eq!(data[2] >> 1 & 0b11, 2, "not a dynamic block");
+        assert_eq!(decompress(&data, usize::MAX).unwrap(), expected);
+    }
+
+    /// data that is compressible, but not trivially
+    fn sample_data(len: usize) -> Vec<u8> {
+        let mut x: u32 = 7;
+        let words: [&[u8]; 6] = [b"pixel ", b"row ", b"filter ", b"a", b"zlib ", b"\x00\xff"];
+        let mut data = Vec::new();
+        while data.len() < len {
+            x = x.wrapping_mul(1103515245).wrapping_add(12345) & 0x7fffffff;
+            if x.is_multiple_of(5) {
+                data.push((x >> 8) as u8);
+            } else {
+                data.extend_from_slice(words[(x >> 16) as usize % words.len()]);
+            }
+        }
+        data.truncate(len);
+        data
+    }
+
+    #[test]
+    fn roundtrip() {
+        let mut x: u32 = 3;
+        let random: Vec<u8> = (0..70_000)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect();
+        let inputs = [
+            Vec::new(),
+            vec![42],
+            vec![0; 1_000_000],
+            b"abcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabcabc".to_vec(),
+            sample_data(200_000),
+            random,
+        ];
+
+        for data in &inputs {
+            for level in 0..=9 {
+                let compressed = compress(data, level);
+                assert_eq!(
+                    decompress(&compressed, usize::MAX).unwrap(),
+                    *data,
+                    "level {level}, {} bytes",
+                    data.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compression_ratio() {
+        let data = sample_data(100_000);
+        let sizes: Vec<usize> = (0..=9).map(|l| compress(&data, l).len()).collect();
+        assert!(sizes[0] > data.len());
+        assert!(sizes[1] < data.len() / 3, "{sizes:?}");
+        assert!(sizes[9] <= sizes[1], "{sizes:?}");
+
+        // long runs compress to almost nothing
+        assert!(compress(&[7; 100_000], 6).len() < 300);
+        // incompressible data grows only by the block headers
+        let mut x: u32 = 1;
+        let random: Vec<u8> = (0..100_000)
+            .map(|_| {
+                x = x.wrapping_mul(1103515245).wrapping_add(12345);
+                (x >> 16) as u8
+            })
+            .collect();
+        assert!(compress(&random, 9).len() < random.len() + 64);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(decompress(&[], 100), Err(InflateError::UnexpectedEof));
+        assert_eq!(
+            decompress(&[0x78, 0x9d], 100),
+            Err(InflateError::InvalidZlibHeader)
+        );
+
+        let mut checksum = FIXED;
+        *checksum.last_mut().unwrap() ^= 1;
+        assert_eq!(
+            decompress(&checksum, 100),
+            Err(InflateError::ChecksumMismatch)
+        );
+
+        assert_eq!(decompress(&FIXED, 10), Err(InflateError::OutputTooLarge));
+
+        for len in 0..FIXED.len() {
+            assert!(decompress(&FIXED[..len], 100).is_err());
+        }
+
+        // block type 3
+        assert_eq!(
+            decompress(&[0x78, 0x01, 0b111], 100),
+            Err(InflateError::InvalidBlockType)
+        );
+
+        let mut invalid = stored(b"abc");
+        invalid[5] ^= 1;
+        assert_eq!(
+            decompress(&invalid, 100),
+            Err(InflateError::InvalidStoredLength)
+        );
+    }
+}
@@ -0,0 +1,344 @@
+//! Parsing of css color strings: https://www.w3.org/TR/css-color-4/
+//!
+//! Supported are hex colors, named colors and the `rgb()`, `rgba()`, `hsl()` and `hsla()`
+//! functions in both the legacy (comma separated) and the modern (space separated) syntax.
+
+use std::{fmt::Display, str::FromStr};
+
+use corelib::types::Float;
+
+use super::{spaces::Hsl, ColA};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorParseError {
+    Empty,
+    InvalidHex(String),
+    UnknownName(String),
+    UnknownFunction(String),
+    /// the arguments of a color function are malformed
+    InvalidArguments(String),
+}
+
+impl Display for ColorParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty color string"),
+            Self::InvalidHex(s) => write!(f, "invalid hex color '{s}'"),
+            Self::UnknownName(s) => write!(f, "unknown color name '{s}'"),
+            Self::UnknownFunction(s) => write!(f, "unknown color function '{s}'"),
+            Self::InvalidArguments(s) => write!(f, "invalid color function arguments '{s}'"),
+        }
+    }
+}
+
+impl FromStr for ColA {
+    type Err = ColorParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ColA::parse(s)
+    }
+}
+
+impl ColA {
+    /// Parse a css color string
+    pub fn parse(s: &str) -> Result<Self, ColorParseError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ColorParseError::Empty);
+        }
+
+        if let Some(hex) = s.strip_prefix('#') {
+            return Self::from_hex(hex);
+        }
+
+        if let Some((name, args)) = s.split_once('(') {
+            let Some(args) = args.trim_end().strip_suffix(')') else {
+                return Err(ColorParseError::InvalidArguments(s.to_string()));
+            };
+            let name = name.trim().to_ascii_lowercase();
+            return match name.as_str() {
+                "rgb" | "rgba" => parse_rgb(args),
+                "hsl" | "hsla" => parse_hsl(args),
+                _ => Err(ColorParseError::UnknownFunction(name)),
+            };
+        }
+
+        Self::from_name(s).ok_or_else(|| ColorParseError::UnknownName(s.to_string()))
+    }
+
+    /// Parse a hex color without the leading '#': rgb, rgba, rrggbb or rrggbbaa
+    pub fn from_hex(hex: &str) -> Result<Self, ColorParseError> {
+        let err = || ColorParseError::InvalidHex(hex.to_string());
+
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(err());
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).unwrap();
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+
+        let arr = match hex.len() {
+            3 => [digit(0) * 17, digit(1) * 17, digit(2) * 17, 255],
+            4 => [digit(0) * 17, digit(1) * 17, digit(2) * 17, digit(3) * 17],
+            6 => [byte(0), byte(2), byte(4), 255],
+            8 => [byte(0), byte(2), byte(4), byte(6)],
+            _ => return Err(err()),
+        };
+
+        Ok(Self::from_rgba_arr(arr))
+    }
+
+    /// Look up a css named color (case insensitive)
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name == "transparent" {
+            return Some(Self::TRANSPARENT);
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, rgb)| {
+                Self::from_rgba_arr([(rgb >> 16) as u8, (rgb >> 8) as u8, *rgb as u8, 255])
+            })
+    }
+}
+
+/// Split the arguments of a color function into its components and the optional alpha.
+fn split_args(args: &str) -> Result<(Vec<&str>, Option<&str>), ColorParseError> {
+    let err = || ColorParseError::InvalidArguments(args.to_string());
+
+    let mut parts: Vec<&str> = if args.contains(',') {
+        args.split(',').map(str::trim).collect()
+    } else {
+        let (values, alpha) = match args.split_once('/') {
+            Some((values, alpha)) => (values, Some(alpha.trim())),
+            None => (args, None),
+        };
+        let mut parts: Vec<&str> = values.split_whitespace().collect();
+        if let Some(alpha) = alpha {
+            parts.push(alpha);
+        }
+        parts
+    };
+
+    if parts.iter().any(|p| p.is_empty()) {
+        return Err(err());
+    }
+
+    match parts.len() {
+        3 => Ok((parts, None)),
+        4 => {
+            let alpha = parts.pop();
+            Ok((parts, alpha))
+        }
+        _ => Err(err()),
+    }
+}
+
+fn parse_number(s: &str) -> Option<Float> {
+    s.parse::<Float>().ok().filter(|v| v.is_finite())
+}
+
+/// a number or a percentage mapped to 0..=1, where a plain number has the range 0..=`max`
+fn parse_unit(s: &str, max: Float) -> Option<Float> {
+    let v = match s.strip_suffix('%') {
+        Some(p) => parse_number(p)? / 100.0,
+        None => parse_number(s)? / max,
+    };
+    Some(v.clamp(0.0, 1.0))
+}
+
+fn parse_alpha(s: Option<&str>) -> Option<Float> {
+    s.map_or(Some(1.0), |a| parse_unit(a, 1.0))
+}
+
+/// a hue in degrees
+fn parse_hue(s: &str) -> Option<Float> {
+    let deg = if let Some(v) = s.strip_suffix("deg") {
+        parse_number(v)?
+    } else if let Some(v) = s.strip_suffix("grad") {
+        parse_number(v)? * 0.9
+    } else if let Some(v) = s.strip_suffix("rad") {
+        parse_number(v)?.to_degrees()
+    } else if let Some(v) = s.strip_suffix("turn") {
+        parse_number(v)? * 360.0
+    } else {
+        parse_number(s)?
+    };
+    Some(deg.rem_euclid(360.0))
+}
+
+fn parse_rgb(args: &str) -> Result<ColA, ColorParseError> {
+    let err = || ColorParseError::InvalidArguments(args.to_string());
+    let (parts, alpha) = split_args(args)?;
+
+    let r = parse_unit(parts[0], 255.0).ok_or_else(err)?;
+    let g = parse_unit(parts[1], 255.0).ok_or_else(err)?;
+    let b = parse_unit(parts[2], 255.0).ok_or_else(err)?;
+    let a = parse_alpha(alpha).ok_or_else(err)?;
+
+    Ok(ColA::new(r, g, b, a))
+}
+
+fn parse_hsl(args: &str) -> Result<ColA, ColorParseError> {
+    let err = || ColorParseError::InvalidArguments(args.to_string());
+    let (parts, alpha) = split_args(args)?;
+
+    let h = parse_hue(parts[0]).ok_or_else(err)?;
+    let s = parse_unit(parts[1], 100.0).ok_or_else(err)?;
+    let l = parse_unit(parts[2], 100.0).ok_or_else(err)?;
+    let a = parse_alpha(alpha).ok_or_else(err)?;
+
+    Ok(Hsl { h, s, l, a }.into())
+}
+
+/// https://www.w3.org/TR/css-color-4/#named-colors
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
@@ -0,0 +1,383 @@
+use std::ops;
+
+use corelib::types::Float;
+
+pub mod css;
+pub mod spaces;
+pub mod srgb;
+
+pub use srgb::{linear_to_srgb, linear_to_srgb8, srgb8_to_linear, srgb_to_linear};
+
+use srgb::unit_to_u8;
+
+/// A color with its components encoded in the sRGB transfer function. This is how colors
+/// are usually specified (css, image files, etc.).
+///
+/// Use [`ColA::to_linear`] before doing any math (blending, interpolation) on colors.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ColA {
+    pub r: Float,
+    pub g: Float,
+    pub b: Float,
+    pub a: Float,
+}
+
+impl ColA {
+    pub const TRANSPARENT: Self = Self::new(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Self = Self::new(0.0, 0.0, 0.0, 1.0);
+    pub const WHITE: Self = Self::new(1.0, 1.0, 1.0, 1.0);
+    pub const RED: Self = Self::new(1.0, 0.0, 0.0, 1.0);
+    /// full intensity green. Note that this is `lime` in css, `green` is half as bright.
+    pub const GREEN: Self = Self::new(0.0, 1.0, 0.0, 1.0);
+    pub const BLUE: Self = Self::new(0.0, 0.0, 1.0, 1.0);
+    pub const YELLOW: Self = Self::new(1.0, 1.0, 0.0, 1.0);
+    pub const PINK: Self = Self::new(1.0, 0.752_941_2, 0.796_078_4, 1.0);
+    pub const LIGHT_BLUE: Self = Self::new(0.678, 0.847, 0.902, 1.0);
+
+    pub const fn new(r: Float, g: Float, b: Float, a: Float) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub fn to_rgba_arr(self) -> [u8; 4] {
+        [
+            unit_to_u8(self.r),
+            unit_to_u8(self.g),
+            unit_to_u8(self.b),
+            unit_to_u8(self.a),
+        ]
+    }
+
+    pub fn from_rgba_arr(arr: [u8; 4]) -> Self {
+        Self {
+            r: arr[0] as Float / 255.0,
+            g: arr[1] as Float / 255.0,
+            b: arr[2] as Float / 255.0,
+            a: arr[3] as Float / 255.0,
+        }
+    }
+
+    /// 0xAARRGGBB
+    pub fn as_argb(self) -> u32 {
+        let [r, g, b, a] = self.to_rgba_arr();
+        u32::from_be_bytes([a, r, g, b])
+    }
+
+    /// 0xBBGGRRAA
+    pub fn as_bgra(self) -> u32 {
+        let [r, g, b, a] = self.to_rgba_arr();
+        u32::from_be_bytes([b, g, r, a])
+    }
+
+    /// 0xRRGGBBAA
+    pub fn as_rgba(self) -> u32 {
+        u32::from_be_bytes(self.to_rgba_arr())
+    }
+
+    /// 0xAARRGGBB
+    pub fn from_argb(v: u32) -> Self {
+        let [a, r, g, b] = v.to_be_bytes();
+        Self::from_rgba_arr([r, g, b, a])
+    }
+
+    /// 0xBBGGRRAA
+    pub fn from_bgra(v: u32) -> Self {
+        let [b, g, r, a] = v.to_be_bytes();
+        Self::from_rgba_arr([r, g, b, a])
+    }
+
+    /// 0xRRGGBBAA
+    pub fn from_rgba(v: u32) -> Self {
+        Self::from_rgba_arr(v.to_be_bytes())
+    }
+
+    /// decode the color components. Alpha is always linear.
+    pub fn to_linear(self) -> LinColA {
+        LinColA {
+            r: srgb_to_linear(self.r),
+            g: srgb_to_linear(self.g),
+            b: srgb_to_linear(self.b),
+            a: self.a,
+        }
+    }
+
+    pub fn to_hsl(self) -> spaces::Hsl {
+        self.into()
+    }
+
+    pub fn to_hsv(self) -> spaces::Hsv {
+        self.into()
+    }
+
+    pub fn to_oklab(self) -> spaces::Oklab {
+        self.into()
+    }
+}
+
+/// A color with linear light components, i.e. the values are proportional to the
+/// emitted light. Blending and interpolation have to happen in this space to look right.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LinColA {
+    pub r: Float,
+    pub g: Float,
+    pub b: Float,
+    pub a: Float,
+}
+
+impl LinColA {
+    pub const TRANSPARENT: Self = Self::new(0.0, 0.0, 0.0, 0.0);
+
+    pub const fn new(r: Float, g: Float, b: Float, a: Float) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// encode the color components with the sRGB transfer function.
+    pub fn to_srgb(self) -> ColA {
+        ColA {
+            r: linear_to_srgb(self.r),
+            g: linear_to_srgb(self.g),
+            b: linear_to_srgb(self.b),
+            a: self.a,
+        }
+    }
+
+    /// decode 8 bit sRGB components using a lookup table
+    pub fn from_srgb8(arr: [u8; 4]) -> Self {
+        Self {
+            r: srgb8_to_linear(arr[0]),
+            g: srgb8_to_linear(arr[1]),
+            b: srgb8_to_linear(arr[2]),
+            a: arr[3] as Float / 255.0,
+        }
+    }
+
+    /// encode to 8 bit sRGB components using a lookup table
+    pub fn to_srgb8(self) -> [u8; 4] {
+        [
+            linear_to_srgb8(self.r),
+            linear_to_srgb8(self.g),
+            linear_to_srgb8(self.b),
+            unit_to_u8(self.a),
+        ]
+    }
+
+    pub fn to_oklab(self) -> spaces::Oklab {
+        self.into()
+    }
+
+    /// composite self on top of `dst` (porter duff source over)
+    pub fn over(self, dst: Self) -> Self {
+        let src = self.premultiplied();
+        let res = src + dst.premultiplied() * (1.0 - self.a);
+        res.unpremultiplied()
+    }
+}
+
+macro_rules! impl_color_common {
+    ($t:ident) => {
+        impl $t {
+            /// linear interpolation between two colors: t = 0 is self, t = 1 is other
+            pub fn lerp(self, other: Self, t: Float) -> Self {
+                self + (other - self) * t
+            }
+
+            /// multiply the color components with alpha
+            pub fn premultiplied(self) -> Self {
+                Self::new(self.r * self.a, self.g * self.a, self.b * self.a, self.a)
+            }
+
+            /// undo [`Self::premultiplied`]. Fully transparent colors become transparent black.
+            pub fn unpremultiplied(self) -> Self {
+                if self.a <= 0.0 {
+                    return Self::TRANSPARENT;
+                }
+                Self::new(self.r / self.a, self.g / self.a, self.b / self.a, self.a)
+            }
+
+            /// clamp all components to 0..=1
+            pub fn clamped(self) -> Self {
+                Self::new(
+                    self.r.clamp(0.0, 1.0),
+                    self.g.clamp(0.0, 1.0),
+                    self.b.clamp(0.0, 1.0),
+                    self.a.clamp(0.0, 1.0),
+                )
+            }
+        }
+
+        impl ops::Add for $t {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                Self::new(
+                    self.r + rhs.r,
+                    self.g + rhs.g,
+                    self.b + rhs.b,
+                    self.a + rhs.a,
+                )
+            }
+        }
+
+        impl ops::Sub for $t {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                Self::new(
+                    self.r - rhs.r,
+                    self.g - rhs.g,
+                    self.b - rhs.b,
+                    self.a - rhs.a,
+                )
+            }
+        }
+
+        /// component wise multiplication
+        impl ops::Mul for $t {
+            type Output = Self;
+            fn mul(self, rhs: Self) -> Self {
+                Self::new(
+                    self.r * rhs.r,
+                    self.g * rhs.g,
+                    self.b * rhs.b,
+                    self.a * rhs.a,
+                )
+            }
+        }
+
+        impl ops::Mul<Float> for $t {
+            type Output = Self;
+            fn mul(self, rhs: Float) -> Self {
+                Self::new(self.r * rhs, self.g * rhs, self.b * rhs, self.a * rhs)
+            }
+        }
+
+        impl ops::Div<Float> for $t {
+            type Output = Self;
+            fn div(self, rhs: Float) -> Self {
+                Self::new(self.r / rhs, self.g / rhs, self.b / rhs, self.a / rhs)
+            }
+        }
+
+        impl ops::AddAssign for $t {
+            fn add_assign(&mut self, rhs: Self) {
+                *self = *self + rhs;
+            }
+        }
+
+        impl ops::SubAssign for $t {
+            fn sub_assign(&mut self, rhs: Self) {
+                *self = *self - rhs;
+            }
+        }
+
+        impl ops::MulAssign<Float> for $t {
+            fn mul_assign(&mut self, rhs: Float) {
+                *self = *self * rhs;
+            }
+        }
+    };
+}
+
+impl_color_common!(ColA);
+impl_color_common!(LinColA);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn approx(a: ColA, b: ColA) -> bool {
+        let d = a - b;
+        d.r.abs() < 1e-3 && d.g.abs() < 1e-3 && d.b.abs() < 1e-3 && d.a.abs() < 1e-3
+    }
+
+    #[test]
+    fn srgb8_roundtrip() {
+        for v in 0..=255 {
+            assert_eq!(linear_to_srgb8(srgb8_to_linear(v)), v);
+        }
+    }
+
+    #[test]
+    fn gradient_midpoint_is_brighter_in_linear_space() {
+        let black = LinColA::from_srgb8([0, 0, 0, 255]);
+        let white = LinColA::from_srgb8([255, 255, 255, 255]);
+        // half the light of white is ~188 in sRGB, not 128
+        assert_eq!(black.lerp(white, 0.5).to_srgb8(), [188, 188, 188, 255]);
+    }
+
+    #[test]
+    fn over_respects_alpha() {
+        let dst = LinColA::from_srgb8([0, 0, 255, 255]);
+        let src = LinColA::from_srgb8([255, 0, 0, 0]);
+        assert_eq!(src.over(dst).to_srgb8(), [0, 0, 255, 255]);
+
+        let src = LinColA::from_srgb8([255, 0, 0, 255]);
+        assert_eq!(src.over(dst).to_srgb8(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn packed() {
+        let col = ColA::from_rgba_arr([0x11, 0x22, 0x33, 0x44]);
+        assert_eq!(col.as_argb(), 0x44112233);
+        assert_eq!(col.as_bgra(), 0x33221144);
+        assert_eq!(col.as_rgba(), 0x11223344);
+        assert_eq!(ColA::from_argb(0x44112233), col);
+        assert_eq!(ColA::from_bgra(0x33221144), col);
+        assert_eq!(ColA::from_rgba(0x11223344), col);
+    }
+
+    #[test]
+    fn color_spaces_roundtrip() {
+        for arr in [
+            [255, 0, 0, 255],
+            [12, 200, 99, 128],
+            [255, 255, 255, 255],
+            [0, 0, 0, 0],
+        ] {
+            let col = ColA::from_rgba_arr(arr);
+            assert!(approx(ColA::from(col.to_hsl()), col), "hsl {arr:?}");
+            assert!(approx(ColA::from(col.to_hsv()), col), "hsv {arr:?}");
+            assert!(approx(ColA::from(col.to_oklab()), col), "oklab {arr:?}");
+        }
+
+        let hsl = ColA::from_rgba_arr([0, 0, 255, 255]).to_hsl();
+        assert_eq!((hsl.h, hsl.s, hsl.l), (240.0, 1.0, 0.5));
+        // white has a lightness of 1 in oklab
+        assert!((ColA::WHITE.to_oklab().l - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn css() {
+        let rebecca = ColA::from_rgba_arr([0x66, 0x33, 0x99, 0xff]);
+        assert_eq!(ColA::parse("#663399"), Ok(rebecca));
+        assert_eq!(ColA::parse("RebeccaPurple"), Ok(rebecca));
+        assert_eq!(ColA::parse("rgb(102, 51, 153)"), Ok(rebecca));
+        assert_eq!(ColA::parse("rgb(40% 20% 60%)"), Ok(rebecca));
+        assert_eq!(ColA::parse("#fff"), Ok(ColA::WHITE));
+        assert_eq!("transparent".parse(), Ok(ColA::TRANSPARENT));
+        assert_eq!(
+            ColA::parse("rgba(255, 0, 0, 0.5)"),
+            Ok(ColA::new(1.0, 0.0, 0.0, 0.5))
+        );
+        assert_eq!(
+            ColA::parse("#ff000080").map(|c| c.to_rgba_arr()),
+            Ok([255, 0, 0, 128])
+        );
+        assert!(approx(ColA::parse("hsl(270 50% 40%)").unwrap(), rebecca));
+        assert!(approx(
+            ColA::parse("hsla(0.75turn, 50%, 40%, 1)").unwrap(),
+            rebecca
+        ));
+
+        assert!(ColA::parse("").is_err());
+        assert!(ColA::parse("#12345").is_err());
+        assert!(ColA::parse("#ggg").is_err());
+        assert!(ColA::parse("notacolor").is_err());
+        assert!(ColA::parse("rgb(1, 2)").is_err());
+        assert!(ColA::parse("lab(1 2 3)").is_err());
+    }
+
+    #[test]
+    fn premultiply() {
+        let col = LinColA::new(1.0, 0.5, 0.0, 0.5);
+        assert_eq!(col.premultiplied(), LinColA::new(0.5, 0.25, 0.0, 0.5));
+        assert_eq!(col.premultiplied().unpremultiplied(), col);
+    }
+}
@@ -0,0 +1,166 @@
+//! Alternative color spaces: HSL, HSV and OKLab.
+
+use corelib::types::Float;
+
+use super::{ColA, LinColA};
+
+/// Hue, saturation, lightness. The hue is in degrees (0..360), everything else in 0..=1.
+///
+/// Like css, this is a different representation of the sRGB encoded color.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Hsl {
+    pub h: Float,
+    pub s: Float,
+    pub l: Float,
+    pub a: Float,
+}
+
+/// Hue, saturation, value. The hue is in degrees (0..360), everything else in 0..=1.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Hsv {
+    pub h: Float,
+    pub s: Float,
+    pub v: Float,
+    pub a: Float,
+}
+
+/// The perceptual OKLab color space: https://bottosson.github.io/posts/oklab/
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Oklab {
+    pub l: Float,
+    pub a: Float,
+    pub b: Float,
+    pub alpha: Float,
+}
+
+/// the hue in degrees together with the max and min component
+fn hue(col: ColA) -> (Float, Float, Float) {
+    let max = col.r.max(col.g).max(col.b);
+    let min = col.r.min(col.g).min(col.b);
+    let delta = max - min;
+
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == col.r {
+        60.0 * ((col.g - col.b) / delta).rem_euclid(6.0)
+    } else if max == col.g {
+        60.0 * ((col.b - col.r) / delta + 2.0)
+    } else {
+        60.0 * ((col.r - col.g) / delta + 4.0)
+    };
+
+    (h, max, min)
+}
+
+/// build a color from the hue, the chroma and the value of the smallest component
+fn from_hue(h: Float, chroma: Float, min: Float, a: Float) -> ColA {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = chroma * (1.0 - (h.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match h as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    ColA::new(r + min, g + min, b + min, a)
+}
+
+impl From<ColA> for Hsl {
+    fn from(col: ColA) -> Self {
+        let (h, max, min) = hue(col);
+        let l = (max + min) / 2.0;
+        let s = if max == min {
+            0.0
+        } else {
+            (max - min) / (1.0 - (2.0 * l - 1.0).abs())
+        };
+        Self { h, s, l, a: col.a }
+    }
+}
+
+impl From<Hsl> for ColA {
+    fn from(hsl: Hsl) -> Self {
+        let chroma = (1.0 - (2.0 * hsl.l - 1.0).abs()) * hsl.s;
+        from_hue(hsl.h, chroma, hsl.l - chroma / 2.0, hsl.a)
+    }
+}
+
+impl From<ColA> for Hsv {
+    fn from(col: ColA) -> Self {
+        let (h, max, min) = hue(col);
+        let s = if max == 0.0 { 0.0 } else { (max - min) / max };
+        Self {
+            h,
+            s,
+            v: max,
+            a: col.a,
+        }
+    }
+}
+
+impl From<Hsv> for ColA {
+    fn from(hsv: Hsv) -> Self {
+        let chroma = hsv.v * hsv.s;
+        from_hue(hsv.h, chroma, hsv.v - chroma, hsv.a)
+    }
+}
+
+impl From<LinColA> for Oklab {
+    fn from(col: LinColA) -> Self {
+        let (r, g, b) = (col.r as f64, col.g as f64, col.b as f64);
+
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+
+        Self {
+            l: (0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s) as Float,
+            a: (1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s) as Float,
+            b: (0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s) as Float,
+            alpha: col.a,
+        }
+    }
+}
+
+impl From<Oklab> for LinColA {
+    fn from(lab: Oklab) -> Self {
+        let (ll, a, b) = (lab.l as f64, lab.a as f64, lab.b as f64);
+
+        let l = (ll + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m = (ll - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s = (ll - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+
+        Self {
+            r: (4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s) as Float,
+            g: (-1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s) as Float,
+            b: (-0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s) as Float,
+            a: lab.alpha,
+        }
+    }
+}
+
+impl From<ColA> for Oklab {
+    fn from(col: ColA) -> Self {
+        col.to_linear().into()
+    }
+}
+
+impl From<Oklab> for ColA {
+    fn from(lab: Oklab) -> Self {
+        LinColA::from(lab).to_srgb()
+    }
+}
+
+impl Oklab {
+    /// interpolate in OKLab which gives perceptually even gradients
+    pub fn lerp(self, other: Self, t: Float) -> Self {
+        Self {
+            l: self.l + (other.l - self.l) * t,
+            a: self.a + (other.a - self.a) * t,
+            b: self.b + (other.b - self.b) * t,
+            alpha: self.alpha + (other.alpha - self.alpha) * t,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! The sRGB transfer function, exact and as lookup tables for 8 bit values.
+
+use std::sync::LazyLock;
+
+use corelib::types::Float;
+
+/// the exact sRGB decoding function for a value in 0..=1
+pub fn srgb_to_linear(v: Float) -> Float {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// the exact sRGB encoding function for a value in 0..=1
+pub fn linear_to_srgb(v: Float) -> Float {
+    if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+#[inline]
+pub(crate) fn unit_to_u8(v: Float) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+static SRGB8_TO_LINEAR: LazyLock<[Float; 256]> =
+    LazyLock::new(|| std::array::from_fn(|i| srgb_to_linear(i as Float / 255.0)));
+
+/// The amount of entries in the linear to sRGB lookup table. It has to be fine enough
+/// that every 8 bit value survives a roundtrip through linear space.
+const LINEAR_TO_SRGB8_SIZE: usize = 1 << 13;
+
+static LINEAR_TO_SRGB8: LazyLock<Box<[u8; LINEAR_TO_SRGB8_SIZE]>> = LazyLock::new(|| {
+    Box::new(std::array::from_fn(|i| {
+        unit_to_u8(linear_to_srgb(
+            i as Float / (LINEAR_TO_SRGB8_SIZE - 1) as Float,
+        ))
+    }))
+});
+
+/// decode an 8 bit sRGB value using a lookup table
+#[inline]
+pub fn srgb8_to_linear(v: u8) -> Float {
+    SRGB8_TO_LINEAR[v as usize]
+}
+
+/// encode a linear value to 8 bit sRGB using a lookup table
+#[inline]
+pub fn linear_to_srgb8(v: Float) -> u8 {
+    let idx = (v.clamp(0.0, 1.0) * (LINEAR_TO_SRGB8_SIZE - 1) as Float).round() as usize;
+    LINEAR_TO_SRGB8[idx]
+}
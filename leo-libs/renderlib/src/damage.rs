@@ -0,0 +1,357 @@
+//! Damage tracking for the tiled renderer.
+//!
+//! Instead of redrawing the whole image every frame only the tiles whose content could
+//! have changed are rasterized again. The damaged tiles are either given explicitly as a
+//! list of dirty rectangles or computed by comparing the triangles binned into every tile
+//! with the ones from the previous frame.
+
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::Arc,
+};
+
+use imglib::{Rgba, RgbaImage};
+use mathlib::color::ColA;
+
+use crate::{
+    material::{Material, Texture},
+    primitive::Primitive,
+    raster::PixelRect,
+    tiled::{DEFAULT_TILE_SIZE, TileBins, draw_bins},
+};
+
+/// Remembers what was drawn into every tile of the last frame
+#[derive(Debug)]
+pub struct DamageTracker {
+    tile_size: usize,
+    threads: usize,
+    clear_color: Rgba,
+    dims: (usize, usize),
+    /// a hash of the ordered triangles of every tile from the last frame.
+    /// `None` means the tile has to be redrawn regardless.
+    tile_hashes: Vec<Option<u64>>,
+}
+
+impl DamageTracker {
+    pub fn new(clear_color: Rgba) -> Self {
+        Self::with_tile_size(clear_color, DEFAULT_TILE_SIZE)
+    }
+
+    pub fn with_tile_size(clear_color: Rgba, tile_size: usize) -> Self {
+        Self {
+            tile_size,
+            threads: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1),
+            clear_color,
+            dims: (0, 0),
+            tile_hashes: vec![],
+        }
+    }
+
+    /// Force the next frame to be drawn completely
+    pub fn invalidate(&mut self) {
+        self.tile_hashes.iter_mut().for_each(|h| *h = None);
+    }
+
+    /// Draw the frame, only rasterizing the tiles that changed since the last call.
+    ///
+    /// The target is expected to still contain the last frame. Returns the damaged
+    /// regions of the target.
+    pub fn render(&mut self, prims: &[Primitive], target: &mut RgbaImage) -> Vec<PixelRect> {
+        self.render_inner(prims, target, |_, _| false)
+    }
+
+    /// Draw the frame, only rasterizing the tiles touching one of the dirty rectangles.
+    ///
+    /// Tiles that changed without being covered by a dirty rectangle are redrawn as well
+    /// so the result is always the same as a full redraw.
+    pub fn render_dirty(
+        &mut self,
+        prims: &[Primitive],
+        target: &mut RgbaImage,
+        dirty: &[PixelRect],
+    ) -> Vec<PixelRect> {
+        let tile_size = self.tile_size;
+        self.render_inner(prims, target, |tx, ty| {
+            let tile = PixelRect::new(
+                tx * tile_size,
+                ty * tile_size,
+                (tx + 1) * tile_size,
+                (ty + 1) * tile_size,
+            );
+            dirty.iter().any(|d| d.intersects(&tile))
+        })
+    }
+
+    fn render_inner(
+        &mut self,
+        prims: &[Primitive],
+        target: &mut RgbaImage,
+        forced: impl Fn(usize, usize) -> bool,
+    ) -> Vec<PixelRect> {
+        let dims = target.dimensions();
+        let bins = TileBins::new(prims, dims, self.tile_size);
+
+        if dims != self.dims {
+            self.dims = dims;
+            self.tile_hashes = vec![None; bins.bins.len()];
+        }
+
+        let mut damaged = vec![false; bins.bins.len()];
+        let mut rects = vec![];
+        for ty in 0..bins.tiles_y {
+            for tx in 0..bins.tiles_x {
+                let idx = ty * bins.tiles_x + tx;
+                let hash = tile_hash(prims, &bins, idx);
+                if self.tile_hashes[idx] != Some(hash) || forced(tx, ty) {
+                    self.tile_hashes[idx] = Some(hash);
+                    damaged[idx] = true;
+                    rects.push(bins.tile_rect(tx, ty, dims));
+                }
+            }
+        }
+
+        for rect in &rects {
+            for y in rect.min_y..rect.max_y {
+                target.data[y * dims.0 + rect.min_x..y * dims.0 + rect.max_x]
+                    .fill(self.clear_color);
+            }
+        }
+
+        draw_bins(prims, &bins, target, self.threads, |tx, ty| {
+            damaged[ty * bins.tiles_x + tx]
+        });
+
+        rects
+    }
+}
+
+fn tile_hash(prims: &[Primitive], bins: &TileBins, idx: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let tile = bins.tile_rect(
+        idx % bins.tiles_x,
+        idx / bins.tiles_x,
+        (usize::MAX, usize::MAX),
+    );
+    for tri in &bins.bins[idx] {
+        let primitive = &prims[tri.primitive];
+        let (a, b, c) = bins.mesh(prims, tri.primitive).triangle(tri.first_index);
+        let vertices = [a, b, c];
+        for v in vertices {
+            v.x.to_bits().hash(&mut hasher);
+            v.y.to_bits().hash(&mut hasher);
+        }
+        hash_material(&primitive.material, &mut hasher);
+        if let Material::Texture(texture) = &primitive.material {
+            // the bounds of the triangle within the tile
+            let low = |v: [f32; 3]| v.into_iter().fold(f32::INFINITY, f32::min).floor().max(0.0);
+            let high = |v: [f32; 3]| v.into_iter().fold(0.0, f32::max).ceil();
+            let (xs, ys) = (vertices.map(|v| v.x), vertices.map(|v| v.y));
+            let area = PixelRect::new(
+                (low(xs) as usize).max(tile.min_x),
+                (low(ys) as usize).max(tile.min_y),
+                (high(xs) as usize).min(tile.max_x),
+                (high(ys) as usize).min(tile.max_y),
+            );
+            hash_texels(texture, area, &mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn hash_material(material: &Material, hasher: &mut impl Hasher) {
+    fn hash_col(col: &ColA, hasher: &mut impl Hasher) {
+        col.r.to_bits().hash(hasher);
+        col.g.to_bits().hash(hasher);
+        col.b.to_bits().hash(hasher);
+        col.a.to_bits().hash(hasher);
+    }
+
+    match material {
+        Material::SingleColor(col) => {
+            0u8.hash(hasher);
+            hash_col(col, hasher);
+        }
+        Material::SimpleGradient {
+            color1,
+            color2,
+            direction,
+            size,
+        } => {
+            1u8.hash(hasher);
+            hash_col(color1, hasher);
+            hash_col(color2, hasher);
+            direction.to_bits().hash(hasher);
+            size.to_bits().hash(hasher);
+        }
+        Material::Texture(texture) => {
+            2u8.hash(hasher);
+            texture.origin.x.to_bits().hash(hasher);
+            texture.origin.y.to_bits().hash(hasher);
+            hash_col(&texture.color, hasher);
+            Arc::as_ptr(&texture.image).hash(hasher);
+        }
+    }
+}
+
+/// Hash the texels a texture puts onto the pixels of `area`. An atlas like the glyph
+/// cache's is drawn into in place, so a new glyph can take the place of an old one
+/// without the image or the quad changing.
+fn hash_texels(texture: &Texture, area: PixelRect, hasher: &mut impl Hasher) {
+    let image = &texture.image;
+    // the texel under a pixel, like `Paint::shade` picks it
+    let offset = (
+        -texture.origin.x.round() as isize,
+        -texture.origin.y.round() as isize,
+    );
+    let texel =
+        |p: usize, offset: isize, len: usize| (p as isize + offset).clamp(0, len as isize) as usize;
+    let (min_x, max_x) = (
+        texel(area.min_x, offset.0, image.width),
+        texel(area.max_x, offset.0, image.width),
+    );
+    for y in area.min_y..area.max_y {
+        let ty = y as isize + offset.1;
+        if ty < 0 || ty as usize >= image.height {
+            continue;
+        }
+        let row = ty as usize * image.width;
+        image.data[row + min_x..row + max_x.max(min_x)].hash(hasher);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use imglib::{Image, Rgba, RgbaImage};
+    use mathlib::{color::ColA, vectors::Vec2F};
+
+    use crate::{
+        draw_primitives,
+        material::{Material, Texture},
+        primitive::{Mesh, MeshType, Primitive},
+        raster::PixelRect,
+    };
+
+    use super::DamageTracker;
+
+    fn quad(x: f32, y: f32, size: f32) -> Primitive {
+        Primitive {
+            mesh: Mesh {
+                ty: MeshType::Triangle,
+                vertices: vec![
+                    Vec2F::new(x, y),
+                    Vec2F::new(x + size, y),
+                    Vec2F::new(x + size, y + size),
+                    Vec2F::new(x, y + size),
+                ],
+                indices: vec![0, 1, 2, 0, 2, 3],
+            },
+            material: Material::SingleColor(ColA::WHITE),
+        }
+    }
+
+    fn full_redraw(prims: &[Primitive]) -> RgbaImage {
+        let mut img = RgbaImage::new(200, 200, Rgba::BLACK);
+        draw_primitives(prims, &mut img);
+        img
+    }
+
+    #[test]
+    fn only_changed_tiles_are_redrawn() {
+        let mut tracker = DamageTracker::with_tile_size(Rgba::BLACK, 32);
+        let mut img = RgbaImage::new(200, 200, Rgba::BLACK);
+
+        let frame1 = vec![quad(10.0, 10.0, 20.0), quad(100.0, 100.0, 50.0)];
+        let damaged = tracker.render(&frame1, &mut img);
+        assert_eq!(damaged.len(), 7 * 7);
+        assert_eq!(img, full_redraw(&frame1));
+
+        let damaged = tracker.render(&frame1, &mut img);
+        assert!(damaged.is_empty());
+
+        // moving the small quad only touches the tiles it was and is in
+        let frame2 = vec![quad(40.0, 10.0, 20.0), quad(100.0, 100.0, 50.0)];
+        let damaged = tracker.render(&frame2, &mut img);
+        assert_eq!(damaged.len(), 2);
+        assert_eq!(img, full_redraw(&frame2));
+
+        let damaged = tracker.render_dirty(&frame2, &mut img, &[PixelRect::new(190, 0, 200, 1)]);
+        assert_eq!(
+            damaged,
+            vec![
+                PixelRect::new(160, 0, 192, 32),
+                PixelRect::new(192, 0, 200, 32)
+            ]
+        );
+        assert_eq!(img, full_redraw(&frame2));
+    }
+
+    /// A quad that shows the texture with its top left texel at the corner
+    fn texture_quad(x: f32, y: f32, image: &Arc<Image<u8>>, color: ColA) -> Primitive {
+        Primitive {
+            material: Material::Texture(Texture {
+                image: image.clone(),
+                origin: Vec2F::new(x, y),
+                color,
+            }),
+            ..quad(x, y, image.width as f32)
+        }
+    }
+
+    #[test]
+    fn texture_changes_are_redrawn() {
+        let mut tracker = DamageTracker::with_tile_size(Rgba::BLACK, 32);
+        let mut img = RgbaImage::new(200, 200, Rgba::BLACK);
+        // two glyphs side by side in an atlas
+        let mut atlas = Arc::new(Image::from_fn(16, 8, |x, y| {
+            if x < 8 {
+                (x * 30) as u8
+            } else {
+                (y * 30) as u8
+            }
+        }));
+        let mut frame = vec![texture_quad(40.0, 40.0, &atlas, ColA::WHITE)];
+        tracker.render(&frame, &mut img);
+        assert!(tracker.render(&frame, &mut img).is_empty());
+
+        // only the color changes
+        let red = ColA::new(1.0, 0.0, 0.0, 1.0);
+        frame = vec![texture_quad(40.0, 40.0, &atlas, red)];
+        assert_eq!(
+            tracker.render(&frame, &mut img),
+            [PixelRect::new(32, 32, 64, 64)]
+        );
+        assert_eq!(img, full_redraw(&frame));
+
+        // the atlas gets another glyph in the same place, the image stays the same
+        let ptr = Arc::as_ptr(&atlas);
+        frame.clear();
+        Arc::make_mut(&mut atlas).data[0] = 255;
+        assert_eq!(Arc::as_ptr(&atlas), ptr);
+        frame = vec![texture_quad(40.0, 40.0, &atlas, red)];
+        assert_eq!(
+            tracker.render(&frame, &mut img),
+            [PixelRect::new(32, 32, 64, 64)]
+        );
+        assert_eq!(img, full_redraw(&frame));
+
+        // texels outside of the quad don't matter
+        frame.clear();
+        Arc::make_mut(&mut atlas).data[15] = 255;
+        frame = vec![Primitive {
+            mesh: quad(40.0, 40.0, 8.0).mesh,
+            ..texture_quad(40.0, 40.0, &atlas, red)
+        }];
+        tracker.render(&frame, &mut img);
+        frame.clear();
+        Arc::make_mut(&mut atlas).data[15] = 0;
+        frame = vec![Primitive {
+            mesh: quad(40.0, 40.0, 8.0).mesh,
+            ..texture_quad(40.0, 40.0, &atlas, red)
+        }];
+        assert!(tracker.render(&frame, &mut img).is_empty());
+    }
+}
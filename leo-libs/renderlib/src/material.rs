@@ -1,10 +1,23 @@
+use std::sync::Arc;
+
 use corelib::types::Float;
-use mathlib::color::ColA;
+use imglib::{Image, Rgba};
+use mathlib::{
+    color::{ColA, LinColA},
+    vectors::{Vec2, Vec2F},
+};
+
+use crate::primitive::Mesh;
 
 /// The material of a Mesh or Point Strip
 #[derive(Debug)]
 pub enum Material {
     SingleColor(ColA),
+    /// A linear gradient from `color1` to `color2`.
+    ///
+    /// The gradient runs in the `direction` (an angle in radians, 0 pointing along the
+    /// x axis) and starts at the edge of the mesh that is furthest in the opposite
+    /// direction. After `size` pixels `color2` is reached.
     SimpleGradient {
         color1: ColA,
         color2: ColA,
@@ -14,5 +27,118 @@ pub enum Material {
     Texture(Texture),
 }
 
+/// A coverage texture tinted with a color, like a glyph from a glyph atlas.
+///
+/// Texels map 1:1 to pixels: the pixel at `p` shows the texel at `p - origin`, pixels
+/// outside of the image are not painted.
+#[derive(Debug, Clone)]
+pub struct Texture {
+    /// how much of the color covers a pixel, 255 is fully covered
+    pub image: Arc<Image<u8>>,
+    /// where the top left texel of the image lands on the target
+    pub origin: Vec2F,
+    pub color: ColA,
+}
+
+/// A material prepared for rasterizing a specific mesh.
+///
+/// All color math happens in linear light.
 #[derive(Debug)]
-pub struct Texture;
+pub enum Paint {
+    Solid(LinColA),
+    Gradient {
+        from: LinColA,
+        to: LinColA,
+        /// the direction of the gradient divided by its size
+        step: Vec2F,
+        /// the value of `dot(p, step)` at the start of the gradient
+        start: Float,
+    },
+    Texture {
+        image: Arc<Image<u8>>,
+        /// the texel offset of the pixel at (0, 0)
+        offset: (isize, isize),
+        color: LinColA,
+    },
+}
+
+impl Paint {
+    pub fn new(material: &Material, mesh: &Mesh) -> Self {
+        match material {
+            Material::SingleColor(col) => Self::Solid(col.to_linear()),
+            Material::SimpleGradient {
+                color1,
+                color2,
+                direction,
+                size,
+            } => {
+                let step = Vec2::dir(*direction) / size.max(Float::EPSILON);
+                let start = mesh
+                    .vertices
+                    .iter()
+                    .map(|v| v.dot(&step))
+                    .fold(Float::INFINITY, Float::min);
+                Self::Gradient {
+                    from: color1.to_linear(),
+                    to: color2.to_linear(),
+                    step,
+                    start,
+                }
+            }
+            Material::Texture(texture) => Self::Texture {
+                image: texture.image.clone(),
+                offset: (
+                    -texture.origin.x.round() as isize,
+                    -texture.origin.y.round() as isize,
+                ),
+                color: texture.color.to_linear(),
+            },
+        }
+    }
+
+    /// The color of the pixel at (x, y) after painting over `dst`
+    #[inline]
+    pub fn shade(&self, x: usize, y: usize, dst: Rgba) -> Rgba {
+        let col = match self {
+            Self::Solid(col) => *col,
+            Self::Gradient {
+                from,
+                to,
+                step,
+                start,
+            } => {
+                let p = Vec2::new(x as Float, y as Float);
+                let t = (p.dot(step) - start).clamp(0.0, 1.0);
+                from.lerp(*to, t)
+            }
+            Self::Texture {
+                image,
+                offset,
+                color,
+            } => {
+                let tx = x as isize + offset.0;
+                let ty = y as isize + offset.1;
+                if tx < 0 || ty < 0 || tx as usize >= image.width || ty as usize >= image.height {
+                    return dst;
+                }
+                let coverage = image.get_pixel(tx as usize, ty as usize);
+                if coverage == 0 {
+                    return dst;
+                }
+                LinColA {
+                    a: color.a * coverage as Float / 255.0,
+                    ..*color
+                }
+            }
+        };
+
+        let res = if col.a >= 1.0 {
+            col
+        } else {
+            col.over(LinColA::from_srgb8([dst.r, dst.g, dst.b, dst.a]))
+        };
+
+        let [r, g, b, a] = res.to_srgb8();
+        Rgba { r, g, b, a }
+    }
+}
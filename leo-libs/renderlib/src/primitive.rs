@@ -1,3 +1,6 @@
+use std::borrow::Cow;
+
+use corelib::types::Float;
 use mathlib::vectors::Vec2F;
 
 use crate::material::Material;
@@ -8,26 +11,267 @@ pub struct Primitive {
     pub material: Material,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Mesh {
     pub ty: MeshType,
     pub vertices: Vec<Vec2F>,
     pub indices: Vec<usize>,
 }
 
-#[derive(Debug)]
+impl Mesh {
+    /// The mesh as triangles or a triangle strip, fill shapes are split into trapezoids
+    /// covering the area inside their outline.
+    pub fn triangulate(&self) -> Cow<'_, Mesh> {
+        match self.ty {
+            MeshType::Triangle | MeshType::TriangleStrip => Cow::Borrowed(self),
+            MeshType::FillShape { fill_rule } => {
+                let outline = self.indices.iter().map(|&i| self.vertices[i]);
+                Cow::Owned(fill_shape(&outline.collect::<Vec<_>>(), fill_rule))
+            }
+        }
+    }
+
+    /// The positions in the index buffer where the triangles of a triangulated mesh start,
+    /// a triangle is made of the three indices from there on.
+    pub(crate) fn triangle_starts(&self) -> impl Iterator<Item = usize> {
+        let (count, step) = match self.ty {
+            MeshType::Triangle => (self.indices.len() / 3, 3),
+            MeshType::TriangleStrip => (self.indices.len().saturating_sub(2), 1),
+            MeshType::FillShape { .. } => unreachable!("fill shapes are triangulated first"),
+        };
+        (0..count).map(move |i| i * step)
+    }
+
+    /// The corners of the triangle starting at `start` in the index buffer
+    pub fn triangle(&self, start: usize) -> (Vec2F, Vec2F, Vec2F) {
+        (
+            self.vertices[self.indices[start]],
+            self.vertices[self.indices[start + 1]],
+            self.vertices[self.indices[start + 2]],
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MeshType {
     /// Simple triangles assembled using the vertices and indices
     Triangle,
     /// A triangle strip: https://en.wikipedia.org/wiki/Triangle_strip
     TriangleStrip,
-    /// A list of vertices wihch are filled in as a shape using either
-    /// a non-zero or even odd fill rule.
+    /// A closed outline through the vertices in the order of the indices, the area inside
+    /// it is filled using either a non-zero or even odd fill rule. The outline may cross
+    /// itself.
     FillShape { fill_rule: FillRule },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum FillRule {
     NonZero,
     EvenOdd,
 }
+
+/// An edge of an outline going from `top` down to `bottom`
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    top: Vec2F,
+    bottom: Vec2F,
+    /// 1 if the outline goes down along the edge, -1 if it goes up
+    winding: i32,
+}
+
+impl Edge {
+    fn x_at(&self, y: Float) -> Float {
+        if y == self.bottom.y {
+            return self.bottom.x;
+        }
+        let t = (y - self.top.y) / (self.bottom.y - self.top.y);
+        self.top.x + (self.bottom.x - self.top.x) * t
+    }
+
+    /// The y coordinate where two edges cross, if they do between their ends
+    fn crossing(&self, other: &Edge) -> Option<Float> {
+        let d1 = self.bottom - self.top;
+        let d2 = other.bottom - other.top;
+        let denom = d1.cross(&d2);
+        if denom == 0.0 {
+            return None;
+        }
+        let offset = other.top - self.top;
+        let t = offset.cross(&d2) / denom;
+        let u = offset.cross(&d1) / denom;
+        ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)).then_some(self.top.y + d1.y * t)
+    }
+}
+
+/// Triangulates the area inside a closed outline. The outline is cut into horizontal
+/// slabs at every vertex and crossing, within a slab no edges cross so the inside is a
+/// row of trapezoids between the edges sorted by x.
+fn fill_shape(outline: &[Vec2F], fill_rule: FillRule) -> Mesh {
+    let mut edges = Vec::new();
+    for (i, &a) in outline.iter().enumerate() {
+        let b = outline[(i + 1) % outline.len()];
+        if a.y < b.y {
+            edges.push(Edge {
+                top: a,
+                bottom: b,
+                winding: 1,
+            });
+        } else if a.y > b.y {
+            edges.push(Edge {
+                top: b,
+                bottom: a,
+                winding: -1,
+            });
+        }
+    }
+
+    let mut ys = outline.iter().map(|v| v.y).collect::<Vec<_>>();
+    for (i, a) in edges.iter().enumerate() {
+        ys.extend(edges[i + 1..].iter().filter_map(|b| a.crossing(b)));
+    }
+    ys.retain(|y| y.is_finite());
+    ys.sort_by(Float::total_cmp);
+    ys.dedup();
+
+    let mut mesh = Mesh {
+        ty: MeshType::Triangle,
+        vertices: vec![],
+        indices: vec![],
+    };
+    let mut active: Vec<&Edge> = Vec::new();
+    for slab in ys.windows(2) {
+        let (y0, y1) = (slab[0], slab[1]);
+        let mid = (y0 + y1) / 2.0;
+        active.clear();
+        active.extend(edges.iter().filter(|e| e.top.y <= y0 && e.bottom.y >= y1));
+        active.sort_by(|a, b| a.x_at(mid).total_cmp(&b.x_at(mid)));
+
+        let mut winding = 0;
+        let mut left: Option<&Edge> = None;
+        for edge in &active {
+            winding += edge.winding;
+            let inside = match fill_rule {
+                FillRule::NonZero => winding != 0,
+                FillRule::EvenOdd => winding % 2 != 0,
+            };
+            match (left, inside) {
+                (None, true) => left = Some(edge),
+                (Some(l), false) => {
+                    let start = mesh.vertices.len();
+                    mesh.vertices.extend([
+                        Vec2F::new(l.x_at(y0), y0),
+                        Vec2F::new(edge.x_at(y0), y0),
+                        Vec2F::new(edge.x_at(y1), y1),
+                        Vec2F::new(l.x_at(y1), y1),
+                    ]);
+                    mesh.indices.extend([0, 1, 2, 0, 2, 3].map(|i| start + i));
+                    left = None;
+                }
+                _ => {}
+            }
+        }
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod test {
+    use imglib::{Rgba, RgbaImage};
+    use mathlib::color::ColA;
+
+    use super::*;
+    use crate::draw_primitives;
+
+    /// The winding number of the outline around a point
+    fn winding(outline: &[Vec2F], p: Vec2F) -> i32 {
+        let mut winding = 0;
+        for (i, &a) in outline.iter().enumerate() {
+            let b = outline[(i + 1) % outline.len()];
+            let side = (b - a).cross(&(p - a));
+            if a.y <= p.y && b.y > p.y && side > 0.0 {
+                winding += 1;
+            } else if b.y <= p.y && a.y > p.y && side < 0.0 {
+                winding -= 1;
+            }
+        }
+        winding
+    }
+
+    #[test]
+    fn fill_shapes() {
+        let color = ColA::new(0.2, 0.6, 1.0, 0.5);
+        let draw = |mesh: Mesh| {
+            let mut img = RgbaImage::new(100, 100, Rgba::BLACK);
+            let material = Material::SingleColor(color);
+            draw_primitives(&[Primitive { mesh, material }], &mut img);
+            img
+        };
+
+        // the color of a pixel that is covered once
+        let covered = draw(Mesh {
+            ty: MeshType::Triangle,
+            vertices: vec![
+                Vec2F::new(-10.0, -10.0),
+                Vec2F::new(300.0, 0.0),
+                Vec2F::new(0.0, 300.0),
+            ],
+            indices: vec![0, 1, 2],
+        })
+        .get_pixel(50, 50);
+
+        let center = Vec2F::new(50.3, 49.6);
+        let star = (0..5)
+            .map(|i| center + Vec2F::dir(i as Float * 0.4 * std::f32::consts::TAU) * 45.0)
+            .collect::<Vec<_>>();
+        // a square with a dent, the corners are on pixel centers
+        let dented = [
+            (10.5, 10.5),
+            (90.5, 10.5),
+            (90.5, 90.5),
+            (50.5, 40.5),
+            (10.5, 90.5),
+        ]
+        .map(|(x, y)| Vec2F::new(x, y))
+        .to_vec();
+
+        for outline in [star.clone(), dented] {
+            for fill_rule in [FillRule::NonZero, FillRule::EvenOdd] {
+                let img = draw(Mesh {
+                    ty: MeshType::FillShape { fill_rule },
+                    vertices: outline.clone(),
+                    indices: (0..outline.len()).collect(),
+                });
+                for (x, y, pixel) in img.pixels() {
+                    let winding = winding(&outline, Vec2F::new(x as Float, y as Float));
+                    let inside = match fill_rule {
+                        FillRule::NonZero => winding != 0,
+                        FillRule::EvenOdd => winding % 2 != 0,
+                    };
+                    let expected = if inside { covered } else { Rgba::BLACK };
+                    assert_eq!(pixel, expected, "{fill_rule:?} at ({x}, {y})");
+                }
+            }
+        }
+
+        // the pentagon in the middle of the star is wound twice
+        let star = |fill_rule| {
+            draw(Mesh {
+                ty: MeshType::FillShape { fill_rule },
+                vertices: star.clone(),
+                indices: (0..5).collect(),
+            })
+            .get_pixel(50, 50)
+        };
+        assert_eq!(star(FillRule::NonZero), covered);
+        assert_eq!(star(FillRule::EvenOdd), Rgba::BLACK);
+
+        let empty = Mesh {
+            ty: MeshType::FillShape {
+                fill_rule: FillRule::NonZero,
+            },
+            vertices: vec![],
+            indices: vec![],
+        };
+        assert!(empty.triangulate().indices.is_empty());
+    }
+}
@@ -0,0 +1,153 @@
+use corelib::types::Float;
+use imglib::Rgba;
+use mathlib::vectors::{Vec2, Vec2F};
+
+use crate::material::Paint;
+
+/// A rectangle of pixels. The max values are exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelRect {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+impl PixelRect {
+    pub fn new(min_x: usize, min_y: usize, max_x: usize, max_y: usize) -> Self {
+        Self {
+            min_x,
+            min_y,
+            max_x,
+            max_y,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min_x >= self.max_x || self.min_y >= self.max_y
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min_x < other.max_x
+            && other.min_x < self.max_x
+            && self.min_y < other.max_y
+            && other.min_y < self.max_y
+    }
+}
+
+/// A horizontal band of rows of the target image.
+///
+/// The band only borrows its own rows, which makes it possible to hand out
+/// multiple bands of the same image to different threads.
+pub struct Band<'a> {
+    pub data: &'a mut [Rgba],
+    pub width: usize,
+    /// the y coordinate of the first row of the band in the whole image
+    pub start_y: usize,
+}
+
+impl Band<'_> {
+    #[inline]
+    pub fn put_pixel(&mut self, x: usize, y: usize, pixel: Rgba) {
+        self.data[(y - self.start_y) * self.width + x] = pixel;
+    }
+
+    #[inline]
+    pub fn get_pixel(&self, x: usize, y: usize) -> Rgba {
+        self.data[(y - self.start_y) * self.width + x]
+    }
+}
+
+/// The pixel bounds covered by the bounding box of the triangle. The bounds are
+/// clamped to the image and inclusive (like the loops in [`fill_triangle`]).
+pub fn triangle_bounds(
+    a: Vec2F,
+    b: Vec2F,
+    c: Vec2F,
+    dims: (usize, usize),
+) -> (usize, usize, usize, usize) {
+    let min_x = a.x.min(b.x).min(c.x);
+    let max_x = a.x.max(b.x).max(c.x);
+    let min_y = a.y.min(b.y).min(c.y);
+    let max_y = a.y.max(b.y).max(c.y);
+
+    let min_x = min_x.clamp(0.0, (dims.0 - 1) as Float);
+    let max_x = max_x.clamp(0.0, (dims.0 - 1) as Float);
+    let min_y = min_y.clamp(0.0, (dims.1 - 1) as Float);
+    let max_y = max_y.clamp(0.0, (dims.1 - 1) as Float);
+
+    (
+        min_x as usize,
+        min_y as usize,
+        max_x as usize,
+        max_y as usize,
+    )
+}
+
+/// Fill a single triangle into the band. Only pixels inside of `clip` are touched.
+///
+/// The result for a given pixel only depends on the triangle and the pixel position,
+/// so rendering an image in multiple clipped pieces produces the exact same output
+/// as rendering it in one go.
+pub fn fill_triangle(
+    a: Vec2F,
+    b: Vec2F,
+    c: Vec2F,
+    dims: (usize, usize),
+    clip: PixelRect,
+    paint: &Paint,
+    band: &mut Band,
+) {
+    if clip.is_empty() {
+        return;
+    }
+
+    let cross_product = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+
+    let tria = a;
+    let (trib, tric) = if cross_product < 0.0 {
+        // Swap b and c to make the order clockwise
+        (c, b)
+    } else {
+        (b, c)
+    };
+
+    let (min_x, min_y, max_x, max_y) = triangle_bounds(tria, trib, tric, dims);
+
+    let min_x = min_x.max(clip.min_x);
+    let min_y = min_y.max(clip.min_y);
+    let max_x = max_x.min(clip.max_x - 1);
+    let max_y = max_y.min(clip.max_y - 1);
+
+    // pixels exactly on an edge are only filled for top and left edges, so that pixels on
+    // an edge shared by two triangles are not blended twice.
+    let top_left1 = is_top_left(tria, trib);
+    let top_left2 = is_top_left(trib, tric);
+    let top_left3 = is_top_left(tric, tria);
+
+    for y in min_y..=max_y {
+        for x in min_x..=max_x {
+            let p = Vec2::new(x as Float, y as Float);
+
+            let edge1 = (trib.x - tria.x) * (p.y - tria.y) - (trib.y - tria.y) * (p.x - tria.x);
+            let edge2 = (tric.x - trib.x) * (p.y - trib.y) - (tric.y - trib.y) * (p.x - trib.x);
+            let edge3 = (tria.x - tric.x) * (p.y - tric.y) - (tria.y - tric.y) * (p.x - tric.x);
+
+            if inside(edge1, top_left1) && inside(edge2, top_left2) && inside(edge3, top_left3) {
+                let dst = band.get_pixel(x, y);
+                band.put_pixel(x, y, paint.shade(x, y, dst));
+            }
+        }
+    }
+}
+
+#[inline]
+fn is_top_left(a: Vec2F, b: Vec2F) -> bool {
+    let dy = b.y - a.y;
+    (dy == 0.0 && b.x > a.x) || dy < 0.0
+}
+
+#[inline]
+fn inside(edge: Float, top_left: bool) -> bool {
+    edge > 0.0 || (edge == 0.0 && top_left)
+}
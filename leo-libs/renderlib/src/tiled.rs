@@ -0,0 +1,306 @@
+//! A binning renderer: primitives are sorted into screen tiles first and the tiles are
+//! then rasterized in parallel.
+//!
+//! The output is identical to [`crate::draw_primitives`] since every pixel still sees
+//! the triangles covering it in the original order.
+
+use std::borrow::Cow;
+
+use imglib::RgbaImage;
+
+use crate::{
+    material::Paint,
+    primitive::{Mesh, Primitive},
+    raster::{Band, PixelRect, fill_triangle, triangle_bounds},
+};
+
+pub const DEFAULT_TILE_SIZE: usize = 64;
+
+/// A reference to a single triangle of a primitive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TriRef {
+    pub primitive: usize,
+    /// the index into the index buffer of the triangulated mesh where the triangle starts,
+    /// see [`TileBins::mesh`]
+    pub first_index: usize,
+}
+
+/// The triangles of a list of primitives sorted into the tiles they touch.
+#[derive(Debug)]
+pub struct TileBins {
+    pub tile_size: usize,
+    pub tiles_x: usize,
+    pub tiles_y: usize,
+    pub bins: Vec<Vec<TriRef>>,
+    /// the triangles of the primitives that are fill shapes
+    fill_shapes: Vec<Option<Mesh>>,
+}
+
+impl TileBins {
+    pub fn new(prims: &[Primitive], dims: (usize, usize), tile_size: usize) -> Self {
+        assert!(tile_size > 0, "the tile size has to be at least one pixel");
+
+        let tiles_x = dims.0.div_ceil(tile_size);
+        let tiles_y = dims.1.div_ceil(tile_size);
+        let mut bins = vec![vec![]; tiles_x * tiles_y];
+        let fill_shapes = prims
+            .iter()
+            .map(|p| match p.mesh.triangulate() {
+                Cow::Borrowed(_) => None,
+                Cow::Owned(mesh) => Some(mesh),
+            })
+            .collect::<Vec<_>>();
+
+        if tiles_x == 0 || tiles_y == 0 {
+            return Self {
+                tile_size,
+                tiles_x,
+                tiles_y,
+                bins,
+                fill_shapes,
+            };
+        }
+
+        for (prim_idx, primitive) in prims.iter().enumerate() {
+            let mesh = fill_shapes[prim_idx].as_ref().unwrap_or(&primitive.mesh);
+            for start in mesh.triangle_starts() {
+                let (a, b, c) = mesh.triangle(start);
+                let (min_x, min_y, max_x, max_y) = triangle_bounds(a, b, c, dims);
+
+                for ty in min_y / tile_size..=max_y / tile_size {
+                    for tx in min_x / tile_size..=max_x / tile_size {
+                        bins[ty * tiles_x + tx].push(TriRef {
+                            primitive: prim_idx,
+                            first_index: start,
+                        });
+                    }
+                }
+            }
+        }
+
+        Self {
+            tile_size,
+            tiles_x,
+            tiles_y,
+            bins,
+            fill_shapes,
+        }
+    }
+
+    /// The triangulated mesh of a primitive that the triangles in the bins refer to
+    pub fn mesh<'a>(&'a self, prims: &'a [Primitive], primitive: usize) -> &'a Mesh {
+        self.fill_shapes[primitive]
+            .as_ref()
+            .unwrap_or(&prims[primitive].mesh)
+    }
+
+    /// The pixels covered by a tile clamped to the image dimensions
+    pub fn tile_rect(&self, tx: usize, ty: usize, dims: (usize, usize)) -> PixelRect {
+        PixelRect::new(
+            tx * self.tile_size,
+            ty * self.tile_size,
+            ((tx + 1) * self.tile_size).min(dims.0),
+            ((ty + 1) * self.tile_size).min(dims.1),
+        )
+    }
+}
+
+/// Render the primitives onto the target using all available cores.
+pub fn draw_primitives_tiled(prims: &[Primitive], target: &mut RgbaImage) {
+    let threads = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    draw_primitives_tiled_with(prims, target, DEFAULT_TILE_SIZE, threads);
+}
+
+/// Render the primitives onto the target with a specific tile size and thread count.
+pub fn draw_primitives_tiled_with(
+    prims: &[Primitive],
+    target: &mut RgbaImage,
+    tile_size: usize,
+    threads: usize,
+) {
+    let bins = TileBins::new(prims, target.dimensions(), tile_size);
+    draw_bins(prims, &bins, target, threads, |_, _| true);
+}
+
+/// Rasterize the tiles of already binned primitives. Only the tiles for which
+/// `filter(tx, ty)` returns true are drawn.
+pub fn draw_bins(
+    prims: &[Primitive],
+    bins: &TileBins,
+    target: &mut RgbaImage,
+    threads: usize,
+    filter: impl Fn(usize, usize) -> bool + Sync,
+) {
+    let dims = target.dimensions();
+    let width = dims.0;
+    if width == 0 || dims.1 == 0 {
+        return;
+    }
+
+    // every row of tiles is a separate band of the image so that the threads
+    // never write to the same memory.
+    let mut bands = target
+        .data
+        .chunks_mut(bins.tile_size * width)
+        .enumerate()
+        .map(|(ty, data)| {
+            (
+                ty,
+                Band {
+                    data,
+                    width,
+                    start_y: ty * bins.tile_size,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let bands_per_thread = bands.len().div_ceil(threads.max(1));
+
+    let paints = prims
+        .iter()
+        .map(|p| Paint::new(&p.material, &p.mesh))
+        .collect::<Vec<_>>();
+
+    let draw_band = |ty: usize, band: &mut Band| {
+        for tx in 0..bins.tiles_x {
+            if !filter(tx, ty) {
+                continue;
+            }
+            let clip = bins.tile_rect(tx, ty, dims);
+            for tri in &bins.bins[ty * bins.tiles_x + tx] {
+                let (a, b, c) = bins.mesh(prims, tri.primitive).triangle(tri.first_index);
+                fill_triangle(a, b, c, dims, clip, &paints[tri.primitive], band);
+            }
+        }
+    };
+
+    if bands_per_thread >= bands.len() {
+        for (ty, band) in bands.iter_mut() {
+            draw_band(*ty, band);
+        }
+        return;
+    }
+
+    let draw_band = &draw_band;
+    std::thread::scope(|s| {
+        for group in bands.chunks_mut(bands_per_thread) {
+            s.spawn(move || {
+                for (ty, band) in group.iter_mut() {
+                    draw_band(*ty, band);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use imglib::{Rgba, RgbaImage};
+    use mathlib::{color::ColA, vectors::Vec2F};
+
+    use crate::{
+        draw_primitives,
+        material::Material,
+        primitive::{FillRule, Mesh, MeshType, Primitive},
+    };
+
+    use super::draw_primitives_tiled_with;
+
+    fn fan(center: Vec2F, radius: f32, segments: usize) -> Primitive {
+        let mut vertices = vec![center];
+        for i in 0..=segments {
+            let angle = i as f32 / segments as f32 * std::f32::consts::TAU;
+            vertices.push(center + Vec2F::dir(angle) * radius);
+        }
+        let indices = (1..=segments).flat_map(|i| [0, i, i + 1]).collect();
+        Primitive {
+            mesh: Mesh {
+                ty: MeshType::Triangle,
+                vertices,
+                indices,
+            },
+            material: Material::SingleColor(ColA::WHITE),
+        }
+    }
+
+    /// A five pointed star drawn in one stroke, the outline crosses itself
+    fn star(center: Vec2F, radius: f32, fill_rule: FillRule) -> Primitive {
+        let vertices = (0..5)
+            .map(|i| center + Vec2F::dir(i as f32 * 0.4 * std::f32::consts::TAU) * radius)
+            .collect();
+        Primitive {
+            mesh: Mesh {
+                ty: MeshType::FillShape { fill_rule },
+                vertices,
+                indices: (0..5).collect(),
+            },
+            material: Material::SingleColor(ColA::new(0.2, 0.6, 1.0, 0.5)),
+        }
+    }
+
+    #[test]
+    fn tiled_matches_single_threaded() {
+        let prims = vec![
+            fan(Vec2F::new(50.0, 60.0), 45.0, 17),
+            fan(Vec2F::new(130.0, 20.0), 80.0, 5),
+            fan(Vec2F::new(-10.0, 150.0), 30.0, 9),
+            star(Vec2F::new(90.0, 80.0), 60.0, FillRule::NonZero),
+            star(Vec2F::new(140.0, 120.0), 40.0, FillRule::EvenOdd),
+        ];
+
+        let mut expected = RgbaImage::new(173, 151, Rgba::BLACK);
+        draw_primitives(&prims, &mut expected);
+
+        for (tile_size, threads) in [(1, 1), (16, 3), (64, 8), (1000, 2)] {
+            let mut tiled = RgbaImage::new(173, 151, Rgba::BLACK);
+            draw_primitives_tiled_with(&prims, &mut tiled, tile_size, threads);
+            assert_eq!(expected, tiled, "tile size {tile_size}, {threads} threads");
+        }
+    }
+
+    #[test]
+    fn triangle_strips() {
+        // a wavy ribbon with alternating windings
+        let vertices = (0..12)
+            .map(|i| {
+                Vec2F::new(
+                    10.0 + i as f32 * 12.0,
+                    30.0 + (i % 2) as f32 * 40.0 + i as f32 * 3.0,
+                )
+            })
+            .collect::<Vec<_>>();
+        let strip = Primitive {
+            mesh: Mesh {
+                ty: MeshType::TriangleStrip,
+                vertices: vertices.clone(),
+                indices: (0..vertices.len()).collect(),
+            },
+            material: Material::SingleColor(ColA::WHITE),
+        };
+        let triangles = Primitive {
+            mesh: Mesh {
+                ty: MeshType::Triangle,
+                vertices: vertices.clone(),
+                indices: (0..vertices.len() - 2)
+                    .flat_map(|i| [i, i + 1, i + 2])
+                    .collect(),
+            },
+            material: Material::SingleColor(ColA::WHITE),
+        };
+        let mut expected = RgbaImage::new(160, 120, Rgba::BLACK);
+        draw_primitives(&[triangles], &mut expected);
+        assert!(expected.data.iter().any(|&p| p != Rgba::BLACK));
+
+        let prims = [strip];
+        let mut single = RgbaImage::new(160, 120, Rgba::BLACK);
+        draw_primitives(&prims, &mut single);
+        assert_eq!(expected, single);
+
+        let mut tiled = RgbaImage::new(160, 120, Rgba::BLACK);
+        draw_primitives_tiled_with(&prims, &mut tiled, 16, 3);
+        assert_eq!(expected, tiled);
+    }
+}
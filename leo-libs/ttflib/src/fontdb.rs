@@ -0,0 +1,446 @@
+//! A database of the fonts in directories, to pick them by family and style the way CSS
+//! does and to find another font for the characters the picked one has no glyphs for.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use corelib::reader::Reader;
+use unicodelib::grapheme::grapheme_indices;
+
+use crate::{font_count, load_font, tables::cmap::CmapTable, FaceStyle, Font, FontError, Style};
+
+/// The index of a face in a [`FontDb`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FaceId(pub usize);
+
+/// What the database knows about a face without loading it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaceInfo {
+    /// the file of the face, None for font data that was added directly
+    pub path: Option<PathBuf>,
+    /// the index of the face in its collection, see [`load_font`]
+    pub index: usize,
+    pub family: String,
+    pub style: FaceStyle,
+}
+
+struct Face {
+    info: FaceInfo,
+    data: Arc<[u8]>,
+    /// the characters the face has glyphs for
+    cmap: CmapTable,
+}
+
+/// The font a text asks for, the families in the order they are preferred
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<'a> {
+    pub families: &'a [&'a str],
+    pub style: FaceStyle,
+}
+
+/// Font faces indexed by family. The font data is kept in memory, so the faces can be
+/// loaded without reading their files again.
+#[derive(Default)]
+pub struct FontDb {
+    faces: Vec<Face>,
+    /// the faces of every family by its lowercase name
+    families: HashMap<String, Vec<FaceId>>,
+}
+
+impl FontDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the fonts in a directory and its subdirectories, `.ttf`, `.otf`, `.ttc` and
+    /// `.otc` files. Fonts that can't be read are skipped. Returns the number of faces that
+    /// were added.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> io::Result<usize> {
+        let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|entry| entry.path());
+
+        let mut added = 0;
+        for entry in entries {
+            let path = entry.path();
+            // symbolic links to directories are not followed, they could form a loop
+            if entry.file_type()?.is_dir() {
+                added += self.load_dir(&path)?;
+                continue;
+            }
+            let is_font = path
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .is_some_and(|extension| {
+                    ["ttf", "otf", "ttc", "otc"].contains(&extension.to_ascii_lowercase().as_str())
+                });
+            if !is_font {
+                continue;
+            }
+            if let Ok(data) = fs::read(&path) {
+                added += self.load_font_data(data, Some(path));
+            }
+        }
+        Ok(added)
+    }
+
+    /// Add the faces of a font or a collection, `path` is where the data comes from. Faces
+    /// that can't be loaded or have no family name are skipped. Returns the number of faces
+    /// that were added.
+    pub fn load_font_data(&mut self, data: Vec<u8>, path: Option<PathBuf>) -> usize {
+        let data: Arc<[u8]> = data.into();
+        let mut added = 0;
+        for index in 0..font_count(&data).unwrap_or(0) {
+            let Ok(font) = load_font(&data, index) else {
+                continue;
+            };
+            let Some(family) = font.family_name() else {
+                continue;
+            };
+            let id = FaceId(self.faces.len());
+            self.families
+                .entry(family.to_lowercase())
+                .or_default()
+                .push(id);
+            self.faces.push(Face {
+                info: FaceInfo {
+                    path: path.clone(),
+                    index,
+                    family,
+                    style: font.face_style(),
+                },
+                data: data.clone(),
+                cmap: font.cmap.clone(),
+            });
+            added += 1;
+        }
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.faces.is_empty()
+    }
+
+    pub fn faces(&self) -> impl Iterator<Item = (FaceId, &FaceInfo)> {
+        self.faces
+            .iter()
+            .enumerate()
+            .map(|(i, face)| (FaceId(i), &face.info))
+    }
+
+    pub fn face(&self, id: FaceId) -> Option<&FaceInfo> {
+        self.faces.get(id.0).map(|face| &face.info)
+    }
+
+    /// The faces of a family, the name is compared without case
+    pub fn family(&self, family: &str) -> &[FaceId] {
+        self.families
+            .get(&family.to_lowercase())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Load a face from the data in the database
+    pub fn load(&self, id: FaceId) -> Result<Font<'_>, FontError> {
+        let face = self
+            .faces
+            .get(id.0)
+            .ok_or(FontError::InvalidFontIndex(id.0))?;
+        load_font(&face.data, face.info.index)
+    }
+
+    /// Whether a face has a glyph for a character
+    pub fn has_char(&self, id: FaceId, c: char) -> bool {
+        let Some(face) = self.faces.get(id.0) else {
+            return false;
+        };
+        let mut reader = Reader::new_big_endian(&face.data, 0);
+        face.cmap
+            .get_glyph_id(&mut reader, c)
+            .is_some_and(|glyph| glyph != 0)
+    }
+
+    /// The face of a family that matches the style best, see [`match_style`]
+    pub fn best_match(&self, family: &str, style: FaceStyle) -> Option<FaceId> {
+        self.closest(self.family(family).iter().copied(), style)
+    }
+
+    /// The best match of the first family of the query that the database has
+    pub fn query(&self, query: &Query) -> Option<FaceId> {
+        query
+            .families
+            .iter()
+            .find_map(|family| self.best_match(family, query.style))
+    }
+
+    /// The face to draw a character with: the best match of the first family of the query
+    /// that has a glyph for it, or else the face of any family that has one and matches the
+    /// style best. None if no face has the character.
+    pub fn query_char(&self, query: &Query, c: char) -> Option<FaceId> {
+        query
+            .families
+            .iter()
+            .filter_map(|family| self.best_match(family, query.style))
+            .find(|&id| self.has_char(id, c))
+            .or_else(|| {
+                let faces = (0..self.faces.len()).map(FaceId);
+                self.closest(faces.filter(|&id| self.has_char(id, c)), query.style)
+            })
+    }
+
+    /// The face to draw a grapheme cluster with, like [`FontDb::query_char`] but the face
+    /// has to have all characters of the cluster so that marks stay with their letter. The
+    /// first character decides if no face has them all.
+    pub fn query_cluster(&self, query: &Query, cluster: &str) -> Option<FaceId> {
+        let has_all = |id: FaceId| cluster.chars().all(|c| self.has_char(id, c));
+        query
+            .families
+            .iter()
+            .filter_map(|family| self.best_match(family, query.style))
+            .find(|&id| has_all(id))
+            .or_else(|| {
+                let faces = (0..self.faces.len()).map(FaceId);
+                self.closest(faces.filter(|&id| has_all(id)), query.style)
+            })
+            .or_else(|| self.query_char(query, cluster.chars().next()?))
+    }
+
+    /// Split a text into runs of grapheme clusters that are drawn with one face, see
+    /// [`FontDb::query_cluster`]. Clusters that no face has join the run before them, or the
+    /// run after them at the start of the text. A text that no face has is one run without
+    /// a face.
+    pub fn runs(&self, query: &Query, text: &str) -> Vec<(Range<usize>, Option<FaceId>)> {
+        let mut runs: Vec<(Range<usize>, Option<FaceId>)> = Vec::new();
+        for (start, cluster) in grapheme_indices(text) {
+            let end = start + cluster.len();
+            let face = self.query_cluster(query, cluster);
+            match (runs.last_mut(), face) {
+                (Some((range, last)), face) if *last == face || face.is_none() => range.end = end,
+                (Some((range, last @ None)), face) => {
+                    *last = face;
+                    range.end = end;
+                }
+                _ => runs.push((start..end, face)),
+            }
+        }
+        runs
+    }
+
+    /// The face of `faces` that matches the style best, the first one of equal faces
+    fn closest(&self, faces: impl Iterator<Item = FaceId>, style: FaceStyle) -> Option<FaceId> {
+        faces.min_by(|a, b| {
+            match_style(
+                style,
+                self.faces[a.0].info.style,
+                self.faces[b.0].info.style,
+            )
+        })
+    }
+}
+
+/// Which of two faces matches the wanted style better, Less for `a`. Like the CSS font
+/// matching the stretch is compared first, then the style and then the weight.
+pub fn match_style(wanted: FaceStyle, a: FaceStyle, b: FaceStyle) -> Ordering {
+    let key = |face: FaceStyle| {
+        (
+            stretch_key(wanted.stretch, face.stretch),
+            style_key(wanted.style, face.style),
+            weight_key(wanted.weight, face.weight),
+        )
+    };
+    key(a).cmp(&key(b))
+}
+
+/// Narrower widths are tried first for normal and condensed ones, wider for expanded
+fn stretch_key(wanted: u16, stretch: u16) -> (bool, u16) {
+    let narrower_first = wanted <= FaceStyle::NORMAL_STRETCH;
+    let other_side = match stretch.cmp(&wanted) {
+        Ordering::Equal => false,
+        Ordering::Less => !narrower_first,
+        Ordering::Greater => narrower_first,
+    };
+    (other_side, stretch.abs_diff(wanted))
+}
+
+/// Italic and oblique faces stand in for each other before upright ones
+fn style_key(wanted: Style, style: Style) -> usize {
+    let order = match wanted {
+        Style::Normal => [Style::Normal, Style::Oblique, Style::Italic],
+        Style::Italic => [Style::Italic, Style::Oblique, Style::Normal],
+        Style::Oblique => [Style::Oblique, Style::Italic, Style::Normal],
+    };
+    order
+        .iter()
+        .position(|&s| s == style)
+        .unwrap_or(order.len())
+}
+
+/// For regular weights bolder faces up to 500 come first, then lighter and then bolder
+/// ones. Lighter weights go on with lighter faces and bolder ones with bolder faces.
+fn weight_key(wanted: u16, weight: u16) -> (u8, u16) {
+    let distance = weight.abs_diff(wanted);
+    let group = match wanted {
+        400..=500 if (wanted..=500).contains(&weight) => 0,
+        400..=500 if weight < wanted => 1,
+        400..=500 => 2,
+        0..=399 if weight <= wanted => 0,
+        0..=399 => 1,
+        _ if weight >= wanted => 0,
+        _ => 1,
+    };
+    (group, distance)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{collection, roboto, roboto_cff};
+
+    fn style(weight: u16, stretch: u16, style: Style) -> FaceStyle {
+        FaceStyle {
+            weight,
+            stretch,
+            style,
+        }
+    }
+
+    /// The index of the face that matches the wanted style best
+    fn best(faces: &[FaceStyle], wanted: FaceStyle) -> usize {
+        (0..faces.len())
+            .min_by(|&a, &b| match_style(wanted, faces[a], faces[b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn weights() {
+        let faces = [300, 600, 900].map(|weight| style(weight, 5, Style::Normal));
+        let weight = |weight| faces[best(&faces, style(weight, 5, Style::Normal))].weight;
+        // regular weights take lighter faces before bolder ones above 500
+        assert_eq!(weight(400), 300);
+        assert_eq!(weight(500), 300);
+        assert_eq!(weight(600), 600);
+        assert_eq!(weight(700), 900);
+        assert_eq!(weight(950), 900);
+        assert_eq!(weight(350), 300);
+        assert_eq!(weight(200), 300);
+
+        let faces = [400, 500, 700].map(|weight| style(weight, 5, Style::Normal));
+        let weight = |weight| faces[best(&faces, style(weight, 5, Style::Normal))].weight;
+        assert_eq!(weight(450), 500);
+        assert_eq!(weight(300), 400);
+        assert_eq!(weight(600), 700);
+    }
+
+    #[test]
+    fn stretches_and_styles() {
+        let faces = [3, 7].map(|stretch| style(400, stretch, Style::Normal));
+        let stretch = |stretch| faces[best(&faces, style(400, stretch, Style::Normal))].stretch;
+        assert_eq!(stretch(5), 3);
+        assert_eq!(stretch(4), 3);
+        assert_eq!(stretch(6), 7);
+        assert_eq!(stretch(9), 7);
+
+        let styles = |styles: &[Style], wanted| {
+            let faces: Vec<_> = styles.iter().map(|&s| style(400, 5, s)).collect();
+            styles[best(&faces, style(400, 5, wanted))]
+        };
+        use Style::*;
+        assert_eq!(styles(&[Normal, Oblique], Italic), Oblique);
+        assert_eq!(styles(&[Normal, Italic], Oblique), Italic);
+        assert_eq!(styles(&[Italic, Oblique], Normal), Oblique);
+        assert_eq!(styles(&[Normal], Italic), Normal);
+
+        // the stretch counts before the style and the style before the weight
+        let faces = [
+            style(700, 5, Italic),
+            style(400, 3, Normal),
+            style(400, 5, Italic),
+        ];
+        assert_eq!(best(&faces, style(400, 5, Normal)), 2);
+    }
+
+    #[test]
+    fn database() {
+        let mut db = FontDb::new();
+        assert_eq!(db.load_dir("../../test-data").unwrap(), 2);
+        assert!(db.load_dir("../../test-data/missing").is_err());
+        let roboto = db.best_match("roboto", FaceStyle::default()).unwrap();
+        let cff = db.best_match("Roboto CFF", FaceStyle::default()).unwrap();
+        let info = db.face(cff).unwrap();
+        assert_eq!(info.family, "Roboto CFF");
+        assert!(info
+            .path
+            .as_ref()
+            .unwrap()
+            .ends_with("RobotoCFF-Regular.otf"));
+        assert_eq!(db.load(roboto).unwrap().num_glyphs(), 1294);
+        assert_eq!(db.best_match("Arial", FaceStyle::default()), None);
+
+        // the first family that the database has
+        let families = ["Arial", "Roboto CFF", "Roboto"];
+        let query = Query {
+            families: &families,
+            style: style(700, 5, Style::Italic),
+        };
+        assert_eq!(db.query(&query), Some(cff));
+        assert_eq!(db.query(&Query::default()), None);
+
+        // characters the test font doesn't have fall back to roboto
+        assert_eq!(db.query_char(&query, 'a'), Some(cff));
+        assert_eq!(db.query_char(&query, 'z'), Some(roboto));
+        assert_eq!(db.query_char(&Query::default(), 'a'), Some(roboto));
+        assert_eq!(db.query_char(&query, '\u{e000}'), None);
+        assert_eq!(
+            db.runs(&query, "\u{e000}Hello ba\u{301}nana \u{e000}zz"),
+            [
+                (0..5, Some(cff)),
+                (5..7, Some(roboto)),
+                (7..10, Some(cff)),
+                // the test font has no combining acute
+                (10..13, Some(roboto)),
+                (13..21, Some(cff)),
+                (21..23, Some(roboto)),
+            ]
+        );
+        assert_eq!(db.runs(&query, "\u{e000}"), [(0..3, None)]);
+        assert_eq!(db.runs(&query, ""), []);
+    }
+
+    #[test]
+    fn collections() {
+        let (ttf, otf) = (roboto(), roboto_cff());
+        let mut db = FontDb::new();
+        assert_eq!(db.load_font_data(collection(&[&otf, &ttf]), None), 2);
+        assert_eq!(db.load_font_data(b"not a font".to_vec(), None), 0);
+        let infos: Vec<_> = db.faces().map(|(_, info)| info.clone()).collect();
+        assert_eq!(
+            infos,
+            [
+                FaceInfo {
+                    path: None,
+                    index: 0,
+                    family: "Roboto CFF".to_string(),
+                    style: FaceStyle::default()
+                },
+                FaceInfo {
+                    path: None,
+                    index: 1,
+                    family: "Roboto".to_string(),
+                    style: FaceStyle::default()
+                },
+            ]
+        );
+        assert!(db.load(FaceId(1)).unwrap().glyph_id('z').is_some());
+        assert!(db.has_char(FaceId(1), 'z'));
+        assert!(!db.has_char(FaceId(0), 'z'));
+        assert!(db.load(FaceId(2)).is_err());
+    }
+}
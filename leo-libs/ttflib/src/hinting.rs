@@ -0,0 +1,1952 @@
+//! The TrueType instruction interpreter, which fits the outlines of a font to the pixel
+//! grid of a size. The font program defines functions, the control value program adapts
+//! the control values to the size, and the program of every glyph moves its points. It
+//! works like the classic rasterizer (version 35) and hints in both directions.
+
+use std::mem;
+
+use crate::{
+    tables::{
+        glyf::{self, BoundingBox, GlyphData, GlyphTable, MAX_COMPONENTS, MAX_COMPONENT_DEPTH},
+        maxp::MaxpHeader,
+    },
+    Font, FontError, GlyphId,
+};
+
+/// The instructions a program may execute, so that loops in broken fonts end
+const MAX_STEPS: usize = 1_000_000;
+/// How deep function calls may nest
+const MAX_CALL_DEPTH: usize = 64;
+/// The zone of the points that are not part of the outline
+const TWILIGHT: usize = 0;
+/// The zone of the outline and its phantom points
+const GLYPH: usize = 1;
+const TOUCHED_X: u8 = 1;
+const TOUCHED_Y: u8 = 2;
+
+/// A point in 1/64 pixels, or a unit vector in 2.14 fixed point
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+const X_AXIS: Point = Point { x: 0x4000, y: 0 };
+const Y_AXIS: Point = Point { x: 0, y: 0x4000 };
+
+impl Point {
+    fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x.wrapping_sub(other.x), self.y.wrapping_sub(other.y))
+    }
+
+    fn get(self, x_axis: bool) -> i32 {
+        if x_axis {
+            self.x
+        } else {
+            self.y
+        }
+    }
+
+    fn set(&mut self, x_axis: bool, value: i32) {
+        if x_axis {
+            self.x = value
+        } else {
+            self.y = value
+        }
+    }
+}
+
+/// `a * b / c` rounded to the nearest integer
+fn mul_div(a: i32, b: i32, c: i32) -> i32 {
+    if c == 0 {
+        return if (a ^ b) < 0 { -i32::MAX } else { i32::MAX };
+    }
+    let (a, b, c) = (a as i64, b as i64, c as i64);
+    let value = (a.abs() * b.abs() + c.abs() / 2) / c.abs();
+    let value = if (a < 0) ^ (b < 0) ^ (c < 0) {
+        -value
+    } else {
+        value
+    };
+    value.clamp(-(i32::MAX as i64), i32::MAX as i64) as i32
+}
+
+/// `a * b` with `b` in 16.16 fixed point
+fn mul_fix(a: i32, b: i32) -> i32 {
+    let ab = a as i64 * b as i64;
+    ((ab + 0x8000 + (ab >> 63)) >> 16) as i32
+}
+
+/// `a / b` in 16.16 fixed point
+fn div_fix(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        return i32::MAX;
+    }
+    let q = ((a as i64).abs() * 0x10000 + (b as i64).abs() / 2) / (b as i64).abs();
+    let q = q.min(i32::MAX as i64) as i32;
+    if (a < 0) ^ (b < 0) {
+        -q
+    } else {
+        q
+    }
+}
+
+/// The length of a vector projected onto a unit vector
+fn dot(p: Point, v: Point) -> i32 {
+    let product = p.x as i64 * v.x as i64 + p.y as i64 * v.y as i64;
+    ((product + 0x2000 + (product >> 63)) >> 14) as i32
+}
+
+/// A vector with the direction of `(x, y)`, the x axis for a zero vector
+fn normalize(x: i32, y: i32) -> Point {
+    let length = (x as f64).hypot(y as f64);
+    if length == 0.0 {
+        return X_AXIS;
+    }
+    Point::new(
+        (x as f64 / length * 16384.0).round() as i32,
+        (y as f64 / length * 16384.0).round() as i32,
+    )
+}
+
+/// The direction from `b` to `a`, turned counterclockwise by 90 degrees if `perpendicular`
+fn line_vector(a: Point, b: Point, perpendicular: bool) -> Point {
+    let d = a.sub(b);
+    if d == Point::default() {
+        X_AXIS
+    } else if perpendicular {
+        normalize(d.y.wrapping_neg(), d.x)
+    } else {
+        normalize(d.x, d.y)
+    }
+}
+
+fn round_to_pixel(value: i32) -> i32 {
+    value.wrapping_add(32) & -64
+}
+
+/// How distances are rounded, whole pixels by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Round {
+    Grid,
+    HalfGrid,
+    DoubleGrid,
+    DownToGrid,
+    UpToGrid,
+    Off,
+    /// multiples of `period` shifted by `phase`, distances round up from `threshold` on
+    Super {
+        period: i32,
+        phase: i32,
+        threshold: i32,
+    },
+}
+
+impl Round {
+    /// Round the length of a distance, the sign doesn't change
+    fn apply(self, distance: i32) -> i32 {
+        let d = (distance as i64).abs();
+        let rounded = match self {
+            Self::Grid => (d + 32) & -64,
+            Self::HalfGrid => (d & -64) + 32,
+            Self::DoubleGrid => (d + 16) & -32,
+            Self::DownToGrid => d & -64,
+            Self::UpToGrid => (d + 63) & -64,
+            Self::Off => d,
+            Self::Super {
+                period,
+                phase,
+                threshold,
+            } => {
+                let d = d + (threshold - phase) as i64;
+                if d < 0 {
+                    phase as i64
+                } else {
+                    d / period as i64 * period as i64 + phase as i64
+                }
+            }
+        };
+        let rounded = rounded.min(i32::MAX as i64) as i32;
+        if distance < 0 {
+            -rounded
+        } else {
+            rounded
+        }
+    }
+
+    /// The rounding of SROUND and S45ROUND, `grid_period` is in 2.14 fixed point
+    fn new_super(selector: i32, grid_period: i32) -> Self {
+        let period = match selector & 0xc0 {
+            0x00 => grid_period / 2,
+            0x80 => grid_period * 2,
+            _ => grid_period,
+        };
+        let phase = match selector & 0x30 {
+            0x00 => 0,
+            0x10 => period / 4,
+            0x20 => period / 2,
+            _ => period * 3 / 4,
+        };
+        let threshold = match selector & 0x0f {
+            0 => period - 1,
+            n => (n - 4) * period / 8,
+        };
+        Self::Super {
+            period: period >> 8,
+            phase: phase >> 8,
+            threshold: threshold >> 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GraphicsState {
+    /// distances are measured along the projection vector
+    projection: Point,
+    /// the projection vector for the original outline
+    dual: Point,
+    /// points move along the freedom vector
+    freedom: Point,
+    /// rp0, rp1 and rp2
+    ref_points: [i32; 3],
+    /// zp0, zp1 and zp2, each [`TWILIGHT`] or [`GLYPH`]
+    zones: [usize; 3],
+    /// how often the next instruction that loops is repeated
+    loop_count: i32,
+    min_distance: i32,
+    round: Round,
+    /// control values that differ more than this from the outline are not used
+    control_value_cut_in: i32,
+    /// distances closer than this to the single width become the single width
+    single_width_cut_in: i32,
+    single_width: i32,
+    delta_base: i32,
+    delta_shift: i32,
+    auto_flip: bool,
+    /// set by the control value program, bit 0 turns the glyph programs off and bit 1
+    /// makes them start from the default state
+    instruct_control: i32,
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        Self {
+            projection: X_AXIS,
+            dual: X_AXIS,
+            freedom: X_AXIS,
+            ref_points: [0; 3],
+            zones: [GLYPH; 3],
+            loop_count: 1,
+            min_distance: 64,
+            round: Round::Grid,
+            control_value_cut_in: 68,
+            single_width_cut_in: 0,
+            single_width: 0,
+            delta_base: 9,
+            delta_shift: 3,
+            auto_flip: true,
+            instruct_control: 0,
+        }
+    }
+}
+
+impl GraphicsState {
+    /// The state a glyph program starts with, the control value program decides about the
+    /// rest
+    fn for_glyph(self) -> Self {
+        let default = Self::default();
+        let base = if self.instruct_control & 2 != 0 {
+            Self {
+                instruct_control: self.instruct_control,
+                ..default
+            }
+        } else {
+            self
+        };
+        Self {
+            projection: default.projection,
+            dual: default.dual,
+            freedom: default.freedom,
+            ref_points: default.ref_points,
+            zones: default.zones,
+            loop_count: default.loop_count,
+            round: default.round,
+            ..base
+        }
+    }
+}
+
+/// The points that the instructions move
+#[derive(Debug, Clone, Default)]
+struct Zone {
+    /// the original points in font units, distances in the original outline are measured
+    /// with them
+    units: Vec<Point>,
+    /// font units to 1/64 pixels in 16.16 fixed point
+    units_scale: i32,
+    /// the original points scaled to the size
+    original: Vec<Point>,
+    current: Vec<Point>,
+    touched: Vec<u8>,
+    on_curve: Vec<bool>,
+    /// the last point of every contour
+    ends: Vec<usize>,
+}
+
+impl Zone {
+    fn twilight(len: usize) -> Self {
+        Self {
+            units: vec![Point::default(); len],
+            units_scale: 0,
+            original: vec![Point::default(); len],
+            current: vec![Point::default(); len],
+            touched: vec![0; len],
+            on_curve: vec![false; len],
+            ends: Vec::new(),
+        }
+    }
+
+    /// IUP, move the points that weren't touched in one direction like the touched points
+    /// around them in their contour
+    fn interpolate_untouched(&mut self, x_axis: bool) {
+        let flag = if x_axis { TOUCHED_X } else { TOUCHED_Y };
+        let mut start = 0;
+        for &end in &self.ends {
+            if end >= self.current.len() || end < start {
+                break;
+            }
+            let touched: Vec<usize> = (start..=end)
+                .filter(|&i| self.touched[i] & flag != 0)
+                .collect();
+            if let [point] = touched[..] {
+                let delta = self.current[point]
+                    .get(x_axis)
+                    .wrapping_sub(self.original[point].get(x_axis));
+                for i in (start..=end).filter(|&i| i != point) {
+                    let value = self.current[i].get(x_axis).wrapping_add(delta);
+                    self.current[i].set(x_axis, value);
+                }
+            } else {
+                for (k, &a) in touched.iter().enumerate() {
+                    let b = touched[(k + 1) % touched.len()];
+                    // the points after a up to b, around the end of the contour
+                    let mut i = a;
+                    loop {
+                        i = if i == end { start } else { i + 1 };
+                        if i == b {
+                            break;
+                        }
+                        let value = self.interpolate(i, a, b, x_axis);
+                        self.current[i].set(x_axis, value);
+                    }
+                }
+            }
+            start = end + 1;
+        }
+    }
+
+    /// Where an untouched point goes between two touched ones
+    fn interpolate(&self, i: usize, a: usize, b: usize, x_axis: bool) -> i32 {
+        let (a, b) = if self.units[a].get(x_axis) > self.units[b].get(x_axis) {
+            (b, a)
+        } else {
+            (a, b)
+        };
+        let unit = |p: usize| self.units[p].get(x_axis);
+        let original = |p: usize| self.original[p].get(x_axis);
+        let current = |p: usize| self.current[p].get(x_axis);
+        // points outside of the two are shifted like the closer one
+        if original(i) <= original(a) {
+            return original(i).wrapping_add(current(a).wrapping_sub(original(a)));
+        }
+        if original(i) >= original(b) {
+            return original(i).wrapping_add(current(b).wrapping_sub(original(b)));
+        }
+        if unit(a) == unit(b) {
+            return current(a);
+        }
+        let scale = div_fix(current(b).wrapping_sub(current(a)), unit(b) - unit(a));
+        current(a).wrapping_add(mul_fix(unit(i) - unit(a), scale))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Program {
+    Font,
+    ControlValue,
+    Glyph,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Programs<'a> {
+    font: &'a [u8],
+    control_value: &'a [u8],
+    glyph: &'a [u8],
+}
+
+impl<'a> Programs<'a> {
+    fn code(&self, program: Program) -> &'a [u8] {
+        match program {
+            Program::Font => self.font,
+            Program::ControlValue => self.control_value,
+            Program::Glyph => self.glyph,
+        }
+    }
+}
+
+/// A function or an instruction defined by the font
+#[derive(Debug, Clone, Copy)]
+struct Definition {
+    program: Program,
+    start: usize,
+}
+
+/// A function that is running and where to go after it
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    program: Program,
+    return_pc: usize,
+    definition: Definition,
+    /// the calls of a LOOPCALL that are left, this one included
+    remaining: i32,
+}
+
+/// How much room the programs get, from maxp
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    twilight_points: usize,
+    storage: usize,
+    function_defs: usize,
+    stack: usize,
+}
+
+/// The length of the instruction at `pc` including the data it pushes
+fn instruction_length(code: &[u8], pc: usize) -> Result<usize, FontError> {
+    let len = match code[pc] {
+        0x40 => 2 + *code.get(pc + 1).ok_or(FontError::InvalidProgram)? as usize,
+        0x41 => 2 + 2 * *code.get(pc + 1).ok_or(FontError::InvalidProgram)? as usize,
+        op @ 0xb0..=0xb7 => 1 + (op - 0xaf) as usize,
+        op @ 0xb8..=0xbf => 1 + 2 * (op - 0xb7) as usize,
+        _ => 1,
+    };
+    if pc + len > code.len() {
+        return Err(FontError::InvalidProgram);
+    }
+    Ok(len)
+}
+
+/// Skip the instructions of an IF or ELSE branch that isn't taken, `pc` is after the IF
+/// or ELSE. The result is after the matching EIF, or after the ELSE if `to_else`.
+fn skip_branch(code: &[u8], mut pc: usize, to_else: bool) -> Result<usize, FontError> {
+    let mut depth = 0;
+    while pc < code.len() {
+        match code[pc] {
+            // IF
+            0x58 => depth += 1,
+            // ELSE
+            0x1b if depth == 0 && to_else => return Ok(pc + 1),
+            // EIF
+            0x59 if depth == 0 => return Ok(pc + 1),
+            0x59 => depth -= 1,
+            _ => {}
+        }
+        pc += instruction_length(code, pc)?;
+    }
+    Err(FontError::InvalidProgram)
+}
+
+/// The ENDF of a definition that starts at `pc`
+fn find_end(code: &[u8], mut pc: usize) -> Result<usize, FontError> {
+    while pc < code.len() {
+        match code[pc] {
+            0x2d => return Ok(pc),
+            // definitions don't nest
+            0x2c | 0x89 => break,
+            _ => pc += instruction_length(code, pc)?,
+        }
+    }
+    Err(FontError::InvalidProgram)
+}
+
+#[derive(Debug, Clone)]
+struct Vm {
+    state: GraphicsState,
+    stack: Vec<i32>,
+    max_stack: usize,
+    storage: Vec<i32>,
+    /// the control values in 1/64 pixels
+    cvt: Vec<i32>,
+    functions: Vec<Option<Definition>>,
+    instruction_defs: Vec<Option<Definition>>,
+    zones: [Zone; 2],
+    /// pixels per em in 1/64 pixels
+    ppem: i32,
+    /// font units to 1/64 pixels in 16.16 fixed point
+    scale: i32,
+}
+
+impl Vm {
+    fn new(limits: Limits, ppem: i32, units_per_em: u16, cvt: &[i16]) -> Self {
+        let mut vm = Self {
+            state: GraphicsState::default(),
+            stack: Vec::new(),
+            // broken fonts often need a few more than they say
+            max_stack: limits.stack + 32,
+            storage: vec![0; limits.storage],
+            cvt: Vec::new(),
+            functions: vec![None; limits.function_defs],
+            instruction_defs: vec![None; 256],
+            zones: [Zone::twilight(limits.twilight_points), Zone::default()],
+            ppem,
+            scale: div_fix(ppem, units_per_em as i32),
+        };
+        vm.cvt = cvt.iter().map(|&v| vm.scale(v as i32)).collect();
+        vm
+    }
+
+    /// Font units to 1/64 pixels
+    fn scale(&self, units: i32) -> i32 {
+        mul_fix(units, self.scale)
+    }
+
+    fn scale_point(&self, p: Point) -> Point {
+        Point::new(self.scale(p.x), self.scale(p.y))
+    }
+
+    /// False if the control value program turned the glyph programs off for this size
+    fn hinted(&self) -> bool {
+        self.state.instruct_control & 1 == 0
+    }
+
+    /// The pixels per em that MPPEM and the deltas use
+    fn whole_ppem(&self) -> i32 {
+        (self.ppem + 32) >> 6
+    }
+
+    /// The top of the stack, like FreeType an empty stack gives zeros
+    fn pop(&mut self) -> i32 {
+        self.stack.pop().unwrap_or(0)
+    }
+
+    fn push(&mut self, value: i32) -> Result<(), FontError> {
+        if self.stack.len() >= self.max_stack {
+            return Err(FontError::InvalidProgram);
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn push_bool(&mut self, value: bool) -> Result<(), FontError> {
+        self.push(value as i32)
+    }
+
+    /// The zone that zp0, zp1 or zp2 points to
+    fn zone(&self, zp: usize) -> usize {
+        self.state.zones[zp]
+    }
+
+    /// Check that a point is in the zone of zp0, zp1 or zp2
+    fn point(&self, zp: usize, point: i32) -> Result<usize, FontError> {
+        let len = self.zones[self.zone(zp)].current.len();
+        usize::try_from(point)
+            .ok()
+            .filter(|&p| p < len)
+            .ok_or(FontError::InvalidProgram)
+    }
+
+    fn pop_point(&mut self, zp: usize) -> Result<usize, FontError> {
+        let point = self.pop();
+        self.point(zp, point)
+    }
+
+    /// The loop count for an instruction that repeats, it is reset afterwards
+    fn take_loop(&mut self) -> i32 {
+        mem::replace(&mut self.state.loop_count, 1)
+    }
+
+    fn project(&self, p: Point) -> i32 {
+        dot(p, self.state.projection)
+    }
+
+    fn dual_project(&self, p: Point) -> i32 {
+        dot(p, self.state.dual)
+    }
+
+    fn current(&self, zone: usize, point: usize) -> Point {
+        self.zones[zone].current[point]
+    }
+
+    fn original(&self, zone: usize, point: usize) -> Point {
+        self.zones[zone].original[point]
+    }
+
+    fn round(&self, distance: i32) -> i32 {
+        self.state.round.apply(distance)
+    }
+
+    /// At least the minimum distance in the direction of the original distance
+    fn keep_min_distance(&self, distance: i32, original: i32) -> i32 {
+        let min = self.state.min_distance;
+        if original >= 0 {
+            distance.max(min)
+        } else {
+            distance.min(min.wrapping_neg())
+        }
+    }
+
+    fn cvt(&self, index: i32) -> i32 {
+        usize::try_from(index)
+            .ok()
+            .and_then(|i| self.cvt.get(i).copied())
+            .unwrap_or(0)
+    }
+
+    fn set_cvt(&mut self, index: i32, value: i32) {
+        if let Some(v) = usize::try_from(index)
+            .ok()
+            .and_then(|i| self.cvt.get_mut(i))
+        {
+            *v = value;
+        }
+    }
+
+    /// How far a point moves along the projection vector when it moves by one along the
+    /// freedom vector, in 2.14 fixed point
+    fn freedom_factor(&self) -> i32 {
+        let (p, f) = (self.state.projection, self.state.freedom);
+        let factor = ((p.x as i64 * f.x as i64 + p.y as i64 * f.y as i64) >> 14) as i32;
+        // almost perpendicular vectors would move the point very far
+        if factor.abs() < 0x400 {
+            0x4000
+        } else {
+            factor
+        }
+    }
+
+    /// The distance between two points in the original outline, measured in font units in
+    /// the glyph zone for precision
+    fn original_distance(&self, zone_a: usize, a: usize, zone_b: usize, b: usize) -> i32 {
+        if zone_a == TWILIGHT || zone_b == TWILIGHT {
+            return self.dual_project(self.original(zone_a, a).sub(self.original(zone_b, b)));
+        }
+        let zone = &self.zones[GLYPH];
+        let units = self.dual_project(zone.units[a].sub(zone.units[b]));
+        mul_fix(units, zone.units_scale)
+    }
+
+    /// Move a point along the freedom vector so that its projection changes by `distance`
+    fn move_point(&mut self, zone: usize, point: usize, distance: i32) {
+        let freedom = self.state.freedom;
+        let factor = self.freedom_factor();
+        let zone = &mut self.zones[zone];
+        if freedom.x != 0 {
+            let x = &mut zone.current[point].x;
+            *x = x.wrapping_add(mul_div(distance, freedom.x, factor));
+            zone.touched[point] |= TOUCHED_X;
+        }
+        if freedom.y != 0 {
+            let y = &mut zone.current[point].y;
+            *y = y.wrapping_add(mul_div(distance, freedom.y, factor));
+            zone.touched[point] |= TOUCHED_Y;
+        }
+    }
+
+    /// Shift a point by a vector along the freedom vector
+    fn shift_point(&mut self, zone: usize, point: usize, d: Point, touch: bool) {
+        let freedom = self.state.freedom;
+        let zone = &mut self.zones[zone];
+        if freedom.x != 0 {
+            zone.current[point].x = zone.current[point].x.wrapping_add(d.x);
+            if touch {
+                zone.touched[point] |= TOUCHED_X;
+            }
+        }
+        if freedom.y != 0 {
+            zone.current[point].y = zone.current[point].y.wrapping_add(d.y);
+            if touch {
+                zone.touched[point] |= TOUCHED_Y;
+            }
+        }
+    }
+
+    /// How far SHP, SHC and SHZ shift, the movement of rp1 in zp0 or of rp2 in zp1, with
+    /// its zone and point
+    fn displacement(&self, opcode: u8) -> Result<(usize, usize, Point), FontError> {
+        let (zp, ref_point) = if opcode & 1 != 0 { (0, 1) } else { (1, 2) };
+        let point = self.point(zp, self.state.ref_points[ref_point])?;
+        let zone = self.zone(zp);
+        let d = self.project(self.current(zone, point).sub(self.original(zone, point)));
+        let freedom = self.state.freedom;
+        let factor = self.freedom_factor();
+        let d = Point::new(mul_div(d, freedom.x, factor), mul_div(d, freedom.y, factor));
+        Ok((zone, point, d))
+    }
+
+    /// Run a program from the start, with the functions it calls
+    fn run(&mut self, programs: &Programs, program: Program) -> Result<(), FontError> {
+        let mut current = program;
+        let mut code = programs.code(program);
+        let mut pc = 0;
+        let mut calls: Vec<Frame> = Vec::new();
+        self.stack.clear();
+        for _ in 0..MAX_STEPS {
+            if pc >= code.len() {
+                // only the program itself may end without ENDF
+                return match calls.is_empty() {
+                    true => Ok(()),
+                    false => Err(FontError::InvalidProgram),
+                };
+            }
+            let opcode = code[pc];
+            let mut next = pc + instruction_length(code, pc)?;
+            let mut call = None;
+            match opcode {
+                // NPUSHB, NPUSHW, PUSHB and PUSHW
+                0x40 | 0x41 | 0xb0..=0xbf => {
+                    let (words, start) = match opcode {
+                        0x40 => (false, pc + 2),
+                        0x41 => (true, pc + 2),
+                        op => (op >= 0xb8, pc + 1),
+                    };
+                    if words {
+                        for bytes in code[start..next].chunks_exact(2) {
+                            self.push(i16::from_be_bytes([bytes[0], bytes[1]]) as i32)?;
+                        }
+                    } else {
+                        for &byte in &code[start..next] {
+                            self.push(byte as i32)?;
+                        }
+                    }
+                }
+                // IF
+                0x58 => {
+                    if self.pop() == 0 {
+                        next = skip_branch(code, next, true)?;
+                    }
+                }
+                // ELSE, reached at the end of the branch that was taken
+                0x1b => next = skip_branch(code, next, false)?,
+                // EIF
+                0x59 => {}
+                // JMPR, JROT and JROF
+                0x1c | 0x78 | 0x79 => {
+                    let jump = match opcode {
+                        0x1c => true,
+                        0x78 => self.pop() != 0,
+                        _ => self.pop() == 0,
+                    };
+                    let offset = self.pop();
+                    if jump {
+                        next = pc
+                            .checked_add_signed(offset as isize)
+                            .filter(|&pc| pc <= code.len())
+                            .ok_or(FontError::InvalidProgram)?;
+                    }
+                }
+                // FDEF and IDEF
+                0x2c | 0x89 => {
+                    if program == Program::Glyph {
+                        return Err(FontError::InvalidProgram);
+                    }
+                    let index = self.pop();
+                    let end = find_end(code, next)?;
+                    let definitions = match opcode {
+                        0x2c => &mut self.functions,
+                        _ => &mut self.instruction_defs,
+                    };
+                    let slot = usize::try_from(index)
+                        .ok()
+                        .and_then(|i| definitions.get_mut(i))
+                        .ok_or(FontError::InvalidProgram)?;
+                    *slot = Some(Definition {
+                        program: current,
+                        start: next,
+                    });
+                    next = end + 1;
+                }
+                // CALL and LOOPCALL
+                0x2b | 0x2a => {
+                    let index = self.pop();
+                    let count = if opcode == 0x2a { self.pop() } else { 1 };
+                    let definition = usize::try_from(index)
+                        .ok()
+                        .and_then(|i| self.functions.get(i).copied().flatten())
+                        .ok_or(FontError::InvalidProgram)?;
+                    call = Some((definition, count));
+                }
+                // ENDF
+                0x2d => {
+                    let frame = calls.last_mut().ok_or(FontError::InvalidProgram)?;
+                    frame.remaining -= 1;
+                    if frame.remaining > 0 {
+                        next = frame.definition.start;
+                    } else {
+                        current = frame.program;
+                        code = programs.code(current);
+                        next = frame.return_pc;
+                        calls.pop();
+                    }
+                }
+                _ => {
+                    if !self.execute(opcode, program)? {
+                        let definition = self.instruction_defs[opcode as usize]
+                            .ok_or(FontError::InvalidProgram)?;
+                        call = Some((definition, 1));
+                    }
+                }
+            }
+
+            if let Some((definition, count)) = call {
+                if count > 0 {
+                    if calls.len() >= MAX_CALL_DEPTH {
+                        return Err(FontError::InvalidProgram);
+                    }
+                    calls.push(Frame {
+                        program: current,
+                        return_pc: next,
+                        definition,
+                        remaining: count,
+                    });
+                    current = definition.program;
+                    code = programs.code(current);
+                    next = definition.start;
+                }
+            }
+            pc = next;
+        }
+        Err(FontError::InvalidProgram)
+    }
+
+    /// Execute an instruction that doesn't change where the program goes on, `program` is
+    /// the one that runs, not the one that defined a function. False if the opcode is not
+    /// one of the standard instructions.
+    fn execute(&mut self, opcode: u8, program: Program) -> Result<bool, FontError> {
+        match opcode {
+            // SVTCA, SPVTCA and SFVTCA, the even ones choose the y axis
+            0x00..=0x05 => {
+                let axis = if opcode & 1 == 0 { Y_AXIS } else { X_AXIS };
+                if opcode < 0x04 {
+                    self.state.projection = axis;
+                    self.state.dual = axis;
+                }
+                if !(0x02..0x04).contains(&opcode) {
+                    self.state.freedom = axis;
+                }
+            }
+            // SPVTL and SFVTL, the odd ones turn the line by 90 degrees
+            0x06..=0x09 => {
+                let b = self.pop_point(2)?;
+                let a = self.pop_point(1)?;
+                let vector = line_vector(
+                    self.current(self.zone(1), a),
+                    self.current(self.zone(2), b),
+                    opcode & 1 != 0,
+                );
+                if opcode < 0x08 {
+                    self.state.projection = vector;
+                    self.state.dual = vector;
+                } else {
+                    self.state.freedom = vector;
+                }
+            }
+            // SPVFS and SFVFS
+            0x0a | 0x0b => {
+                let y = self.pop();
+                let x = self.pop();
+                let vector = normalize(x, y);
+                if opcode == 0x0a {
+                    self.state.projection = vector;
+                    self.state.dual = vector;
+                } else {
+                    self.state.freedom = vector;
+                }
+            }
+            // GPV and GFV
+            0x0c | 0x0d => {
+                let vector = match opcode {
+                    0x0c => self.state.projection,
+                    _ => self.state.freedom,
+                };
+                self.push(vector.x)?;
+                self.push(vector.y)?;
+            }
+            // SFVTPV
+            0x0e => self.state.freedom = self.state.projection,
+            // ISECT
+            0x0f => self.intersect()?,
+            // SRP0, SRP1 and SRP2
+            0x10..=0x12 => self.state.ref_points[(opcode - 0x10) as usize] = self.pop(),
+            // SZP0, SZP1, SZP2 and SZPS
+            0x13..=0x16 => {
+                let zone = match self.pop() {
+                    0 => TWILIGHT,
+                    1 => GLYPH,
+                    _ => return Err(FontError::InvalidProgram),
+                };
+                match opcode {
+                    0x16 => self.state.zones = [zone; 3],
+                    _ => self.state.zones[(opcode - 0x13) as usize] = zone,
+                }
+            }
+            // SLOOP
+            0x17 => {
+                let count = self.pop();
+                if count < 0 {
+                    return Err(FontError::InvalidProgram);
+                }
+                self.state.loop_count = count.min(0xffff);
+            }
+            // RTG and RTHG
+            0x18 => self.state.round = Round::Grid,
+            0x19 => self.state.round = Round::HalfGrid,
+            // SMD
+            0x1a => self.state.min_distance = self.pop(),
+            // SCVTCI, SSWCI and SSW
+            0x1d => self.state.control_value_cut_in = self.pop(),
+            0x1e => self.state.single_width_cut_in = self.pop(),
+            0x1f => {
+                let width = self.pop();
+                self.state.single_width = self.scale(width);
+            }
+            // DUP
+            0x20 => {
+                let value = *self.stack.last().ok_or(FontError::InvalidProgram)?;
+                self.push(value)?;
+            }
+            // POP
+            0x21 => {
+                self.pop();
+            }
+            // CLEAR
+            0x22 => self.stack.clear(),
+            // SWAP
+            0x23 => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push(b)?;
+                self.push(a)?;
+            }
+            // DEPTH
+            0x24 => self.push(self.stack.len() as i32)?,
+            // CINDEX and MINDEX copy or move the element k from the top
+            0x25 | 0x26 => {
+                let k = self.pop();
+                let index = usize::try_from(k)
+                    .ok()
+                    .filter(|&k| (1..=self.stack.len()).contains(&k))
+                    .map(|k| self.stack.len() - k)
+                    .ok_or(FontError::InvalidProgram)?;
+                let value = match opcode {
+                    0x25 => self.stack[index],
+                    _ => self.stack.remove(index),
+                };
+                self.push(value)?;
+            }
+            // ALIGNPTS
+            0x27 => {
+                let b = self.pop_point(0)?;
+                let a = self.pop_point(1)?;
+                let (z0, z1) = (self.zone(0), self.zone(1));
+                let distance = self.project(self.current(z0, b).sub(self.current(z1, a))) / 2;
+                self.move_point(z1, a, distance);
+                self.move_point(z0, b, -distance);
+            }
+            // UTP
+            0x29 => {
+                let point = self.pop_point(0)?;
+                let freedom = self.state.freedom;
+                let touched = &mut self.zones[self.state.zones[0]].touched[point];
+                if freedom.x != 0 {
+                    *touched &= !TOUCHED_X;
+                }
+                if freedom.y != 0 {
+                    *touched &= !TOUCHED_Y;
+                }
+            }
+            // MDAP
+            0x2e | 0x2f => {
+                let point = self.pop_point(0)?;
+                let zone = self.zone(0);
+                let distance = if opcode & 1 != 0 {
+                    let d = self.project(self.current(zone, point));
+                    self.round(d).wrapping_sub(d)
+                } else {
+                    0
+                };
+                self.move_point(zone, point, distance);
+                self.state.ref_points[0] = point as i32;
+                self.state.ref_points[1] = point as i32;
+            }
+            // IUP, the odd one for x
+            0x30 | 0x31 => self.zones[GLYPH].interpolate_untouched(opcode & 1 != 0),
+            // SHP
+            0x32 | 0x33 => {
+                let (_, _, d) = self.displacement(opcode)?;
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(2)?;
+                    self.shift_point(self.zone(2), point, d, true);
+                }
+            }
+            // SHC
+            0x34 | 0x35 => {
+                let (ref_zone, ref_point, d) = self.displacement(opcode)?;
+                let contour = self.pop();
+                let zone = self.zone(2);
+                let ends = &self.zones[zone].ends;
+                let index = usize::try_from(contour)
+                    .ok()
+                    .filter(|&c| c < ends.len())
+                    .ok_or(FontError::InvalidProgram)?;
+                let start = if index == 0 { 0 } else { ends[index - 1] + 1 };
+                let end = ends[index].min(self.zones[zone].current.len() - 1);
+                for point in start..=end {
+                    if zone != ref_zone || point != ref_point {
+                        self.shift_point(zone, point, d, true);
+                    }
+                }
+            }
+            // SHZ, the phantom points stay
+            0x36 | 0x37 => {
+                let (ref_zone, ref_point, d) = self.displacement(opcode)?;
+                if !(0..=1).contains(&self.pop()) {
+                    return Err(FontError::InvalidProgram);
+                }
+                let zone = self.zone(2);
+                let len = match zone {
+                    TWILIGHT => self.zones[zone].current.len(),
+                    _ => self.zones[zone].ends.last().map_or(0, |&end| end + 1),
+                };
+                for point in 0..len.min(self.zones[zone].current.len()) {
+                    if zone != ref_zone || point != ref_point {
+                        self.shift_point(zone, point, d, false);
+                    }
+                }
+            }
+            // SHPIX
+            0x38 => {
+                let distance = self.pop();
+                let freedom = self.state.freedom;
+                let d = Point::new(
+                    mul_div(distance, freedom.x, 0x4000),
+                    mul_div(distance, freedom.y, 0x4000),
+                );
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(2)?;
+                    self.shift_point(self.zone(2), point, d, true);
+                }
+            }
+            // IP
+            0x39 => self.interpolate_points()?,
+            // MSIRP
+            0x3a | 0x3b => {
+                let distance = self.pop();
+                let point = self.pop_point(1)?;
+                let rp0 = self.point(0, self.state.ref_points[0])?;
+                let (z0, z1) = (self.zone(0), self.zone(1));
+                if z1 == TWILIGHT {
+                    let original = self.original(z0, rp0);
+                    self.zones[z1].original[point] = original;
+                    self.zones[z1].current[point] = original;
+                }
+                let current = self.project(self.current(z1, point).sub(self.current(z0, rp0)));
+                self.move_point(z1, point, distance.wrapping_sub(current));
+                self.state.ref_points[1] = rp0 as i32;
+                self.state.ref_points[2] = point as i32;
+                if opcode & 1 != 0 {
+                    self.state.ref_points[0] = point as i32;
+                }
+            }
+            // ALIGNRP
+            0x3c => {
+                let rp0 = self.point(0, self.state.ref_points[0])?;
+                let (z0, z1) = (self.zone(0), self.zone(1));
+                for _ in 0..self.take_loop() {
+                    let point = self.pop_point(1)?;
+                    let distance = self.project(self.current(z1, point).sub(self.current(z0, rp0)));
+                    self.move_point(z1, point, distance.wrapping_neg());
+                }
+            }
+            // RTDG
+            0x3d => self.state.round = Round::DoubleGrid,
+            // MIAP
+            0x3e | 0x3f => {
+                let cvt = self.pop();
+                let point = self.pop_point(0)?;
+                let zone = self.zone(0);
+                let mut distance = self.cvt(cvt);
+                if zone == TWILIGHT {
+                    let freedom = self.state.freedom;
+                    let p = Point::new(
+                        mul_div(distance, freedom.x, 0x4000),
+                        mul_div(distance, freedom.y, 0x4000),
+                    );
+                    self.zones[zone].original[point] = p;
+                    self.zones[zone].current[point] = p;
+                }
+                let current = self.project(self.current(zone, point));
+                if opcode & 1 != 0 {
+                    if (distance.wrapping_sub(current)).abs() > self.state.control_value_cut_in {
+                        distance = current;
+                    }
+                    distance = self.round(distance);
+                }
+                self.move_point(zone, point, distance.wrapping_sub(current));
+                self.state.ref_points[0] = point as i32;
+                self.state.ref_points[1] = point as i32;
+            }
+            // WS and RS, storage outside of the area reads as 0
+            0x42 => {
+                let value = self.pop();
+                let index = self.pop();
+                if let Some(v) = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| self.storage.get_mut(i))
+                {
+                    *v = value;
+                }
+            }
+            0x43 => {
+                let index = self.pop();
+                let value = usize::try_from(index)
+                    .ok()
+                    .and_then(|i| self.storage.get(i).copied())
+                    .unwrap_or(0);
+                self.push(value)?;
+            }
+            // WCVTP and WCVTF, the latter in font units
+            0x44 | 0x70 => {
+                let mut value = self.pop();
+                let index = self.pop();
+                if opcode == 0x70 {
+                    value = self.scale(value);
+                }
+                self.set_cvt(index, value);
+            }
+            // RCVT
+            0x45 => {
+                let index = self.pop();
+                self.push(self.cvt(index))?;
+            }
+            // GC, the odd one measures the original outline
+            0x46 | 0x47 => {
+                let point = self.pop_point(2)?;
+                let zone = self.zone(2);
+                let value = match opcode {
+                    0x46 => self.project(self.current(zone, point)),
+                    _ => self.dual_project(self.original(zone, point)),
+                };
+                self.push(value)?;
+            }
+            // SCFS
+            0x48 => {
+                let value = self.pop();
+                let point = self.pop_point(2)?;
+                let zone = self.zone(2);
+                let current = self.project(self.current(zone, point));
+                self.move_point(zone, point, value.wrapping_sub(current));
+                if zone == TWILIGHT {
+                    self.zones[zone].original[point] = self.zones[zone].current[point];
+                }
+            }
+            // MD, the odd one measures the hinted outline
+            0x49 | 0x4a => {
+                let b = self.pop_point(1)?;
+                let a = self.pop_point(0)?;
+                let (z0, z1) = (self.zone(0), self.zone(1));
+                let distance = match opcode {
+                    0x49 => self.project(self.current(z0, a).sub(self.current(z1, b))),
+                    _ => self.original_distance(z0, a, z1, b),
+                };
+                self.push(distance)?;
+            }
+            // MPPEM and MPS, a point is a pixel
+            0x4b => self.push(self.whole_ppem())?,
+            0x4c => self.push(self.whole_ppem())?,
+            // FLIPON and FLIPOFF
+            0x4d => self.state.auto_flip = true,
+            0x4e => self.state.auto_flip = false,
+            // DEBUG, SANGW, AA, SCANCTRL and SCANTYPE have nothing to do here
+            0x4f | 0x7e | 0x7f | 0x85 | 0x8d => {
+                self.pop();
+            }
+            // LT, LTEQ, GT, GTEQ, EQ and NEQ
+            0x50..=0x55 => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push_bool(match opcode {
+                    0x50 => a < b,
+                    0x51 => a <= b,
+                    0x52 => a > b,
+                    0x53 => a >= b,
+                    0x54 => a == b,
+                    _ => a != b,
+                })?;
+            }
+            // ODD and EVEN
+            0x56 | 0x57 => {
+                let value = self.pop();
+                let rounded = self.round(value) & 127;
+                self.push_bool(rounded == if opcode == 0x56 { 64 } else { 0 })?;
+            }
+            // AND, OR and NOT
+            0x5a | 0x5b => {
+                let b = self.pop() != 0;
+                let a = self.pop() != 0;
+                self.push_bool(if opcode == 0x5a { a && b } else { a || b })?;
+            }
+            0x5c => {
+                let value = self.pop();
+                self.push_bool(value == 0)?;
+            }
+            // DELTAP1, DELTAP2 and DELTAP3
+            0x5d | 0x71 | 0x72 => {
+                let range = match opcode {
+                    0x5d => 0,
+                    0x71 => 16,
+                    _ => 32,
+                };
+                let count = self.pop();
+                let zone = self.zone(0);
+                for _ in 0..count.max(0) {
+                    // a count larger than the pairs on the stack stops at the last pair
+                    if self.stack.len() < 2 {
+                        self.stack.clear();
+                        break;
+                    }
+                    let point = self.pop();
+                    let arg = self.pop();
+                    if let Some(delta) = self.delta(arg, range) {
+                        // points that don't exist are skipped like other rasterizers do
+                        if let Ok(point) = self.point(0, point) {
+                            self.move_point(zone, point, delta);
+                        }
+                    }
+                }
+            }
+            // SDB and SDS
+            0x5e => self.state.delta_base = self.pop(),
+            0x5f => {
+                let shift = self.pop();
+                if !(0..=6).contains(&shift) {
+                    return Err(FontError::InvalidProgram);
+                }
+                self.state.delta_shift = shift;
+            }
+            // ADD, SUB, DIV and MUL, in 1/64
+            0x60..=0x63 => {
+                let b = self.pop();
+                let a = self.pop();
+                let value = match opcode {
+                    0x60 => a.wrapping_add(b),
+                    0x61 => a.wrapping_sub(b),
+                    0x62 if b == 0 => return Err(FontError::InvalidProgram),
+                    0x62 => {
+                        (a as i64 * 64 / b as i64).clamp(-(i32::MAX as i64), i32::MAX as i64) as i32
+                    }
+                    _ => mul_div(a, b, 64),
+                };
+                self.push(value)?;
+            }
+            // ABS, NEG, FLOOR and CEILING
+            0x64..=0x67 => {
+                let value = self.pop();
+                self.push(match opcode {
+                    0x64 => value.wrapping_abs(),
+                    0x65 => value.wrapping_neg(),
+                    0x66 => value & -64,
+                    _ => value.wrapping_add(63) & -64,
+                })?;
+            }
+            // ROUND, the engine compensation is always 0
+            0x68..=0x6b => {
+                let value = self.pop();
+                self.push(self.round(value))?;
+            }
+            // NROUND
+            0x6c..=0x6f => {}
+            // DELTAC1, DELTAC2 and DELTAC3
+            0x73..=0x75 => {
+                let range = (opcode - 0x73) as i32 * 16;
+                let count = self.pop();
+                for _ in 0..count.max(0) {
+                    if self.stack.len() < 2 {
+                        self.stack.clear();
+                        break;
+                    }
+                    let index = self.pop();
+                    let arg = self.pop();
+                    if let Some(delta) = self.delta(arg, range) {
+                        let value = self.cvt(index).wrapping_add(delta);
+                        self.set_cvt(index, value);
+                    }
+                }
+            }
+            // SROUND and S45ROUND, the period of the latter is the diagonal of a pixel
+            0x76 | 0x77 => {
+                let selector = self.pop();
+                let grid_period = if opcode == 0x76 { 0x4000 } else { 0x2d41 };
+                self.state.round = Round::new_super(selector, grid_period);
+            }
+            // ROFF, RUTG and RDTG
+            0x7a => self.state.round = Round::Off,
+            0x7c => self.state.round = Round::UpToGrid,
+            0x7d => self.state.round = Round::DownToGrid,
+            // FLIPPT
+            0x80 => {
+                for _ in 0..self.take_loop() {
+                    let point = self.pop();
+                    let on_curve = usize::try_from(point)
+                        .ok()
+                        .and_then(|p| self.zones[GLYPH].on_curve.get_mut(p))
+                        .ok_or(FontError::InvalidProgram)?;
+                    *on_curve = !*on_curve;
+                }
+            }
+            // FLIPRGON and FLIPRGOFF
+            0x81 | 0x82 => {
+                let high = self.pop();
+                let low = self.pop();
+                let on_curve = &mut self.zones[GLYPH].on_curve;
+                let (Ok(low), Ok(high)) = (usize::try_from(low), usize::try_from(high)) else {
+                    return Err(FontError::InvalidProgram);
+                };
+                let points = on_curve
+                    .get_mut(low..=high)
+                    .ok_or(FontError::InvalidProgram)?;
+                points.fill(opcode == 0x81);
+            }
+            // SDPVTL, the dual vector comes from the original outline
+            0x86 | 0x87 => {
+                let b = self.pop_point(2)?;
+                let a = self.pop_point(1)?;
+                let (z1, z2) = (self.zone(1), self.zone(2));
+                let perpendicular = opcode & 1 != 0;
+                self.state.dual =
+                    line_vector(self.original(z1, a), self.original(z2, b), perpendicular);
+                self.state.projection =
+                    line_vector(self.current(z1, a), self.current(z2, b), perpendicular);
+            }
+            // GETINFO, the classic rasterizer with grayscale rendering
+            0x88 => {
+                let selector = self.pop();
+                let mut info = 0;
+                if selector & 1 != 0 {
+                    info |= 35;
+                }
+                if selector & 32 != 0 {
+                    info |= 1 << 12;
+                }
+                self.push(info)?;
+            }
+            // ROLL
+            0x8a => {
+                let c = self.pop();
+                let b = self.pop();
+                let a = self.pop();
+                self.push(b)?;
+                self.push(c)?;
+                self.push(a)?;
+            }
+            // MAX and MIN
+            0x8b | 0x8c => {
+                let b = self.pop();
+                let a = self.pop();
+                self.push(if opcode == 0x8b { a.max(b) } else { a.min(b) })?;
+            }
+            // INSTCTRL, only the control value program may use it
+            0x8e => {
+                let selector = self.pop();
+                let value = self.pop();
+                if !(1..=3).contains(&selector) {
+                    return Err(FontError::InvalidProgram);
+                }
+                if program == Program::ControlValue {
+                    let bit = 1 << (selector - 1);
+                    self.state.instruct_control &= !bit;
+                    if value != 0 {
+                        self.state.instruct_control |= bit;
+                    }
+                }
+            }
+            // MDRP
+            0xc0..=0xdf => self.move_direct_relative(opcode)?,
+            // MIRP
+            0xe0..=0xff => self.move_indirect_relative(opcode)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// The distance a DELTAP or DELTAC argument moves at this size, None at other sizes
+    fn delta(&self, arg: i32, range: i32) -> Option<i32> {
+        let ppem = ((arg as u32 & 0xf0) >> 4) as i32 + self.state.delta_base + range;
+        if ppem != self.whole_ppem() {
+            return None;
+        }
+        // the steps are -8 to -1 and 1 to 8
+        let mut step = (arg & 0xf) - 8;
+        if step >= 0 {
+            step += 1;
+        }
+        Some(step * (1 << (6 - self.state.delta_shift)))
+    }
+
+    /// ISECT, move a point to where the lines through two pairs of points cross
+    fn intersect(&mut self) -> Result<(), FontError> {
+        let b1 = self.pop_point(0)?;
+        let b0 = self.pop_point(0)?;
+        let a1 = self.pop_point(1)?;
+        let a0 = self.pop_point(1)?;
+        let point = self.pop_point(2)?;
+        let (z0, z1, z2) = (self.zone(0), self.zone(1), self.zone(2));
+        let (a0, a1) = (self.current(z1, a0), self.current(z1, a1));
+        let (b0, b1) = (self.current(z0, b0), self.current(z0, b1));
+
+        let b = b1.sub(b0);
+        let a = a1.sub(a0);
+        let d = b0.sub(a0);
+        let discriminant = mul_div(a.x, -b.y, 64).wrapping_add(mul_div(a.y, b.x, 64));
+        let dot_product = mul_div(a.x, b.x, 64).wrapping_add(mul_div(a.y, b.y, 64));
+        // lines that are almost parallel meet in the middle of the points
+        let p = if 19 * (discriminant as i64).abs() > (dot_product as i64).abs() {
+            let value = mul_div(d.x, -b.y, 64).wrapping_add(mul_div(d.y, b.x, 64));
+            Point::new(
+                a0.x.wrapping_add(mul_div(value, a.x, discriminant)),
+                a0.y.wrapping_add(mul_div(value, a.y, discriminant)),
+            )
+        } else {
+            let mean = |a: i32, b: i32, c: i32, d: i32| {
+                ((a as i64 + b as i64 + c as i64 + d as i64) / 4) as i32
+            };
+            Point::new(mean(a0.x, a1.x, b0.x, b1.x), mean(a0.y, a1.y, b0.y, b1.y))
+        };
+        let zone = &mut self.zones[z2];
+        zone.current[point] = p;
+        zone.touched[point] |= TOUCHED_X | TOUCHED_Y;
+        Ok(())
+    }
+
+    /// IP, keep points where they were between rp1 and rp2
+    fn interpolate_points(&mut self) -> Result<(), FontError> {
+        let rp1 = self.point(0, self.state.ref_points[1])?;
+        let rp2 = self.point(1, self.state.ref_points[2])?;
+        let (z0, z1, z2) = (self.zone(0), self.zone(1), self.zone(2));
+        // the original distances are measured in font units unless a point is in the
+        // twilight zone
+        let twilight = self.state.zones.contains(&TWILIGHT);
+        let units_scale = self.zones[GLYPH].units_scale;
+        let original = |vm: &Self, zone: usize, point: usize| match twilight {
+            true => vm.original(zone, point),
+            false => vm.zones[zone].units[point],
+        };
+        let original_base = original(self, z0, rp1);
+        let current_base = self.current(z0, rp1);
+        let original_range = self.dual_project(original(self, z1, rp2).sub(original_base));
+        let current_range = self.project(self.current(z1, rp2).sub(current_base));
+        for _ in 0..self.take_loop() {
+            let point = self.pop_point(2)?;
+            let distance = self.dual_project(original(self, z2, point).sub(original_base));
+            let current = self.project(self.current(z2, point).sub(current_base));
+            let new = match (distance, original_range) {
+                (0, _) => 0,
+                // rp1 and rp2 were in the same place, keep the original distance
+                (_, 0) if twilight => distance,
+                (_, 0) => mul_fix(distance, units_scale),
+                _ => mul_div(distance, current_range, original_range),
+            };
+            self.move_point(z2, point, new.wrapping_sub(current));
+        }
+        Ok(())
+    }
+
+    /// MDRP, keep the original distance of a point in zp1 to rp0 in zp0. The flags of the
+    /// opcode set rp0 to the point, keep the minimum distance and round.
+    fn move_direct_relative(&mut self, opcode: u8) -> Result<(), FontError> {
+        let point = self.pop_point(1)?;
+        let rp0 = self.point(0, self.state.ref_points[0])?;
+        let (z0, z1) = (self.zone(0), self.zone(1));
+
+        let mut original = self.original_distance(z1, point, z0, rp0);
+        let (width, cut_in) = (self.state.single_width, self.state.single_width_cut_in);
+        if cut_in > 0 && original < width + cut_in && original > width - cut_in {
+            original = if original >= 0 { width } else { -width };
+        }
+        let mut distance = match opcode & 4 {
+            0 => original,
+            _ => self.round(original),
+        };
+        if opcode & 8 != 0 {
+            distance = self.keep_min_distance(distance, original);
+        }
+        let current = self.project(self.current(z1, point).sub(self.current(z0, rp0)));
+        self.move_point(z1, point, distance.wrapping_sub(current));
+
+        self.state.ref_points[1] = rp0 as i32;
+        self.state.ref_points[2] = point as i32;
+        if opcode & 0x10 != 0 {
+            self.state.ref_points[0] = point as i32;
+        }
+        Ok(())
+    }
+
+    /// MIRP, place a point in zp1 at the distance of a control value from rp0 in zp0. The
+    /// flags are those of MDRP, rounding also replaces control values that are too far off
+    /// with the original distance.
+    fn move_indirect_relative(&mut self, opcode: u8) -> Result<(), FontError> {
+        let cvt = self.pop();
+        let point = self.pop_point(1)?;
+        let rp0 = self.point(0, self.state.ref_points[0])?;
+        let (z0, z1) = (self.zone(0), self.zone(1));
+
+        let mut cvt_distance = if cvt == -1 { 0 } else { self.cvt(cvt) };
+        let (width, cut_in) = (self.state.single_width, self.state.single_width_cut_in);
+        if (cvt_distance.wrapping_sub(width)).abs() < cut_in {
+            cvt_distance = if cvt_distance >= 0 { width } else { -width };
+        }
+        if z1 == TWILIGHT {
+            let freedom = self.state.freedom;
+            let base = self.original(z0, rp0);
+            let p = Point::new(
+                base.x
+                    .wrapping_add(mul_div(cvt_distance, freedom.x, 0x4000)),
+                base.y
+                    .wrapping_add(mul_div(cvt_distance, freedom.y, 0x4000)),
+            );
+            self.zones[z1].original[point] = p;
+            self.zones[z1].current[point] = p;
+        }
+
+        let original = self.dual_project(self.original(z1, point).sub(self.original(z0, rp0)));
+        let current = self.project(self.current(z1, point).sub(self.current(z0, rp0)));
+        if self.state.auto_flip && (original ^ cvt_distance) < 0 {
+            cvt_distance = cvt_distance.wrapping_neg();
+        }
+        let mut distance = cvt_distance;
+        if opcode & 4 != 0 {
+            if z0 == z1
+                && (cvt_distance.wrapping_sub(original)).abs() > self.state.control_value_cut_in
+            {
+                distance = original;
+            }
+            distance = self.round(distance);
+        }
+        if opcode & 8 != 0 {
+            distance = self.keep_min_distance(distance, original);
+        }
+        self.move_point(z1, point, distance.wrapping_sub(current));
+
+        self.state.ref_points[1] = rp0 as i32;
+        self.state.ref_points[2] = point as i32;
+        if opcode & 0x10 != 0 {
+            self.state.ref_points[0] = point as i32;
+        }
+        Ok(())
+    }
+}
+
+/// Hints the glyphs of a font at one size. The font and control value programs run when
+/// it is made, each glyph then starts from what they left behind, so glyphs come out the
+/// same in any order.
+#[derive(Debug, Clone)]
+pub struct Hinter {
+    /// see [`Font::id`]
+    font: u64,
+    font_program: Vec<u8>,
+    control_value_program: Vec<u8>,
+    /// the interpreter after the control value program
+    prepared: Vm,
+}
+
+impl Hinter {
+    /// Run the programs of a font for a size in pixels per em. Fails if the font has no
+    /// hinting limits in maxp or its programs are broken.
+    pub fn new(font: &Font, ppem: f32) -> Result<Self, FontError> {
+        let MaxpHeader::Version10(maxp) = &font.maxp else {
+            return Err(FontError::InvalidMaxpVersion(0x5000));
+        };
+        let limits = Limits {
+            twilight_points: maxp.max_twilight_points as usize,
+            storage: maxp.max_storage as usize,
+            function_defs: maxp.max_function_defs as usize,
+            stack: maxp.max_stack_elements as usize,
+        };
+        let mut ppem = ((ppem * 64.0).round() as i32).max(1);
+        // fonts can ask for whole pixels per em
+        if font.head_flags & 0x0008 != 0 {
+            ppem = round_to_pixel(ppem).max(64);
+        }
+        let cvt: Vec<i16> = font
+            .cvt
+            .unwrap_or_default()
+            .chunks_exact(2)
+            .map(|v| i16::from_be_bytes([v[0], v[1]]))
+            .collect();
+
+        let mut hinter = Self {
+            font: font.id(),
+            font_program: font.fpgm.unwrap_or_default().to_vec(),
+            control_value_program: font.prep.unwrap_or_default().to_vec(),
+            prepared: Vm::new(limits, ppem, font.units_per_em(), &cvt),
+        };
+        let programs = Programs {
+            font: &hinter.font_program,
+            control_value: &hinter.control_value_program,
+            glyph: &[],
+        };
+        let vm = &mut hinter.prepared;
+        vm.run(&programs, Program::Font)?;
+        vm.state = GraphicsState::default();
+        vm.run(&programs, Program::ControlValue)?;
+        vm.state = vm.state.for_glyph();
+        Ok(hinter)
+    }
+
+    /// The pixels per em the glyphs are hinted for
+    pub fn ppem(&self) -> f32 {
+        self.prepared.ppem as f32 / 64.0
+    }
+
+    /// The hinted outline of a glyph in 1/64 pixels with the origin on the pen position,
+    /// the bounding box stays the one in font units. A glyph whose program fails keeps
+    /// the points it moved until then, like FreeType does.
+    pub fn outline(&self, font: &Font, id: GlyphId) -> Result<GlyphTable, FontError> {
+        debug_assert_eq!(font.id(), self.font, "the hinter belongs to another font");
+        let (mut glyph, phantom) = self.hint_glyph(font, id.0, 0, &mut 0)?;
+        // the first phantom point is where the pen is
+        for x in &mut glyph.x_coordinates {
+            *x -= phantom[0].x;
+        }
+        Ok(glyph)
+    }
+
+    /// Hint a glyph, the components of a composite glyph are hinted first. `components`
+    /// counts the components read so far. Returns the outline and the phantom points,
+    /// which are the pen before and after the glyph and the ascender and descender.
+    fn hint_glyph(
+        &self,
+        font: &Font,
+        id: u16,
+        depth: usize,
+        components: &mut usize,
+    ) -> Result<(GlyphTable, [Point; 4]), FontError> {
+        let (offset, loca) = font.glyf()?;
+        let data = glyf::read_glyph_data(offset, font.src, id as usize, loca)?;
+        let metrics = font.h_metrics(GlyphId(id));
+        let phantom = |bounding_box: BoundingBox| {
+            let left = bounding_box.x_min as i32 - metrics.lsb as i32;
+            [
+                Point::new(left, 0),
+                Point::new(left + metrics.advance_width as i32, 0),
+                Point::new(0, font.hhea.ascender as i32),
+                Point::new(0, font.hhea.descender as i32),
+            ]
+        };
+        let vm = &self.prepared;
+
+        let (glyph, units, units_scale, original, instructions) = match data {
+            GlyphData::Simple(mut glyph) => {
+                let mut units: Vec<Point> = glyph
+                    .x_coordinates
+                    .iter()
+                    .zip(&glyph.y_coordinates)
+                    .map(|(&x, &y)| Point::new(x, y))
+                    .collect();
+                units.extend(phantom(glyph.bounding_box));
+                let original = units.iter().map(|&p| vm.scale_point(p)).collect();
+                let instructions = mem::take(&mut glyph.instructions);
+                (glyph, units, vm.scale, original, instructions)
+            }
+            GlyphData::Composite {
+                bounding_box,
+                components: parts,
+                instructions,
+            } => {
+                if depth >= MAX_COMPONENT_DEPTH {
+                    return Err(FontError::ComponentLimit);
+                }
+                let mut glyph = GlyphTable {
+                    bounding_box,
+                    ..Default::default()
+                };
+                let mut metrics = phantom(bounding_box).map(|p| vm.scale_point(p));
+                for (child, component) in parts {
+                    *components += 1;
+                    if *components > MAX_COMPONENTS {
+                        return Err(FontError::ComponentLimit);
+                    }
+                    let (child, child_metrics) =
+                        self.hint_glyph(font, child, depth + 1, components)?;
+                    if component.use_my_metrics {
+                        metrics = child_metrics;
+                    }
+                    component.append(&mut glyph, child, |(x, y)| {
+                        let p = vm.scale_point(Point::new(x.round() as i32, y.round() as i32));
+                        match component.round_to_grid && vm.hinted() {
+                            true => (round_to_pixel(p.x) as f32, round_to_pixel(p.y) as f32),
+                            false => (p.x as f32, p.y as f32),
+                        }
+                    })?;
+                }
+                // the instructions of a composite glyph move the hinted components, they
+                // are its original outline
+                let mut original: Vec<Point> = glyph
+                    .x_coordinates
+                    .iter()
+                    .zip(&glyph.y_coordinates)
+                    .map(|(&x, &y)| Point::new(x, y))
+                    .collect();
+                original.extend(metrics);
+                (glyph, original.clone(), 0x10000, original, instructions)
+            }
+        };
+        self.hint_points(glyph, units, units_scale, original, &instructions)
+    }
+
+    /// Run the program of a glyph on its points, which end with the phantom points
+    fn hint_points(
+        &self,
+        mut glyph: GlyphTable,
+        units: Vec<Point>,
+        units_scale: i32,
+        original: Vec<Point>,
+        instructions: &[u8],
+    ) -> Result<(GlyphTable, [Point; 4]), FontError> {
+        let vm = &self.prepared;
+        let mut current = original.clone();
+        let n = current.len();
+        if vm.hinted() {
+            // the phantom points are rounded so that the pen stays on whole pixels
+            current[n - 4].x = round_to_pixel(current[n - 4].x);
+            current[n - 3].x = round_to_pixel(current[n - 3].x);
+            current[n - 2].y = round_to_pixel(current[n - 2].y);
+            current[n - 1].y = round_to_pixel(current[n - 1].y);
+        }
+        let mut on_curve: Vec<bool> = glyph.flags.iter().map(|f| f.on_curve_point).collect();
+        on_curve.resize(n, false);
+
+        if !instructions.is_empty() && vm.hinted() {
+            let mut vm = vm.clone();
+            vm.zones[GLYPH] = Zone {
+                units,
+                units_scale,
+                original,
+                touched: vec![0; n],
+                current,
+                on_curve,
+                ends: glyph
+                    .end_pts_of_contours
+                    .iter()
+                    .map(|&end| end as usize)
+                    .collect(),
+            };
+            let programs = Programs {
+                font: &self.font_program,
+                control_value: &self.control_value_program,
+                glyph: instructions,
+            };
+            // like FreeType, a program that fails leaves the points where it moved them
+            let _ = vm.run(&programs, Program::Glyph);
+            let zone = mem::take(&mut vm.zones[GLYPH]);
+            current = zone.current;
+            on_curve = zone.on_curve;
+        }
+
+        let phantom = [
+            current[n - 4],
+            current[n - 3],
+            current[n - 2],
+            current[n - 1],
+        ];
+        current.truncate(n - 4);
+        glyph.x_coordinates = current.iter().map(|p| p.x).collect();
+        glyph.y_coordinates = current.iter().map(|p| p.y).collect();
+        for (flags, on_curve) in glyph.flags.iter_mut().zip(on_curve) {
+            flags.on_curve_point = on_curve;
+        }
+        Ok((glyph, phantom))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        twilight_points: 4,
+        storage: 8,
+        function_defs: 8,
+        stack: 32,
+    };
+
+    /// An interpreter at 16 pixels per em for a font with 2048 units per em, with its
+    /// points on the x axis
+    fn glyph_vm(points: &[i32]) -> Vm {
+        let mut vm = Vm::new(LIMITS, 16 * 64, 2048, &[100, 1000]);
+        let units: Vec<Point> = points.iter().map(|&x| Point::new(x, 0)).collect();
+        let original: Vec<Point> = units.iter().map(|&p| vm.scale_point(p)).collect();
+        vm.zones[GLYPH] = Zone {
+            units,
+            units_scale: vm.scale,
+            current: original.clone(),
+            original,
+            touched: vec![0; points.len()],
+            on_curve: vec![true; points.len()],
+            ends: vec![points.len() - 1],
+        };
+        vm
+    }
+
+    fn run(vm: &mut Vm, font: &[u8], glyph: &[u8]) -> Result<(), FontError> {
+        let programs = Programs {
+            font,
+            control_value: &[],
+            glyph,
+        };
+        vm.run(&programs, Program::Font)?;
+        vm.run(&programs, Program::Glyph)
+    }
+
+    fn x_coordinates(vm: &Vm) -> Vec<i32> {
+        vm.zones[GLYPH].current.iter().map(|p| p.x).collect()
+    }
+
+    fn roboto() -> Vec<u8> {
+        std::fs::read("../../test-data/Roboto-Regular.ttf").unwrap()
+    }
+
+    #[test]
+    fn arithmetic_and_branches() {
+        let mut vm = glyph_vm(&[0]);
+        #[rustfmt::skip]
+        let code = [
+            // 3 + 5, then 1 if that is 8 and 2 otherwise
+            0xb1, 3, 5, 0x60, 0x20, 0xb0, 8, 0x54, 0x58, 0xb0, 1, 0x1b, 0xb0, 2, 0x59,
+            // FLOOR and CEIL of 100/64
+            0xb0, 100, 0x66, 0xb0, 100, 0x67,
+            // DIV and MUL in 1/64
+            0xb1, 128, 64, 0x62, 0xb1, 128, 32, 0x63,
+        ];
+        run(&mut vm, &[], &code).unwrap();
+        assert_eq!(vm.stack, [8, 1, 64, 128, 128, 64]);
+
+        // dividing by zero fails and an empty stack reads zeros
+        assert!(run(&mut vm, &[], &[0xb0, 1, 0xb0, 0, 0x62]).is_err());
+        vm.stack.clear();
+        run(&mut vm, &[], &[0x60]).unwrap();
+        assert_eq!(vm.stack, [0]);
+    }
+
+    #[test]
+    fn functions() {
+        let mut vm = glyph_vm(&[0]);
+        // function 0 adds one
+        let font = [0xb0, 0, 0x2c, 0xb0, 1, 0x60, 0x2d];
+        // CALL it on 10, then LOOPCALL it three times
+        let glyph = [0xb1, 10, 0, 0x2b, 0xb1, 3, 0, 0x2a];
+        run(&mut vm, &font, &glyph).unwrap();
+        assert_eq!(vm.stack, [14]);
+
+        // glyph programs can't define functions, calls need a function and loops end
+        assert!(run(&mut vm, &[], &[0xb0, 1, 0x2c, 0x2d]).is_err());
+        assert!(run(&mut vm, &[], &[0xb0, 5, 0x2b]).is_err());
+        assert!(run(&mut vm, &[], &[0xb8, 0xff, 0xfd, 0x1c]).is_err());
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(Round::Grid.apply(-95), -64);
+        assert_eq!(Round::HalfGrid.apply(10), 32);
+        assert_eq!(Round::DoubleGrid.apply(40), 32);
+        assert_eq!(Round::DownToGrid.apply(127), 64);
+        assert_eq!(Round::UpToGrid.apply(65), 128);
+        assert_eq!(Round::Off.apply(65), 65);
+
+        // a period of a pixel with no phase that rounds up from half a pixel
+        let round = Round::new_super(0x48, 0x4000);
+        assert_eq!((round.apply(95), round.apply(96)), (64, 128));
+        // the same shifted by half a pixel
+        let round = Round::new_super(0x68, 0x4000);
+        assert_eq!((round.apply(10), round.apply(70)), (32, 96));
+    }
+
+    #[test]
+    fn move_and_interpolate() {
+        // 0, 500 and 1000 in 1/64 pixels
+        let mut vm = glyph_vm(&[0, 1000, 2000]);
+        #[rustfmt::skip]
+        let glyph = [
+            // MIAP point 0 to control value 0, which is close enough to be used
+            0xb1, 0, 0, 0x3f,
+            // MDRP point 2 with its rounded distance to point 0, then IUP[x]
+            0xb0, 2, 0xc4, 0x31,
+        ];
+        run(&mut vm, &[], &glyph).unwrap();
+        assert_eq!(x_coordinates(&vm), [64, 576, 1088]);
+        assert_eq!(vm.zones[GLYPH].touched, [TOUCHED_X, 0, TOUCHED_X]);
+
+        // SHP[rp2] shifts point 1 as much as rp2 has moved
+        let mut vm = glyph_vm(&[0, 1000, 2000]);
+        vm.zones[GLYPH].current[0].x = 64;
+        run(&mut vm, &[], &[0xb0, 1, 0x32]).unwrap();
+        assert_eq!(x_coordinates(&vm), [64, 564, 1000]);
+    }
+
+    #[test]
+    fn deltas() {
+        let mut vm = glyph_vm(&[0, 1000]);
+        // a step of 1/8 pixel up at 16 pixels per em and one at 15 that is ignored
+        let glyph = [0xb4, 104, 1, 120, 1, 2, 0x5d];
+        run(&mut vm, &[], &glyph).unwrap();
+        assert_eq!(x_coordinates(&vm), [0, 508]);
+
+        // a count beyond the stack stops at the last pair
+        run(&mut vm, &[], &[0xb2, 120, 1, 5, 0x5d]).unwrap();
+        assert_eq!(x_coordinates(&vm), [0, 516]);
+        assert!(vm.stack.is_empty());
+
+        // DELTAC1 changes control value 1
+        run(&mut vm, &[], &[0xb2, 120, 1, 1, 0x73]).unwrap();
+        assert_eq!(vm.cvt, [50, 500 + 8]);
+    }
+
+    #[test]
+    fn roboto_glyphs() {
+        let data = roboto();
+        let font = crate::load_ttf(&data).unwrap();
+        for ppem in [7.0, 9.0, 12.0, 16.0, 32.0] {
+            let hinter = Hinter::new(&font, ppem).unwrap();
+            assert_eq!(hinter.ppem(), ppem);
+            for id in 0..font.num_glyphs() {
+                hinter.outline(&font, GlyphId(id)).unwrap();
+            }
+        }
+
+        // the baseline, the x-height and the advance of 'a' fall on whole pixels
+        let hinter = Hinter::new(&font, 16.0).unwrap();
+        let a = font.glyph_id('a').unwrap();
+        let (glyph, phantom) = hinter.hint_glyph(&font, a.0, 0, &mut 0).unwrap();
+        let y = &glyph.y_coordinates;
+        assert_eq!((y.iter().min(), y.iter().max()), (Some(&0), Some(&576)));
+        assert_eq!(phantom[1].x - phantom[0].x, 576);
+        let outline = hinter.outline(&font, a).unwrap();
+        assert_eq!(outline.x_coordinates[..3], [413, 405, 400]);
+
+        // 'á' is 'a' and an accent, moved by whole pixels
+        let aacute = hinter.outline(&font, font.glyph_id('á').unwrap()).unwrap();
+        let n = outline.x_coordinates.len();
+        let dx = aacute.x_coordinates[0] - outline.x_coordinates[0];
+        assert_eq!(dx % 64, 0);
+        for i in 0..n {
+            assert_eq!(aacute.x_coordinates[i] - outline.x_coordinates[i], dx);
+            assert_eq!(aacute.y_coordinates[i], outline.y_coordinates[i]);
+        }
+
+        // Roboto turns hinting off below 9 pixels per em
+        let hinter = Hinter::new(&font, 8.0).unwrap();
+        assert!(!hinter.prepared.hinted());
+    }
+}
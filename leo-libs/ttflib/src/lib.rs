@@ -1,90 +1,756 @@
+use std::{
+    fmt::{Debug, Display},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
 use corelib::reader::Reader;
-use tabledir::TableDirectory;
+use tabledir::{TableDirectory, TableRecord};
 use tables::{
-    cmap::{self, CMAPSubtable},
-    glyf::{self, GlyphTable},
+    cff::{CffTable, Segment},
+    cmap::{self, CmapTable},
+    glyf::{self, BoundingBox, GlyphTable},
     head,
+    hhea::{self, HheaHeader},
+    hmtx::{self, HorizontalMetricsTable, LongHorMetric},
+    layout::Table,
     loca::{self, LocaTable},
-    maxp,
+    maxp, name,
+    os2::Os2Table,
 };
+use util::ensure;
 
+pub use fontdb::{match_style, FaceId, FaceInfo, FontDb, Query};
+pub use hinting::Hinter;
+pub use shape::{shape, Features, PositionedGlyph};
+
+mod fontdb;
+mod hinting;
+mod shape;
 mod tabledir;
 pub mod tables;
 mod util;
 
-pub fn load_ttf<'a>(src: &'a [u8]) -> Font<'a> {
-    let mut reader = Reader::new_big_endian(src, 0);
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FontError {
+    /// the data ends before a table or value that should be there
+    UnexpectedEof,
+    MissingTable(&'static str),
+    /// the table record points outside of the file
+    InvalidTableRecord(String),
+    /// there is no cmap subtable with a unicode or symbol encoding
+    MissingUnicodeCmap,
+    UnsupportedCmapFormat(u16),
+    InvalidMaxpVersion(u32),
+    /// the units per em are outside of 16 to 16384
+    InvalidUnitsPerEm(u16),
+    InvalidIndexToLocFormat(i16),
+    /// the glyph id is not in the loca table or its data is out of place
+    InvalidGlyph(usize),
+    /// a composite glyph nests too deep or has too many components or points
+    ComponentLimit,
+    /// a component is placed by a point that does not exist
+    InvalidComponent,
+    /// a hinting program is broken or runs too long
+    InvalidProgram,
+    /// the CFF table or the charstring of a glyph is broken
+    InvalidCff,
+    /// the collection has no font with this index, other data only has font 0
+    InvalidFontIndex(usize),
+}
 
-    let table_dirs = reader.read::<TableDirectory>();
+impl Display for FontError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEof => write!(f, "unexpected end of font data"),
+            Self::MissingTable(tag) => write!(f, "the font has no {tag} table"),
+            Self::InvalidTableRecord(tag) => {
+                write!(f, "the {tag} table lies outside of the font data")
+            }
+            Self::MissingUnicodeCmap => write!(f, "the font has no unicode character map"),
+            Self::UnsupportedCmapFormat(format) => {
+                write!(f, "unsupported cmap subtable format {format}")
+            }
+            Self::InvalidMaxpVersion(version) => write!(f, "invalid maxp version {version:#x}"),
+            Self::InvalidUnitsPerEm(units) => write!(f, "invalid units per em {units}"),
+            Self::InvalidIndexToLocFormat(format) => {
+                write!(f, "invalid index to location format {format}")
+            }
+            Self::InvalidGlyph(id) => write!(f, "invalid glyph {id}"),
+            Self::ComponentLimit => write!(f, "composite glyph is too complex"),
+            Self::InvalidComponent => write!(f, "invalid composite glyph component"),
+            Self::InvalidProgram => write!(f, "invalid hinting program"),
+            Self::InvalidCff => write!(f, "invalid CFF table"),
+            Self::InvalidFontIndex(index) => write!(f, "there is no font {index}"),
+        }
+    }
+}
 
-    let cmap_table_record = table_dirs
+fn find_table<'dir>(
+    table_dirs: &'dir TableDirectory,
+    src: &[u8],
+    tag: &'static str,
+) -> Result<&'dir TableRecord, FontError> {
+    let record = table_dirs
         .table_records
         .iter()
-        .find(|tr| tr.table_tag.0 == "cmap")
-        .unwrap();
+        .find(|tr| tr.table_tag.0 == tag)
+        .ok_or(FontError::MissingTable(tag))?;
+    if record.offset as u64 + record.length as u64 > src.len() as u64 {
+        return Err(FontError::InvalidTableRecord(tag.to_string()));
+    }
+    Ok(record)
+}
 
-    let glyf_table_record = table_dirs
-        .table_records
-        .iter()
-        .find(|tr| tr.table_tag.0 == "glyf")
-        .unwrap();
+/// Like [`find_table`] for the tables a font doesn't need to have
+fn find_optional_table<'dir>(
+    table_dirs: &'dir TableDirectory,
+    src: &[u8],
+    tag: &'static str,
+) -> Result<Option<&'dir TableRecord>, FontError> {
+    match find_table(table_dirs, src, tag) {
+        Ok(record) => Ok(Some(record)),
+        Err(FontError::MissingTable(_)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
 
-    let loca_table_record = table_dirs
-        .table_records
-        .iter()
-        .find(|tr| tr.table_tag.0 == "loca")
-        .unwrap();
+/// The id of the next loaded font, see [`Font::id`]
+static NEXT_FONT_ID: AtomicU64 = AtomicU64::new(0);
 
-    let head_table_record = table_dirs
-        .table_records
-        .iter()
-        .find(|tr| tr.table_tag.0 == "head")
-        .unwrap();
+/// The offsets of the table directories of the fonts in a TrueType collection, or the
+/// one at the start of a single font
+fn font_offsets(src: &[u8]) -> Result<Vec<usize>, FontError> {
+    if !src.starts_with(b"ttcf") {
+        return Ok(vec![0]);
+    }
+    let mut reader = Reader::new_big_endian(src, 8);
+    ensure(&reader, 4)?;
+    let num_fonts = reader.read::<u32>() as usize;
+    ensure(&reader, num_fonts.saturating_mul(4))?;
+    Ok((0..num_fonts)
+        .map(|_| reader.read::<u32>() as usize)
+        .collect())
+}
 
-    let maxp_table_record = table_dirs
-        .table_records
-        .iter()
-        .find(|tr| tr.table_tag.0 == "maxp")
-        .unwrap();
+/// The number of fonts in the data, a TrueType collection (`.ttc`) has several that share
+/// tables, other font files have one
+pub fn font_count(src: &[u8]) -> Result<usize, FontError> {
+    Ok(font_offsets(src)?.len())
+}
+
+/// Load a font file, the first font of a collection, see [`load_font`]
+pub fn load_ttf<'a>(src: &'a [u8]) -> Result<Font<'a>, FontError> {
+    load_font(src, 0)
+}
+
+/// Load the font with the `index` of a collection, see [`font_count`]
+pub fn load_font<'a>(src: &'a [u8], index: usize) -> Result<Font<'a>, FontError> {
+    let offset = *font_offsets(src)?
+        .get(index)
+        .ok_or(FontError::InvalidFontIndex(index))?;
+    let mut reader = Reader::new_big_endian(src, offset);
+
+    let table_dirs = TableDirectory::parse(&mut reader)?;
+
+    let cmap_table_record = find_table(&table_dirs, src, "cmap")?;
+    // TrueType outlines, or PostScript ones in fonts that have no glyf table
+    let glyf_table_record = find_optional_table(&table_dirs, src, "glyf")?;
+    let head_table_record = find_table(&table_dirs, src, "head")?;
+    let maxp_table_record = find_table(&table_dirs, src, "maxp")?;
+    let hhea_table_record = find_table(&table_dirs, src, "hhea")?;
+    let hmtx_table_record = find_table(&table_dirs, src, "hmtx")?;
+    // OS/2 is required on windows but not on apple platforms
+    let os2_table_record = find_optional_table(&table_dirs, src, "OS/2")?;
+    // the layout tables are read when a text is shaped
+    let table_data = |tag| -> Result<Option<&'a [u8]>, FontError> {
+        let record = find_optional_table(&table_dirs, src, tag)?;
+        Ok(record.map(|r| &src[r.offset as usize..][..r.length as usize]))
+    };
+    let gsub = table_data("GSUB")?;
+    let gpos = table_data("GPOS")?;
+    let gdef = table_data("GDEF")?;
+    let kern = table_data("kern")?;
+    // the hinting programs and their control values
+    let fpgm = table_data("fpgm")?;
+    let prep = table_data("prep")?;
+    let cvt = table_data("cvt ")?;
+    let name = table_data("name")?;
 
-    let cmap = cmap::get_cmap(&mut reader, cmap_table_record);
+    let cmap = cmap::get_cmap(&mut reader, cmap_table_record)?;
 
     reader.set_pos(head_table_record.offset as usize);
+    ensure(&reader, head::HEAD_SIZE)?;
     let head = reader.read::<head::HeadHeader>();
+    if !(16..=16384).contains(&head.units_per_em) {
+        return Err(FontError::InvalidUnitsPerEm(head.units_per_em));
+    }
 
     reader.set_pos(maxp_table_record.offset as usize);
-    let maxp = reader.read::<maxp::MaxpHeader>();
+    let maxp = maxp::MaxpHeader::parse(&mut reader)?;
+
+    let outlines = match glyf_table_record {
+        Some(glyf_table_record) => {
+            let loca_table_record = find_table(&table_dirs, src, "loca")?;
+            reader.set_pos(loca_table_record.offset as usize);
+            let loca = loca::get_loca_table(
+                &mut reader,
+                maxp.get_num_glyphs() as usize,
+                head.index_to_loc_format,
+            )?;
+            Outlines::Glyf {
+                offset: glyf_table_record.offset as usize,
+                loca,
+            }
+        }
+        None => {
+            let cff = table_data("CFF ")?.ok_or(FontError::MissingTable("glyf"))?;
+            Outlines::Cff(CffTable::parse(cff)?)
+        }
+    };
+
+    reader.set_pos(hhea_table_record.offset as usize);
+    ensure(&reader, hhea::HHEA_SIZE)?;
+    let hhea = reader.read::<HheaHeader>();
 
-    reader.set_pos(loca_table_record.offset as usize);
-    let loca = loca::get_loca_table(
+    reader.set_pos(hmtx_table_record.offset as usize);
+    let hmtx = hmtx::get_hmtx(
         &mut reader,
+        hhea.number_of_h_metrics as usize,
         maxp.get_num_glyphs() as usize,
-        head.index_to_loc_format,
-    );
+    )?;
 
-    Font {
+    let os2 = match os2_table_record {
+        Some(record) => {
+            reader.set_pos(record.offset as usize);
+            Some(Os2Table::parse(&mut reader, record.length as usize)?)
+        }
+        None => None,
+    };
+
+    Ok(Font {
+        id: NEXT_FONT_ID.fetch_add(1, Ordering::Relaxed),
         src,
-        glyf_table_record_offset: glyf_table_record.offset as usize,
-        loca,
+        outlines,
+        num_glyphs: maxp.get_num_glyphs(),
+        maxp,
         cmap,
+        units_per_em: head.units_per_em,
+        head_flags: head.flags,
+        mac_style: head.mac_style,
+        bounding_box: BoundingBox {
+            x_min: head.x_min,
+            y_min: head.y_min,
+            x_max: head.x_max,
+            y_max: head.y_max,
+        },
+        hhea,
+        hmtx,
+        os2,
+        gsub,
+        gpos,
+        gdef,
+        kern,
+        fpgm,
+        prep,
+        cvt,
+        name,
+    })
+}
+
+/// Font wide metrics in font units, see [`Font::units_per_em`]. Y goes up from the
+/// baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontMetrics {
+    pub ascender: i16,
+    /// usually negative
+    pub descender: i16,
+    pub line_gap: i16,
+    pub x_height: Option<i16>,
+    pub cap_height: Option<i16>,
+    /// contains the bounding boxes of all glyphs
+    pub bounding_box: BoundingBox,
+}
+
+impl FontMetrics {
+    /// The distance between the baselines of two lines
+    pub fn line_height(&self) -> i32 {
+        self.ascender as i32 - self.descender as i32 + self.line_gap as i32
+    }
+}
+
+/// Whether the letters of a font are upright or slanted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Style {
+    #[default]
+    Normal,
+    /// letters with a cursive design
+    Italic,
+    /// upright letters that are slanted
+    Oblique,
+}
+
+/// How a font looks within its family, fonts are matched by this like in CSS
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FaceStyle {
+    /// from 1 to 1000, 400 is regular and 700 bold
+    pub weight: u16,
+    /// from 1 (ultra condensed) to 9 (ultra expanded), 5 is normal
+    pub stretch: u16,
+    pub style: Style,
+}
+
+impl FaceStyle {
+    pub const REGULAR: u16 = 400;
+    pub const BOLD: u16 = 700;
+    pub const NORMAL_STRETCH: u16 = 5;
+}
+
+impl Default for FaceStyle {
+    fn default() -> Self {
+        Self {
+            weight: Self::REGULAR,
+            stretch: Self::NORMAL_STRETCH,
+            style: Style::Normal,
+        }
     }
 }
 
+/// The outline of a glyph in font units
+#[derive(Debug)]
+pub enum Outline {
+    /// quadratic curves from the glyf table
+    Glyf(GlyphTable),
+    /// cubic curves from the CFF table
+    Cff(Vec<Segment>),
+}
+
+impl Outline {
+    /// The points of the outline with the control points, the outline lies within them
+    pub fn points(&self) -> Vec<(f32, f32)> {
+        match self {
+            Self::Glyf(glyph) => glyph
+                .x_coordinates
+                .iter()
+                .zip(&glyph.y_coordinates)
+                .map(|(&x, &y)| (x as f32, y as f32))
+                .collect(),
+            Self::Cff(segments) => segments
+                .iter()
+                .flat_map(|segment| match *segment {
+                    Segment::MoveTo(p) | Segment::LineTo(p) => vec![p],
+                    Segment::CurveTo(a, b, c) => vec![a, b, c],
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Where the outlines of the glyphs are
+#[derive(Debug)]
+enum Outlines<'a> {
+    Glyf { offset: usize, loca: LocaTable },
+    Cff(CffTable<'a>),
+}
+
+/// The index of a glyph in the font
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GlyphId(pub u16);
+
+impl GlyphId {
+    /// The glyph for characters the font has no glyph for, usually a box
+    pub const NOTDEF: Self = Self(0);
+}
+
 pub struct Font<'a> {
+    id: u64,
     src: &'a [u8],
-    glyf_table_record_offset: usize,
-    loca: LocaTable,
-    cmap: CMAPSubtable,
+    outlines: Outlines<'a>,
+    num_glyphs: u16,
+    maxp: maxp::MaxpHeader,
+    cmap: CmapTable,
+    units_per_em: u16,
+    head_flags: u16,
+    mac_style: u16,
+    bounding_box: BoundingBox,
+    hhea: HheaHeader,
+    hmtx: HorizontalMetricsTable,
+    os2: Option<Os2Table>,
+    gsub: Option<&'a [u8]>,
+    gpos: Option<&'a [u8]>,
+    gdef: Option<&'a [u8]>,
+    kern: Option<&'a [u8]>,
+    fpgm: Option<&'a [u8]>,
+    prep: Option<&'a [u8]>,
+    cvt: Option<&'a [u8]>,
+    name: Option<&'a [u8]>,
+}
+
+impl Debug for Font<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Font")
+            .field("id", &self.id)
+            .field("num_glyphs", &self.num_glyphs)
+            .field("units_per_em", &self.units_per_em)
+            .finish_non_exhaustive()
+    }
 }
 
 impl<'a> Font<'a> {
-    pub fn get_glyph(&self, c: char) -> GlyphTable {
-        glyf::get_glyf(
-            self.glyf_table_record_offset,
-            &self.src,
-            c,
-            &self.loca,
-            &self.cmap,
-        )
+    /// Different for every loaded font, even if they are loaded from the same data. Caches
+    /// use it to tell fonts apart.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn units_per_em(&self) -> u16 {
+        self.units_per_em
+    }
+
+    /// The factor from font units to pixels for a font size in pixels per em
+    pub fn scale(&self, font_size: f32) -> f32 {
+        font_size / self.units_per_em as f32
+    }
+
+    /// The vertical metrics come from hhea unless OS/2 asks to use its typo metrics
+    pub fn metrics(&self) -> FontMetrics {
+        let (ascender, descender, line_gap) = match &self.os2 {
+            Some(os2) if os2.fs_selection & Os2Table::USE_TYPO_METRICS != 0 => (
+                os2.s_typo_ascender,
+                os2.s_typo_descender,
+                os2.s_typo_line_gap,
+            ),
+            _ => (self.hhea.ascender, self.hhea.descender, self.hhea.line_gap),
+        };
+        FontMetrics {
+            ascender,
+            descender,
+            line_gap,
+            x_height: self.os2.as_ref().and_then(|os2| os2.sx_height),
+            cap_height: self.os2.as_ref().and_then(|os2| os2.s_cap_height),
+            bounding_box: self.bounding_box,
+        }
+    }
+
+    pub fn num_glyphs(&self) -> u16 {
+        self.num_glyphs
+    }
+
+    /// The glyph of a character, None if the font has none, in which case
+    /// [`GlyphId::NOTDEF`] is drawn instead
+    pub fn glyph_id(&self, c: char) -> Option<GlyphId> {
+        let id = self
+            .cmap
+            .get_glyph_id(&mut Reader::new_big_endian(self.src, 0), c)?;
+        (id < self.num_glyphs).then_some(GlyphId(id))
+    }
+
+    /// The glyph of a character followed by a variation selector (U+FE00 to U+FE0F or
+    /// U+E0100 to U+E01EF), falls back to the glyph of the character without the selector
+    /// if the font has no variant for the sequence
+    pub fn glyph_id_with_variation(&self, c: char, selector: char) -> Option<GlyphId> {
+        let id =
+            self.cmap
+                .get_variation_glyph_id(&mut Reader::new_big_endian(self.src, 0), c, selector);
+        match id {
+            Some(id) if id < self.num_glyphs => Some(GlyphId(id)),
+            _ => self.glyph_id(c),
+        }
+    }
+
+    /// The advance width and left side bearing of a glyph in font units
+    pub fn h_metrics(&self, id: GlyphId) -> LongHorMetric {
+        self.hmtx.get_h_metric(id.0 as usize)
+    }
+
+    /// A name from the name table in english if the font has it, see [`tables::name`] for
+    /// the ids
+    pub fn name(&self, id: u16) -> Option<String> {
+        name::get_name(Table::new(self.name?), id)
+    }
+
+    /// The family with all weights and widths of the font
+    pub fn family_name(&self) -> Option<String> {
+        self.name(name::TYPOGRAPHIC_FAMILY)
+            .or_else(|| self.name(name::FAMILY))
+    }
+
+    /// The weight, width and slant from the OS/2 table, or only bold and italic from the
+    /// head table of fonts without one
+    pub fn face_style(&self) -> FaceStyle {
+        let Some(os2) = &self.os2 else {
+            return FaceStyle {
+                weight: match self.mac_style & 1 != 0 {
+                    true => FaceStyle::BOLD,
+                    false => FaceStyle::REGULAR,
+                },
+                stretch: FaceStyle::NORMAL_STRETCH,
+                style: match self.mac_style & 2 != 0 {
+                    true => Style::Italic,
+                    false => Style::Normal,
+                },
+            };
+        };
+        FaceStyle {
+            weight: match os2.us_weight_class {
+                0 => FaceStyle::REGULAR,
+                weight => weight.min(1000),
+            },
+            stretch: match os2.us_width_class {
+                1..=9 => os2.us_width_class,
+                _ => FaceStyle::NORMAL_STRETCH,
+            },
+            style: if os2.fs_selection & Os2Table::ITALIC != 0 {
+                Style::Italic
+            } else if os2.fs_selection & Os2Table::OBLIQUE != 0 {
+                Style::Oblique
+            } else {
+                Style::Normal
+            },
+        }
+    }
+
+    /// Whether the glyphs have cubic outlines from a CFF table
+    pub fn has_cff_outlines(&self) -> bool {
+        matches!(self.outlines, Outlines::Cff(_))
+    }
+
+    /// The TrueType outline of a glyph in font units, fonts with PostScript outlines have
+    /// none, see [`Font::glyph_outline`]
+    pub fn outline(&self, id: GlyphId) -> Result<GlyphTable, FontError> {
+        let (offset, loca) = self.glyf()?;
+        glyf::get_glyf(offset, self.src, id.0 as usize, loca)
+    }
+
+    /// The outline of a glyph in font units, of either kind
+    pub fn glyph_outline(&self, id: GlyphId) -> Result<Outline, FontError> {
+        match &self.outlines {
+            Outlines::Glyf { .. } => self.outline(id).map(Outline::Glyf),
+            Outlines::Cff(cff) => cff.outline(id.0).map(Outline::Cff),
+        }
+    }
+
+    /// Where the glyf table is and its loca table, fails for CFF outlines
+    fn glyf(&self) -> Result<(usize, &LocaTable), FontError> {
+        match &self.outlines {
+            Outlines::Glyf { offset, loca } => Ok((*offset, loca)),
+            Outlines::Cff(_) => Err(FontError::MissingTable("glyf")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    pub(crate) fn roboto() -> Vec<u8> {
+        std::fs::read("../../test-data/Roboto-Regular.ttf").unwrap()
+    }
+
+    /// Glyphs of Roboto with CFF outlines, written by `test-data/make_roboto_cff.py`
+    pub(crate) fn roboto_cff() -> Vec<u8> {
+        std::fs::read("../../test-data/RobotoCFF-Regular.otf").unwrap()
+    }
+
+    /// A TrueType collection of the fonts, their tables are not shared
+    pub(crate) fn collection(fonts: &[&[u8]]) -> Vec<u8> {
+        let mut data = b"ttcf\0\x01\0\0".to_vec();
+        data.extend((fonts.len() as u32).to_be_bytes());
+        let mut offset = data.len() + fonts.len() * 4;
+        let mut bodies = Vec::new();
+        for font in fonts {
+            data.extend((offset as u32).to_be_bytes());
+            // the table offsets are from the start of the collection
+            let mut font = font.to_vec();
+            let num_tables = u16::from_be_bytes([font[4], font[5]]) as usize;
+            for i in 0..num_tables {
+                let pos = 12 + i * 16 + 8;
+                let table = u32::from_be_bytes(font[pos..pos + 4].try_into().unwrap());
+                font[pos..pos + 4].copy_from_slice(&(table + offset as u32).to_be_bytes());
+            }
+            font.resize(font.len().next_multiple_of(4), 0);
+            offset += font.len();
+            bodies.extend(font);
+        }
+        data.extend(bodies);
+        data
+    }
+
+    #[test]
+    fn metrics() {
+        let data = roboto();
+        let font = load_ttf(&data).unwrap();
+        assert_eq!(font.units_per_em(), 2048);
+        assert_eq!(font.scale(16.0), 16.0 / 2048.0);
+
+        let metrics = font.metrics();
+        assert_eq!(
+            (metrics.ascender, metrics.descender, metrics.line_gap),
+            (1900, -500, 0)
+        );
+        assert_eq!(metrics.line_height(), 2400);
+        assert_eq!(
+            (metrics.x_height, metrics.cap_height),
+            (Some(1082), Some(1456))
+        );
+        assert_eq!(
+            metrics.bounding_box,
+            BoundingBox {
+                x_min: -1509,
+                y_min: -555,
+                x_max: 2352,
+                y_max: 2163
+            }
+        );
+
+        let metric = |advance_width, lsb| LongHorMetric { advance_width, lsb };
+        let h_metrics = |c| font.h_metrics(font.glyph_id(c).unwrap());
+        assert_eq!(h_metrics('a'), metric(1114, 109));
+        assert_eq!(h_metrics(' '), metric(507, 0));
+        assert_eq!(h_metrics('W'), metric(1817, 61));
+        assert_eq!(h_metrics('\u{e9}'), metric(1085, 93));
+
+        let glyph = font.outline(font.glyph_id('a').unwrap()).unwrap();
+        assert_eq!(
+            glyph.bounding_box,
+            BoundingBox {
+                x_min: 109,
+                y_min: -20,
+                x_max: 1002,
+                y_max: 1102
+            }
+        );
+    }
+
+    #[test]
+    fn glyph_ids() {
+        let data = roboto();
+        let font = load_ttf(&data).unwrap();
+        assert_eq!(font.num_glyphs(), 1294);
+        assert_eq!(font.glyph_id('a'), Some(GlyphId(69)));
+        assert_eq!(font.glyph_id(' '), Some(GlyphId(4)));
+        assert_eq!(font.glyph_id('\u{e9}'), Some(GlyphId(675)));
+        // private use and unassigned characters
+        assert_eq!(font.glyph_id('\u{e000}'), None);
+        assert_eq!(font.glyph_id('\u{10ffff}'), None);
+        // roboto has no variation sequences
+        assert_eq!(
+            font.glyph_id_with_variation('a', '\u{fe00}'),
+            Some(GlyphId(69))
+        );
+
+        let notdef = font.outline(GlyphId::NOTDEF).unwrap();
+        assert!(!notdef.end_pts_of_contours.is_empty());
+        assert!(matches!(
+            font.outline(GlyphId(1294)),
+            Err(FontError::InvalidGlyph(1294))
+        ));
+    }
+
+    #[test]
+    fn cff_outlines() {
+        let data = roboto_cff();
+        let font = load_ttf(&data).unwrap();
+        assert!(font.has_cff_outlines());
+        assert_eq!(font.num_glyphs(), 11);
+        assert!(matches!(
+            font.outline(GlyphId::NOTDEF),
+            Err(FontError::MissingTable("glyf"))
+        ));
+
+        // the same 'a' as the TrueType roboto, drawn with cubic curves
+        let a = font.glyph_outline(font.glyph_id('a').unwrap()).unwrap();
+        let points = a.points();
+        let (xs, ys): (Vec<_>, Vec<_>) = points.iter().copied().unzip();
+        let min = |v: &[f32]| v.iter().copied().fold(f32::MAX, f32::min);
+        let max = |v: &[f32]| v.iter().copied().fold(f32::MIN, f32::max);
+        assert_eq!((min(&xs), min(&ys)), (109.0, -20.0));
+        assert_eq!((max(&xs), max(&ys)), (1002.0, 1102.0));
+
+        // the accented 'a' calls the subroutines of the 'a' and the acute
+        let (Outline::Cff(a), Outline::Cff(a_acute)) = (
+            a,
+            font.glyph_outline(font.glyph_id('\u{e1}').unwrap())
+                .unwrap(),
+        ) else {
+            panic!("not a CFF outline");
+        };
+        assert_eq!(a_acute[..a.len()], a[..]);
+        assert_eq!(
+            a_acute[a.len()..],
+            [
+                Segment::MoveTo((672.0, 1536.0)),
+                Segment::LineTo((896.0, 1536.0)),
+                Segment::LineTo((628.0, 1242.0)),
+                Segment::LineTo((479.0, 1242.0)),
+            ]
+        );
+
+        let data = roboto();
+        assert!(!load_ttf(&data).unwrap().has_cff_outlines());
+    }
+
+    #[test]
+    fn names_and_style() {
+        let data = roboto();
+        let font = load_ttf(&data).unwrap();
+        assert_eq!(font.family_name().as_deref(), Some("Roboto"));
+        assert_eq!(font.name(name::SUBFAMILY).as_deref(), Some("Regular"));
+        assert_eq!(
+            font.name(name::POSTSCRIPT_NAME).as_deref(),
+            Some("Roboto-Regular")
+        );
+        assert_eq!(font.face_style(), FaceStyle::default());
+
+        let data = roboto_cff();
+        let font = load_ttf(&data).unwrap();
+        assert_eq!(font.family_name().as_deref(), Some("Roboto CFF"));
+        assert_eq!(
+            font.name(name::FULL_NAME).as_deref(),
+            Some("Roboto CFF Regular")
+        );
+    }
+
+    #[test]
+    fn collections() {
+        let (ttf, otf) = (roboto(), roboto_cff());
+        assert_eq!(font_count(&ttf), Ok(1));
+        assert!(matches!(
+            load_font(&ttf, 1),
+            Err(FontError::InvalidFontIndex(1))
+        ));
+
+        let data = collection(&[&ttf, &otf]);
+        assert_eq!(font_count(&data), Ok(2));
+        let first = load_ttf(&data).unwrap();
+        assert_eq!(first.family_name().as_deref(), Some("Roboto"));
+        assert_eq!(first.num_glyphs(), 1294);
+        let second = load_font(&data, 1).unwrap();
+        assert_eq!(second.family_name().as_deref(), Some("Roboto CFF"));
+        assert!(second.glyph_outline(GlyphId(5)).is_ok());
+        assert!(matches!(
+            load_font(&data, 2),
+            Err(FontError::InvalidFontIndex(2))
+        ));
+
+        // more fonts than the header has room for
+        let mut broken = data[..20].to_vec();
+        broken[11] = 3;
+        assert_eq!(font_count(&broken), Err(FontError::UnexpectedEof));
+        // an offset past the end
+        broken[11] = 1;
+        broken[12..16].copy_from_slice(&[0xff; 4]);
+        assert_eq!(load_ttf(&broken).unwrap_err(), FontError::UnexpectedEof);
+    }
+
+    #[test]
+    fn invalid_units_per_em() {
+        let mut data = roboto();
+        let head = data.windows(4).position(|w| w == b"head").unwrap();
+        let offset = u32::from_be_bytes(data[head + 8..head + 12].try_into().unwrap()) as usize;
+        data[offset + 18..offset + 20].copy_from_slice(&[0, 0]);
+        assert!(matches!(
+            load_ttf(&data),
+            Err(FontError::InvalidUnitsPerEm(0))
+        ));
     }
 }
@@ -0,0 +1,218 @@
+//! Turning a text into positioned glyphs with the substitutions and positioning of the
+//! font. Only left to right text is supported.
+
+use crate::{
+    tables::{
+        gpos, gsub, kern,
+        layout::{Gdef, LayoutTable, Table, Tag},
+    },
+    Font, GlyphId,
+};
+
+/// A glyph of a shaped text, all values are in font units
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PositionedGlyph {
+    pub id: GlyphId,
+    /// the byte index of the first character of the text the glyph stands for
+    pub cluster: usize,
+    /// how far to move the pen after the glyph
+    pub x_advance: i32,
+    pub y_advance: i32,
+    /// where to draw the glyph relative to the pen
+    pub x_offset: i32,
+    pub y_offset: i32,
+}
+
+/// The script, language and features to shape a text with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Features {
+    /// the OpenType script tag, fonts without the script use their default one
+    pub script: Tag,
+    /// the OpenType language system tag, None for the default of the script
+    pub language: Option<Tag>,
+    /// the features to apply, see [`Features::DEFAULT_TAGS`]
+    pub tags: Vec<Tag>,
+}
+
+impl Features {
+    /// The features that are on unless they are turned off
+    pub const DEFAULT_TAGS: [Tag; 7] = [
+        *b"ccmp", *b"locl", *b"rlig", *b"liga", *b"clig", *b"calt", *b"kern",
+    ];
+
+    /// The default features without the ones in `tags`
+    pub fn without(tags: &[Tag]) -> Self {
+        let mut features = Self::default();
+        features.tags.retain(|tag| !tags.contains(tag));
+        features
+    }
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Self {
+            script: *b"latn",
+            language: None,
+            tags: Self::DEFAULT_TAGS.to_vec(),
+        }
+    }
+}
+
+/// Whether the character selects a variant of the one before it
+fn is_variation_selector(c: char) -> bool {
+    matches!(c, '\u{fe00}'..='\u{fe0f}' | '\u{e0100}'..='\u{e01ef}')
+}
+
+/// Map the characters to glyphs, substitute them with GSUB and position them with GPOS,
+/// or with the kern table if GPOS has no kerning
+pub fn shape(text: &str, font: &Font, features: &Features) -> Vec<PositionedGlyph> {
+    let mut glyphs: Vec<PositionedGlyph> = Vec::with_capacity(text.len());
+    let mut last_char = None;
+    for (cluster, c) in text.char_indices() {
+        if let (true, Some(base), Some(glyph)) =
+            (is_variation_selector(c), last_char, glyphs.last_mut())
+        {
+            glyph.id = font
+                .glyph_id_with_variation(base, c)
+                .unwrap_or(GlyphId::NOTDEF);
+            last_char = None;
+            continue;
+        }
+        glyphs.push(PositionedGlyph {
+            id: font.glyph_id(c).unwrap_or(GlyphId::NOTDEF),
+            cluster,
+            x_advance: 0,
+            y_advance: 0,
+            x_offset: 0,
+            y_offset: 0,
+        });
+        last_char = Some(c);
+    }
+
+    let gdef = font
+        .gdef
+        .map_or(Gdef::default(), |gdef| Gdef::parse(Table::new(gdef)));
+    let gsub = font
+        .gsub
+        .and_then(|gsub| LayoutTable::parse(Table::new(gsub), gsub::EXTENSION));
+    if let Some(gsub) = gsub {
+        for index in gsub.lookup_indices(features.script, features.language, &features.tags) {
+            gsub::apply_lookup(&gsub, &gdef, index, &mut glyphs);
+        }
+    }
+
+    for glyph in &mut glyphs {
+        glyph.x_advance = font.h_metrics(glyph.id).advance_width as i32;
+    }
+
+    let gpos = font
+        .gpos
+        .and_then(|gpos| LayoutTable::parse(Table::new(gpos), gpos::EXTENSION));
+    if let Some(gpos) = &gpos {
+        for index in gpos.lookup_indices(features.script, features.language, &features.tags) {
+            gpos::apply_lookup(gpos, &gdef, index, &mut glyphs);
+        }
+    }
+
+    let kern_tag = *b"kern";
+    let gpos_kerns = gpos.is_some_and(|gpos| {
+        !gpos
+            .lookup_indices(features.script, features.language, &[kern_tag])
+            .is_empty()
+    });
+    if let (Some(kern), false, true) = (font.kern, gpos_kerns, features.tags.contains(&kern_tag)) {
+        for i in 1..glyphs.len() {
+            let (left, right) = (glyphs[i - 1].id.0, glyphs[i].id.0);
+            glyphs[i - 1].x_advance += kern::get_kerning(Table::new(kern), left, right);
+        }
+    }
+
+    glyphs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::load_ttf;
+
+    fn roboto() -> Vec<u8> {
+        std::fs::read("../../test-data/Roboto-Regular.ttf").unwrap()
+    }
+
+    fn ids(glyphs: &[PositionedGlyph]) -> Vec<u16> {
+        glyphs.iter().map(|g| g.id.0).collect()
+    }
+
+    #[test]
+    fn ligatures() {
+        let data = roboto();
+        let font = load_ttf(&data).unwrap();
+        let id = |c| font.glyph_id(c).unwrap().0;
+
+        let plain = shape("fi", &font, &Features::without(&[*b"liga"]));
+        assert_eq!(ids(&plain), [id('f'), id('i')]);
+
+        let shaped = shape("a fi", &font, &Features::default());
+        assert_eq!(shaped.len(), 3);
+        assert_eq!(ids(&shaped[..2]), [id('a'), id(' ')]);
+        assert_ne!(shaped[2].id.0, id('f'));
+        assert_eq!(
+            shaped.iter().map(|g| g.cluster).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert_eq!(
+            shaped[2].x_advance,
+            font.h_metrics(shaped[2].id).advance_width as i32
+        );
+    }
+
+    #[test]
+    fn single_substitution() {
+        let data = roboto();
+        let font = load_ttf(&data).unwrap();
+        let id = |c| font.glyph_id(c).unwrap().0;
+        let features = Features {
+            tags: vec![*b"smcp"],
+            ..Features::default()
+        };
+        let shaped = shape("aA", &font, &features);
+        // small caps replace the lower case letters only
+        assert_ne!(shaped[0].id.0, id('a'));
+        assert_eq!(shaped[1].id.0, id('A'));
+    }
+
+    #[test]
+    fn kerning() {
+        let data = roboto();
+        let font = load_ttf(&data).unwrap();
+        let advance = |c| font.h_metrics(font.glyph_id(c).unwrap()).advance_width as i32;
+
+        let plain = shape("AV", &font, &Features::without(&[*b"kern"]));
+        assert_eq!(plain[0].x_advance, advance('A'));
+
+        let kerned = shape("AV", &font, &Features::default());
+        assert!(kerned[0].x_advance < advance('A'));
+        assert_eq!(kerned[1].x_advance, advance('V'));
+        assert_eq!(ids(&kerned), ids(&plain));
+    }
+
+    #[test]
+    fn unmapped_and_variation_selectors() {
+        let data = roboto();
+        let font = load_ttf(&data).unwrap();
+        let shaped = shape("a\u{fe00}\u{e000}b", &font, &Features::default());
+        assert_eq!(
+            ids(&shaped),
+            [
+                font.glyph_id('a').unwrap().0,
+                GlyphId::NOTDEF.0,
+                font.glyph_id('b').unwrap().0
+            ]
+        );
+        assert_eq!(
+            shaped.iter().map(|g| g.cluster).collect::<Vec<_>>(),
+            [0, 4, 7]
+        );
+        assert!(shape("", &font, &Features::default()).is_empty());
+    }
+}
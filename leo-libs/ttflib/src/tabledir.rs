@@ -1,6 +1,9 @@
 use corelib::reader::{read_vec, ByteReader, Readable, Reader};
 
-use crate::util::FourByteTag;
+use crate::{
+    util::{ensure, FourByteTag},
+    FontError,
+};
 
 #[derive(Debug, Clone)]
 pub struct TableDirectory {
@@ -12,22 +15,24 @@ pub struct TableDirectory {
     pub table_records: Vec<TableRecord>,
 }
 
-impl Readable for TableDirectory {
-    fn read(reader: &mut Reader<impl ByteReader>) -> Self {
+impl TableDirectory {
+    pub fn parse(reader: &mut Reader<impl ByteReader>) -> Result<Self, FontError> {
+        ensure(reader, 12)?;
         let sfnt_version: u32 = reader.read();
         let num_tables: u16 = reader.read();
         let search_range: u16 = reader.read();
         let entry_selector: u16 = reader.read();
         let range_shift: u16 = reader.read();
+        ensure(reader, num_tables as usize * 16)?;
         let table_records: Vec<TableRecord> = read_vec(reader, num_tables as usize);
-        Self {
+        Ok(Self {
             sfnt_version,
             num_tables,
             search_range,
             entry_selector,
             range_shift,
             table_records,
-        }
+        })
     }
 }
 
@@ -0,0 +1,907 @@
+//! The Compact Font Format table of OpenType fonts with PostScript outlines. The glyphs
+//! are Type 2 charstrings, small programs that draw cubic curves and call subroutines
+//! the glyphs share. Hints only help rasterizers that snap to pixels, they are skipped.
+
+use crate::FontError;
+
+/// A part of a PostScript outline in font units. Every contour starts with a move and is
+/// closed, there is no segment back to its start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    MoveTo((f32, f32)),
+    LineTo((f32, f32)),
+    /// two control points and the end of a cubic curve
+    CurveTo((f32, f32), (f32, f32), (f32, f32)),
+}
+
+/// The operands a charstring may have on its stack
+const MAX_STACK: usize = 48;
+/// How deep subroutine calls may nest
+const MAX_CALL_DEPTH: usize = 10;
+/// The operators a glyph may run, calls that nest can make short charstrings run long
+const MAX_OPERATORS: usize = 100_000;
+
+/// An INDEX, a list of byte strings
+#[derive(Debug, Clone, Copy, Default)]
+struct Index<'a> {
+    count: usize,
+    off_size: usize,
+    offsets: &'a [u8],
+    data: &'a [u8],
+}
+
+impl<'a> Index<'a> {
+    /// The INDEX at `pos` of the table and where the data after it starts
+    fn parse(cff: &'a [u8], pos: usize) -> Result<(Self, usize), FontError> {
+        let count = read_u16(cff, pos)? as usize;
+        if count == 0 {
+            return Ok((Self::default(), pos + 2));
+        }
+        let off_size = *cff.get(pos + 2).ok_or(FontError::UnexpectedEof)? as usize;
+        if !(1..=4).contains(&off_size) {
+            return Err(FontError::InvalidCff);
+        }
+        let offsets = cff
+            .get(pos + 3..pos + 3 + (count + 1) * off_size)
+            .ok_or(FontError::UnexpectedEof)?;
+        let mut index = Self {
+            count,
+            off_size,
+            offsets,
+            data: &[],
+        };
+        // the offsets start at 1, from the byte before the data
+        let start = pos + 2 + offsets.len();
+        let end = start + index.offset(count);
+        index.data = cff.get(start + 1..end).ok_or(FontError::UnexpectedEof)?;
+        Ok((index, end))
+    }
+
+    fn offset(&self, i: usize) -> usize {
+        let bytes = &self.offsets[i * self.off_size..][..self.off_size];
+        bytes.iter().fold(0, |offset, &b| offset << 8 | b as usize)
+    }
+
+    fn get(&self, i: usize) -> Option<&'a [u8]> {
+        if i >= self.count {
+            return None;
+        }
+        let (start, end) = (self.offset(i), self.offset(i + 1));
+        self.data.get(start.checked_sub(1)?..end.checked_sub(1)?)
+    }
+}
+
+fn read_u16(data: &[u8], pos: usize) -> Result<u16, FontError> {
+    let bytes = data.get(pos..pos + 2).ok_or(FontError::UnexpectedEof)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// The operators of a DICT with their operands, escaped operators are 1200 and up
+fn parse_dict(mut data: &[u8]) -> Result<Vec<(u16, Vec<f64>)>, FontError> {
+    let mut entries = Vec::new();
+    let mut operands = Vec::new();
+    while let Some((&b0, rest)) = data.split_first() {
+        data = rest;
+        let take = |data: &mut &[u8], len: usize| -> Result<i64, FontError> {
+            let bytes = data.get(..len).ok_or(FontError::UnexpectedEof)?;
+            *data = &data[len..];
+            Ok(bytes.iter().fold(0, |v, &b| v << 8 | b as i64))
+        };
+        match b0 {
+            0..=21 => {
+                let operator = match b0 {
+                    12 => 1200 + take(&mut data, 1)? as u16,
+                    _ => b0 as u16,
+                };
+                entries.push((operator, std::mem::take(&mut operands)));
+            }
+            28 => operands.push(take(&mut data, 2)? as i16 as f64),
+            29 => operands.push(take(&mut data, 4)? as i32 as f64),
+            30 => operands.push(parse_real(&mut data)?),
+            32..=246 => operands.push(b0 as f64 - 139.0),
+            247..=250 => {
+                operands.push(((b0 as f64 - 247.0) * 256.0) + take(&mut data, 1)? as f64 + 108.0)
+            }
+            251..=254 => {
+                operands.push(-((b0 as f64 - 251.0) * 256.0) - take(&mut data, 1)? as f64 - 108.0)
+            }
+            _ => return Err(FontError::InvalidCff),
+        }
+        if operands.len() > MAX_STACK {
+            return Err(FontError::InvalidCff);
+        }
+    }
+    Ok(entries)
+}
+
+/// A real number in a DICT, its digits are written in nibbles
+fn parse_real(data: &mut &[u8]) -> Result<f64, FontError> {
+    let mut text = String::new();
+    loop {
+        let (&byte, rest) = data.split_first().ok_or(FontError::UnexpectedEof)?;
+        *data = rest;
+        for nibble in [byte >> 4, byte & 0xf] {
+            match nibble {
+                0..=9 => text.push((b'0' + nibble) as char),
+                0xa => text.push('.'),
+                0xb => text.push('E'),
+                0xc => text.push_str("E-"),
+                0xe => text.push('-'),
+                0xf => return text.parse().map_err(|_| FontError::InvalidCff),
+                _ => return Err(FontError::InvalidCff),
+            }
+        }
+    }
+}
+
+/// The operands of a DICT operator, None if it isn't there
+fn dict_get(dict: &[(u16, Vec<f64>)], operator: u16) -> Option<&[f64]> {
+    dict.iter()
+        .find(|(op, _)| *op == operator)
+        .map(|(_, operands)| operands.as_slice())
+}
+
+/// An offset or a size in a DICT
+fn dict_offset(dict: &[(u16, Vec<f64>)], operator: u16, i: usize) -> Result<usize, FontError> {
+    match dict_get(dict, operator).and_then(|operands| operands.get(i)) {
+        Some(&v) if v >= 0.0 => Ok(v as usize),
+        _ => Err(FontError::InvalidCff),
+    }
+}
+
+/// Which font dict, and so which local subroutines, a glyph of a CID keyed font uses
+#[derive(Debug, Clone, Copy)]
+enum FdSelect<'a> {
+    /// one font dict for every glyph
+    Format0(&'a [u8]),
+    /// ranges of glyphs as the first glyph and its font dict, and the end of the last
+    Format3(&'a [u8]),
+}
+
+impl FdSelect<'_> {
+    fn get(&self, glyph: u16) -> Option<usize> {
+        match *self {
+            Self::Format0(fds) => fds.get(glyph as usize).map(|&fd| fd as usize),
+            Self::Format3(data) => {
+                let count = read_u16(data, 0).ok()? as usize;
+                let range = |i: usize| -> Option<(u16, u8)> {
+                    let first = read_u16(data, 2 + i * 3).ok()?;
+                    Some((first, *data.get(4 + i * 3)?))
+                };
+                let end = read_u16(data, 2 + count * 3).ok()?;
+                if glyph >= end {
+                    return None;
+                }
+                // the last range that starts at the glyph or before it
+                let (mut low, mut high) = (0, count);
+                while high - low > 1 {
+                    let mid = (low + high) / 2;
+                    if range(mid)?.0 <= glyph {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                let (first, fd) = range(low)?;
+                (first <= glyph).then_some(fd as usize)
+            }
+        }
+    }
+}
+
+/// The parts of a CFF table that draw glyphs
+#[derive(Debug, Clone)]
+pub struct CffTable<'a> {
+    char_strings: Index<'a>,
+    global_subrs: Index<'a>,
+    /// the local subroutines of every font dict, a font that is not CID keyed has one
+    local_subrs: Vec<Index<'a>>,
+    fd_select: Option<FdSelect<'a>>,
+}
+
+impl<'a> CffTable<'a> {
+    pub fn parse(cff: &'a [u8]) -> Result<Self, FontError> {
+        let major = *cff.first().ok_or(FontError::UnexpectedEof)?;
+        let header_size = *cff.get(2).ok_or(FontError::UnexpectedEof)? as usize;
+        if major != 1 {
+            return Err(FontError::InvalidCff);
+        }
+        // one font in the name and top DICT indexes, then the strings that are not needed
+        let (_, pos) = Index::parse(cff, header_size)?;
+        let (top_dicts, pos) = Index::parse(cff, pos)?;
+        let (_, pos) = Index::parse(cff, pos)?;
+        let (global_subrs, _) = Index::parse(cff, pos)?;
+        let top = parse_dict(top_dicts.get(0).ok_or(FontError::InvalidCff)?)?;
+
+        // only Type 2 charstrings are supported
+        if dict_get(&top, 1206).is_some_and(|operands| operands != [2.0]) {
+            return Err(FontError::InvalidCff);
+        }
+        let (char_strings, _) = Index::parse(cff, dict_offset(&top, 17, 0)?)?;
+
+        // a CID keyed font has a font dict with a private DICT for every group of glyphs
+        let (local_subrs, fd_select) = match dict_get(&top, 1236) {
+            Some(_) => {
+                let (font_dicts, _) = Index::parse(cff, dict_offset(&top, 1236, 0)?)?;
+                let local_subrs = (0..font_dicts.count)
+                    .map(|i| {
+                        let dict = parse_dict(font_dicts.get(i).ok_or(FontError::InvalidCff)?)?;
+                        Self::local_subrs(cff, &dict)
+                    })
+                    .collect::<Result<_, _>>()?;
+                let pos = dict_offset(&top, 1237, 0)?;
+                let data = cff.get(pos + 1..).ok_or(FontError::UnexpectedEof)?;
+                let fd_select = match cff[pos] {
+                    0 => FdSelect::Format0(data),
+                    3 => FdSelect::Format3(data),
+                    _ => return Err(FontError::InvalidCff),
+                };
+                (local_subrs, Some(fd_select))
+            }
+            None => (vec![Self::local_subrs(cff, &top)?], None),
+        };
+
+        Ok(Self {
+            char_strings,
+            global_subrs,
+            local_subrs,
+            fd_select,
+        })
+    }
+
+    /// The subroutines of the private DICT of a top or font DICT, their offset is from the
+    /// start of the private DICT
+    fn local_subrs(cff: &'a [u8], dict: &[(u16, Vec<f64>)]) -> Result<Index<'a>, FontError> {
+        if dict_get(dict, 18).is_none() {
+            return Ok(Index::default());
+        }
+        let (size, offset) = (dict_offset(dict, 18, 0)?, dict_offset(dict, 18, 1)?);
+        let private = cff
+            .get(offset..offset + size)
+            .ok_or(FontError::UnexpectedEof)?;
+        let private = parse_dict(private)?;
+        match dict_get(&private, 19) {
+            Some(_) => Ok(Index::parse(cff, offset + dict_offset(&private, 19, 0)?)?.0),
+            None => Ok(Index::default()),
+        }
+    }
+
+    /// The outline of a glyph in font units
+    pub fn outline(&self, glyph: u16) -> Result<Vec<Segment>, FontError> {
+        let code = self
+            .char_strings
+            .get(glyph as usize)
+            .ok_or(FontError::InvalidGlyph(glyph as usize))?;
+        let local_subrs = match &self.fd_select {
+            Some(fd_select) => fd_select
+                .get(glyph)
+                .and_then(|fd| self.local_subrs.get(fd))
+                .ok_or(FontError::InvalidCff)?,
+            None => &self.local_subrs[0],
+        };
+        let mut charstring = Charstring {
+            global_subrs: self.global_subrs,
+            local_subrs: *local_subrs,
+            stack: Vec::new(),
+            stems: 0,
+            has_width: false,
+            operators: 0,
+            segments: Vec::new(),
+            pen: (0.0, 0.0),
+        };
+        charstring.run(code, 0)?;
+        Ok(charstring.segments)
+    }
+}
+
+/// The number that is added to the operand of a subroutine call
+fn bias(subrs: &Index) -> i32 {
+    match subrs.count {
+        0..1240 => 107,
+        1240..33900 => 1131,
+        _ => 32768,
+    }
+}
+
+/// The state of a glyph's charstring while it runs
+struct Charstring<'a> {
+    global_subrs: Index<'a>,
+    local_subrs: Index<'a>,
+    stack: Vec<f32>,
+    /// the stem hints so far, the hint masks have a bit for each
+    stems: usize,
+    /// whether the first stack clearing operator has come, its extra operand is the width
+    has_width: bool,
+    operators: usize,
+    segments: Vec<Segment>,
+    pen: (f32, f32),
+}
+
+impl Charstring<'_> {
+    /// Run a charstring or subroutine, true if it ended the glyph
+    fn run(&mut self, code: &[u8], depth: usize) -> Result<bool, FontError> {
+        if depth > MAX_CALL_DEPTH {
+            return Err(FontError::InvalidCff);
+        }
+        let mut pc = 0;
+        while let Some(&b0) = code.get(pc) {
+            pc += 1;
+            let byte = |pc: usize| code.get(pc).copied().ok_or(FontError::UnexpectedEof);
+            let value = match b0 {
+                28 => Some(i16::from_be_bytes([byte(pc)?, byte(pc + 1)?]) as f32),
+                32..=246 => Some(b0 as f32 - 139.0),
+                247..=250 => Some((b0 as f32 - 247.0) * 256.0 + byte(pc)? as f32 + 108.0),
+                251..=254 => Some(-(b0 as f32 - 251.0) * 256.0 - byte(pc)? as f32 - 108.0),
+                // 16.16 fixed point
+                255 => {
+                    let bytes = [byte(pc)?, byte(pc + 1)?, byte(pc + 2)?, byte(pc + 3)?];
+                    Some(i32::from_be_bytes(bytes) as f32 / 65536.0)
+                }
+                _ => None,
+            };
+            if let Some(value) = value {
+                pc += match b0 {
+                    28 => 2,
+                    247..=254 => 1,
+                    255 => 4,
+                    _ => 0,
+                };
+                if self.stack.len() >= MAX_STACK {
+                    return Err(FontError::InvalidCff);
+                }
+                self.stack.push(value);
+                continue;
+            }
+
+            self.operators += 1;
+            if self.operators > MAX_OPERATORS {
+                return Err(FontError::InvalidCff);
+            }
+            let operator = match b0 {
+                12 => {
+                    pc += 1;
+                    1200 + byte(pc - 1)? as u16
+                }
+                _ => b0 as u16,
+            };
+            match operator {
+                // hstem, vstem, hstemhm and vstemhm
+                1 | 3 | 18 | 23 => {
+                    self.take_width(self.stack.len() % 2 == 1);
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                }
+                // hintmask and cntrmask, the operands are vertical stems and the mask
+                // follows with a bit for each stem
+                19 | 20 => {
+                    self.take_width(self.stack.len() % 2 == 1);
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    pc += self.stems.div_ceil(8);
+                }
+                // rmoveto, hmoveto and vmoveto
+                21 | 22 | 4 => {
+                    let count = if operator == 21 { 2 } else { 1 };
+                    self.take_width(self.stack.len() > count);
+                    let d = match operator {
+                        21 => (self.arg(0)?, self.arg(1)?),
+                        22 => (self.arg(0)?, 0.0),
+                        _ => (0.0, self.arg(0)?),
+                    };
+                    let to = self.offset(d);
+                    self.segments.push(Segment::MoveTo(to));
+                    self.stack.clear();
+                }
+                // rlineto
+                5 => {
+                    for i in (0..self.stack.len() / 2 * 2).step_by(2) {
+                        let to = self.offset((self.stack[i], self.stack[i + 1]));
+                        self.line_to(to)?;
+                    }
+                    self.stack.clear();
+                }
+                // hlineto and vlineto, the lines alternate between the directions
+                6 | 7 => {
+                    let mut horizontal = operator == 6;
+                    for i in 0..self.stack.len() {
+                        let d = self.stack[i];
+                        let to = self.offset(if horizontal { (d, 0.0) } else { (0.0, d) });
+                        self.line_to(to)?;
+                        horizontal = !horizontal;
+                    }
+                    self.stack.clear();
+                }
+                // rrcurveto
+                8 => {
+                    for i in (0..self.stack.len() / 6 * 6).step_by(6) {
+                        self.curve_by(self.operands(i))?;
+                    }
+                    self.stack.clear();
+                }
+                // rcurveline, curves and then a line
+                24 => {
+                    let len = self.stack.len();
+                    if len < 8 {
+                        return Err(FontError::InvalidCff);
+                    }
+                    for i in (0..(len - 2) / 6 * 6).step_by(6) {
+                        self.curve_by(self.operands(i))?;
+                    }
+                    let to = self.offset((self.stack[len - 2], self.stack[len - 1]));
+                    self.line_to(to)?;
+                    self.stack.clear();
+                }
+                // rlinecurve, lines and then a curve
+                25 => {
+                    let len = self.stack.len();
+                    if len < 8 {
+                        return Err(FontError::InvalidCff);
+                    }
+                    for i in (0..(len - 6) / 2 * 2).step_by(2) {
+                        let to = self.offset((self.stack[i], self.stack[i + 1]));
+                        self.line_to(to)?;
+                    }
+                    self.curve_by(self.operands(len - 6))?;
+                    self.stack.clear();
+                }
+                // vvcurveto and hhcurveto, curves that start and end in one direction,
+                // an odd operand is the other coordinate of the first control point
+                26 | 27 => {
+                    let vertical = operator == 26;
+                    let mut first = 0.0;
+                    let mut i = 0;
+                    if self.stack.len() % 4 == 1 {
+                        first = self.stack[0];
+                        i = 1;
+                    }
+                    while i + 4 <= self.stack.len() {
+                        let s = &self.stack[i..i + 4];
+                        let curve = match vertical {
+                            true => [first, s[0], s[1], s[2], 0.0, s[3]],
+                            false => [s[0], first, s[1], s[2], s[3], 0.0],
+                        };
+                        self.curve_by(curve)?;
+                        first = 0.0;
+                        i += 4;
+                    }
+                    self.stack.clear();
+                }
+                // vhcurveto and hvcurveto, the curves alternate between starting vertical
+                // and horizontal, a fifth operand of the last is the end's other coordinate
+                30 | 31 => {
+                    let mut horizontal = operator == 31;
+                    let len = self.stack.len();
+                    let mut i = 0;
+                    while i + 4 <= len {
+                        let s = &self.stack[i..i + 4];
+                        let last = if i + 5 == len { self.stack[i + 4] } else { 0.0 };
+                        let curve = match horizontal {
+                            true => [s[0], 0.0, s[1], s[2], last, s[3]],
+                            false => [0.0, s[0], s[1], s[2], s[3], last],
+                        };
+                        self.curve_by(curve)?;
+                        horizontal = !horizontal;
+                        i += 4;
+                    }
+                    self.stack.clear();
+                }
+                // callsubr and callgsubr
+                10 | 29 => {
+                    let subrs = if operator == 10 {
+                        self.local_subrs
+                    } else {
+                        self.global_subrs
+                    };
+                    let index =
+                        self.stack.pop().ok_or(FontError::InvalidCff)? as i32 + bias(&subrs);
+                    let subr = usize::try_from(index)
+                        .ok()
+                        .and_then(|i| subrs.get(i))
+                        .ok_or(FontError::InvalidCff)?;
+                    if self.run(subr, depth + 1)? {
+                        return Ok(true);
+                    }
+                }
+                // return
+                11 => return Ok(false),
+                // endchar, the accented characters of Type 1 fonts are not supported
+                14 => {
+                    self.take_width(self.stack.len() == 1 || self.stack.len() == 5);
+                    return Ok(true);
+                }
+                // hflex
+                1234 => {
+                    let s = self.args::<7>()?;
+                    self.curve_by([s[0], 0.0, s[1], s[2], s[3], 0.0])?;
+                    self.curve_by([s[4], 0.0, s[5], -s[2], s[6], 0.0])?;
+                }
+                // flex
+                1235 => {
+                    let s = self.args::<13>()?;
+                    self.curve_by([s[0], s[1], s[2], s[3], s[4], s[5]])?;
+                    self.curve_by([s[6], s[7], s[8], s[9], s[10], s[11]])?;
+                }
+                // hflex1
+                1236 => {
+                    let s = self.args::<9>()?;
+                    self.curve_by([s[0], s[1], s[2], s[3], s[4], 0.0])?;
+                    let dy = -(s[1] + s[3] + s[7]);
+                    self.curve_by([s[5], 0.0, s[6], s[7], s[8], dy])?;
+                }
+                // flex1, the last operand is the coordinate that changes more
+                1237 => {
+                    let s = self.args::<11>()?;
+                    let dx: f32 = s[..10].iter().step_by(2).sum();
+                    let dy: f32 = s[1..10].iter().step_by(2).sum();
+                    let last = match dx.abs() > dy.abs() {
+                        true => (s[10], -dy),
+                        false => (-dx, s[10]),
+                    };
+                    self.curve_by([s[0], s[1], s[2], s[3], s[4], s[5]])?;
+                    self.curve_by([s[6], s[7], s[8], s[9], last.0, last.1])?;
+                }
+                _ => return Err(FontError::InvalidCff),
+            }
+        }
+        // a charstring without endchar ends as well
+        Ok(depth == 0)
+    }
+
+    /// The first operand of the first stack clearing operator is the width if `extra`
+    fn take_width(&mut self, extra: bool) {
+        if !self.has_width {
+            self.has_width = true;
+            if extra {
+                self.stack.remove(0);
+            }
+        }
+    }
+
+    fn arg(&self, i: usize) -> Result<f32, FontError> {
+        self.stack.get(i).copied().ok_or(FontError::InvalidCff)
+    }
+
+    /// Six operands from `i` on, there have to be enough
+    fn operands(&self, i: usize) -> [f32; 6] {
+        std::array::from_fn(|k| self.stack[i + k])
+    }
+
+    /// The operands of an operator that needs `N`, the stack is cleared
+    fn args<const N: usize>(&mut self) -> Result<[f32; N], FontError> {
+        let args = self.stack.get(..N).ok_or(FontError::InvalidCff)?;
+        let args = std::array::from_fn(|i| args[i]);
+        self.stack.clear();
+        Ok(args)
+    }
+
+    /// Move the pen by `d`, returns the new position
+    fn offset(&mut self, d: (f32, f32)) -> (f32, f32) {
+        self.pen = (self.pen.0 + d.0, self.pen.1 + d.1);
+        self.pen
+    }
+
+    fn line_to(&mut self, to: (f32, f32)) -> Result<(), FontError> {
+        self.check_contour()?;
+        self.segments.push(Segment::LineTo(to));
+        Ok(())
+    }
+
+    /// A curve from the operands as three offsets from the point before
+    fn curve_by(&mut self, d: [f32; 6]) -> Result<(), FontError> {
+        self.check_contour()?;
+        let a = self.offset((d[0], d[1]));
+        let b = self.offset((d[2], d[3]));
+        let c = self.offset((d[4], d[5]));
+        self.segments.push(Segment::CurveTo(a, b, c));
+        Ok(())
+    }
+
+    /// Drawing needs a move first
+    fn check_contour(&self) -> Result<(), FontError> {
+        match self.segments.is_empty() {
+            true => Err(FontError::InvalidCff),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// An INDEX of the items with 1 byte offsets
+    fn index(items: &[&[u8]]) -> Vec<u8> {
+        let mut data = (items.len() as u16).to_be_bytes().to_vec();
+        if items.is_empty() {
+            return data;
+        }
+        data.push(1);
+        let mut offset = 1;
+        data.push(offset);
+        for item in items {
+            offset += item.len() as u8;
+            data.push(offset);
+        }
+        data.extend(items.concat());
+        data
+    }
+
+    /// A charstring number operand
+    fn num(v: i16) -> Vec<u8> {
+        match v {
+            -107..=107 => vec![(v + 139) as u8],
+            _ => [&[28][..], &v.to_be_bytes()].concat(),
+        }
+    }
+
+    /// A charstring of numbers and then an operator, for each part
+    fn code(parts: &[(&[i16], &[u8])]) -> Vec<u8> {
+        parts
+            .iter()
+            .flat_map(|(operands, operator)| {
+                operands
+                    .iter()
+                    .flat_map(|&v| num(v))
+                    .chain(operator.iter().copied())
+            })
+            .collect()
+    }
+
+    fn run(code: &[u8], local_subrs: &[&[u8]]) -> Result<Vec<Segment>, FontError> {
+        let local = index(local_subrs);
+        let global = index(&[]);
+        let mut charstring = Charstring {
+            global_subrs: Index::parse(&global, 0)?.0,
+            local_subrs: Index::parse(&local, 0)?.0,
+            stack: Vec::new(),
+            stems: 0,
+            has_width: false,
+            operators: 0,
+            segments: Vec::new(),
+            pen: (0.0, 0.0),
+        };
+        charstring.run(code, 0)?;
+        Ok(charstring.segments)
+    }
+
+    use Segment::*;
+
+    #[test]
+    fn indexes() {
+        let data = index(&[b"ab", b"", b"cde"]);
+        let (index, end) = Index::parse(&data, 0).unwrap();
+        assert_eq!(end, data.len());
+        assert_eq!(index.get(0), Some(&b"ab"[..]));
+        assert_eq!(index.get(1), Some(&b""[..]));
+        assert_eq!(index.get(2), Some(&b"cde"[..]));
+        assert_eq!(index.get(3), None);
+
+        let (empty, end) = Index::parse(&[0, 0, 9], 0).unwrap();
+        assert_eq!((empty.count, end), (0, 2));
+        assert!(matches!(
+            Index::parse(&[0, 1, 5], 0),
+            Err(FontError::InvalidCff)
+        ));
+        assert!(matches!(
+            Index::parse(&[0, 1, 1, 1, 9], 0),
+            Err(FontError::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn dicts() {
+        // 0 and -2.25 for the font matrix's first operands, 1000 in two bytes and an
+        // offset in five
+        let dict = [
+            139, 30, 0xe2, 0xa2, 0x5f, 12, 7, 28, 3, 232, 29, 0, 1, 0, 0, 17,
+        ];
+        let dict = parse_dict(&dict).unwrap();
+        assert_eq!(dict_get(&dict, 1207), Some(&[0.0, -2.25][..]));
+        assert_eq!(dict_get(&dict, 17), Some(&[1000.0, 65536.0][..]));
+        assert_eq!(dict_offset(&dict, 17, 1).unwrap(), 65536);
+        assert!(dict_get(&dict, 18).is_none());
+        assert!(matches!(
+            dict_offset(&dict, 18, 0),
+            Err(FontError::InvalidCff)
+        ));
+
+        let mut real: &[u8] = &[0x1a, 0x5c, 0x2f];
+        assert_eq!(parse_real(&mut real).unwrap(), 1.5e-2);
+    }
+
+    #[test]
+    fn lines_and_width() {
+        // a width before the move, hlineto alternates and rlineto takes pairs
+        let segments = run(
+            &code(&[
+                (&[500, 10, 20], &[21]),
+                (&[100, 50, -100], &[6]),
+                (&[0, -20, 5, 5], &[5]),
+                (&[], &[14]),
+            ]),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            segments,
+            [
+                MoveTo((10.0, 20.0)),
+                LineTo((110.0, 20.0)),
+                LineTo((110.0, 70.0)),
+                LineTo((10.0, 70.0)),
+                LineTo((10.0, 50.0)),
+                LineTo((15.0, 55.0)),
+            ]
+        );
+
+        // without a width, and one for the hmoveto
+        let segments = run(&code(&[(&[30], &[22]), (&[40], &[7])]), &[]).unwrap();
+        assert_eq!(segments, [MoveTo((30.0, 0.0)), LineTo((30.0, 40.0))]);
+        let segments = run(&code(&[(&[600, 30], &[22])]), &[]).unwrap();
+        assert_eq!(segments, [MoveTo((30.0, 0.0))]);
+    }
+
+    #[test]
+    fn curves() {
+        let curve = |operator: &[u8], operands: &[i16]| {
+            let segments = run(&code(&[(&[0, 0], &[21]), (operands, operator)]), &[]);
+            segments.unwrap()[1..].to_vec()
+        };
+        let c = |a: (f32, f32), b: (f32, f32), c: (f32, f32)| CurveTo(a, b, c);
+
+        assert_eq!(
+            curve(&[8], &[1, 2, 3, 4, 5, 6]),
+            [c((1.0, 2.0), (4.0, 6.0), (9.0, 12.0))]
+        );
+        // rcurveline and rlinecurve
+        assert_eq!(
+            curve(&[24], &[1, 2, 3, 4, 5, 6, 10, 0]),
+            [c((1.0, 2.0), (4.0, 6.0), (9.0, 12.0)), LineTo((19.0, 12.0))]
+        );
+        assert_eq!(
+            curve(&[25], &[10, 0, 1, 2, 3, 4, 5, 6]),
+            [
+                LineTo((10.0, 0.0)),
+                c((11.0, 2.0), (14.0, 6.0), (19.0, 12.0))
+            ]
+        );
+        // hhcurveto with the first control point off the line, and vvcurveto
+        assert_eq!(
+            curve(&[27], &[7, 10, 1, 2, 10]),
+            [c((10.0, 7.0), (11.0, 9.0), (21.0, 9.0))]
+        );
+        assert_eq!(
+            curve(&[26], &[10, 1, 2, 10, 10, 1, 2, 10]),
+            [
+                c((0.0, 10.0), (1.0, 12.0), (1.0, 22.0)),
+                c((1.0, 32.0), (2.0, 34.0), (2.0, 44.0))
+            ]
+        );
+        // hvcurveto alternates, the odd operand ends the last curve off the line
+        assert_eq!(
+            curve(&[31], &[10, 1, 2, 10, 10, 1, 2, 10, 3]),
+            [
+                c((10.0, 0.0), (11.0, 2.0), (11.0, 12.0)),
+                c((11.0, 22.0), (12.0, 24.0), (22.0, 24.0 + 3.0))
+            ]
+        );
+        assert_eq!(
+            curve(&[30], &[10, 1, 2, 10, 5]),
+            [c((0.0, 10.0), (1.0, 12.0), (11.0, 17.0))]
+        );
+        // flex1 ends on the start's y as the curves go further in x
+        assert_eq!(
+            curve(&[12, 37], &[10, 2, 10, 2, 10, 1, 10, -1, 10, -2, 10])[1],
+            c((40.0, 4.0), (50.0, 2.0), (60.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn hints_are_skipped() {
+        // two horizontal stems, one vertical with the hintmask and a mask byte for them
+        let mut code = code(&[(&[0, 10, 20, 10], &[18]), (&[0, 10], &[19])]);
+        code.push(0b1110_0000);
+        code.extend([139, 139, 21, 149, 6, 14]);
+        let segments = run(&code, &[]).unwrap();
+        assert_eq!(segments, [MoveTo((0.0, 0.0)), LineTo((10.0, 0.0))]);
+    }
+
+    #[test]
+    fn subroutines() {
+        // the bias of a small INDEX is 107
+        let subr = code(&[(&[10], &[6]), (&[], &[11])]);
+        let segments = run(
+            &code(&[
+                (&[0, 0], &[21]),
+                (&[-107], &[10]),
+                (&[-107], &[10]),
+                (&[], &[14]),
+            ]),
+            &[&subr],
+        )
+        .unwrap();
+        assert_eq!(
+            segments,
+            [MoveTo((0.0, 0.0)), LineTo((10.0, 0.0)), LineTo((20.0, 0.0))]
+        );
+
+        // a subroutine that ends the glyph
+        let subr = code(&[(&[10], &[6]), (&[], &[14])]);
+        let segments = run(
+            &code(&[(&[0, 0], &[21]), (&[-107], &[10]), (&[10], &[7])]),
+            &[&subr],
+        );
+        assert_eq!(segments.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn errors() {
+        let invalid =
+            |code: &[u8], subrs: &[&[u8]]| matches!(run(code, subrs), Err(FontError::InvalidCff));
+        // a line before the move, a missing operand and an unknown operator
+        assert!(invalid(&code(&[(&[10], &[6])]), &[]));
+        assert!(invalid(&code(&[(&[], &[21])]), &[]));
+        assert!(invalid(&code(&[(&[0, 0], &[21]), (&[], &[0])]), &[]));
+        // too many operands
+        assert!(invalid(&[139; MAX_STACK + 1], &[]));
+        // a missing subroutine and one that calls itself
+        assert!(invalid(&code(&[(&[0], &[10])]), &[]));
+        let recursive = code(&[(&[-107], &[10])]);
+        assert!(invalid(&recursive, &[&recursive]));
+    }
+
+    #[test]
+    fn table() {
+        // a top DICT with the charstrings and a private DICT with subroutines after them
+        let glyphs = code(&[(&[0, 0], &[21]), (&[-107], &[10]), (&[], &[14])]);
+        let subr = code(&[(&[10], &[6]), (&[], &[11])]);
+        let notdef = code(&[(&[], &[14])]);
+        let char_strings = index(&[&notdef, &glyphs]);
+        let private = code(&[(&[2], &[19])]);
+        let subrs = index(&[&subr]);
+
+        let header = [1, 0, 4, 1];
+        let name = index(&[b"Test"]);
+        let strings = index(&[]);
+        let global = index(&[]);
+        // the operands take 3 bytes each so that the offsets don't change the length
+        let operand = |v: usize| [&[28][..], &(v as i16).to_be_bytes()].concat();
+        let top_len = 11;
+        let start = header.len() + name.len() + (5 + top_len) + strings.len() + global.len();
+        let private_start = start + char_strings.len();
+        let top = [
+            &operand(start)[..],
+            &[17],
+            &operand(private.len()),
+            &operand(private_start),
+            &[18],
+        ]
+        .concat();
+
+        let cff = [
+            &header[..],
+            &name,
+            &index(&[&top]),
+            &strings,
+            &global,
+            &char_strings,
+            &private,
+            &subrs,
+        ]
+        .concat();
+        let table = CffTable::parse(&cff).unwrap();
+        assert_eq!(table.outline(0).unwrap(), []);
+        assert_eq!(
+            table.outline(1).unwrap(),
+            [MoveTo((0.0, 0.0)), LineTo((10.0, 0.0))]
+        );
+        assert!(matches!(table.outline(2), Err(FontError::InvalidGlyph(2))));
+
+        let mut other = cff.clone();
+        other[0] = 2;
+        assert!(matches!(
+            CffTable::parse(&other),
+            Err(FontError::InvalidCff)
+        ));
+    }
+}
@@ -1,27 +1,121 @@
-use corelib::reader::{read_vec, Readable};
+use std::cmp::Ordering;
 
-use crate::tabledir::TableRecord;
+use corelib::reader::{read_vec, ByteReader, Readable, Reader};
+
+use crate::{tabledir::TableRecord, util::ensure, FontError};
 
 pub fn get_cmap(
     reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
     cmap_table_record: &TableRecord,
-) -> CMAPSubtable {
+) -> Result<CmapTable, FontError> {
     let offset = cmap_table_record.offset as usize;
     reader.set_pos(offset);
+    ensure(reader, 4)?;
     let cmap = reader.read::<CmapHeader>();
-    println!("{cmap:?}");
 
-    reader.set_pos(
-        offset
-            + cmap
-                .encoding_records
-                .iter()
-                .find(|er| er.platform_id == 0)
-                .unwrap()
-                .subtable_offset as usize,
-    );
-    let subtable = reader.read::<CMAPSubtable>();
-    subtable
+    let mut best: Option<(usize, Encoding, CMAPSubtable)> = None;
+    let mut error = None;
+    let mut variations = None;
+    for record in &cmap.encoding_records {
+        reader.set_pos(offset + record.subtable_offset as usize);
+        if (record.platform_id, record.encoding_id) == (0, 5) {
+            // broken variation sequences only lose the variants
+            variations = CMAPSubtableFormat14::parse(reader).ok();
+            continue;
+        }
+        let Some(rank) = ENCODING_PREFERENCE
+            .iter()
+            .position(|&(platform_id, encoding_id, _)| {
+                (platform_id, encoding_id) == (record.platform_id, record.encoding_id)
+            })
+        else {
+            continue;
+        };
+        if best
+            .as_ref()
+            .is_some_and(|(best_rank, ..)| *best_rank <= rank)
+        {
+            continue;
+        }
+        // a subtable that can't be read leaves the choice to the next best one
+        match CMAPSubtable::parse(reader) {
+            Ok(subtable) => best = Some((rank, ENCODING_PREFERENCE[rank].2, subtable)),
+            Err(e) => error = error.or(Some(e)),
+        }
+    }
+
+    let (_, encoding, subtable) =
+        best.ok_or_else(|| error.unwrap_or(FontError::MissingUnicodeCmap))?;
+    Ok(CmapTable {
+        encoding,
+        subtable,
+        variations,
+    })
+}
+
+/// The (platform, encoding) pairs that can be used for unicode characters, the full
+/// unicode ones before the ones limited to the basic multilingual plane
+const ENCODING_PREFERENCE: [(u16, u16, Encoding); 9] = [
+    (3, 10, Encoding::Unicode),
+    (0, 6, Encoding::Unicode),
+    (0, 4, Encoding::Unicode),
+    (3, 1, Encoding::Unicode),
+    (0, 3, Encoding::Unicode),
+    (0, 2, Encoding::Unicode),
+    (0, 1, Encoding::Unicode),
+    (0, 0, Encoding::Unicode),
+    (3, 0, Encoding::Symbol),
+];
+
+/// How characters are turned into the codes of the subtable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Unicode,
+    /// windows symbol fonts map their characters to 0xf020 to 0xf0ff
+    Symbol,
+}
+
+/// The chosen character map of a font
+#[derive(Debug, Clone)]
+pub struct CmapTable {
+    pub encoding: Encoding,
+    pub subtable: CMAPSubtable,
+    /// the unicode variation sequences, if the font has any
+    pub variations: Option<CMAPSubtableFormat14>,
+}
+
+impl CmapTable {
+    /// The glyph id of a character, None for characters that are not mapped
+    pub fn get_glyph_id(
+        &self,
+        reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
+        c: char,
+    ) -> Option<u16> {
+        let code = c as u32;
+        match self.encoding {
+            Encoding::Unicode => self.subtable.get_char_id(reader, code),
+            Encoding::Symbol if code < 0x100 => self
+                .subtable
+                .get_char_id(reader, code)
+                .or_else(|| self.subtable.get_char_id(reader, 0xf000 + code)),
+            Encoding::Symbol => self.subtable.get_char_id(reader, code),
+        }
+    }
+
+    /// The glyph id of a character followed by a variation selector, None if the font has
+    /// no glyph for this sequence
+    pub fn get_variation_glyph_id(
+        &self,
+        reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
+        c: char,
+        selector: char,
+    ) -> Option<u16> {
+        let variations = self.variations.as_ref()?;
+        match variations.get(reader, c as u32, selector as u32)? {
+            Variation::Default => self.get_glyph_id(reader, c),
+            Variation::Glyph(id) => Some(id),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -35,7 +129,9 @@ impl Readable for CmapHeader {
     fn read(reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>) -> Self {
         let version: u16 = reader.read();
         let num_tables: u16 = reader.read();
-        let encoding_records: Vec<EncodingRecord> = read_vec(reader, num_tables as usize);
+        // a table that claims more records than there is data only gets the ones that fit
+        let num_records = (num_tables as usize).min(reader.remaining() / 8);
+        let encoding_records: Vec<EncodingRecord> = read_vec(reader, num_records);
         Self {
             version,
             num_tables,
@@ -63,44 +159,94 @@ impl Readable for EncodingRecord {
 
 #[derive(Debug, Clone)]
 pub enum CMAPSubtable {
-    Format0(),
-    Format2(),
+    Format0(CMAPSubtableFormat0),
     Format4(CMAPSubtableFormat4),
-    Format6(),
-    Format8(),
-    Format10(),
+    Format6(CMAPSubtableFormat6),
     Format12(CMAPSubtableFormat12),
-    Format13(),
-    Format14(),
+    /// the same layout as format 12, but all characters of a group map to the same glyph
+    Format13(CMAPSubtableFormat12),
 }
 
 impl CMAPSubtable {
+    /// The glyph id of a character code, None for codes that are not mapped or map to
+    /// the missing glyph
     pub fn get_char_id(
         &self,
         reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
-        c: char,
-    ) -> usize {
-        match self {
-            Self::Format4(sub) => sub.get_char_id(reader, c),
-            _ => unimplemented!("subtable formats other than 4"),
-        }
+        code: u32,
+    ) -> Option<u16> {
+        let id = match self {
+            Self::Format0(sub) => sub.get_char_id(code),
+            Self::Format4(sub) => sub.get_char_id(reader, code),
+            Self::Format6(sub) => sub.get_char_id(code),
+            Self::Format12(sub) => sub.get_char_id(code, false),
+            Self::Format13(sub) => sub.get_char_id(code, true),
+        }?;
+        (id != 0).then_some(id)
+    }
+
+    /// Formats 2, 8 and 10 are for legacy multi byte encodings and are not supported,
+    /// format 14 is not a character map on its own, see [`CMAPSubtableFormat14`]
+    pub fn parse(
+        reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
+    ) -> Result<Self, FontError> {
+        ensure(reader, 2)?;
+        let format = reader.read::<u16>();
+        Ok(match format {
+            0 => {
+                ensure(reader, 4 + 256)?;
+                Self::Format0(reader.read::<CMAPSubtableFormat0>())
+            }
+            4 => {
+                ensure(reader, 12)?;
+                let mut header = Reader::new_big_endian(reader.data, reader.get_pos() + 4);
+                let seg_count = header.read::<u16>() as usize / 2;
+                ensure(reader, 14 + seg_count * 8)?;
+                Self::Format4(reader.read::<CMAPSubtableFormat4>())
+            }
+            6 => {
+                ensure(reader, 8)?;
+                let mut header = Reader::new_big_endian(reader.data, reader.get_pos() + 6);
+                let entry_count = header.read::<u16>() as usize;
+                ensure(reader, 8 + entry_count * 2)?;
+                Self::Format6(reader.read::<CMAPSubtableFormat6>())
+            }
+            12 | 13 => {
+                ensure(reader, 14)?;
+                let mut header = Reader::new_big_endian(reader.data, reader.get_pos() + 10);
+                let num_groups = header.read::<u32>() as usize;
+                ensure(reader, 14 + num_groups * 12)?;
+                let sub = reader.read::<CMAPSubtableFormat12>();
+                if format == 12 {
+                    Self::Format12(sub)
+                } else {
+                    Self::Format13(sub)
+                }
+            }
+            _ => return Err(FontError::UnsupportedCmapFormat(format)),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CMAPSubtableFormat0 {
+    pub length: u16,
+    pub language: u16,
+    pub glyph_id_array: Vec<u8>,
+}
+
+impl CMAPSubtableFormat0 {
+    pub fn get_char_id(&self, code: u32) -> Option<u16> {
+        self.glyph_id_array.get(code as usize).map(|&id| id as u16)
     }
 }
 
-impl Readable for CMAPSubtable {
+impl Readable for CMAPSubtableFormat0 {
     fn read(reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>) -> Self {
-        let format = reader.read::<u16>();
-        match format {
-            0 => Self::Format0(),
-            2 => Self::Format2(),
-            6 => Self::Format6(),
-            8 => Self::Format8(),
-            10 => Self::Format10(),
-            13 => Self::Format13(),
-            14 => Self::Format14(),
-            4 => Self::Format4(reader.read::<CMAPSubtableFormat4>()),
-            12 => Self::Format12(reader.read::<CMAPSubtableFormat12>()),
-            _ => unimplemented!("format {format}"),
+        Self {
+            length: reader.read(),
+            language: reader.read(),
+            glyph_id_array: read_vec(reader, 256),
         }
     }
 }
@@ -125,29 +271,22 @@ impl CMAPSubtableFormat4 {
     pub fn get_char_id(
         &self,
         reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
-        c: char,
-    ) -> usize {
+        code: u32,
+    ) -> Option<u16> {
         // Algorithm without pointer magic from: https://tchayen.github.io/posts/ttf-file-parsing
-        let c_code = c as u16;
+        let c_code = u16::try_from(code).ok()?;
 
-        let mut seg_idx = 0;
-        for (id, e_code) in self.end_code.iter().enumerate() {
-            if *e_code >= c_code && self.start_code[id] <= c_code {
-                seg_idx = id;
-                break;
-            }
-        }
-
-        println!("seg_idx: {seg_idx}");
+        let seg_idx = self
+            .end_code
+            .iter()
+            .zip(&self.start_code)
+            .position(|(e_code, s_code)| *e_code >= c_code && *s_code <= c_code)?;
 
         if self.id_range_offset[seg_idx] == 0 {
-            return ((c_code as i32 + self.id_delta[seg_idx] as i32) & 0xFFFF) as usize;
+            return Some(c_code.wrapping_add_signed(self.id_delta[seg_idx]));
         }
 
-        let start_code_offset = (c_code - self.start_code[seg_idx]) as usize * 2;
-        println!("start_code_offset: {start_code_offset}");
         let current_range_offset = seg_idx * 2; // 2 because the numbers are 2 byte big.
-        println!("current_range_offset: {current_range_offset}");
 
         let glyph_index_offset = self.id_range_offsets_start
             + current_range_offset
@@ -155,8 +294,14 @@ impl CMAPSubtableFormat4 {
             + (c_code as usize - self.start_code[seg_idx] as usize) * 2;
 
         reader.set_pos(glyph_index_offset);
+        if reader.remaining() < 2 {
+            return None;
+        }
         let index = reader.read::<u16>();
-        (index as i32 + self.id_delta[seg_idx] as i32) as usize
+        if index == 0 {
+            return None;
+        }
+        Some(index.wrapping_add_signed(self.id_delta[seg_idx]))
     }
 }
 
@@ -193,6 +338,40 @@ impl Readable for CMAPSubtableFormat4 {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct CMAPSubtableFormat6 {
+    pub length: u16,
+    pub language: u16,
+    pub first_code: u16,
+    pub entry_count: u16,
+    pub glyph_id_array: Vec<u16>,
+}
+
+impl CMAPSubtableFormat6 {
+    pub fn get_char_id(&self, code: u32) -> Option<u16> {
+        let index = code.checked_sub(self.first_code as u32)?;
+        self.glyph_id_array.get(index as usize).copied()
+    }
+}
+
+impl Readable for CMAPSubtableFormat6 {
+    fn read(reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>) -> Self {
+        let length: u16 = reader.read();
+        let language: u16 = reader.read();
+        let first_code: u16 = reader.read();
+        let entry_count: u16 = reader.read();
+        let glyph_id_array: Vec<u16> = read_vec(reader, entry_count as usize);
+
+        Self {
+            length,
+            language,
+            first_code,
+            entry_count,
+            glyph_id_array,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CMAPSubtableFormat12 {
     pub reserved: u16,
@@ -202,6 +381,26 @@ pub struct CMAPSubtableFormat12 {
     pub groups: Vec<SequentialMapGroup>,
 }
 
+impl CMAPSubtableFormat12 {
+    /// `constant` maps all characters of a group to its start glyph id, like format 13
+    pub fn get_char_id(&self, code: u32, constant: bool) -> Option<u16> {
+        // the groups are sorted by their character codes
+        let i = self.groups.partition_point(|g| g.end_char_code < code);
+        let group = self.groups.get(i)?;
+        if group.start_char_code > code {
+            return None;
+        }
+        let id = if constant {
+            group.start_glyph_id
+        } else {
+            group
+                .start_glyph_id
+                .checked_add(code - group.start_char_code)?
+        };
+        u16::try_from(id).ok()
+    }
+}
+
 impl Readable for CMAPSubtableFormat12 {
     fn read(reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>) -> Self {
         let reserved: u16 = reader.read();
@@ -236,3 +435,346 @@ impl Readable for SequentialMapGroup {
         }
     }
 }
+
+/// Unicode variation sequences: a character followed by a variation selector either uses
+/// the glyph from the regular character map or a glyph of its own. The sequences are read
+/// from the font data when they are looked up.
+#[derive(Debug, Clone)]
+pub struct CMAPSubtableFormat14 {
+    pub start: usize,
+    pub length: u32,
+    /// sorted by the selector
+    pub var_selector_records: Vec<VariationSelectorRecord>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VariationSelectorRecord {
+    pub var_selector: u32,
+    /// the ranges of characters that use their default glyph, 0 if there are none
+    pub default_uvs_offset: u32,
+    /// the characters with a glyph of their own, 0 if there are none
+    pub non_default_uvs_offset: u32,
+}
+
+impl Readable for VariationSelectorRecord {
+    fn read(reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>) -> Self {
+        Self {
+            var_selector: read_u24(reader),
+            default_uvs_offset: reader.read(),
+            non_default_uvs_offset: reader.read(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variation {
+    /// the sequence uses the glyph of the character without the selector
+    Default,
+    Glyph(u16),
+}
+
+impl CMAPSubtableFormat14 {
+    /// The reader has to be at the start of the subtable
+    pub fn parse(
+        reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
+    ) -> Result<Self, FontError> {
+        let start = reader.get_pos();
+        ensure(reader, 10)?;
+        let format = reader.read::<u16>();
+        if format != 14 {
+            return Err(FontError::UnsupportedCmapFormat(format));
+        }
+        let length = reader.read::<u32>();
+        let num_records = reader.read::<u32>() as usize;
+        ensure(reader, num_records * 11)?;
+        let var_selector_records = read_vec(reader, num_records);
+        Ok(Self {
+            start,
+            length,
+            var_selector_records,
+        })
+    }
+
+    /// How the sequence of a character code and a selector is drawn, None if the font has
+    /// no such sequence
+    pub fn get(
+        &self,
+        reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
+        code: u32,
+        selector: u32,
+    ) -> Option<Variation> {
+        let i = self
+            .var_selector_records
+            .binary_search_by_key(&selector, |r| r.var_selector)
+            .ok()?;
+        let record = &self.var_selector_records[i];
+
+        if record.non_default_uvs_offset != 0 {
+            // UVSMapping records: a 24 bit character and a glyph id
+            let found = self.search(reader, record.non_default_uvs_offset, 5, |reader| {
+                read_u24(reader).cmp(&code)
+            });
+            if let Some(pos) = found {
+                reader.set_pos(pos + 3);
+                return Some(Variation::Glyph(reader.read()));
+            }
+        }
+        if record.default_uvs_offset != 0 {
+            // UnicodeRange records: a 24 bit start and how many characters follow
+            let found = self.search(reader, record.default_uvs_offset, 4, |reader| {
+                let start = read_u24(reader);
+                let additional = reader.read::<u8>() as u32;
+                if code < start {
+                    Ordering::Greater
+                } else if code > start + additional {
+                    Ordering::Less
+                } else {
+                    Ordering::Equal
+                }
+            });
+            if found.is_some() {
+                return Some(Variation::Default);
+            }
+        }
+        None
+    }
+
+    /// Binary search the sorted list of `size` byte records at `offset`, which starts with
+    /// the number of records. Returns the position of the matching record.
+    fn search<R: ByteReader>(
+        &self,
+        reader: &mut Reader<R>,
+        offset: u32,
+        size: usize,
+        mut cmp: impl FnMut(&mut Reader<R>) -> Ordering,
+    ) -> Option<usize> {
+        reader.set_pos(self.start + offset as usize);
+        if reader.remaining() < 4 {
+            return None;
+        }
+        let count = reader.read::<u32>() as usize;
+        let first = reader.get_pos();
+        // a list that runs past the end of the data is cut off
+        let count = count.min(reader.remaining() / size);
+
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = (low + high) / 2;
+            reader.set_pos(first + mid * size);
+            match cmp(reader) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(first + mid * size),
+            }
+        }
+        None
+    }
+}
+
+fn read_u24(reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>) -> u32 {
+    let bytes = reader.read::<[u8; 3]>();
+    u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::util::FourByteTag;
+
+    fn be16(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn be32(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    fn u24(value: u32) -> Vec<u8> {
+        value.to_be_bytes()[1..].to_vec()
+    }
+
+    /// A cmap table with a subtable for every (platform, encoding) record
+    fn cmap(records: &[(u16, u16, Vec<u8>)]) -> (Vec<u8>, Result<CmapTable, FontError>) {
+        let mut data = be16(&[0, records.len() as u16]);
+        let mut offset = 4 + records.len() * 8;
+        for (platform_id, encoding_id, subtable) in records {
+            data.extend(be16(&[*platform_id, *encoding_id]));
+            data.extend(be32(&[offset as u32]));
+            offset += subtable.len();
+        }
+        for (_, _, subtable) in records {
+            data.extend(subtable);
+        }
+        let record = TableRecord {
+            table_tag: FourByteTag("cmap".to_string()),
+            checksum: 0,
+            offset: 0,
+            length: data.len() as u32,
+        };
+        let table = get_cmap(&mut Reader::new_big_endian(&data, 0), &record);
+        (data, table)
+    }
+
+    fn lookup(data: &[u8], table: &CmapTable, c: char) -> Option<u16> {
+        table.get_glyph_id(&mut Reader::new_big_endian(data, 0), c)
+    }
+
+    fn format0() -> Vec<u8> {
+        let mut out = be16(&[0, 262, 0]);
+        let mut ids = [0; 256];
+        ids[b'A' as usize] = 3;
+        ids[0xe9] = 4;
+        out.extend(ids);
+        out
+    }
+
+    /// a to z map to 1 to 26
+    fn format4() -> Vec<u8> {
+        be16(&[
+            4,
+            32,
+            0,
+            4,
+            4,
+            1,
+            0, // header
+            0x7a,
+            0xffff,
+            0, // end codes and padding
+            0x61,
+            0xffff, // start codes
+            (-0x60i16) as u16,
+            1, // deltas
+            0,
+            0, // range offsets
+        ])
+    }
+
+    fn format6(first_code: u16) -> Vec<u8> {
+        be16(&[6, 16, 0, first_code, 3, 10, 0, 12])
+    }
+
+    fn format12(format: u16, groups: &[(u32, u32, u32)]) -> Vec<u8> {
+        let mut out = be16(&[format, 0]);
+        out.extend(be32(&[
+            16 + groups.len() as u32 * 12,
+            0,
+            groups.len() as u32,
+        ]));
+        for &(start, end, glyph) in groups {
+            out.extend(be32(&[start, end, glyph]));
+        }
+        out
+    }
+
+    #[test]
+    fn formats() {
+        let (data, table) = cmap(&[(3, 1, format0())]);
+        let table = table.unwrap();
+        assert_eq!(lookup(&data, &table, 'A'), Some(3));
+        assert_eq!(lookup(&data, &table, '\u{e9}'), Some(4));
+        assert_eq!(lookup(&data, &table, 'B'), None);
+        assert_eq!(lookup(&data, &table, '\u{100}'), None);
+
+        let (data, table) = cmap(&[(3, 1, format4())]);
+        let table = table.unwrap();
+        assert_eq!(lookup(&data, &table, 'a'), Some(1));
+        assert_eq!(lookup(&data, &table, 'z'), Some(26));
+        assert_eq!(lookup(&data, &table, 'A'), None);
+        // the last segment maps 0xffff to the missing glyph
+        assert_eq!(lookup(&data, &table, '\u{ffff}'), None);
+        assert_eq!(lookup(&data, &table, '\u{1f600}'), None);
+
+        let (data, table) = cmap(&[(3, 1, format6(0x3b1))]);
+        let table = table.unwrap();
+        assert_eq!(lookup(&data, &table, '\u{3b1}'), Some(10));
+        assert_eq!(lookup(&data, &table, '\u{3b2}'), None);
+        assert_eq!(lookup(&data, &table, '\u{3b3}'), Some(12));
+        assert_eq!(lookup(&data, &table, '\u{3b0}'), None);
+        assert_eq!(lookup(&data, &table, '\u{3b4}'), None);
+
+        let groups = [
+            (0x41, 0x43, 20),
+            (0x1f600, 0x1f64f, 100),
+            (0x1f650, 0x1f650, 1 << 16),
+        ];
+        let (data, table) = cmap(&[(3, 10, format12(12, &groups))]);
+        let table = table.unwrap();
+        assert_eq!(lookup(&data, &table, 'A'), Some(20));
+        assert_eq!(lookup(&data, &table, 'C'), Some(22));
+        assert_eq!(lookup(&data, &table, 'D'), None);
+        assert_eq!(lookup(&data, &table, '\u{1f601}'), Some(101));
+        // glyph ids above 16 bits don't exist
+        assert_eq!(lookup(&data, &table, '\u{1f650}'), None);
+
+        let (data, table) = cmap(&[(3, 10, format12(13, &[(0x4e00, 0x9fff, 7)]))]);
+        let table = table.unwrap();
+        assert_eq!(lookup(&data, &table, '\u{4e00}'), Some(7));
+        assert_eq!(lookup(&data, &table, '\u{9fff}'), Some(7));
+        assert_eq!(lookup(&data, &table, '\u{a000}'), None);
+    }
+
+    #[test]
+    fn encoding_records() {
+        // full unicode wins over the basic multilingual plane, whatever the order
+        let (data, table) = cmap(&[
+            (0, 3, format4()),
+            (3, 10, format12(12, &[(0x61, 0x61, 50)])),
+            (3, 1, format0()),
+        ]);
+        assert_eq!(lookup(&data, &table.unwrap(), 'a'), Some(50));
+
+        // an unsupported subtable falls back to the next best one
+        let (data, table) = cmap(&[(3, 10, be16(&[8, 0, 0])), (3, 1, format4())]);
+        assert_eq!(lookup(&data, &table.unwrap(), 'a'), Some(1));
+
+        // symbol fonts have their characters in the private use area
+        let (data, table) = cmap(&[(1, 0, format0()), (3, 0, format6(0xf041))]);
+        let table = table.unwrap();
+        assert_eq!(table.encoding, Encoding::Symbol);
+        assert_eq!(lookup(&data, &table, 'A'), Some(10));
+        assert_eq!(lookup(&data, &table, '\u{f043}'), Some(12));
+
+        let (_, table) = cmap(&[(3, 1, be16(&[2, 0, 0]))]);
+        assert_eq!(table.unwrap_err(), FontError::UnsupportedCmapFormat(2));
+        let (_, table) = cmap(&[(1, 0, format0())]);
+        assert_eq!(table.unwrap_err(), FontError::MissingUnicodeCmap);
+        // a subtable past the end of the data
+        let (_, table) = cmap(&[(3, 1, Vec::new())]);
+        assert_eq!(table.unwrap_err(), FontError::UnexpectedEof);
+    }
+
+    #[test]
+    fn variation_sequences() {
+        // 0xfe00: a to c use their default glyph, x has a glyph of its own
+        // 0xfe0f: A has a glyph of its own
+        let mut format14 = be16(&[14]);
+        format14.extend(be32(&[0, 2]));
+        format14.extend(u24(0xfe00));
+        format14.extend(be32(&[32, 40]));
+        format14.extend(u24(0xfe0f));
+        format14.extend(be32(&[0, 49]));
+        format14.extend(be32(&[1]));
+        format14.extend(u24(0x61));
+        format14.push(2);
+        format14.extend(be32(&[1]));
+        format14.extend(u24(0x78));
+        format14.extend(be16(&[500]));
+        format14.extend(be32(&[1]));
+        format14.extend(u24(0x41));
+        format14.extend(be16(&[7]));
+
+        let (data, table) = cmap(&[(3, 1, format4()), (0, 5, format14)]);
+        let table = table.unwrap();
+        let variant = |c, selector| {
+            table.get_variation_glyph_id(&mut Reader::new_big_endian(&data, 0), c, selector)
+        };
+        assert_eq!(variant('a', '\u{fe00}'), Some(1));
+        assert_eq!(variant('c', '\u{fe00}'), Some(3));
+        assert_eq!(variant('d', '\u{fe00}'), None);
+        assert_eq!(variant('x', '\u{fe00}'), Some(500));
+        assert_eq!(variant('A', '\u{fe0f}'), Some(7));
+        assert_eq!(variant('a', '\u{fe0f}'), None);
+        assert_eq!(variant('a', '\u{fe01}'), None);
+    }
+}
@@ -1,114 +1,392 @@
-use corelib::reader::{read_vec, Readable, Reader};
+use corelib::reader::{read_vec, ByteReader, Readable, Reader};
 
-use super::{cmap::CMAPSubtable, loca::LocaTable};
+use super::loca::LocaTable;
+use crate::{util::ensure, FontError};
+
+/// Composite glyphs can't nest deeper than this
+pub(crate) const MAX_COMPONENT_DEPTH: usize = 8;
+/// The most components a glyph can be made of including nested ones, which keeps broken
+/// fonts that use the same component over and over from taking forever
+pub(crate) const MAX_COMPONENTS: usize = 256;
 
 pub fn get_glyf(
     glyf_global_offset: usize,
     src: &[u8],
-    c: char,
+    glyph_id: usize,
+    loca_table: &LocaTable,
+) -> Result<GlyphTable, FontError> {
+    read_glyph(glyf_global_offset, src, glyph_id, loca_table, 0, &mut 0)
+}
+
+/// A glyph as it is stored, the components of a composite glyph are not read yet
+pub(crate) enum GlyphData {
+    Simple(GlyphTable),
+    Composite {
+        bounding_box: BoundingBox,
+        components: Vec<(u16, Component)>,
+        instructions: Vec<u8>,
+    },
+}
+
+/// Read a simple glyph or assemble a composite glyph from its components. `components`
+/// counts the components read so far.
+fn read_glyph(
+    glyf_global_offset: usize,
+    src: &[u8],
+    glyph_id: usize,
+    loca_table: &LocaTable,
+    depth: usize,
+    components: &mut usize,
+) -> Result<GlyphTable, FontError> {
+    let (bounding_box, parts, instructions) =
+        match read_glyph_data(glyf_global_offset, src, glyph_id, loca_table)? {
+            GlyphData::Simple(glyph) => return Ok(glyph),
+            GlyphData::Composite {
+                bounding_box,
+                components,
+                instructions,
+            } => (bounding_box, components, instructions),
+        };
+    if depth >= MAX_COMPONENT_DEPTH {
+        return Err(FontError::ComponentLimit);
+    }
+    let mut glyph = GlyphTable {
+        bounding_box,
+        instruction_length: instructions.len() as u16,
+        instructions,
+        ..Default::default()
+    };
+    for (glyph_index, component) in parts {
+        *components += 1;
+        if *components > MAX_COMPONENTS {
+            return Err(FontError::ComponentLimit);
+        }
+        let child = read_glyph(
+            glyf_global_offset,
+            src,
+            glyph_index as usize,
+            loca_table,
+            depth + 1,
+            components,
+        )?;
+        component.append(&mut glyph, child, |offset| offset)?;
+    }
+    Ok(glyph)
+}
+
+/// Read a simple glyph, or the components and instructions of a composite glyph
+pub(crate) fn read_glyph_data(
+    glyf_global_offset: usize,
+    src: &[u8],
+    glyph_id: usize,
     loca_table: &LocaTable,
-    cmap_table: &CMAPSubtable,
-) -> GlyphTable {
-    let mut reader = Reader::new_big_endian(src, 0);
-    let char_id = cmap_table.get_char_id(&mut reader, c);
-    println!("{char_id}");
-    let offset = loca_table.get_char_id_offset(char_id);
-    println!("glyf_global_offset: {glyf_global_offset}");
-    println!("offset: {offset}");
-    let real_offset = glyf_global_offset + offset as usize;
-    println!("read_offset: {real_offset}");
-    reader.set_pos(real_offset);
+) -> Result<GlyphData, FontError> {
+    let range = loca_table.get_glyph_range(glyph_id)?;
+    let real_offset = glyf_global_offset + range.start;
 
+    // glyphs without an outline, like the space, have no data at all
+    if range.is_empty() {
+        return Ok(GlyphData::Simple(GlyphTable::default()));
+    }
+
+    // the reader ends with the glyph so that broken data can't reach into other glyphs
+    let data = src
+        .get(..glyf_global_offset + range.end)
+        .ok_or(FontError::InvalidGlyph(glyph_id))?;
+    let mut reader = Reader::new_big_endian(data, real_offset);
+
+    ensure(&reader, 10)?;
     let header: GlyfHeader = reader.read();
-    println!("{header:?}");
-
-    if header.number_of_contours > 0 {
-        let end_pts_of_contours: Vec<u16> =
-            read_vec(&mut reader, header.number_of_contours as usize);
-        let num_points = end_pts_of_contours.last().unwrap() + 1;
-        let instruction_length: u16 = reader.read();
-        let instructions: Vec<u8> = read_vec(&mut reader, instruction_length as usize);
-
-        let mut flags_arr = vec![];
-        let mut i = 0;
-        loop {
-            if i >= num_points {
-                break;
-            }
-            let flags = reader.read::<GlyphFlags>();
-            //println!("{flags:b}");
-            if flags.repeat_flag {
-                let num = reader.read_byte();
-                for _ in 0..num as u16 + 1 {
-                    flags_arr.push(flags);
-                }
-            } else {
+
+    if header.number_of_contours >= 0 {
+        return read_simple_glyph(&mut reader, &header).map(GlyphData::Simple);
+    }
+
+    let mut components = Vec::new();
+    let mut flags;
+    loop {
+        ensure(&reader, 4)?;
+        flags = reader.read::<u16>();
+        let glyph_index = reader.read::<u16>();
+        components.push((glyph_index, Component::read(&mut reader, flags)?));
+        if flags & MORE_COMPONENTS == 0 || components.len() > MAX_COMPONENTS {
+            break;
+        }
+    }
+
+    let mut instructions = Vec::new();
+    if flags & WE_HAVE_INSTRUCTIONS != 0 {
+        ensure(&reader, 2)?;
+        let instruction_length = reader.read::<u16>() as usize;
+        ensure(&reader, instruction_length)?;
+        instructions = read_vec(&mut reader, instruction_length);
+    }
+    Ok(GlyphData::Composite {
+        bounding_box: header.bounding_box(),
+        components,
+        instructions,
+    })
+}
+
+fn read_simple_glyph(
+    reader: &mut Reader<impl ByteReader>,
+    header: &GlyfHeader,
+) -> Result<GlyphTable, FontError> {
+    let number_of_contours = header.number_of_contours as usize;
+    ensure(reader, number_of_contours * 2 + 2)?;
+    let end_pts_of_contours: Vec<u16> = read_vec(reader, number_of_contours);
+    let num_points = end_pts_of_contours.last().map_or(0, |&p| p as usize + 1);
+    let instruction_length: u16 = reader.read();
+    ensure(reader, instruction_length as usize)?;
+    let instructions: Vec<u8> = read_vec(reader, instruction_length as usize);
+
+    let mut flags_arr = vec![];
+    while flags_arr.len() < num_points {
+        ensure(reader, 1)?;
+        let flags = reader.read::<GlyphFlags>();
+        //println!("{flags:b}");
+        if flags.repeat_flag {
+            ensure(reader, 1)?;
+            let num = reader.read_byte();
+            for _ in 0..num as u16 + 1 {
                 flags_arr.push(flags);
             }
-
-            i += 1;
+        } else {
+            flags_arr.push(flags);
         }
+    }
+    flags_arr.truncate(num_points);
 
-        let mut x_coordinates: Vec<i32> = Vec::with_capacity(flags_arr.len());
-        for i in 0..flags_arr.len() {
-            let last = x_coordinates.last().unwrap_or(&0);
-            let flags = flags_arr[i];
-            if flags.x_short_vector {
-                let v = reader.read_byte();
-                if flags.x_is_same_or_positive_x_short_vector {
-                    x_coordinates.push(last + v as i32);
-                } else {
-                    x_coordinates.push(last - v as i32);
-                }
+    let coordinate_size = |short: bool, same: bool| match (short, same) {
+        (true, _) => 1,
+        (false, true) => 0,
+        (false, false) => 2,
+    };
+    let coordinates_len: usize = flags_arr
+        .iter()
+        .map(|f| {
+            coordinate_size(f.x_short_vector, f.x_is_same_or_positive_x_short_vector)
+                + coordinate_size(f.y_short_vector, f.y_is_same_or_positive_y_short_vector)
+        })
+        .sum();
+    ensure(reader, coordinates_len)?;
+    let mut x_coordinates: Vec<i32> = Vec::with_capacity(flags_arr.len());
+    for i in 0..flags_arr.len() {
+        let last = x_coordinates.last().unwrap_or(&0);
+        let flags = flags_arr[i];
+        if flags.x_short_vector {
+            let v = reader.read_byte();
+            if flags.x_is_same_or_positive_x_short_vector {
+                x_coordinates.push(last + v as i32);
             } else {
-                if flags.x_is_same_or_positive_x_short_vector {
-                    x_coordinates.push(*last);
-                } else {
-                    x_coordinates.push(*last + reader.read::<i16>() as i32);
-                }
+                x_coordinates.push(last - v as i32);
+            }
+        } else {
+            if flags.x_is_same_or_positive_x_short_vector {
+                x_coordinates.push(*last);
+            } else {
+                x_coordinates.push(*last + reader.read::<i16>() as i32);
             }
         }
+    }
 
-        let mut y_coordinates: Vec<i32> = Vec::with_capacity(flags_arr.len());
-        for i in 0..flags_arr.len() {
-            let last = y_coordinates.last().unwrap_or(&0);
-            let flags = flags_arr[i];
-            if flags.y_short_vector {
-                let v = reader.read_byte();
-                if flags.y_is_same_or_positive_y_short_vector {
-                    y_coordinates.push(last + v as i32);
-                } else {
-                    y_coordinates.push(last - v as i32);
-                }
+    let mut y_coordinates: Vec<i32> = Vec::with_capacity(flags_arr.len());
+    for i in 0..flags_arr.len() {
+        let last = y_coordinates.last().unwrap_or(&0);
+        let flags = flags_arr[i];
+        if flags.y_short_vector {
+            let v = reader.read_byte();
+            if flags.y_is_same_or_positive_y_short_vector {
+                y_coordinates.push(last + v as i32);
             } else {
-                if flags.y_is_same_or_positive_y_short_vector {
-                    y_coordinates.push(*last);
-                } else {
-                    y_coordinates.push(last + reader.read::<i16>() as i32);
-                }
+                y_coordinates.push(last - v as i32);
+            }
+        } else {
+            if flags.y_is_same_or_positive_y_short_vector {
+                y_coordinates.push(*last);
+            } else {
+                y_coordinates.push(last + reader.read::<i16>() as i32);
             }
         }
+    }
+
+    Ok(GlyphTable {
+        bounding_box: header.bounding_box(),
+        end_pts_of_contours,
+        instruction_length,
+        instructions,
+        flags: flags_arr,
+        x_coordinates,
+        y_coordinates,
+    })
+}
+
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const ARGS_ARE_XY_VALUES: u16 = 0x0002;
+const ROUND_XY_TO_GRID: u16 = 0x0004;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+const USE_MY_METRICS: u16 = 0x0200;
+const SCALED_COMPONENT_OFFSET: u16 = 0x0800;
+
+/// How a component is placed in a composite glyph
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Component {
+    /// `x' = a * x + c * y` and `y' = b * x + d * y`
+    transform: [f32; 4],
+    placement: Placement,
+    /// whether the offset is transformed as well, the default is to add it afterwards
+    scaled_offset: bool,
+    /// whether a hinted offset is rounded to whole pixels
+    pub round_to_grid: bool,
+    /// whether the composite glyph has the metrics of this component
+    pub use_my_metrics: bool,
+}
 
-        GlyphTable {
-            end_pts_of_contours,
-            instruction_length,
-            instructions,
-            flags: flags_arr,
-            x_coordinates,
-            y_coordinates,
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Placement {
+    Offset(i32, i32),
+    /// the point of the glyph so far and the point of the component that end up in the
+    /// same place
+    MatchPoints(usize, usize),
+}
+
+/// a 2.14 fixed point number
+fn read_f2dot14(reader: &mut Reader<impl ByteReader>) -> f32 {
+    reader.read::<i16>() as f32 / 16384.0
+}
+
+impl Component {
+    /// Read the arguments and the transform of a component with the flags
+    fn read(reader: &mut Reader<impl ByteReader>, flags: u16) -> Result<Self, FontError> {
+        let words = flags & ARG_1_AND_2_ARE_WORDS != 0;
+        let xy_values = flags & ARGS_ARE_XY_VALUES != 0;
+        ensure(reader, if words { 4 } else { 2 })?;
+        let (arg1, arg2) = match (words, xy_values) {
+            (true, true) => (reader.read::<i16>() as i32, reader.read::<i16>() as i32),
+            (true, false) => (reader.read::<u16>() as i32, reader.read::<u16>() as i32),
+            (false, true) => (reader.read::<i8>() as i32, reader.read::<i8>() as i32),
+            (false, false) => (reader.read::<u8>() as i32, reader.read::<u8>() as i32),
+        };
+        let placement = if xy_values {
+            Placement::Offset(arg1, arg2)
+        } else {
+            Placement::MatchPoints(arg1 as usize, arg2 as usize)
+        };
+
+        let transform = if flags & WE_HAVE_A_SCALE != 0 {
+            ensure(reader, 2)?;
+            let scale = read_f2dot14(reader);
+            [scale, 0.0, 0.0, scale]
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            ensure(reader, 4)?;
+            let x_scale = read_f2dot14(reader);
+            let y_scale = read_f2dot14(reader);
+            [x_scale, 0.0, 0.0, y_scale]
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            ensure(reader, 8)?;
+            std::array::from_fn(|_| read_f2dot14(reader))
+        } else {
+            [1.0, 0.0, 0.0, 1.0]
+        };
+
+        Ok(Self {
+            transform,
+            placement,
+            scaled_offset: flags & SCALED_COMPONENT_OFFSET != 0,
+            round_to_grid: flags & ROUND_XY_TO_GRID != 0,
+            use_my_metrics: flags & USE_MY_METRICS != 0,
+        })
+    }
+
+    /// Transform the outline of the component and add it to `glyph`. `map_offset` turns
+    /// an offset from font units into the units of the outlines, points that are matched
+    /// are already in them.
+    pub fn append(
+        &self,
+        glyph: &mut GlyphTable,
+        child: GlyphTable,
+        map_offset: impl Fn((f32, f32)) -> (f32, f32),
+    ) -> Result<(), FontError> {
+        let [a, b, c, d] = self.transform;
+        let points: Vec<(f32, f32)> = child
+            .x_coordinates
+            .iter()
+            .zip(&child.y_coordinates)
+            .map(|(&x, &y)| {
+                let (x, y) = (x as f32, y as f32);
+                (a * x + c * y, b * x + d * y)
+            })
+            .collect();
+
+        let (dx, dy) = match self.placement {
+            Placement::Offset(x, y) if self.scaled_offset => {
+                let (x, y) = (x as f32, y as f32);
+                map_offset((a * x + c * y, b * x + d * y))
+            }
+            Placement::Offset(x, y) => map_offset((x as f32, y as f32)),
+            Placement::MatchPoints(parent, own) => {
+                let (Some(&px), Some(&py), Some(&(x, y))) = (
+                    glyph.x_coordinates.get(parent),
+                    glyph.y_coordinates.get(parent),
+                    points.get(own),
+                ) else {
+                    return Err(FontError::InvalidComponent);
+                };
+                (px as f32 - x, py as f32 - y)
+            }
+        };
+
+        let first = glyph.x_coordinates.len();
+        if first + points.len() > u16::MAX as usize + 1 {
+            return Err(FontError::ComponentLimit);
+        }
+        glyph.end_pts_of_contours.extend(
+            child
+                .end_pts_of_contours
+                .iter()
+                .map(|&end| (first + end as usize) as u16),
+        );
+        for (x, y) in points {
+            glyph.x_coordinates.push((x + dx).round() as i32);
+            glyph.y_coordinates.push((y + dy).round() as i32);
         }
-    } else {
-        todo!("multiple contours")
+        glyph.flags.extend(child.flags);
+        Ok(())
     }
 }
 
 #[derive(Debug)]
 pub struct GlyfHeader {
-    number_of_contours: i16,
-    x_min: i16,
-    y_min: i16,
-    x_max: i16,
-    y_max: i16,
+    /// negative for composite glyphs
+    pub number_of_contours: i16,
+    pub x_min: i16,
+    pub y_min: i16,
+    pub x_max: i16,
+    pub y_max: i16,
+}
+
+impl GlyfHeader {
+    pub fn bounding_box(&self) -> BoundingBox {
+        BoundingBox {
+            x_min: self.x_min,
+            y_min: self.y_min,
+            x_max: self.x_max,
+            y_max: self.y_max,
+        }
+    }
+}
+
+/// A rectangle in font units, y goes up
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BoundingBox {
+    pub x_min: i16,
+    pub y_min: i16,
+    pub x_max: i16,
+    pub y_max: i16,
 }
 
 impl Readable for GlyfHeader {
@@ -123,8 +401,12 @@ impl Readable for GlyfHeader {
     }
 }
 
-#[derive(Debug)]
+/// The outline of a glyph. For composite glyphs the components are transformed and put
+/// together, so that their contours follow each other.
+#[derive(Debug, Default)]
 pub struct GlyphTable {
+    /// as stored in the font, empty for glyphs without an outline
+    pub bounding_box: BoundingBox,
     pub end_pts_of_contours: Vec<u16>,
     pub instruction_length: u16,
     pub instructions: Vec<u8>,
@@ -166,3 +448,149 @@ impl Readable for GlyphFlags {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tables::loca::get_loca_table;
+
+    /// a glyph with one contour of on curve points
+    fn simple(points: &[(i16, i16)]) -> Vec<u8> {
+        let mut out = vec![0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        out.extend((points.len() as u16 - 1).to_be_bytes());
+        out.extend([0, 0]);
+        out.extend(std::iter::repeat_n(0x01, points.len()));
+        let mut last = (0, 0);
+        for &(x, _) in points {
+            out.extend((x - last.0).to_be_bytes());
+            last.0 = x;
+        }
+        for &(_, y) in points {
+            out.extend((y - last.1).to_be_bytes());
+            last.1 = y;
+        }
+        out
+    }
+
+    fn composite(components: &[(u16, u16, &[i16])]) -> Vec<u8> {
+        let mut out = vec![0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0];
+        for (i, &(flags, glyph, values)) in components.iter().enumerate() {
+            let more = if i + 1 < components.len() {
+                MORE_COMPONENTS
+            } else {
+                0
+            };
+            out.extend((flags | more | ARG_1_AND_2_ARE_WORDS).to_be_bytes());
+            out.extend(glyph.to_be_bytes());
+            for v in values {
+                out.extend(v.to_be_bytes());
+            }
+        }
+        out
+    }
+
+    fn read(glyphs: &[Vec<u8>], id: usize) -> Result<GlyphTable, FontError> {
+        let mut glyf = Vec::new();
+        let mut loca = vec![0, 0, 0, 0];
+        for glyph in glyphs {
+            glyf.extend(glyph);
+            loca.extend((glyf.len() as u32).to_be_bytes());
+        }
+        let loca = get_loca_table(&mut Reader::new_big_endian(&loca, 0), glyphs.len(), 1)?;
+        read_glyph(0, &glyf, id, &loca, 0, &mut 0)
+    }
+
+    fn points(glyph: &GlyphTable) -> Vec<(i32, i32)> {
+        glyph
+            .x_coordinates
+            .iter()
+            .copied()
+            .zip(glyph.y_coordinates.iter().copied())
+            .collect()
+    }
+
+    /// 1.0 and 0.5 in 2.14 fixed point
+    const ONE: i16 = 0x4000;
+    const HALF: i16 = 0x2000;
+
+    #[test]
+    fn offsets_and_transforms() {
+        let triangle = simple(&[(0, 0), (100, 0), (0, 50)]);
+        let glyphs = [
+            triangle.clone(),
+            composite(&[
+                (ARGS_ARE_XY_VALUES, 0, &[10, 20]),
+                (ARGS_ARE_XY_VALUES | WE_HAVE_A_SCALE, 0, &[-5, 0, HALF]),
+                // rotated by 90 degrees
+                (
+                    ARGS_ARE_XY_VALUES | WE_HAVE_A_TWO_BY_TWO,
+                    0,
+                    &[0, 0, 0, ONE, -ONE, 0],
+                ),
+            ]),
+        ];
+        let glyph = read(&glyphs, 1).unwrap();
+        assert_eq!(glyph.end_pts_of_contours, [2, 5, 8]);
+        assert_eq!(
+            points(&glyph),
+            [
+                (10, 20),
+                (110, 20),
+                (10, 70),
+                (-5, 0),
+                (45, 0),
+                (-5, 25),
+                (0, 0),
+                (0, 100),
+                (-50, 0)
+            ]
+        );
+        assert_eq!(glyph.flags.len(), 9);
+
+        // the offset can be transformed as well
+        let glyphs = [
+            triangle,
+            composite(&[(
+                ARGS_ARE_XY_VALUES | WE_HAVE_AN_X_AND_Y_SCALE | SCALED_COMPONENT_OFFSET,
+                0,
+                &[10, 10, HALF, ONE],
+            )]),
+        ];
+        let glyph = read(&glyphs, 1).unwrap();
+        assert_eq!(points(&glyph), [(5, 10), (55, 10), (5, 60)]);
+    }
+
+    #[test]
+    fn nested_and_matched_points() {
+        let glyphs = [
+            simple(&[(0, 0), (10, 0), (10, 10)]),
+            simple(&[(1, 1), (2, 2)]),
+            // the first point of glyph 1 is put onto the last point of glyph 0
+            composite(&[(ARGS_ARE_XY_VALUES, 0, &[0, 0]), (0, 1, &[2, 0])]),
+            composite(&[(ARGS_ARE_XY_VALUES, 2, &[100, 0])]),
+        ];
+        let glyph = read(&glyphs, 3).unwrap();
+        assert_eq!(glyph.end_pts_of_contours, [2, 4]);
+        assert_eq!(
+            points(&glyph),
+            [(100, 0), (110, 0), (110, 10), (110, 10), (111, 11)]
+        );
+    }
+
+    #[test]
+    fn broken_composites() {
+        let glyphs = [
+            simple(&[(0, 0), (10, 0)]),
+            composite(&[(0, 0, &[5, 0])]),
+            // a glyph that contains itself
+            composite(&[(ARGS_ARE_XY_VALUES, 2, &[0, 0])]),
+            composite(&[(ARGS_ARE_XY_VALUES, 9, &[0, 0])]),
+        ];
+        assert_eq!(read(&glyphs, 1).unwrap_err(), FontError::InvalidComponent);
+        assert_eq!(read(&glyphs, 2).unwrap_err(), FontError::ComponentLimit);
+        assert_eq!(read(&glyphs, 3).unwrap_err(), FontError::InvalidGlyph(9));
+        let mut truncated = glyphs.to_vec();
+        truncated[1].pop();
+        assert_eq!(read(&truncated, 1).unwrap_err(), FontError::UnexpectedEof);
+    }
+}
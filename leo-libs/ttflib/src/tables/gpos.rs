@@ -0,0 +1,136 @@
+//! Glyph positioning, only pair adjustments (type 2) are supported. Lookups of other types
+//! are skipped.
+
+use super::layout::{coverage_index, glyph_class, search, Gdef, LayoutTable, Table};
+use crate::PositionedGlyph;
+
+const PAIR: u16 = 2;
+pub const EXTENSION: u16 = 9;
+
+/// The adjustment of one glyph in font units
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ValueRecord {
+    x_placement: i16,
+    y_placement: i16,
+    x_advance: i16,
+    y_advance: i16,
+}
+
+impl ValueRecord {
+    /// The four device table offsets that can follow are ignored
+    fn read(table: Table, pos: usize, format: u16) -> Option<Self> {
+        let mut value = Self::default();
+        let mut pos = pos;
+        let fields = [
+            &mut value.x_placement,
+            &mut value.y_placement,
+            &mut value.x_advance,
+            &mut value.y_advance,
+        ];
+        for (bit, field) in fields.into_iter().enumerate() {
+            if format & (1 << bit) != 0 {
+                *field = table.read::<i16>(pos)?;
+                pos += 2;
+            }
+        }
+        Some(value)
+    }
+
+    fn size(format: u16) -> usize {
+        (format & 0xff).count_ones() as usize * 2
+    }
+
+    fn apply(&self, glyph: &mut PositionedGlyph) {
+        glyph.x_offset += self.x_placement as i32;
+        glyph.y_offset += self.y_placement as i32;
+        glyph.x_advance += self.x_advance as i32;
+        glyph.y_advance += self.y_advance as i32;
+    }
+}
+
+/// Apply a lookup to all glyphs
+pub fn apply_lookup(
+    gpos: &LayoutTable,
+    gdef: &Gdef,
+    lookup_index: u16,
+    glyphs: &mut [PositionedGlyph],
+) {
+    let Some(lookup) = gpos.lookup(lookup_index) else {
+        return;
+    };
+    if lookup.kind != PAIR {
+        return;
+    }
+
+    let mut i = 0;
+    while i < glyphs.len() {
+        if gdef.skips(lookup.flag, glyphs[i].id.0) {
+            i += 1;
+            continue;
+        }
+        let mut j = i + 1;
+        while j < glyphs.len() && gdef.skips(lookup.flag, glyphs[j].id.0) {
+            j += 1;
+        }
+        if j >= glyphs.len() {
+            break;
+        }
+
+        let mut next = j;
+        let pair = lookup
+            .subtables()
+            .find_map(|sub| pair(sub, glyphs[i].id.0, glyphs[j].id.0));
+        if let Some((first, second, second_format)) = pair {
+            first.apply(&mut glyphs[i]);
+            second.apply(&mut glyphs[j]);
+            // a pair that moves the second glyph isn't followed by a pair starting with it
+            if second_format != 0 {
+                next = j + 1;
+            }
+        }
+        i = next;
+    }
+}
+
+/// The adjustments of a glyph pair and the value format of the second glyph, None if the
+/// subtable doesn't contain the pair
+fn pair(subtable: Table, first: u16, second: u16) -> Option<(ValueRecord, ValueRecord, u16)> {
+    let index = coverage_index(subtable.offset(2)?, first)?;
+    let format1 = subtable.read::<u16>(4)?;
+    let format2 = subtable.read::<u16>(6)?;
+    let (size1, size2) = (ValueRecord::size(format1), ValueRecord::size(format2));
+
+    let (table, pos) = match subtable.read::<u16>(0)? {
+        1 => {
+            if index >= subtable.read::<u16>(8)? as usize {
+                return None;
+            }
+            let set = subtable.offset(10 + index * 2)?;
+            let record_size = 2 + size1 + size2;
+            let count = set.read::<u16>(0)? as usize;
+            let i = search(count, |i| {
+                Some(set.read::<u16>(2 + i * record_size)?.cmp(&second))
+            })?;
+            (set, 2 + i * record_size + 2)
+        }
+        2 => {
+            let class1 = glyph_class(subtable.offset(8)?, first) as usize;
+            let class2 = glyph_class(subtable.offset(10)?, second) as usize;
+            let class1_count = subtable.read::<u16>(12)? as usize;
+            let class2_count = subtable.read::<u16>(14)? as usize;
+            if class1 >= class1_count || class2 >= class2_count {
+                return None;
+            }
+            (
+                subtable,
+                16 + (class1 * class2_count + class2) * (size1 + size2),
+            )
+        }
+        _ => return None,
+    };
+    Some((
+        ValueRecord::read(table, pos, format1)?,
+        ValueRecord::read(table, pos + size1, format2)?,
+        format2,
+    ))
+}
@@ -0,0 +1,116 @@
+//! Glyph substitution, only single (type 1) and ligature (type 4) substitutions are
+//! supported. Lookups of other types are skipped.
+
+use super::layout::{coverage_index, Gdef, LayoutTable, Table};
+use crate::{GlyphId, PositionedGlyph};
+
+const SINGLE: u16 = 1;
+const LIGATURE: u16 = 4;
+pub const EXTENSION: u16 = 7;
+
+/// Apply a lookup to all glyphs
+pub fn apply_lookup(
+    gsub: &LayoutTable,
+    gdef: &Gdef,
+    lookup_index: u16,
+    glyphs: &mut Vec<PositionedGlyph>,
+) {
+    let Some(lookup) = gsub.lookup(lookup_index) else {
+        return;
+    };
+    match lookup.kind {
+        SINGLE => {
+            for glyph in glyphs.iter_mut() {
+                if gdef.skips(lookup.flag, glyph.id.0) {
+                    continue;
+                }
+                if let Some(id) = lookup.subtables().find_map(|sub| single(sub, glyph.id.0)) {
+                    glyph.id = GlyphId(id);
+                }
+            }
+        }
+        LIGATURE => {
+            let mut i = 0;
+            while i < glyphs.len() {
+                if !gdef.skips(lookup.flag, glyphs[i].id.0) {
+                    for sub in lookup.subtables() {
+                        if ligature(sub, lookup.flag, gdef, glyphs, i).is_some() {
+                            break;
+                        }
+                    }
+                }
+                i += 1;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The glyph that replaces `glyph`, None if the subtable doesn't cover it
+fn single(subtable: Table, glyph: u16) -> Option<u16> {
+    let index = coverage_index(subtable.offset(2)?, glyph)?;
+    match subtable.read::<u16>(0)? {
+        1 => Some(glyph.wrapping_add_signed(subtable.read::<i16>(4)?)),
+        2 => {
+            let count = subtable.read::<u16>(4)? as usize;
+            if index >= count {
+                return None;
+            }
+            subtable.read::<u16>(6 + index * 2)
+        }
+        _ => None,
+    }
+}
+
+/// Replace the glyphs from `start` with the first ligature of the subtable that matches
+/// them. The ligature keeps the cluster of its first glyph, skipped glyphs between the
+/// components stay where they are.
+fn ligature(
+    subtable: Table,
+    flag: u16,
+    gdef: &Gdef,
+    glyphs: &mut Vec<PositionedGlyph>,
+    start: usize,
+) -> Option<()> {
+    if subtable.read::<u16>(0)? != 1 {
+        return None;
+    }
+    let index = coverage_index(subtable.offset(2)?, glyphs[start].id.0)?;
+    if index >= subtable.read::<u16>(4)? as usize {
+        return None;
+    }
+    let set = subtable.offset(6 + index * 2)?;
+
+    // the ligatures are ordered by preference, longer ones usually come first
+    'ligatures: for l in 0..set.read::<u16>(0)? as usize {
+        let Some(ligature) = set.offset(2 + l * 2) else {
+            continue;
+        };
+        let (Some(ligature_glyph), Some(component_count)) =
+            (ligature.read::<u16>(0), ligature.read::<u16>(2))
+        else {
+            continue;
+        };
+
+        let mut matched = Vec::new();
+        let mut pos = start;
+        for c in 1..component_count as usize {
+            pos += 1;
+            while pos < glyphs.len() && gdef.skips(flag, glyphs[pos].id.0) {
+                pos += 1;
+            }
+            let component = ligature.read::<u16>(4 + (c - 1) * 2);
+            if pos >= glyphs.len() || component != Some(glyphs[pos].id.0) {
+                continue 'ligatures;
+            }
+            matched.push(pos);
+        }
+
+        glyphs[start].id = GlyphId(ligature_glyph);
+        for &pos in matched.iter().rev() {
+            glyphs.remove(pos);
+        }
+        return Some(());
+    }
+    None
+}
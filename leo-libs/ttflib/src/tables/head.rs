@@ -1,5 +1,8 @@
 use corelib::reader::Readable;
 
+/// the size of the head table in bytes
+pub const HEAD_SIZE: usize = 54;
+
 pub struct HeadHeader {
     pub major_version: u16,
     pub minor_version: u16,
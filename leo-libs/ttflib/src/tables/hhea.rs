@@ -1,24 +1,27 @@
 use corelib::reader::Readable;
 
+/// the size of the hhea table in bytes
+pub const HHEA_SIZE: usize = 36;
+
 pub struct HheaHeader {
-    major_version: u16,
-    minor_version: u16,
-    ascender: i16,
-    descender: i16,
-    line_gap: i16,
-    advance_width_max: u16,
-    min_left_side_bearing: i16,
-    min_right_side_bearing: i16,
-    x_max_extent: i16,
-    caret_slope_rise: i16,
-    caret_slope_run: i16,
-    caret_offset: i16,
-    reserved1: i16,
-    reserved2: i16,
-    reserved3: i16,
-    reserved4: i16,
-    metric_data_format: i16,
-    number_of_h_metrics: u16,
+    pub major_version: u16,
+    pub minor_version: u16,
+    pub ascender: i16,
+    pub descender: i16,
+    pub line_gap: i16,
+    pub advance_width_max: u16,
+    pub min_left_side_bearing: i16,
+    pub min_right_side_bearing: i16,
+    pub x_max_extent: i16,
+    pub caret_slope_rise: i16,
+    pub caret_slope_run: i16,
+    pub caret_offset: i16,
+    pub reserved1: i16,
+    pub reserved2: i16,
+    pub reserved3: i16,
+    pub reserved4: i16,
+    pub metric_data_format: i16,
+    pub number_of_h_metrics: u16,
 }
 
 impl Readable for HheaHeader {
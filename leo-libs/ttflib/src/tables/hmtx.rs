@@ -1,27 +1,53 @@
 use corelib::reader::{read_vec, ByteReader, Readable, Reader};
 
+use crate::{util::ensure, FontError};
+
 pub fn get_hmtx(
     reader: &mut Reader<impl ByteReader>,
     number_of_h_metrics: usize,
     num_glyphs: usize,
-) -> HorizontalMetricsTable {
+) -> Result<HorizontalMetricsTable, FontError> {
+    let number_of_h_metrics = number_of_h_metrics.min(num_glyphs);
+    let num_bearings = num_glyphs - number_of_h_metrics;
+    ensure(reader, number_of_h_metrics * 4 + num_bearings * 2)?;
     let h_metrics: Vec<LongHorMetric> = read_vec(reader, number_of_h_metrics);
-    let left_side_bearings: Vec<i16> = read_vec(reader, num_glyphs - number_of_h_metrics);
+    let left_side_bearings: Vec<i16> = read_vec(reader, num_bearings);
 
-    return HorizontalMetricsTable {
+    Ok(HorizontalMetricsTable {
         h_metrics,
         left_side_bearings,
-    };
+    })
 }
 
 pub struct HorizontalMetricsTable {
-    h_metrics: Vec<LongHorMetric>,
-    left_side_bearings: Vec<i16>,
+    pub h_metrics: Vec<LongHorMetric>,
+    /// the bearings of the glyphs after the last one in `h_metrics`, which all have its
+    /// advance width
+    pub left_side_bearings: Vec<i16>,
+}
+
+impl HorizontalMetricsTable {
+    /// The metrics of a glyph. The glyphs after the end of `h_metrics` have the last
+    /// advance width in it.
+    pub fn get_h_metric(&self, glyph_id: usize) -> LongHorMetric {
+        if let Some(&metric) = self.h_metrics.get(glyph_id) {
+            return metric;
+        }
+        let advance_width = self.h_metrics.last().map_or(0, |m| m.advance_width);
+        let lsb = glyph_id
+            .checked_sub(self.h_metrics.len())
+            .and_then(|i| self.left_side_bearings.get(i))
+            .copied()
+            .unwrap_or(0);
+        LongHorMetric { advance_width, lsb }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LongHorMetric {
-    advance_width: u16,
-    lsb: i16,
+    pub advance_width: u16,
+    /// the left side bearing
+    pub lsb: i16,
 }
 
 impl Readable for LongHorMetric {
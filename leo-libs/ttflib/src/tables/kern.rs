@@ -0,0 +1,112 @@
+//! The legacy kern table that older fonts have instead of pair adjustments in GPOS. Both
+//! the windows (version 0) and the apple (version 1.0) layout are read, but only the
+//! pair lists of format 0.
+
+use super::layout::{search, Table};
+
+/// The horizontal kerning of a glyph pair in font units
+pub fn get_kerning(kern: Table, left: u16, right: u16) -> i32 {
+    let key = (left as u32) << 16 | right as u32;
+    let mut kerning = 0;
+    for (subtable, coverage, apple) in subtables(kern) {
+        // horizontal, not cross-stream, not minimum values and format 0
+        let usable = if apple {
+            coverage & 0xe0ff == 0
+        } else {
+            coverage & 0xff07 == 1
+        };
+        if !usable {
+            continue;
+        }
+
+        let count = subtable.read::<u16>(0).unwrap_or(0) as usize;
+        let found = search(count, |i| Some(subtable.read::<u32>(8 + i * 6)?.cmp(&key)));
+        let Some(value) = found.and_then(|i| subtable.read::<i16>(8 + i * 6 + 4)) else {
+            continue;
+        };
+        // the override bit of the windows layout replaces the sum so far
+        if !apple && coverage & 0x8 != 0 {
+            kerning = value as i32;
+        } else {
+            kerning += value as i32;
+        }
+    }
+    kerning
+}
+
+/// The data after the header of every subtable, with its coverage and whether it is in
+/// the apple layout
+fn subtables(kern: Table) -> impl Iterator<Item = (Table, u16, bool)> {
+    let apple = kern.read::<u32>(0) == Some(0x0001_0000);
+    let (count, mut pos) = if apple {
+        (kern.read::<u32>(4).unwrap_or(0), 8)
+    } else if kern.read::<u16>(0) == Some(0) {
+        (kern.read::<u16>(2).unwrap_or(0) as u32, 4)
+    } else {
+        (0, 0)
+    };
+
+    (0..count).map_while(move |_| {
+        let (length, coverage, header) = if apple {
+            (
+                kern.read::<u32>(pos)? as usize,
+                kern.read::<u16>(pos + 4)?,
+                8,
+            )
+        } else {
+            (
+                kern.read::<u16>(pos + 2)? as usize,
+                kern.read::<u16>(pos + 4)?,
+                6,
+            )
+        };
+        if length < header {
+            return None;
+        }
+        let subtable = kern.at(pos + header)?;
+        pos += length;
+        Some((subtable, coverage, apple))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn be16(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn windows_and_apple_layouts() {
+        let pairs = [(3, 5, -40i16), (3, 9, 12), (7, 5, -8)];
+        let mut pair_data = be16(&[pairs.len() as u16, 0, 0, 0]);
+        for (left, right, value) in pairs {
+            pair_data.extend(be16(&[left, right, value as u16]));
+        }
+
+        // a horizontal subtable and a cross-stream one that is ignored
+        let mut windows = be16(&[0, 2]);
+        for coverage in [1, 5] {
+            windows.extend(be16(&[0, 6 + pair_data.len() as u16, coverage]));
+            windows.extend(&pair_data);
+        }
+        let kern = Table::new(&windows);
+        assert_eq!(get_kerning(kern, 3, 5), -40);
+        assert_eq!(get_kerning(kern, 3, 9), 12);
+        assert_eq!(get_kerning(kern, 7, 5), -8);
+        assert_eq!(get_kerning(kern, 5, 3), 0);
+
+        // two horizontal subtables add up
+        let mut apple = be16(&[1, 0, 0, 2]);
+        for _ in 0..2 {
+            apple.extend(be16(&[0, 8 + pair_data.len() as u16, 0, 0]));
+            apple.extend(&pair_data);
+        }
+        assert_eq!(get_kerning(Table::new(&apple), 3, 5), -80);
+
+        // a subtable that claims to be empty ends the table
+        let broken = be16(&[0, 3, 0, 0, 1]);
+        assert_eq!(get_kerning(Table::new(&broken), 3, 5), 0);
+    }
+}
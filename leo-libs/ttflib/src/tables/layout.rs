@@ -0,0 +1,350 @@
+//! The parts that GSUB and GPOS share: scripts, language systems, features and lookups,
+//! and the coverage and class definition tables the lookups use. The tables are read from
+//! the font data when a text is shaped, offsets that point outside of a table end the
+//! lookup they belong to.
+
+use std::{cmp::Ordering, mem::size_of};
+
+use corelib::reader::{Readable, Reader};
+
+/// An OpenType tag like `latn` or `liga`
+pub type Tag = [u8; 4];
+
+/// A part of the font data that is read at positions relative to its start
+#[derive(Debug, Clone, Copy)]
+pub struct Table<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Table<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Read a number or a tag, None if it doesn't fit into the table
+    pub fn read<T: Readable>(&self, pos: usize) -> Option<T> {
+        let mut reader = Reader::new_big_endian(self.data, pos);
+        (reader.remaining() >= size_of::<T>()).then(|| reader.read())
+    }
+
+    /// `len` bytes at `pos`, None if they don't fit into the table
+    pub fn bytes(&self, pos: usize, len: usize) -> Option<&'a [u8]> {
+        self.data.get(pos..pos.checked_add(len)?)
+    }
+
+    /// The table at `offset` from the start of this one
+    pub fn at(&self, offset: usize) -> Option<Table<'a>> {
+        self.data.get(offset..).map(Table::new)
+    }
+
+    /// The table at the 16 bit offset stored at `pos`, None if the offset is 0
+    pub fn offset(&self, pos: usize) -> Option<Table<'a>> {
+        match self.read::<u16>(pos)? {
+            0 => None,
+            offset => self.at(offset as usize),
+        }
+    }
+
+    /// The table at the 32 bit offset stored at `pos`, None if the offset is 0
+    pub fn offset32(&self, pos: usize) -> Option<Table<'a>> {
+        match self.read::<u32>(pos)? {
+            0 => None,
+            offset => self.at(offset as usize),
+        }
+    }
+}
+
+/// Binary search the `count` records of a sorted array, `cmp` compares a record with the
+/// one searched for, None if the record can't be read
+pub fn search(count: usize, mut cmp: impl FnMut(usize) -> Option<Ordering>) -> Option<usize> {
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = (low + high) / 2;
+        match cmp(mid)? {
+            Ordering::Less => low = mid + 1,
+            Ordering::Greater => high = mid,
+            Ordering::Equal => return Some(mid),
+        }
+    }
+    None
+}
+
+/// The coverage index of a glyph, None if the coverage table doesn't contain it
+pub fn coverage_index(coverage: Table, glyph: u16) -> Option<usize> {
+    let count = coverage.read::<u16>(2)? as usize;
+    match coverage.read::<u16>(0)? {
+        1 => search(count, |i| {
+            Some(coverage.read::<u16>(4 + i * 2)?.cmp(&glyph))
+        }),
+        2 => {
+            let i = search(count, |i| {
+                let start = coverage.read::<u16>(4 + i * 6)?;
+                let end = coverage.read::<u16>(4 + i * 6 + 2)?;
+                Some(if end < glyph {
+                    Ordering::Less
+                } else if start > glyph {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                })
+            })?;
+            let start = coverage.read::<u16>(4 + i * 6)?;
+            let start_index = coverage.read::<u16>(4 + i * 6 + 4)?;
+            Some(start_index as usize + (glyph - start) as usize)
+        }
+        _ => None,
+    }
+}
+
+/// The class of a glyph in a class definition table, glyphs that are not listed are in
+/// class 0
+pub fn glyph_class(class_def: Table, glyph: u16) -> u16 {
+    let class = || match class_def.read::<u16>(0)? {
+        1 => {
+            let start = class_def.read::<u16>(2)?;
+            let count = class_def.read::<u16>(4)?;
+            let i = glyph.checked_sub(start).filter(|&i| i < count)?;
+            class_def.read::<u16>(6 + i as usize * 2)
+        }
+        2 => {
+            let count = class_def.read::<u16>(2)? as usize;
+            let i = search(count, |i| {
+                let start = class_def.read::<u16>(4 + i * 6)?;
+                let end = class_def.read::<u16>(4 + i * 6 + 2)?;
+                Some(if end < glyph {
+                    Ordering::Less
+                } else if start > glyph {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                })
+            })?;
+            class_def.read::<u16>(4 + i * 6 + 4)
+        }
+        _ => None,
+    };
+    class().unwrap_or(0)
+}
+
+/// The glyph classes from the GDEF table, which lookups use to skip marks or ligatures
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gdef<'a> {
+    glyph_class_def: Option<Table<'a>>,
+    mark_attach_class_def: Option<Table<'a>>,
+}
+
+impl<'a> Gdef<'a> {
+    pub const BASE: u16 = 1;
+    pub const LIGATURE: u16 = 2;
+    pub const MARK: u16 = 3;
+
+    pub fn parse(table: Table<'a>) -> Self {
+        if table.read::<u16>(0) != Some(1) {
+            return Self::default();
+        }
+        Self {
+            glyph_class_def: table.offset(4),
+            mark_attach_class_def: table.offset(10),
+        }
+    }
+
+    /// The glyph class, 0 if the font doesn't say
+    pub fn class(&self, glyph: u16) -> u16 {
+        self.glyph_class_def
+            .map_or(0, |class_def| glyph_class(class_def, glyph))
+    }
+
+    /// Whether a lookup with `flag` skips the glyph
+    pub fn skips(&self, flag: u16, glyph: u16) -> bool {
+        match self.class(glyph) {
+            Self::BASE => flag & Lookup::IGNORE_BASE_GLYPHS != 0,
+            Self::LIGATURE => flag & Lookup::IGNORE_LIGATURES != 0,
+            Self::MARK if flag & Lookup::IGNORE_MARKS != 0 => true,
+            Self::MARK if flag & Lookup::MARK_ATTACHMENT_TYPE != 0 => {
+                let class = self
+                    .mark_attach_class_def
+                    .map_or(0, |class_def| glyph_class(class_def, glyph));
+                class != flag >> 8
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The script, feature and lookup lists of a GSUB or GPOS table
+#[derive(Debug, Clone, Copy)]
+pub struct LayoutTable<'a> {
+    scripts: Table<'a>,
+    features: Table<'a>,
+    lookups: Table<'a>,
+    /// the lookup type that points to a subtable of another type
+    extension_type: u16,
+}
+
+impl<'a> LayoutTable<'a> {
+    /// None if the version is unknown or a list is missing
+    pub fn parse(table: Table<'a>, extension_type: u16) -> Option<Self> {
+        if table.read::<u16>(0)? != 1 {
+            return None;
+        }
+        Some(Self {
+            scripts: table.offset(4)?,
+            features: table.offset(6)?,
+            lookups: table.offset(8)?,
+            extension_type,
+        })
+    }
+
+    /// Find a tagged record in a script or language system list, which have a count and
+    /// six byte records of a tag and an offset
+    fn find_record(list: Table<'a>, count_pos: usize, tag: Tag) -> Option<Table<'a>> {
+        let count = list.read::<u16>(count_pos)? as usize;
+        (0..count)
+            .map(|i| count_pos + 2 + i * 6)
+            .find(|&pos| list.read::<Tag>(pos) == Some(tag))
+            .and_then(|pos| list.offset(pos + 4))
+    }
+
+    /// The language system of a script, fonts without the script use their default one
+    fn lang_sys(&self, script: Tag, language: Option<Tag>) -> Option<Table<'a>> {
+        let script = [script, *b"DFLT", *b"dflt", *b"latn"]
+            .into_iter()
+            .find_map(|tag| Self::find_record(self.scripts, 0, tag))?;
+        language
+            .and_then(|language| Self::find_record(script, 2, language))
+            .or_else(|| script.offset(0))
+    }
+
+    /// The indices of the lookups of the features, in the order they have to be applied.
+    /// The required feature of the language system is always included.
+    pub fn lookup_indices(&self, script: Tag, language: Option<Tag>, features: &[Tag]) -> Vec<u16> {
+        let Some(lang_sys) = self.lang_sys(script, language) else {
+            return Vec::new();
+        };
+        let required = lang_sys.read::<u16>(2).unwrap_or(0xffff);
+        let count = lang_sys.read::<u16>(4).unwrap_or(0) as usize;
+        let indices = (0..count).filter_map(|i| lang_sys.read::<u16>(6 + i * 2));
+
+        // the offsets of the feature tables, many records may share one
+        let mut offsets = Vec::new();
+        for index in indices.chain(Some(required)) {
+            let pos = 2 + index as usize * 6;
+            let Some(tag) = self.features.read::<Tag>(pos) else {
+                continue;
+            };
+            if index == required || features.contains(&tag) {
+                offsets.extend(self.features.read::<u16>(pos + 4));
+            }
+        }
+        offsets.sort_unstable();
+        offsets.dedup();
+
+        let num_lookups = self.lookups.read::<u16>(0).unwrap_or(0) as usize;
+        let mut used = vec![false; num_lookups];
+        for offset in offsets {
+            let Some(feature) = self.features.at(offset as usize) else {
+                continue;
+            };
+            let count = feature.read::<u16>(2).unwrap_or(0) as usize;
+            for i in 0..count {
+                match feature.read::<u16>(4 + i * 2) {
+                    Some(index) if (index as usize) < num_lookups => used[index as usize] = true,
+                    Some(_) => {}
+                    None => break,
+                }
+            }
+        }
+        (0..num_lookups as u16)
+            .filter(|&i| used[i as usize])
+            .collect()
+    }
+
+    pub fn lookup(&self, index: u16) -> Option<Lookup<'a>> {
+        let table = self.lookups.offset(2 + index as usize * 2)?;
+        let mut kind = table.read::<u16>(0)?;
+        let flag = table.read::<u16>(2)?;
+        let count = table.read::<u16>(4)?;
+        let mut extension = false;
+        if kind == self.extension_type {
+            // the type of all subtables is in the first extension subtable
+            kind = table.offset(6)?.read::<u16>(2)?;
+            extension = true;
+        }
+        Some(Lookup {
+            kind,
+            flag,
+            table,
+            count,
+            extension,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lookup<'a> {
+    pub kind: u16,
+    pub flag: u16,
+    table: Table<'a>,
+    count: u16,
+    /// the subtables are wrapped in extension subtables with 32 bit offsets
+    extension: bool,
+}
+
+impl<'a> Lookup<'a> {
+    pub const IGNORE_BASE_GLYPHS: u16 = 0x2;
+    pub const IGNORE_LIGATURES: u16 = 0x4;
+    pub const IGNORE_MARKS: u16 = 0x8;
+    pub const MARK_ATTACHMENT_TYPE: u16 = 0xff00;
+
+    pub fn subtables(&self) -> impl Iterator<Item = Table<'a>> + '_ {
+        (0..self.count as usize).filter_map(|i| {
+            let subtable = self.table.offset(6 + i * 2)?;
+            if !self.extension {
+                return Some(subtable);
+            }
+            if subtable.read::<u16>(2)? != self.kind {
+                return None;
+            }
+            subtable.offset32(4)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn be16(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn coverage_and_classes() {
+        let format1 = be16(&[1, 3, 5, 9, 20]);
+        let coverage = Table::new(&format1);
+        assert_eq!(coverage_index(coverage, 5), Some(0));
+        assert_eq!(coverage_index(coverage, 20), Some(2));
+        assert_eq!(coverage_index(coverage, 6), None);
+
+        let format2 = be16(&[2, 2, 10, 12, 0, 40, 40, 3]);
+        let coverage = Table::new(&format2);
+        assert_eq!(coverage_index(coverage, 11), Some(1));
+        assert_eq!(coverage_index(coverage, 40), Some(3));
+        assert_eq!(coverage_index(coverage, 13), None);
+        // a count larger than the data
+        let broken = be16(&[1, 100, 5]);
+        assert_eq!(coverage_index(Table::new(&broken), 7), None);
+
+        let format1 = be16(&[1, 7, 3, 1, 2, 1]);
+        let class_def = Table::new(&format1);
+        assert_eq!(glyph_class(class_def, 8), 2);
+        assert_eq!(glyph_class(class_def, 10), 0);
+        assert_eq!(glyph_class(class_def, 6), 0);
+
+        let format2 = be16(&[2, 2, 1, 4, 3, 10, 10, 1]);
+        let class_def = Table::new(&format2);
+        assert_eq!(glyph_class(class_def, 2), 3);
+        assert_eq!(glyph_class(class_def, 10), 1);
+        assert_eq!(glyph_class(class_def, 5), 0);
+    }
+}
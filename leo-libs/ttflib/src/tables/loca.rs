@@ -1,21 +1,31 @@
+use std::ops::Range;
+
 use corelib::reader::{read_vec, ByteReader, Reader};
 
+use crate::{util::ensure, FontError};
+
 pub fn get_loca_table(
     reader: &mut Reader<impl ByteReader>,
     num_glyphs: usize,
     index_to_loc_format: i16,
-) -> LocaTable {
+) -> Result<LocaTable, FontError> {
     match index_to_loc_format {
-        0 => LocaTable {
-            values: read_vec::<u16>(reader, num_glyphs + 1)
-                .into_iter()
-                .map(|v| v as u32 * 2)
-                .collect(),
-        },
-        1 => LocaTable {
-            values: read_vec(reader, num_glyphs + 1),
-        },
-        _ => unreachable!(),
+        0 => {
+            ensure(reader, (num_glyphs + 1) * 2)?;
+            Ok(LocaTable {
+                values: read_vec::<u16>(reader, num_glyphs + 1)
+                    .into_iter()
+                    .map(|v| v as u32 * 2)
+                    .collect(),
+            })
+        }
+        1 => {
+            ensure(reader, (num_glyphs + 1) * 4)?;
+            Ok(LocaTable {
+                values: read_vec(reader, num_glyphs + 1),
+            })
+        }
+        _ => Err(FontError::InvalidIndexToLocFormat(index_to_loc_format)),
     }
 }
 
@@ -25,7 +35,12 @@ pub struct LocaTable {
 }
 
 impl LocaTable {
-    pub fn get_char_id_offset(&self, char_id: usize) -> u32 {
-        self.values[char_id]
+    /// The location of the glyph data relative to the glyf table, empty for glyphs
+    /// without an outline
+    pub fn get_glyph_range(&self, char_id: usize) -> Result<Range<usize>, FontError> {
+        match self.values.get(char_id..char_id + 2) {
+            Some(&[start, end]) if start <= end => Ok(start as usize..end as usize),
+            _ => Err(FontError::InvalidGlyph(char_id)),
+        }
     }
 }
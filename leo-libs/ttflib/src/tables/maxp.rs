@@ -1,24 +1,32 @@
 use corelib::reader::Readable;
 
+use crate::{util::ensure, FontError};
+
 pub enum MaxpHeader {
     Version05(MaxpVersion05),
     Version10(MaxpVersion10),
 }
 
-impl Readable for MaxpHeader {
-    fn read(reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>) -> Self {
+impl MaxpHeader {
+    pub fn parse(
+        reader: &mut corelib::reader::Reader<impl corelib::reader::ByteReader>,
+    ) -> Result<Self, FontError> {
+        ensure(reader, 4)?;
         let format: u32 = reader.read();
-        let major = format >> 16;
-        let minor = format & 0xFFFF;
-        match (major, minor) {
-            (0, 5) => Self::Version05(reader.read()),
-            (1, 0) => Self::Version10(reader.read()),
-            _ => unimplemented!("{:x}", format),
+        match format {
+            // Version 0.5 (fonts with CFF outlines) is stored as 16.16 fixed point.
+            0x0000_5000 => {
+                ensure(reader, 2)?;
+                Ok(Self::Version05(reader.read()))
+            }
+            0x0001_0000 => {
+                ensure(reader, 28)?;
+                Ok(Self::Version10(reader.read()))
+            }
+            _ => Err(FontError::InvalidMaxpVersion(format)),
         }
     }
-}
 
-impl MaxpHeader {
     pub fn get_num_glyphs(&self) -> u16 {
         match self {
             Self::Version05(m) => m.get_num_glyphs(),
@@ -28,7 +36,7 @@ impl MaxpHeader {
 }
 
 pub struct MaxpVersion05 {
-    num_glyphs: u16,
+    pub num_glyphs: u16,
 }
 
 impl MaxpVersion05 {
@@ -46,20 +54,20 @@ impl Readable for MaxpVersion05 {
 }
 
 pub struct MaxpVersion10 {
-    num_glyphs: u16,
-    max_points: u16,
-    max_contours: u16,
-    max_composite_points: u16,
-    max_composite_contours: u16,
-    max_zones: u16,
-    max_twilight_points: u16,
-    max_storage: u16,
-    max_function_defs: u16,
-    max_instruction_defs: u16,
-    max_stack_elements: u16,
-    max_size_of_instructions: u16,
-    max_component_elements: u16,
-    max_component_depth: u16,
+    pub num_glyphs: u16,
+    pub max_points: u16,
+    pub max_contours: u16,
+    pub max_composite_points: u16,
+    pub max_composite_contours: u16,
+    pub max_zones: u16,
+    pub max_twilight_points: u16,
+    pub max_storage: u16,
+    pub max_function_defs: u16,
+    pub max_instruction_defs: u16,
+    pub max_stack_elements: u16,
+    pub max_size_of_instructions: u16,
+    pub max_component_elements: u16,
+    pub max_component_depth: u16,
 }
 
 impl MaxpVersion10 {
@@ -1,7 +1,14 @@
+pub mod cff;
 pub mod cmap;
 pub mod glyf;
+pub mod gpos;
+pub mod gsub;
 pub mod head;
 pub mod hhea;
 pub mod hmtx;
+pub mod kern;
+pub mod layout;
 pub mod loca;
 pub mod maxp;
+pub mod name;
+pub mod os2;
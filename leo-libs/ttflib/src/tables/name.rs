@@ -0,0 +1,140 @@
+//! The naming table with the names of the font, its family and its style, often in many
+//! languages. Only names in Unicode are decoded, and Macintosh names that are plain ASCII.
+
+use super::layout::Table;
+
+/// The family of up to four styles: regular, italic, bold and bold italic
+pub const FAMILY: u16 = 1;
+/// The style within [`FAMILY`]
+pub const SUBFAMILY: u16 = 2;
+/// The family and the style, like "Roboto Bold Italic"
+pub const FULL_NAME: u16 = 4;
+pub const POSTSCRIPT_NAME: u16 = 6;
+/// The family of all weights and widths, fonts that have more than four styles split them
+/// into several [`FAMILY`]s
+pub const TYPOGRAPHIC_FAMILY: u16 = 16;
+/// The style within [`TYPOGRAPHIC_FAMILY`]
+pub const TYPOGRAPHIC_SUBFAMILY: u16 = 17;
+
+/// The windows language id of american english
+const ENGLISH_US: u16 = 0x409;
+
+/// A name of the font in american english, some english or else in the first language the
+/// font has. None if there is no such name in an encoding that can be decoded.
+pub fn get_name(name: Table, name_id: u16) -> Option<String> {
+    let count = name.read::<u16>(2)? as usize;
+    let storage = name.at(name.read::<u16>(4)? as usize)?;
+
+    let mut best: Option<(u8, String)> = None;
+    for i in 0..count {
+        let field = |k: usize| name.read::<u16>(6 + i * 12 + k * 2);
+        let (Some(platform), Some(encoding), Some(language), Some(id)) =
+            (field(0), field(1), field(2), field(3))
+        else {
+            break;
+        };
+        if id != name_id {
+            continue;
+        }
+        // lower is better, the unicode platform has no languages
+        let rank = match (platform, encoding, language) {
+            (3, 1 | 10, ENGLISH_US) => 0,
+            (0, ..) => 1,
+            (3, 1 | 10, language) if language & 0x3ff == 0x09 => 2,
+            (1, 0, 0) => 3,
+            (3, 1 | 10, _) => 4,
+            (1, 0, _) => 5,
+            _ => continue,
+        };
+        if best.as_ref().is_some_and(|(best, _)| *best <= rank) {
+            continue;
+        }
+        let Some(bytes) = field(4)
+            .zip(field(5))
+            .and_then(|(len, offset)| storage.bytes(offset as usize, len as usize))
+        else {
+            continue;
+        };
+        let text = match platform {
+            1 if bytes.is_ascii() => String::from_utf8_lossy(bytes).into_owned(),
+            1 => continue,
+            _ => {
+                let units = bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+                char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            }
+        };
+        best = Some((rank, text));
+    }
+    best.map(|(_, text)| text)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A name table with the records as platform, encoding, language, name id and the
+    /// encoded name
+    fn name_table(records: &[(u16, u16, u16, u16, &[u8])]) -> Vec<u8> {
+        let storage_offset = 6 + records.len() * 12;
+        let mut data = [0, records.len() as u16, storage_offset as u16]
+            .iter()
+            .flat_map(|v| v.to_be_bytes())
+            .collect::<Vec<_>>();
+        let mut storage = Vec::new();
+        for &(platform, encoding, language, id, name) in records {
+            let fields = [
+                platform,
+                encoding,
+                language,
+                id,
+                name.len() as u16,
+                storage.len() as u16,
+            ];
+            data.extend(fields.iter().flat_map(|v| v.to_be_bytes()));
+            storage.extend_from_slice(name);
+        }
+        data.extend(storage);
+        data
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(|u| u.to_be_bytes()).collect()
+    }
+
+    #[test]
+    fn languages() {
+        let german = utf16("Schrift");
+        let english = utf16("Font \u{e9}");
+        let data = name_table(&[
+            (1, 0, 0, FAMILY, b"Mac"),
+            (3, 1, 0x407, FAMILY, &german),
+            (3, 1, ENGLISH_US, FAMILY, &english),
+            (3, 1, 0x407, SUBFAMILY, &german),
+            (1, 0, 0, FULL_NAME, b"Mac \xe9"),
+            (1, 0, 0, POSTSCRIPT_NAME, b"Mac-Name"),
+            (3, 1, 0x809, POSTSCRIPT_NAME, &utf16("British")),
+        ]);
+        let table = Table::new(&data);
+        assert_eq!(get_name(table, FAMILY).as_deref(), Some("Font \u{e9}"));
+        assert_eq!(get_name(table, SUBFAMILY).as_deref(), Some("Schrift"));
+        assert_eq!(get_name(table, POSTSCRIPT_NAME).as_deref(), Some("British"));
+        // mac roman that is not ascii
+        assert_eq!(get_name(table, FULL_NAME), None);
+        assert_eq!(get_name(table, TYPOGRAPHIC_FAMILY), None);
+    }
+
+    #[test]
+    fn broken() {
+        // a name outside of the storage and a count that is too large
+        let mut data = name_table(&[(3, 1, ENGLISH_US, FAMILY, b"\0A")]);
+        data[6 + 10..6 + 12].copy_from_slice(&[0, 9]);
+        assert_eq!(get_name(Table::new(&data), FAMILY), None);
+        data[3] = 100;
+        assert_eq!(get_name(Table::new(&data), FAMILY), None);
+        assert_eq!(get_name(Table::new(&[]), FAMILY), None);
+    }
+}
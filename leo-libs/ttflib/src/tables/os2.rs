@@ -0,0 +1,94 @@
+use corelib::reader::{ByteReader, Reader};
+
+use crate::{util::ensure, FontError};
+
+/// The size of version 0 of the table, the later versions add fields at the end
+const OS2_V0_SIZE: usize = 78;
+
+/// The OS/2 and Windows metrics table. Only the fields up to version 0 and the x and cap
+/// height of version 2 are read.
+pub struct Os2Table {
+    pub version: u16,
+    pub x_avg_char_width: i16,
+    pub us_weight_class: u16,
+    pub us_width_class: u16,
+    pub fs_type: u16,
+    pub y_subscript_x_size: i16,
+    pub y_subscript_y_size: i16,
+    pub y_subscript_x_offset: i16,
+    pub y_subscript_y_offset: i16,
+    pub y_superscript_x_size: i16,
+    pub y_superscript_y_size: i16,
+    pub y_superscript_x_offset: i16,
+    pub y_superscript_y_offset: i16,
+    pub y_strikeout_size: i16,
+    pub y_strikeout_position: i16,
+    pub s_family_class: i16,
+    pub panose: [u8; 10],
+    pub ul_unicode_range: [u32; 4],
+    pub ach_vend_id: [u8; 4],
+    pub fs_selection: u16,
+    pub us_first_char_index: u16,
+    pub us_last_char_index: u16,
+    pub s_typo_ascender: i16,
+    pub s_typo_descender: i16,
+    pub s_typo_line_gap: i16,
+    pub us_win_ascent: u16,
+    pub us_win_descent: u16,
+    /// version 2 and later
+    pub sx_height: Option<i16>,
+    /// version 2 and later
+    pub s_cap_height: Option<i16>,
+}
+
+impl Os2Table {
+    pub const ITALIC: u16 = 1;
+    /// the typo metrics should be used instead of the ones from hhea
+    pub const USE_TYPO_METRICS: u16 = 1 << 7;
+    /// slanted but not italic, since version 4
+    pub const OBLIQUE: u16 = 1 << 9;
+
+    pub fn parse(reader: &mut Reader<impl ByteReader>, length: usize) -> Result<Self, FontError> {
+        ensure(reader, OS2_V0_SIZE)?;
+        let mut table = Self {
+            version: reader.read(),
+            x_avg_char_width: reader.read(),
+            us_weight_class: reader.read(),
+            us_width_class: reader.read(),
+            fs_type: reader.read(),
+            y_subscript_x_size: reader.read(),
+            y_subscript_y_size: reader.read(),
+            y_subscript_x_offset: reader.read(),
+            y_subscript_y_offset: reader.read(),
+            y_superscript_x_size: reader.read(),
+            y_superscript_y_size: reader.read(),
+            y_superscript_x_offset: reader.read(),
+            y_superscript_y_offset: reader.read(),
+            y_strikeout_size: reader.read(),
+            y_strikeout_position: reader.read(),
+            s_family_class: reader.read(),
+            panose: reader.read(),
+            ul_unicode_range: [reader.read(), reader.read(), reader.read(), reader.read()],
+            ach_vend_id: reader.read(),
+            fs_selection: reader.read(),
+            us_first_char_index: reader.read(),
+            us_last_char_index: reader.read(),
+            s_typo_ascender: reader.read(),
+            s_typo_descender: reader.read(),
+            s_typo_line_gap: reader.read(),
+            us_win_ascent: reader.read(),
+            us_win_descent: reader.read(),
+            sx_height: None,
+            s_cap_height: None,
+        };
+
+        // skip the code page ranges of version 1
+        if table.version >= 2 && length >= OS2_V0_SIZE + 12 && ensure(reader, 12).is_ok() {
+            let pos = reader.get_pos();
+            reader.set_pos(pos + 8);
+            table.sx_height = Some(reader.read());
+            table.s_cap_height = Some(reader.read());
+        }
+        Ok(table)
+    }
+}
@@ -1,4 +1,6 @@
-use corelib::reader::Readable;
+use corelib::reader::{ByteReader, Readable, Reader};
+
+use crate::FontError;
 
 #[derive(Debug, Clone)]
 pub struct FourByteTag(pub String);
@@ -10,6 +12,15 @@ impl Readable for FourByteTag {
         let b3 = reader.read_byte();
         let b4 = reader.read_byte();
 
-        Self(String::from_utf8(vec![b1, b2, b3, b4]).unwrap())
+        Self(String::from_utf8_lossy(&[b1, b2, b3, b4]).into_owned())
+    }
+}
+
+/// Fails if fewer than `len` bytes are left. The reader panics when reading past the end,
+/// so every length that comes from the font is checked before reading.
+pub(crate) fn ensure(reader: &Reader<impl ByteReader>, len: usize) -> Result<(), FontError> {
+    if reader.remaining() < len {
+        return Err(FontError::UnexpectedEof);
     }
+    Ok(())
 }
@@ -0,0 +1,191 @@
+//! Generates the property tables from the files of the Unicode character database in
+//! `ucd/`. Every file has lines of a code point or a range and `;` separated fields,
+//! `#` starts a comment. The property files give the values of code points they don't
+//! list in `# @missing:` lines, where later lines override earlier ones.
+
+use std::{env, fmt::Write, fs, path::Path};
+
+/// The lines of a UCD file
+struct UcdFile {
+    /// the code point ranges and the fields after them
+    entries: Vec<(u32, u32, Vec<String>)>,
+    /// the ranges and values of the `@missing` lines
+    missing: Vec<(u32, u32, String)>,
+}
+
+/// The code point or range at the start of a line
+fn parse_range(name: &str, range: &str) -> (u32, u32) {
+    let code = |s: &str| u32::from_str_radix(s, 16).unwrap_or_else(|e| panic!("{name}: {e}"));
+    match range.split_once("..") {
+        Some((start, end)) => (code(start), code(end)),
+        None => (code(range), code(range)),
+    }
+}
+
+fn parse(name: &str) -> UcdFile {
+    let path = Path::new("ucd").join(name);
+    println!("cargo::rerun-if-changed={}", path.display());
+    let data = fs::read_to_string(&path).unwrap_or_else(|e| panic!("{name}: {e}"));
+
+    let mut entries = Vec::new();
+    let mut missing = Vec::new();
+    for line in data.lines() {
+        if let Some(default) = line.strip_prefix("# @missing:") {
+            let (range, value) = default
+                .split_once(';')
+                .unwrap_or_else(|| panic!("{name}: {line}"));
+            let (start, end) = parse_range(name, range.trim());
+            missing.push((start, end, value.trim().to_string()));
+            continue;
+        }
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut fields = line.split(';').map(str::trim);
+        let (start, end) = parse_range(name, fields.next().unwrap());
+        entries.push((start, end, fields.map(String::from).collect()));
+    }
+    entries.sort_by_key(|entry| entry.0);
+    UcdFile { entries, missing }
+}
+
+/// A table of ranges with the value of a property, `variant` turns the value in the file
+/// into a variant of `ty`. Code points with the `default` variant are left out, the
+/// lookup falls back to it.
+fn property_table(
+    out: &mut String,
+    name: &str,
+    ty: &str,
+    file: &str,
+    default: &str,
+    variant: impl Fn(&str) -> String,
+) {
+    let UcdFile { entries, missing } = parse(file);
+    let mut values = vec![None; 0x110000];
+    for (start, end, value) in &missing {
+        values[*start as usize..=*end as usize].fill(Some(value.as_str()));
+    }
+    for (start, end, fields) in &entries {
+        values[*start as usize..=*end as usize].fill(Some(fields[0].as_str()));
+    }
+
+    writeln!(out, "pub(crate) static {name}: &[(u32, u32, {ty})] = &[").unwrap();
+    let mut c = 0;
+    while c < values.len() {
+        let start = c;
+        while c < values.len() && values[c] == values[start] {
+            c += 1;
+        }
+        let Some(value) = values[start] else {
+            continue;
+        };
+        let value = variant(value);
+        if value != default {
+            let end = c - 1;
+            writeln!(out, "    ({start:#x}, {end:#x}, {ty}::{value}),").unwrap();
+        }
+    }
+    writeln!(out, "];\n").unwrap();
+}
+
+/// The variant of the short or long name of a bidi class
+fn bidi_class(value: &str) -> String {
+    let short = match value {
+        "Left_To_Right" => "L",
+        "Right_To_Left" => "R",
+        "Arabic_Letter" => "AL",
+        "European_Number" => "EN",
+        "European_Separator" => "ES",
+        "European_Terminator" => "ET",
+        "Arabic_Number" => "AN",
+        "Common_Separator" => "CS",
+        "Nonspacing_Mark" => "NSM",
+        "Boundary_Neutral" => "BN",
+        "Paragraph_Separator" => "B",
+        "Segment_Separator" => "S",
+        "White_Space" => "WS",
+        "Other_Neutral" => "ON",
+        "Left_To_Right_Embedding" => "LRE",
+        "Left_To_Right_Override" => "LRO",
+        "Right_To_Left_Embedding" => "RLE",
+        "Right_To_Left_Override" => "RLO",
+        "Pop_Directional_Format" => "PDF",
+        "Left_To_Right_Isolate" => "LRI",
+        "Right_To_Left_Isolate" => "RLI",
+        "First_Strong_Isolate" => "FSI",
+        "Pop_Directional_Isolate" => "PDI",
+        short => short,
+    };
+    short.to_string()
+}
+
+fn main() {
+    let mut out = String::new();
+
+    property_table(
+        &mut out,
+        "BIDI_CLASSES",
+        "BidiClass",
+        "DerivedBidiClass.txt",
+        "L",
+        bidi_class,
+    );
+    property_table(
+        &mut out,
+        "GRAPHEME_BREAKS",
+        "GraphemeBreak",
+        "GraphemeBreakProperty.txt",
+        "Other",
+        |v| v.replace('_', ""),
+    );
+
+    writeln!(
+        out,
+        "pub(crate) static EXTENDED_PICTOGRAPHIC: &[(u32, u32)] = &["
+    )
+    .unwrap();
+    for (start, end, fields) in parse("emoji-data.txt").entries {
+        if fields[0] == "Extended_Pictographic" {
+            writeln!(out, "    ({start:#x}, {end:#x}),").unwrap();
+        }
+    }
+    writeln!(out, "];\n").unwrap();
+
+    // the file has more properties, InCB is the one with a value after its name
+    writeln!(
+        out,
+        "pub(crate) static INDIC_CONJUNCT_BREAKS: &[(u32, u32, IndicConjunctBreak)] = &["
+    )
+    .unwrap();
+    for (start, end, fields) in parse("DerivedCoreProperties.txt").entries {
+        if fields[0] == "InCB" {
+            let value = &fields[1];
+            writeln!(
+                out,
+                "    ({start:#x}, {end:#x}, IndicConjunctBreak::{value}),"
+            )
+            .unwrap();
+        }
+    }
+    writeln!(out, "];\n").unwrap();
+
+    // the pair of a bracket and whether it opens
+    writeln!(out, "pub(crate) static BRACKETS: &[(u32, u32, bool)] = &[").unwrap();
+    for (start, _, fields) in parse("BidiBrackets.txt").entries {
+        let pair = u32::from_str_radix(&fields[0], 16).unwrap();
+        let opening = fields[1] == "o";
+        writeln!(out, "    ({start:#x}, {pair:#x}, {opening}),").unwrap();
+    }
+    writeln!(out, "];\n").unwrap();
+
+    writeln!(out, "pub(crate) static MIRRORS: &[(u32, u32)] = &[").unwrap();
+    for (start, _, fields) in parse("BidiMirroring.txt").entries {
+        let mirror = u32::from_str_radix(&fields[0], 16).unwrap();
+        writeln!(out, "    ({start:#x}, {mirror:#x}),").unwrap();
+    }
+    writeln!(out, "];").unwrap();
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("tables.rs"), out).unwrap();
+}
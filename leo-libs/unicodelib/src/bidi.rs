@@ -0,0 +1,942 @@
+//! The Unicode bidirectional algorithm (UAX #9): the embedding levels of the characters of
+//! a paragraph, from its explicit formatting characters and the directions of the
+//! characters, and the order a line of them is displayed in.
+//!
+//! Text is read in logical order, an even level is displayed left to right and an odd one
+//! right to left. Characters that are displayed right to left may be mirrored, see
+//! [`mirrored`].
+
+use std::ops::Range;
+
+use crate::tables::{BIDI_CLASSES, BRACKETS, MIRRORS, lookup};
+
+/// The Bidi_Class property
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidiClass {
+    /// left to right
+    L,
+    /// right to left
+    R,
+    /// arabic letters
+    AL,
+    /// european numbers
+    EN,
+    /// european number separators like a plus
+    ES,
+    /// european number terminators like a percent sign
+    ET,
+    /// arabic numbers
+    AN,
+    /// number separators like a comma
+    CS,
+    /// nonspacing marks
+    NSM,
+    /// boundary neutrals, invisible characters like joiners
+    BN,
+    /// paragraph separators
+    B,
+    /// segment separators like a tab
+    S,
+    /// white space
+    WS,
+    /// other neutrals
+    ON,
+    LRE,
+    LRO,
+    RLE,
+    RLO,
+    /// pop directional format
+    PDF,
+    LRI,
+    RLI,
+    /// first strong isolate
+    FSI,
+    /// pop directional isolate
+    PDI,
+}
+
+use BidiClass::*;
+
+impl BidiClass {
+    fn is_isolate_initiator(self) -> bool {
+        matches!(self, LRI | RLI | FSI)
+    }
+
+    /// Removed by rule X9, they don't take part in the resolution of the other types
+    fn is_removed(self) -> bool {
+        matches!(self, RLE | LRE | RLO | LRO | PDF | BN)
+    }
+
+    /// Neutral and isolate formatting characters, resolved by their surroundings
+    fn is_neutral(self) -> bool {
+        matches!(self, B | S | WS | ON | LRI | RLI | FSI | PDI)
+    }
+
+    /// The strong direction, numbers count as right to left
+    fn strong(self) -> Option<BidiClass> {
+        match self {
+            L => Some(L),
+            R | AL | EN | AN => Some(R),
+            _ => None,
+        }
+    }
+}
+
+pub fn bidi_class(c: char) -> BidiClass {
+    lookup(BIDI_CLASSES, c).unwrap_or(L)
+}
+
+/// The character to display instead of `c` when it is right to left, like `)` for `(`
+pub fn mirrored(c: char) -> Option<char> {
+    let i = MIRRORS.binary_search_by_key(&(c as u32), |m| m.0).ok()?;
+    char::from_u32(MIRRORS[i].1)
+}
+
+/// The other bracket of a pair and whether `c` is the opening one
+fn bracket(c: char) -> Option<(char, bool)> {
+    let i = BRACKETS.binary_search_by_key(&(c as u32), |b| b.0).ok()?;
+    let (_, pair, opening) = BRACKETS[i];
+    Some((char::from_u32(pair)?, opening))
+}
+
+/// Brackets that are canonically equivalent match each other
+fn canonical_bracket(c: char) -> char {
+    match c {
+        '\u{2329}' => '\u{3008}',
+        '\u{232a}' => '\u{3009}',
+        c => c,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    LeftToRight,
+    RightToLeft,
+}
+
+/// An embedding level, even levels are left to right
+pub type Level = u8;
+
+/// The deepest level of embeddings and isolates
+pub const MAX_DEPTH: Level = 125;
+
+/// L for even levels and R for odd ones
+fn level_direction(level: Level) -> BidiClass {
+    if level.is_multiple_of(2) { L } else { R }
+}
+
+/// The direction of the first strong character, skipping isolates. None if there is no
+/// strong character (rules P2 and P3).
+fn first_strong(classes: &[BidiClass]) -> Option<Direction> {
+    let mut isolates = 0;
+    for &class in classes {
+        match class {
+            L if isolates == 0 => return Some(Direction::LeftToRight),
+            R | AL if isolates == 0 => return Some(Direction::RightToLeft),
+            LRI | RLI | FSI => isolates += 1,
+            PDI if isolates > 0 => isolates -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// The index of the PDI that ends every isolate initiator, rule BD9
+fn matching_pdis(classes: &[BidiClass]) -> Vec<Option<usize>> {
+    let mut matches = vec![None; classes.len()];
+    let mut open = Vec::new();
+    for (i, &class) in classes.iter().enumerate() {
+        match class {
+            LRI | RLI | FSI => open.push(i),
+            PDI => {
+                if let Some(initiator) = open.pop() {
+                    matches[initiator] = Some(i);
+                }
+            }
+            B => open.clear(),
+            _ => {}
+        }
+    }
+    matches
+}
+
+/// An entry of the directional status stack
+#[derive(Debug, Clone, Copy)]
+struct Status {
+    level: Level,
+    /// the class all characters get, L or R
+    override_class: Option<BidiClass>,
+    isolate: bool,
+}
+
+/// The levels and class overrides of the explicit embeddings, overrides and isolates,
+/// rules X1 to X8
+fn explicit_levels(
+    classes: &mut [BidiClass],
+    matching_pdis: &[Option<usize>],
+    paragraph_level: Level,
+) -> Vec<Level> {
+    let mut levels = vec![paragraph_level; classes.len()];
+    let mut stack = vec![Status {
+        level: paragraph_level,
+        override_class: None,
+        isolate: false,
+    }];
+    let mut overflow_isolates = 0;
+    let mut overflow_embeddings = 0;
+    let mut valid_isolates = 0;
+
+    for i in 0..classes.len() {
+        let last = *stack.last().unwrap();
+        let class = classes[i];
+        match class {
+            RLE | LRE | RLO | LRO | RLI | LRI | FSI => {
+                levels[i] = last.level;
+                let isolate = class.is_isolate_initiator();
+                if isolate && let Some(class) = last.override_class {
+                    classes[i] = class;
+                }
+                let rtl = match class {
+                    RLE | RLO | RLI => true,
+                    FSI => {
+                        let end = matching_pdis[i].unwrap_or(classes.len());
+                        first_strong(&classes[i + 1..end]) == Some(Direction::RightToLeft)
+                    }
+                    _ => false,
+                };
+                let level = if rtl {
+                    (last.level + 1) | 1
+                } else {
+                    (last.level + 2) & !1
+                };
+                if level <= MAX_DEPTH && overflow_isolates == 0 && overflow_embeddings == 0 {
+                    if isolate {
+                        valid_isolates += 1;
+                    }
+                    stack.push(Status {
+                        level,
+                        override_class: match class {
+                            RLO => Some(R),
+                            LRO => Some(L),
+                            _ => None,
+                        },
+                        isolate,
+                    });
+                } else if isolate {
+                    overflow_isolates += 1;
+                } else if overflow_isolates == 0 {
+                    overflow_embeddings += 1;
+                }
+            }
+            PDI => {
+                if overflow_isolates > 0 {
+                    overflow_isolates -= 1;
+                } else if valid_isolates > 0 {
+                    overflow_embeddings = 0;
+                    while !stack.last().unwrap().isolate {
+                        stack.pop();
+                    }
+                    stack.pop();
+                    valid_isolates -= 1;
+                }
+                let last = *stack.last().unwrap();
+                levels[i] = last.level;
+                if let Some(class) = last.override_class {
+                    classes[i] = class;
+                }
+            }
+            PDF => {
+                levels[i] = last.level;
+                // a PDF inside of an overflowing isolate does nothing
+                if overflow_isolates == 0 && overflow_embeddings > 0 {
+                    overflow_embeddings -= 1;
+                } else if overflow_isolates == 0 && !last.isolate && stack.len() >= 2 {
+                    stack.pop();
+                }
+            }
+            B => levels[i] = paragraph_level,
+            BN => levels[i] = last.level,
+            _ => {
+                levels[i] = last.level;
+                if let Some(class) = last.override_class {
+                    classes[i] = class;
+                }
+            }
+        }
+    }
+    levels
+}
+
+/// The characters whose types are resolved together, level runs that are connected by
+/// isolates (rule X10), with the types before and after them
+struct IsolatingRunSequence {
+    indices: Vec<usize>,
+    level: Level,
+    sos: BidiClass,
+    eos: BidiClass,
+}
+
+fn isolating_run_sequences(
+    original: &[BidiClass],
+    levels: &[Level],
+    matching_pdis: &[Option<usize>],
+    paragraph_level: Level,
+) -> Vec<IsolatingRunSequence> {
+    // the level runs of the characters that aren't removed
+    let mut runs: Vec<Vec<usize>> = Vec::new();
+    let mut last_level = None;
+    for i in (0..original.len()).filter(|&i| !original[i].is_removed()) {
+        if last_level == Some(levels[i]) {
+            runs.last_mut().unwrap().push(i);
+        } else {
+            runs.push(vec![i]);
+        }
+        last_level = Some(levels[i]);
+    }
+
+    // the run that starts with a PDI continues the sequence of its isolate initiator
+    let mut run_of_pdi = vec![None; original.len()];
+    for (n, run) in runs.iter().enumerate() {
+        if original[run[0]] == PDI {
+            run_of_pdi[run[0]] = Some(n);
+        }
+    }
+    let mut continued = vec![false; runs.len()];
+    let mut sequences = Vec::new();
+    for n in 0..runs.len() {
+        if continued[n] {
+            continue;
+        }
+        let mut indices = runs[n].clone();
+        loop {
+            let last = *indices.last().unwrap();
+            let next = original[last]
+                .is_isolate_initiator()
+                .then(|| matching_pdis[last])
+                .flatten()
+                .and_then(|pdi| run_of_pdi[pdi]);
+            let Some(next) = next else {
+                break;
+            };
+            continued[next] = true;
+            indices.extend(&runs[next]);
+        }
+
+        let (first, last) = (indices[0], *indices.last().unwrap());
+        let level = levels[first];
+        let before = (0..first)
+            .rev()
+            .find(|&i| !original[i].is_removed())
+            .map_or(paragraph_level, |i| levels[i]);
+        let after = if original[last].is_isolate_initiator() {
+            paragraph_level
+        } else {
+            (last + 1..original.len())
+                .find(|&i| !original[i].is_removed())
+                .map_or(paragraph_level, |i| levels[i])
+        };
+        sequences.push(IsolatingRunSequence {
+            indices,
+            level,
+            sos: level_direction(level.max(before)),
+            eos: level_direction(level.max(after)),
+        });
+    }
+    sequences
+}
+
+/// Resolve the weak types, rules W1 to W7
+fn resolve_weak(seq: &IsolatingRunSequence, types: &mut [BidiClass]) {
+    let len = types.len();
+    // W1
+    for i in 0..len {
+        if types[i] == NSM {
+            types[i] = match i.checked_sub(1).map(|i| types[i]) {
+                None => seq.sos,
+                Some(LRI | RLI | FSI | PDI) => ON,
+                Some(class) => class,
+            };
+        }
+    }
+    // W2 and W3
+    let mut last_strong = seq.sos;
+    for class in types.iter_mut() {
+        match *class {
+            L | R | AL => last_strong = *class,
+            EN if last_strong == AL => *class = AN,
+            _ => {}
+        }
+    }
+    for class in types.iter_mut() {
+        if *class == AL {
+            *class = R;
+        }
+    }
+    // W4
+    for i in 1..len.saturating_sub(1) {
+        let (before, after) = (types[i - 1], types[i + 1]);
+        types[i] = match (before, types[i], after) {
+            (EN, ES | CS, EN) => EN,
+            (AN, CS, AN) => AN,
+            (_, class, _) => class,
+        };
+    }
+    // W5
+    let mut i = 0;
+    while i < len {
+        if types[i] != ET {
+            i += 1;
+            continue;
+        }
+        let end = (i..len).find(|&j| types[j] != ET).unwrap_or(len);
+        let touches_number = (i > 0 && types[i - 1] == EN) || (end < len && types[end] == EN);
+        if touches_number {
+            types[i..end].fill(EN);
+        }
+        i = end;
+    }
+    // W6
+    for class in types.iter_mut() {
+        if matches!(class, ES | ET | CS) {
+            *class = ON;
+        }
+    }
+    // W7
+    let mut last_strong = seq.sos;
+    for class in types.iter_mut() {
+        match *class {
+            L | R => last_strong = *class,
+            EN if last_strong == L => *class = L,
+            _ => {}
+        }
+    }
+}
+
+/// The most pairs of brackets that are looked for, rule BD16
+const MAX_BRACKET_DEPTH: usize = 63;
+
+/// Resolve paired brackets, rule N0
+fn resolve_brackets(
+    seq: &IsolatingRunSequence,
+    chars: &[char],
+    original: &[BidiClass],
+    types: &mut [BidiClass],
+) {
+    // BD16, the positions in the sequence of the bracket pairs
+    let mut pairs = Vec::new();
+    let mut open: Vec<(char, usize)> = Vec::new();
+    for (n, &i) in seq.indices.iter().enumerate() {
+        if types[n] != ON {
+            continue;
+        }
+        let Some((pair, opening)) = bracket(chars[i]) else {
+            continue;
+        };
+        if opening {
+            if open.len() == MAX_BRACKET_DEPTH {
+                break;
+            }
+            open.push((canonical_bracket(pair), n));
+        } else if let Some(depth) = open
+            .iter()
+            .rposition(|&(closing, _)| closing == canonical_bracket(chars[i]))
+        {
+            pairs.push((open[depth].1, n));
+            open.truncate(depth);
+        }
+    }
+    pairs.sort_unstable();
+
+    let embedding = level_direction(seq.level);
+    for (start, end) in pairs {
+        let mut opposite = false;
+        let mut direction = None;
+        for &class in &types[start + 1..end] {
+            match class.strong() {
+                Some(class) if class == embedding => {
+                    direction = Some(embedding);
+                    break;
+                }
+                Some(_) => opposite = true,
+                None => {}
+            }
+        }
+        if direction.is_none() && opposite {
+            // the context before the brackets decides
+            let before = types[..start]
+                .iter()
+                .rev()
+                .find_map(|class| class.strong())
+                .unwrap_or(seq.sos);
+            direction = Some(if before != embedding {
+                before
+            } else {
+                embedding
+            });
+        }
+        let Some(direction) = direction else {
+            continue;
+        };
+        for bracket in [start, end] {
+            types[bracket] = direction;
+            // marks on the brackets follow them
+            for n in bracket + 1..types.len() {
+                if original[seq.indices[n]] != NSM {
+                    break;
+                }
+                types[n] = direction;
+            }
+        }
+    }
+}
+
+/// Resolve the neutral and isolate formatting types, rules N1 and N2
+fn resolve_neutral(seq: &IsolatingRunSequence, types: &mut [BidiClass]) {
+    let embedding = level_direction(seq.level);
+    let mut i = 0;
+    while i < types.len() {
+        if !types[i].is_neutral() {
+            i += 1;
+            continue;
+        }
+        let end = (i..types.len())
+            .find(|&j| !types[j].is_neutral())
+            .unwrap_or(types.len());
+        let before = match i {
+            0 => seq.sos,
+            _ => types[i - 1].strong().unwrap_or(embedding),
+        };
+        let after = types
+            .get(end)
+            .map_or(seq.eos, |class| class.strong().unwrap_or(embedding));
+        let class = if before == after { before } else { embedding };
+        types[i..end].fill(class);
+        i = end;
+    }
+}
+
+/// A paragraph with the embedding levels of its characters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BidiParagraph {
+    /// the level of the paragraph, 0 for left to right paragraphs
+    pub level: Level,
+    /// the level of every byte of the text, before the rules that depend on lines
+    pub levels: Vec<Level>,
+}
+
+/// A part of a line with one level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// the bytes of the text
+    pub range: Range<usize>,
+    pub level: Level,
+}
+
+impl Run {
+    pub fn is_rtl(&self) -> bool {
+        self.level % 2 == 1
+    }
+}
+
+impl BidiParagraph {
+    /// Resolve the levels of a paragraph, its direction is the one of the first strong
+    /// character if it isn't given. A paragraph separator should only end the text.
+    pub fn new(text: &str, direction: Option<Direction>) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let original: Vec<BidiClass> = chars.iter().map(|&c| bidi_class(c)).collect();
+        let paragraph_level = match direction.or_else(|| first_strong(&original)) {
+            Some(Direction::RightToLeft) => 1,
+            _ => 0,
+        };
+
+        let matching_pdis = matching_pdis(&original);
+        let mut classes = original.clone();
+        let mut levels = explicit_levels(&mut classes, &matching_pdis, paragraph_level);
+
+        for seq in isolating_run_sequences(&original, &levels, &matching_pdis, paragraph_level) {
+            let mut types: Vec<BidiClass> = seq.indices.iter().map(|&i| classes[i]).collect();
+            resolve_weak(&seq, &mut types);
+            resolve_brackets(&seq, &chars, &original, &mut types);
+            resolve_neutral(&seq, &mut types);
+
+            // I1 and I2
+            for (&i, class) in seq.indices.iter().zip(types) {
+                levels[i] += match (seq.level % 2, class) {
+                    (0, R) => 1,
+                    (0, AN | EN) => 2,
+                    (1, L | EN | AN) => 1,
+                    _ => 0,
+                };
+            }
+        }
+        // removed characters get the level of the one before them
+        for i in 0..chars.len() {
+            if original[i].is_removed() {
+                levels[i] = if i == 0 {
+                    paragraph_level
+                } else {
+                    levels[i - 1]
+                };
+            }
+        }
+
+        let levels = chars
+            .iter()
+            .zip(levels)
+            .flat_map(|(c, level)| std::iter::repeat_n(level, c.len_utf8()))
+            .collect();
+        Self {
+            level: paragraph_level,
+            levels,
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        match self.level % 2 {
+            0 => Direction::LeftToRight,
+            _ => Direction::RightToLeft,
+        }
+    }
+
+    /// The levels of the bytes of a line of the text: separators and the white space
+    /// before them and at the end of the line get the paragraph level (rule L1)
+    pub fn line_levels(&self, text: &str, line: Range<usize>) -> Vec<Level> {
+        let mut levels = self.levels[line.clone()].to_vec();
+        // going backwards, whether the characters are followed by a separator or the end
+        let mut reset = true;
+        for (i, c) in text[line.clone()].char_indices().rev() {
+            let class = bidi_class(c);
+            let bytes = i..i + c.len_utf8();
+            match class {
+                S | B => {
+                    levels[bytes].fill(self.level);
+                    reset = true;
+                }
+                WS | LRI | RLI | FSI | PDI => {
+                    if reset {
+                        levels[bytes].fill(self.level);
+                    }
+                }
+                class if class.is_removed() => {
+                    if reset {
+                        levels[bytes].fill(self.level);
+                    }
+                }
+                _ => reset = false,
+            }
+        }
+        levels
+    }
+
+    /// The runs of a line of the text in the order they are displayed from left to right.
+    /// The characters of a right to left run are displayed from its end to its start.
+    pub fn visual_runs(&self, text: &str, line: Range<usize>) -> Vec<Run> {
+        let levels = self.line_levels(text, line.clone());
+        let mut runs: Vec<Run> = Vec::new();
+        for (i, &level) in levels.iter().enumerate() {
+            let pos = line.start + i;
+            match runs.last_mut() {
+                Some(run) if run.level == level => run.range.end = pos + 1,
+                _ => runs.push(Run {
+                    range: pos..pos + 1,
+                    level,
+                }),
+            }
+        }
+        let order = reorder(&runs.iter().map(|run| run.level).collect::<Vec<_>>());
+        order.into_iter().map(|i| runs[i].clone()).collect()
+    }
+}
+
+/// The logical indices of items with the levels in the order they are displayed, from the
+/// highest level to the lowest odd one every sequence of items at that level or higher is
+/// reversed (rule L2)
+pub fn reorder(levels: &[Level]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let Some(&highest) = levels.iter().max() else {
+        return order;
+    };
+    let lowest_odd = levels.iter().map(|&l| l | 1).min().unwrap();
+    for level in (lowest_odd..=highest).rev() {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let end = (i..order.len())
+                .find(|&j| levels[order[j]] < level)
+                .unwrap_or(order.len());
+            order[i..end].reverse();
+            i = end;
+        }
+    }
+    order
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The level of every character
+    fn levels(text: &str, direction: Option<Direction>) -> Vec<Level> {
+        let paragraph = BidiParagraph::new(text, direction);
+        let levels = paragraph.line_levels(text, 0..text.len());
+        text.char_indices().map(|(i, _)| levels[i]).collect()
+    }
+
+    /// The characters of a line in display order
+    fn visual(text: &str) -> String {
+        let paragraph = BidiParagraph::new(text, None);
+        let runs = paragraph.visual_runs(text, 0..text.len());
+        runs.iter()
+            .flat_map(|run| {
+                let chars: Vec<char> = text[run.range.clone()].chars().collect();
+                match run.is_rtl() {
+                    true => chars.into_iter().rev().collect::<Vec<_>>(),
+                    false => chars,
+                }
+            })
+            .collect()
+    }
+
+    /// The paragraph level, the level of every character and the display order of a line,
+    /// like the conformance tests give them: the characters that rule X9 removes have no
+    /// level and aren't in the order
+    fn resolve(
+        text: &str,
+        direction: Option<Direction>,
+    ) -> (Level, Vec<Option<Level>>, Vec<usize>) {
+        let paragraph = BidiParagraph::new(text, direction);
+        let line = paragraph.line_levels(text, 0..text.len());
+        let levels: Vec<_> = text
+            .char_indices()
+            .map(|(i, c)| (!bidi_class(c).is_removed()).then_some(line[i]))
+            .collect();
+        let kept: Vec<usize> = (0..levels.len()).filter(|&i| levels[i].is_some()).collect();
+        let order = reorder(&kept.iter().map(|&i| levels[i].unwrap()).collect::<Vec<_>>());
+        let order = order.into_iter().map(|i| kept[i]).collect();
+        (paragraph.level, levels, order)
+    }
+
+    /// The levels of a conformance test, x for a removed character
+    fn parse_levels(levels: &str) -> Vec<Option<Level>> {
+        levels
+            .split_whitespace()
+            .map(|level| level.parse().ok())
+            .collect()
+    }
+
+    fn parse_order(order: &str) -> Vec<usize> {
+        order
+            .split_whitespace()
+            .map(|i| i.parse().unwrap())
+            .collect()
+    }
+
+    fn read_test(name: &str) -> String {
+        let path = format!("{}/ucd/{name}", env!("CARGO_MANIFEST_DIR"));
+        std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"))
+    }
+
+    #[test]
+    fn properties() {
+        assert_eq!(bidi_class('a'), L);
+        assert_eq!(bidi_class('\u{5d0}'), R);
+        assert_eq!(bidi_class('\u{627}'), AL);
+        assert_eq!(bidi_class('1'), EN);
+        assert_eq!(bidi_class('\u{661}'), AN);
+        assert_eq!(bidi_class(' '), WS);
+        assert_eq!(bidi_class('\u{2067}'), RLI);
+        // unassigned code points in right to left blocks
+        assert_eq!(bidi_class('\u{5ff}'), R);
+        assert_eq!(mirrored('('), Some(')'));
+        assert_eq!(mirrored('\u{2264}'), Some('\u{2265}'));
+        assert_eq!(mirrored('a'), None);
+        assert_eq!(bracket('['), Some((']', true)));
+        assert_eq!(bracket('\u{232a}'), Some(('\u{2329}', false)));
+    }
+
+    #[test]
+    fn implicit_levels() {
+        assert_eq!(levels("abc", None), [0, 0, 0]);
+        assert_eq!(BidiParagraph::new("\u{5d0}\u{5d1}", None).level, 1);
+        assert_eq!(levels("car \u{5d0}\u{5d1}.", None), [0, 0, 0, 0, 1, 1, 0]);
+        // numbers in right to left text
+        assert_eq!(levels("\u{5d0} 12", None), [1, 1, 2, 2]);
+        assert_eq!(levels("\u{627} 1,2", None), [1, 1, 2, 2, 2]);
+        // numbers after left to right text are left to right
+        assert_eq!(levels("a 1%", Some(Direction::RightToLeft)), [2, 2, 2, 2]);
+        // the text in the brackets decides their direction
+        assert_eq!(
+            levels("\u{5d0} (ab) \u{5d1}", None),
+            [1, 1, 1, 2, 2, 1, 1, 1]
+        );
+        assert_eq!(levels("ab (\u{5d0}) c", None), [0, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(
+            levels("\u{5d0} ab(c\u{5d1})", Some(Direction::LeftToRight)),
+            [1, 0, 0, 0, 0, 0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn explicit_levels() {
+        // isolates don't decide the paragraph direction
+        assert_eq!(
+            BidiParagraph::new("\u{2067}\u{5d0}\u{2069} a", None).level,
+            0
+        );
+        assert_eq!(levels("\u{2067}ab\u{2069} c", None), [0, 2, 2, 0, 0, 0]);
+        // the direction of a first strong isolate comes from its content
+        assert_eq!(levels("a\u{2068}\u{5d0}b\u{2069}", None), [0, 0, 1, 2, 0]);
+        // overrides
+        assert_eq!(levels("\u{202e}ab\u{202c}c", None), [0, 1, 1, 1, 0]);
+        assert_eq!(levels("\u{202d}\u{5d0}\u{5d1}", None), [1, 2, 2]);
+        // embeddings past the maximum depth are ignored, the last right to left one makes
+        // the letter go one level deeper
+        let deep = "\u{202b}\u{202a}".repeat(70) + "a";
+        assert_eq!(*levels(&deep, None).last().unwrap(), MAX_DEPTH + 1);
+    }
+
+    #[test]
+    fn lines() {
+        // white space at the end of a line and before a tab gets the paragraph level
+        let ltr = Some(Direction::LeftToRight);
+        assert_eq!(levels("\u{202b}\u{5d0} \u{202c}", ltr), [0, 1, 0, 0]);
+        assert_eq!(levels("\u{5d0} \ta", ltr), [1, 0, 0, 0]);
+
+        assert_eq!(
+            visual("abc \u{5d0}\u{5d1}\u{5d2} def"),
+            "abc \u{5d2}\u{5d1}\u{5d0} def"
+        );
+        assert_eq!(
+            visual("\u{5d0}\u{5d1} abc \u{5d2} 12"),
+            "12 \u{5d2} abc \u{5d1}\u{5d0}"
+        );
+        let text = "\u{5d0}\u{5d1}\u{5d2} abc \u{5d3}";
+        let runs = BidiParagraph::new(text, None).visual_runs(text, 0..text.len());
+        let ranges: Vec<_> = runs
+            .iter()
+            .map(|run| (run.range.clone(), run.level))
+            .collect();
+        assert_eq!(ranges, [(10..13, 1), (7..10, 2), (0..7, 1)]);
+
+        assert_eq!(reorder(&[0, 1, 1, 2, 2, 1, 0]), [0, 5, 3, 4, 2, 1, 6]);
+        assert_eq!(reorder(&[]), Vec::<usize>::new());
+    }
+
+    /// Every line of the conformance test of UAX #9 with bidi classes, each class stands
+    /// for a character that has it, with every paragraph direction of the line
+    #[test]
+    fn bidi_test() {
+        let representative = |class: &str| match class {
+            "L" => 'a',
+            "R" => '\u{5d0}',
+            "AL" => '\u{627}',
+            "EN" => '0',
+            "ES" => '+',
+            "ET" => '$',
+            "AN" => '\u{660}',
+            "CS" => ',',
+            "NSM" => '\u{300}',
+            "BN" => '\u{ad}',
+            "B" => '\u{2029}',
+            "S" => '\t',
+            "WS" => ' ',
+            "ON" => '!',
+            "LRE" => '\u{202a}',
+            "LRO" => '\u{202d}',
+            "RLE" => '\u{202b}',
+            "RLO" => '\u{202e}',
+            "PDF" => '\u{202c}',
+            "LRI" => '\u{2066}',
+            "RLI" => '\u{2067}',
+            "FSI" => '\u{2068}',
+            "PDI" => '\u{2069}',
+            class => panic!("unknown bidi class {class}"),
+        };
+        let data = read_test("BidiTest.txt");
+        let mut levels = vec![];
+        let mut order = vec![];
+        let mut failures = vec![];
+        let mut count = 0;
+        for (number, line) in data.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if let Some(l) = line.strip_prefix("@Levels:") {
+                levels = parse_levels(l);
+                continue;
+            }
+            if let Some(o) = line.strip_prefix("@Reorder:") {
+                order = parse_order(o);
+                continue;
+            }
+            let Some((classes, bitset)) = line.split_once(';') else {
+                continue;
+            };
+            let text: String = classes.split_whitespace().map(representative).collect();
+            let bitset = u32::from_str_radix(bitset.trim(), 16).unwrap();
+            for (bit, direction) in [
+                (1, None),
+                (2, Some(Direction::LeftToRight)),
+                (4, Some(Direction::RightToLeft)),
+            ] {
+                if bitset & bit == 0 {
+                    continue;
+                }
+                count += 1;
+                let (_, actual_levels, actual_order) = resolve(&text, direction);
+                if actual_levels != levels || actual_order != order {
+                    failures.push((number + 1, bit));
+                }
+            }
+        }
+        assert!(count > 0);
+        assert!(
+            failures.is_empty(),
+            "{} of {count} cases fail, the line and direction of the first ones: {:?}",
+            failures.len(),
+            &failures[..failures.len().min(20)]
+        );
+    }
+
+    /// Every line of the conformance test of UAX #9 with characters
+    #[test]
+    fn bidi_character_test() {
+        let data = read_test("BidiCharacterTest.txt");
+        let mut failures = vec![];
+        let mut count = 0;
+        for (number, line) in data.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(';').collect();
+            let text: String = fields[0]
+                .split_whitespace()
+                .map(|c| char::from_u32(u32::from_str_radix(c, 16).unwrap()).unwrap())
+                .collect();
+            let direction = match fields[1].trim() {
+                "0" => Some(Direction::LeftToRight),
+                "1" => Some(Direction::RightToLeft),
+                _ => None,
+            };
+            let expected = (
+                fields[2].trim().parse().unwrap(),
+                parse_levels(fields[3]),
+                parse_order(fields[4]),
+            );
+            count += 1;
+            if resolve(&text, direction) != expected {
+                failures.push(number + 1);
+            }
+        }
+        assert!(count > 0);
+        assert!(
+            failures.is_empty(),
+            "{} of {count} lines fail, the first ones: {:?}",
+            failures.len(),
+            &failures[..failures.len().min(20)]
+        );
+    }
+}
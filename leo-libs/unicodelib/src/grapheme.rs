@@ -0,0 +1,356 @@
+//! Extended grapheme clusters (UAX #29): a base character with the marks and joiners that
+//! belong to it, a Hangul syllable, an emoji sequence or a flag. A cursor moves and a
+//! selection grows by whole clusters.
+//!
+//! The rules are the ones of Unicode 15.1, which joins consonants of some Indic scripts
+//! with a virama into a cluster (GB9c).
+
+use crate::tables::{
+    EXTENDED_PICTOGRAPHIC, GRAPHEME_BREAKS, INDIC_CONJUNCT_BREAKS, contains, lookup,
+};
+
+/// The Grapheme_Cluster_Break property
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphemeBreak {
+    Other,
+    Prepend,
+    CR,
+    LF,
+    Control,
+    Extend,
+    RegionalIndicator,
+    SpacingMark,
+    /// Hangul leading consonants
+    L,
+    /// Hangul vowels
+    V,
+    /// Hangul trailing consonants
+    T,
+    /// Hangul syllables of a leading consonant and a vowel
+    LV,
+    /// Hangul syllables with a trailing consonant
+    LVT,
+    ZWJ,
+}
+
+pub fn grapheme_break(c: char) -> GraphemeBreak {
+    lookup(GRAPHEME_BREAKS, c).unwrap_or(GraphemeBreak::Other)
+}
+
+/// Emoji and the code points reserved for them, joined by ZWJ into one cluster
+pub fn is_extended_pictographic(c: char) -> bool {
+    contains(EXTENDED_PICTOGRAPHIC, c)
+}
+
+/// The Indic_Conjunct_Break property, the characters of a conjunct cluster (GB9c)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IndicConjunctBreak {
+    None,
+    /// the virama of a script that forms conjuncts
+    Linker,
+    Consonant,
+    /// marks and ZWJ that may be between a consonant and a linker
+    Extend,
+}
+
+fn indic_conjunct_break(c: char) -> IndicConjunctBreak {
+    lookup(INDIC_CONJUNCT_BREAKS, c).unwrap_or(IndicConjunctBreak::None)
+}
+
+/// The state of the rules that look further back than one character
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    /// the regional indicators in a row before the position
+    regional_indicators: usize,
+    /// an extended pictographic character, maybe followed by Extend, is before the position
+    pictographic: bool,
+    /// like `pictographic`, but with a ZWJ after it
+    pictographic_zwj: bool,
+    /// a consonant, maybe followed by linkers and InCB Extend, is before the position
+    consonant: bool,
+    /// like `consonant`, but with at least one linker after the consonant
+    consonant_linker: bool,
+}
+
+impl State {
+    /// Whether there is a boundary between `before` and `after`, and update the state
+    /// for the position after `after`
+    fn is_boundary(&mut self, before: char, after: char) -> bool {
+        use GraphemeBreak::*;
+
+        let (a, b) = (grapheme_break(before), grapheme_break(after));
+        let boundary = match (a, b) {
+            // GB3, GB4 and GB5
+            (CR, LF) => false,
+            (CR | LF | Control, _) | (_, CR | LF | Control) => true,
+            // GB6, GB7 and GB8
+            (L, L | V | LV | LVT) | (LV | V, V | T) | (LVT | T, T) => false,
+            // GB9, GB9a and GB9b
+            (_, Extend | ZWJ | SpacingMark) | (Prepend, _) => false,
+            // GB9c
+            _ if self.consonant_linker
+                && indic_conjunct_break(after) == IndicConjunctBreak::Consonant =>
+            {
+                false
+            }
+            // GB11
+            (ZWJ, _) if self.pictographic_zwj && is_extended_pictographic(after) => false,
+            // GB12 and GB13, regional indicators pair up
+            (RegionalIndicator, RegionalIndicator) => self.regional_indicators.is_multiple_of(2),
+            _ => true,
+        };
+
+        self.regional_indicators = match b {
+            RegionalIndicator => self.regional_indicators + 1,
+            _ => 0,
+        };
+        let pictographic = self.pictographic;
+        self.pictographic = is_extended_pictographic(after) || (pictographic && b == Extend);
+        self.pictographic_zwj = pictographic && b == ZWJ;
+        let consonant = self.consonant;
+        (self.consonant, self.consonant_linker) = match indic_conjunct_break(after) {
+            IndicConjunctBreak::Consonant => (true, false),
+            IndicConjunctBreak::Linker => (consonant, consonant),
+            IndicConjunctBreak::Extend => (consonant, self.consonant_linker),
+            IndicConjunctBreak::None => (false, false),
+        };
+        boundary
+    }
+
+    /// The state after the first character of a cluster
+    fn new(first: char) -> Self {
+        Self {
+            regional_indicators: (grapheme_break(first) == GraphemeBreak::RegionalIndicator)
+                as usize,
+            pictographic: is_extended_pictographic(first),
+            pictographic_zwj: false,
+            consonant: indic_conjunct_break(first) == IndicConjunctBreak::Consonant,
+            consonant_linker: false,
+        }
+    }
+}
+
+/// The end of the cluster that starts at `start`, which has to be a boundary
+fn cluster_end(text: &str, start: usize) -> usize {
+    let mut chars = text[start..].char_indices();
+    let Some((_, mut before)) = chars.next() else {
+        return start;
+    };
+    let mut state = State::new(before);
+    for (i, after) in chars {
+        if state.is_boundary(before, after) {
+            return start + i;
+        }
+        before = after;
+    }
+    text.len()
+}
+
+/// The clusters of a text with their byte index
+#[derive(Debug, Clone)]
+pub struct GraphemeIndices<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Iterator for GraphemeIndices<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos >= self.text.len() {
+            return None;
+        }
+        let start = self.pos;
+        self.pos = cluster_end(self.text, start);
+        Some((start, &self.text[start..self.pos]))
+    }
+}
+
+pub fn grapheme_indices(text: &str) -> GraphemeIndices<'_> {
+    GraphemeIndices { text, pos: 0 }
+}
+
+pub fn graphemes(text: &str) -> impl Iterator<Item = &str> {
+    grapheme_indices(text).map(|(_, cluster)| cluster)
+}
+
+/// A boundary at or before `pos`: the text start or the position after the last control
+/// character or line feed, which always end a cluster
+fn boundary_before(text: &str, pos: usize) -> usize {
+    text[..pos]
+        .char_indices()
+        .rev()
+        .find(|&(_, c)| {
+            matches!(
+                grapheme_break(c),
+                GraphemeBreak::LF | GraphemeBreak::Control
+            )
+        })
+        .map_or(0, |(i, c)| i + c.len_utf8())
+}
+
+/// The boundaries from the one at `start` on
+fn boundaries_from(text: &str, start: usize) -> impl Iterator<Item = usize> + '_ {
+    let clusters = GraphemeIndices { text, pos: start };
+    clusters.map(|(i, _)| i).chain(Some(text.len()))
+}
+
+/// Whether a cluster starts or ends at the byte index
+pub fn is_grapheme_boundary(text: &str, pos: usize) -> bool {
+    boundaries_from(text, boundary_before(text, pos))
+        .find(|&i| i >= pos)
+        .is_some_and(|i| i == pos)
+}
+
+/// Where the cursor goes when it moves right from `pos` in left to right text, the end of
+/// the text if it is already there
+pub fn next_grapheme_boundary(text: &str, pos: usize) -> usize {
+    boundaries_from(text, boundary_before(text, pos))
+        .find(|&i| i > pos)
+        .unwrap_or(text.len())
+}
+
+/// Where the cursor goes when it moves left from `pos` in left to right text, 0 if it is
+/// already there
+pub fn prev_grapheme_boundary(text: &str, pos: usize) -> usize {
+    if pos == 0 {
+        return 0;
+    }
+    let before = text[..pos].char_indices().next_back().map_or(0, |(i, _)| i);
+    boundaries_from(text, boundary_before(text, before))
+        .take_while(|&i| i < pos)
+        .last()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn clusters(text: &str) -> Vec<&str> {
+        graphemes(text).collect()
+    }
+
+    #[test]
+    fn properties() {
+        assert_eq!(grapheme_break('a'), GraphemeBreak::Other);
+        assert_eq!(grapheme_break('\u{301}'), GraphemeBreak::Extend);
+        assert_eq!(grapheme_break('\u{ac00}'), GraphemeBreak::LV);
+        assert_eq!(grapheme_break('\u{ac01}'), GraphemeBreak::LVT);
+        assert_eq!(
+            grapheme_break('\u{1f1e9}'),
+            GraphemeBreak::RegionalIndicator
+        );
+        assert_eq!(grapheme_break('\u{600}'), GraphemeBreak::Prepend);
+        assert_eq!(grapheme_break('\u{93f}'), GraphemeBreak::SpacingMark);
+        assert!(is_extended_pictographic('\u{1f600}'));
+        assert!(!is_extended_pictographic('a'));
+    }
+
+    #[test]
+    fn clusters_of_the_rules() {
+        assert_eq!(clusters(""), Vec::<&str>::new());
+        assert_eq!(clusters("ab"), ["a", "b"]);
+        assert_eq!(clusters("e\u{301}x"), ["e\u{301}", "x"]);
+        assert_eq!(clusters("\r\n\n\r"), ["\r\n", "\n", "\r"]);
+        // a control character isn't extended
+        assert_eq!(clusters("\n\u{301}"), ["\n", "\u{301}"]);
+        // jamo and syllables
+        assert_eq!(
+            clusters("\u{1100}\u{1161}\u{11a8}\u{ac00}\u{11a8}"),
+            ["\u{1100}\u{1161}\u{11a8}", "\u{ac00}\u{11a8}"]
+        );
+        // a virama joins consonants, spacing marks are extended
+        assert_eq!(
+            clusters("\u{915}\u{94d}\u{937}\u{93f}"),
+            ["\u{915}\u{94d}\u{937}\u{93f}"]
+        );
+        // but only in the scripts with conjuncts, not in Tamil
+        assert_eq!(
+            clusters("\u{b95}\u{bcd}\u{bb7}"),
+            ["\u{b95}\u{bcd}", "\u{bb7}"]
+        );
+        assert_eq!(clusters("\u{600}1"), ["\u{600}1"]);
+        // flags are pairs of regional indicators
+        assert_eq!(
+            clusters("\u{1f1e9}\u{1f1ea}\u{1f1eb}\u{1f1f7}\u{1f1eb}"),
+            ["\u{1f1e9}\u{1f1ea}", "\u{1f1eb}\u{1f1f7}", "\u{1f1eb}"]
+        );
+        // emoji joined by ZWJ, with skin tones
+        let family = "\u{1f468}\u{1f3fb}\u{200d}\u{1f469}\u{200d}\u{1f467}";
+        assert_eq!(clusters(family), [family]);
+        assert_eq!(clusters("a\u{200d}\u{1f467}"), ["a\u{200d}", "\u{1f467}"]);
+    }
+
+    #[test]
+    fn cursor_movement() {
+        let text = "e\u{301}\u{1f1e9}\u{1f1ea}\u{1f1eb}\u{1f1f7}\r\nx";
+        let boundaries = [0, 3, 11, 19, 21, 22];
+        let mut pos = 0;
+        for &next in &boundaries[1..] {
+            pos = next_grapheme_boundary(text, pos);
+            assert_eq!(pos, next);
+        }
+        assert_eq!(next_grapheme_boundary(text, pos), text.len());
+        for &prev in boundaries[..5].iter().rev() {
+            pos = prev_grapheme_boundary(text, pos);
+            assert_eq!(pos, prev);
+        }
+        assert_eq!(prev_grapheme_boundary(text, 0), 0);
+
+        // from inside of a cluster
+        assert_eq!(next_grapheme_boundary(text, 1), 3);
+        assert_eq!(prev_grapheme_boundary(text, 15), 11);
+        assert!(is_grapheme_boundary(text, 11));
+        assert!(!is_grapheme_boundary(text, 15));
+        assert!(!is_grapheme_boundary(text, 20));
+        assert!(is_grapheme_boundary(text, text.len()));
+    }
+
+    /// Every line of the conformance test of UAX #29, the text with ÷ where a cluster
+    /// ends and × between characters of a cluster
+    #[test]
+    fn grapheme_break_test() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/ucd/auxiliary/GraphemeBreakTest.txt"
+        );
+        let data = std::fs::read_to_string(path).unwrap();
+        let mut failures = Vec::new();
+        let mut count = 0;
+        for (number, line) in data.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let mut text = String::new();
+            let mut expected = vec![];
+            let mut start = 0;
+            for token in line.split_whitespace() {
+                match token {
+                    "\u{f7}" => {
+                        if text.len() > start {
+                            expected.push(text[start..].to_string());
+                        }
+                        start = text.len();
+                    }
+                    "\u{d7}" => {}
+                    code => {
+                        text.push(char::from_u32(u32::from_str_radix(code, 16).unwrap()).unwrap())
+                    }
+                }
+            }
+            count += 1;
+            if clusters(&text) != expected {
+                failures.push(number + 1);
+            }
+        }
+        assert!(count > 0);
+        assert!(
+            failures.is_empty(),
+            "{} of {count} lines fail: {failures:?}",
+            failures.len()
+        );
+    }
+}
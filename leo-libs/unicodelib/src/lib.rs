@@ -0,0 +1,13 @@
+//! Unicode algorithms for text: the bidirectional algorithm (UAX #9) that orders mixed
+//! left to right and right to left text for display, and extended grapheme clusters
+//! (UAX #29), the characters a user sees, for cursor movement and selection.
+//!
+//! The property tables are generated at build time from the files of the Unicode
+//! character database in `ucd/`, version 14.0.0 with the Indic_Conjunct_Break property of
+//! 15.1. The files there are derived from the database by `ucd/generate.pl` and have the
+//! same format, `ucd/fetch.sh` replaces them with the published ones. The conformance
+//! tests of both algorithms in `ucd/` are the published files, they run with the tests.
+
+pub mod bidi;
+pub mod grapheme;
+mod tables;
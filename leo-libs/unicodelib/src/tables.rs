@@ -0,0 +1,25 @@
+//! The property tables that the build script generates from the UCD files
+
+use crate::{
+    bidi::BidiClass,
+    grapheme::{GraphemeBreak, IndicConjunctBreak},
+};
+
+include!(concat!(env!("OUT_DIR"), "/tables.rs"));
+
+/// The value of the range that contains the character, None if no range does
+pub(crate) fn lookup<T: Copy>(table: &[(u32, u32, T)], c: char) -> Option<T> {
+    let c = c as u32;
+    let i = table.partition_point(|&(_, end, _)| end < c);
+    table
+        .get(i)
+        .filter(|&&(start, _, _)| start <= c)
+        .map(|e| e.2)
+}
+
+/// Whether one of the ranges contains the character
+pub(crate) fn contains(table: &[(u32, u32)], c: char) -> bool {
+    let c = c as u32;
+    let i = table.partition_point(|&(_, end)| end < c);
+    table.get(i).is_some_and(|&(start, _)| start <= c)
+}
@@ -0,0 +1,22 @@
+#! /usr/bin/bash
+# Replaces the derived files in this directory with the published files of one release of
+# the Unicode character database, together with its conformance tests. The rules of the
+# grapheme clusters are the ones of 15.1, a release with other rules fails the tests.
+# Run it in this directory: ./fetch.sh [version]
+VERSION=${1:-15.1.0}
+URL="https://www.unicode.org/Public/$VERSION/ucd"
+
+for FILE in extracted/DerivedBidiClass.txt BidiBrackets.txt BidiMirroring.txt \
+  DerivedCoreProperties.txt auxiliary/GraphemeBreakProperty.txt emoji/emoji-data.txt \
+  BidiTest.txt BidiCharacterTest.txt auxiliary/GraphemeBreakTest.txt; do
+  # the properties are in this directory, only the grapheme test is in auxiliary/
+  case $FILE in
+    auxiliary/GraphemeBreakTest.txt) OUT=$FILE ;;
+    *) OUT=$(basename $FILE) ;;
+  esac
+  curl -fsS "$URL/$FILE" -o "$OUT"
+  if [ $? -ne 0 ]; then
+    echo "Can't download $FILE."
+    exit 1
+  fi
+done
@@ -0,0 +1,181 @@
+#!/usr/bin/perl
+# Writes the UCD files in this directory from the Unicode 14.0 tables that come with Perl
+# (Unicode::UCD), for machines without access to unicode.org. The files are derived data,
+# not the published ones: they have only the properties the build script reads and no
+# comments after the values, but the same format, so the published files can replace
+# them, see fetch.sh. Run it in this directory: perl generate.pl
+#
+# The data is © 2021 Unicode, Inc., see license.txt for the terms of use.
+use strict;
+use warnings;
+use Unicode::UCD qw(prop_invmap prop_invlist);
+
+die "Perl has Unicode ", Unicode::UCD::UnicodeVersion(), ", not 14.0.0\n"
+    unless Unicode::UCD::UnicodeVersion() eq "14.0.0";
+
+sub range { my ($a, $b) = @_; return $a == $b ? sprintf("%04X", $a) : sprintf("%04X..%04X", $a, $b); }
+
+sub header {
+    my ($file, @lines) = @_;
+    return "# $file-14.0.0.txt\n" .
+        "# Derived from the Unicode Character Database 14.0.0 by generate.pl, this is\n" .
+        "# not the published file. (c) 2021 Unicode, Inc., see license.txt.\n#\n" .
+        join("", map { $_ eq "" ? "#\n" : "# $_\n" } @lines) . "\n";
+}
+
+# The value of a property for every code point, from an inversion map
+sub values_of {
+    my ($prop, %rename) = @_;
+    my ($l, $m) = prop_invmap($prop);
+    my @values;
+    for my $i (0 .. $#$l) {
+        my $end = $i < $#$l ? $l->[$i + 1] - 1 : 0x10FFFF;
+        my $v = exists $rename{$m->[$i]} ? $rename{$m->[$i]} : $m->[$i];
+        $values[$_] = $v for $l->[$i] .. $end;
+    }
+    return @values;
+}
+
+# Lines of ranges with the same value, skipping the code points `skip` returns true for
+sub write_ranges {
+    my ($fh, $values, $skip) = @_;
+    my $start;
+    for my $c (0 .. 0x110000) {
+        my $in = $c <= 0x10FFFF && !$skip->($c);
+        if (defined $start && (!$in || $values->[$c] ne $values->[$start])) {
+            printf $fh "%-14s; %s\n", range($start, $c - 1), $values->[$start];
+            undef $start;
+        }
+        $start = $c if $in && !defined $start;
+    }
+}
+
+my %unassigned;
+{
+    my @l = prop_invlist("General_Category=Unassigned");
+    for (my $i = 0; $i < @l; $i += 2) {
+        my $end = $i + 1 < @l ? $l[$i + 1] - 1 : 0x10FFFF;
+        $unassigned{$_} = 1 for $l[$i] .. $end;
+    }
+}
+
+# Bidi_Class, with the values of unassigned code points as @missing lines with the long
+# value names like the published file. Each line covers the unassigned code points from
+# the first to the last of one value, the assigned ones between them are listed.
+{
+    my %long = (
+        L => "Left_To_Right", R => "Right_To_Left", AL => "Arabic_Letter",
+        EN => "European_Number", ES => "European_Separator", ET => "European_Terminator",
+        AN => "Arabic_Number", CS => "Common_Separator", NSM => "Nonspacing_Mark",
+        BN => "Boundary_Neutral", B => "Paragraph_Separator", S => "Segment_Separator",
+        WS => "White_Space", ON => "Other_Neutral", LRE => "Left_To_Right_Embedding",
+        LRO => "Left_To_Right_Override", RLE => "Right_To_Left_Embedding",
+        RLO => "Right_To_Left_Override", PDF => "Pop_Directional_Format",
+        LRI => "Left_To_Right_Isolate", RLI => "Right_To_Left_Isolate",
+        FSI => "First_Strong_Isolate", PDI => "Pop_Directional_Isolate",
+    );
+    my @values = values_of("Bidi_Class");
+    my @missing = ([0, 0x10FFFF, "L"]);
+    for my $c (0 .. 0x10FFFF) {
+        next unless $unassigned{$c};
+        my $v = $values[$c];
+        my $last = $missing[-1];
+        if ($v eq "L") {
+            push @missing, undef if defined $last && @missing > 1;
+        } elsif (@missing > 1 && defined $last && $last->[2] eq $v) {
+            $last->[1] = $c;
+        } else {
+            push @missing, [$c, $c, $v];
+        }
+    }
+    open my $fh, '>', "DerivedBidiClass.txt" or die;
+    print $fh header("DerivedBidiClass", "Bidi_Class",
+        "", "The values of unassigned code points, later lines override earlier ones:",
+        map { sprintf("\@missing: %s; %s", range($_->[0], $_->[1]), $long{$_->[2]}) }
+            grep { defined } @missing);
+    write_ranges($fh, \@values, sub { $unassigned{$_[0]} });
+}
+
+{
+    my @values = values_of("GCB", ExtPict_XX => "Other");
+    open my $fh, '>', "GraphemeBreakProperty.txt" or die;
+    print $fh header("GraphemeBreakProperty", "Grapheme_Cluster_Break",
+        "", "\@missing: 0000..10FFFF; Other");
+    write_ranges($fh, \@values, sub { $values[$_[0]] eq "Other" });
+}
+
+{
+    open my $fh, '>', "emoji-data.txt" or die;
+    print $fh header("emoji-data", "Only the Extended_Pictographic property, the one grapheme clusters need");
+    my @l = prop_invlist("Extended_Pictographic");
+    for (my $i = 0; $i < @l; $i += 2) {
+        my $end = $i + 1 < @l ? $l[$i + 1] - 1 : 0x10FFFF;
+        printf $fh "%-14s; Extended_Pictographic\n", range($l[$i], $end);
+    }
+}
+
+{
+    my ($l, $m) = prop_invmap("bpb");
+    my ($tl, $tm) = prop_invmap("bpt");
+    my %type;
+    for my $i (0 .. $#$tl) {
+        next if $tm->[$i] eq "n";
+        $type{$_} = $tm->[$i] for $tl->[$i] .. $tl->[$i + 1] - 1;
+    }
+    open my $fh, '>', "BidiBrackets.txt" or die;
+    print $fh header("BidiBrackets", "Bidi_Paired_Bracket and Bidi_Paired_Bracket_Type",
+        "Field 0: the code point of a bracket", "Field 1: the code point of its pair",
+        "Field 2: o for an opening and c for a closing bracket");
+    for my $i (0 .. $#$l) {
+        next if $m->[$i] eq "";
+        for my $c ($l->[$i] .. $l->[$i + 1] - 1) {
+            my $pair = $m->[$i] + ($c - $l->[$i]);
+            printf $fh "%04X; %04X; %s # %s\n", $c, $pair, $type{$c}, Unicode::UCD::charinfo($c)->{name};
+        }
+    }
+}
+
+{
+    my ($l, $m) = prop_invmap("bmg");
+    open my $fh, '>', "BidiMirroring.txt" or die;
+    print $fh header("BidiMirroring", "Bidi_Mirroring_Glyph",
+        "Field 0: the code point of a character that is mirrored in right to left text",
+        "Field 1: the code point of a character that looks like its mirror image");
+    for my $i (0 .. $#$l) {
+        next if $m->[$i] eq "";
+        for my $c ($l->[$i] .. $l->[$i + 1] - 1) {
+            printf $fh "%04X; %04X # %s\n", $c, $m->[$i], Unicode::UCD::charinfo($c)->{name};
+        }
+    }
+}
+
+# Indic_Conjunct_Break, the property of rule GB9c that Unicode 15.1 added, derived from
+# the 14.0 properties the way UAX #44 of 15.1 defines it. Only the InCB lines of the file.
+{
+    my %linking = map { $_ => 1 } qw(Bengali Devanagari Gujarati Malayalam Oriya Telugu);
+    my @script = values_of("Script");
+    my @insc = values_of("InSC");
+    my @ccc = values_of("ccc");
+    my @gcb = values_of("GCB");
+    my @values;
+    for my $c (0 .. 0x10FFFF) {
+        my $in_linking = $linking{$script[$c]};
+        if ($in_linking && $insc[$c] eq "Virama") {
+            $values[$c] = "Linker";
+        } elsif ($in_linking && $insc[$c] eq "Consonant") {
+            $values[$c] = "Consonant";
+        } elsif ($gcb[$c] eq "ZWJ" || ($gcb[$c] eq "Extend" && $ccc[$c] != 0)) {
+            $values[$c] = "Extend";
+        } else {
+            $values[$c] = "None";
+        }
+    }
+    open my $fh, '>', "DerivedCoreProperties.txt" or die;
+    print $fh header("DerivedCoreProperties",
+        "Only the Indic_Conjunct_Break property (InCB) of Unicode 15.1, from the 14.0 tables",
+        "", "\@missing: 0000..10FFFF; InCB; None");
+    for my $value (qw(Linker Consonant Extend)) {
+        write_ranges($fh, [map { "InCB; $_" } @values], sub { $values[$_[0]] ne $value });
+        print $fh "\n";
+    }
+}
@@ -0,0 +1,220 @@
+//! Fuzz style robustness testing: valid sample files are mutated at random and fed to a
+//! decoder, which has to return an error instead of panicking or allocating huge buffers
+//! because of the sizes in a broken header.
+//!
+//! The samples live in `test-data/fuzz/`. Inputs that fail are written to `target/fuzz/` so
+//! they can be turned into regular tests. The runs are deterministic, `LEO_FUZZ_SEED` and
+//! `LEO_FUZZ_ITERATIONS` change which and how many inputs are tried.
+//!
+//! Allocations are only measured if the test binary uses [`TrackingAllocator`] as its
+//! global allocator.
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    fs,
+    panic::{self, RefUnwindSafe},
+    path::PathBuf,
+};
+
+/// The largest allocation a decoder may make for one of the small samples. It is far more
+/// than any valid sample needs, but far less than a broken header can ask for.
+pub const MAX_ALLOCATION: usize = 64 << 20;
+
+const DEFAULT_SEED: u64 = 0x1e0_5eed;
+const DEFAULT_ITERATIONS: usize = 2000;
+
+/// A xorshift64* random number generator, good enough to pick mutations
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // the state must not be 0
+        Self((seed ^ 0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`, `n` has to be larger than 0
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+}
+
+/// Change between one and four random places of `sample`. Half of the changes go to the 64
+/// bytes after one of the `hot` offsets, which is where headers keep their sizes and offsets.
+pub fn mutate(rng: &mut Rng, sample: &[u8], hot: &[usize]) -> Vec<u8> {
+    let mut data = sample.to_vec();
+    for _ in 0..1 + rng.below(4) {
+        if data.is_empty() {
+            data.push(rng.byte());
+            continue;
+        }
+        let pos = if !hot.is_empty() && rng.below(2) == 0 {
+            hot[rng.below(hot.len())] + rng.below(64)
+        } else {
+            rng.below(data.len())
+        }
+        .min(data.len() - 1);
+
+        match rng.below(8) {
+            0 => data[pos] ^= 1 << rng.below(8),
+            1 => data[pos] = rng.byte(),
+            2 => data[pos] = [0, 1, 0x7f, 0x80, 0xfe, 0xff][rng.below(6)],
+            // a large number, like a width or an offset in either byte order
+            3 => {
+                let end = (pos + 2 + rng.below(3)).min(data.len());
+                data[pos..end].fill(0xff);
+                if rng.below(2) == 0 {
+                    data[pos] = 0x7f;
+                }
+            }
+            4 => data.truncate(pos),
+            5 => {
+                let end = (pos + 1 + rng.below(16)).min(data.len());
+                data.drain(pos..end);
+            }
+            6 => {
+                let end = (pos + 1 + rng.below(16)).min(data.len());
+                let copy = data[pos..end].to_vec();
+                data.splice(pos..pos, copy);
+            }
+            _ => {
+                let bytes: Vec<u8> = (0..1 + rng.below(8)).map(|_| rng.byte()).collect();
+                data.splice(pos..pos, bytes);
+            }
+        }
+    }
+    data
+}
+
+/// A global allocator that remembers the largest allocation of the current thread, so that
+/// the tests running in parallel don't see each other's allocations.
+pub struct TrackingAllocator;
+
+thread_local! {
+    static LARGEST_ALLOCATION: Cell<usize> = const { Cell::new(0) };
+}
+
+fn track(size: usize) {
+    // the thread local is gone while the thread shuts down
+    let _ = LARGEST_ALLOCATION.try_with(|largest| largest.set(largest.get().max(size)));
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        track(layout.size());
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        track(layout.size());
+        unsafe { System.alloc_zeroed(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        track(new_size);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+/// Run `f` and return the size of the largest allocation it made on this thread
+pub fn largest_allocation(f: impl FnOnce()) -> usize {
+    LARGEST_ALLOCATION.with(|largest| largest.set(0));
+    f();
+    LARGEST_ALLOCATION.with(|largest| largest.get())
+}
+
+/// Why an input failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    Panic(String),
+    Allocation(usize),
+}
+
+/// Decode `data` and report a panic or an allocation above [`MAX_ALLOCATION`]. What the
+/// decoder returns does not matter.
+pub fn check_input(data: &[u8], decode: &(impl Fn(&[u8]) + RefUnwindSafe)) -> Option<Failure> {
+    let mut result = Ok(());
+    let size = largest_allocation(|| result = panic::catch_unwind(|| decode(data)));
+    match result {
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Some(Failure::Panic(message))
+        }
+        Ok(()) if size > MAX_ALLOCATION => Some(Failure::Allocation(size)),
+        Ok(()) => None,
+    }
+}
+
+pub fn sample_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../test-data")
+}
+
+pub fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/fuzz")
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Feed mutations of the samples to `decode` and panic with a summary if any of them
+/// fails. See [`mutate`] for `hot`.
+pub fn fuzz(
+    name: &str,
+    samples: &[Vec<u8>],
+    hot: &[usize],
+    decode: impl Fn(&[u8]) + RefUnwindSafe,
+) {
+    let seed = env_or("LEO_FUZZ_SEED", DEFAULT_SEED);
+    let iterations = env_or("LEO_FUZZ_ITERATIONS", DEFAULT_ITERATIONS);
+    let mut rng = Rng::new(seed);
+
+    // the samples themselves have to work, otherwise the mutations test nothing
+    for sample in samples {
+        assert_eq!(check_input(sample, &decode), None, "{name}: a sample fails");
+    }
+
+    let mut failures = Vec::new();
+    for i in 0..iterations {
+        let sample = &samples[rng.below(samples.len())];
+        let data = mutate(&mut rng, sample, hot);
+        if let Some(failure) = check_input(&data, &decode) {
+            let dir = output_dir();
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(format!("{name}-{seed}-{i}.bin"));
+            fs::write(&path, &data).unwrap();
+            failures.push(format!("{failure:?}, input saved to {}", path.display()));
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "{name}: {} of {iterations} inputs failed with LEO_FUZZ_SEED={seed}:\n{}",
+            failures.len(),
+            failures.join("\n")
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! Golden image testing: rendered images are compared against committed reference images.
+//!
+//! The references live in `leo-tests/golden/<name>.qoi`. If a comparison fails the
+//! actual image and a diff image are written as png files to `target/golden/` for inspection.
+//! Running the tests with `LEO_BLESS=1` overwrites the references with the current output.
+
+use std::{fmt::Display, fs, path::PathBuf};
+
+use imglib::{Rgba, RgbaImage, qoi};
+
+/// How much two images are allowed to differ
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// the maximum difference of a single channel for two pixels to count as equal
+    pub per_channel: u8,
+    /// how many pixels may differ by more than `per_channel`
+    pub max_pixels: usize,
+}
+
+impl Tolerance {
+    pub const EXACT: Self = Self {
+        per_channel: 0,
+        max_pixels: 0,
+    };
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    MissingReference(PathBuf),
+    DimensionMismatch {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    PixelMismatch {
+        differing: usize,
+        max_channel_diff: u8,
+    },
+}
+
+impl Display for GoldenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingReference(path) => write!(
+                f,
+                "no reference image at {} (run with LEO_BLESS=1 to create it)",
+                path.display()
+            ),
+            Self::DimensionMismatch { expected, actual } => write!(
+                f,
+                "expected an image of size {expected:?} but got {actual:?}"
+            ),
+            Self::PixelMismatch {
+                differing,
+                max_channel_diff,
+            } => write!(
+                f,
+                "{differing} pixels differ (maximum channel difference: {max_channel_diff})"
+            ),
+        }
+    }
+}
+
+pub fn reference_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+pub fn output_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../target/golden")
+}
+
+fn bless() -> bool {
+    std::env::var("LEO_BLESS").is_ok_and(|v| v == "1")
+}
+
+/// Compare the image against the reference called `name` and panic if they differ.
+pub fn assert_golden(name: &str, actual: &RgbaImage, tolerance: Tolerance) {
+    if let Err(e) = check_golden(name, actual, tolerance) {
+        panic!(
+            "golden image '{name}' does not match: {e}\nsee {} for the output",
+            output_dir().display()
+        );
+    }
+}
+
+/// Compare the image against the reference called `name`.
+///
+/// On failure the actual image is saved as `<name>.actual.png` and, if the dimensions
+/// match, a diff image as `<name>.diff.png` to the output directory.
+pub fn check_golden(
+    name: &str,
+    actual: &RgbaImage,
+    tolerance: Tolerance,
+) -> Result<(), GoldenError> {
+    let reference_path = reference_dir().join(format!("{name}.qoi"));
+
+    if bless() {
+        write_qoi(&reference_path, actual);
+        return Ok(());
+    }
+
+    let Some(expected) = read_qoi(&reference_path) else {
+        write_output(name, "actual", actual);
+        return Err(GoldenError::MissingReference(reference_path));
+    };
+
+    let res = compare(&expected, actual, tolerance);
+
+    if res.is_err() {
+        write_output(name, "actual", actual);
+        if expected.dimensions() == actual.dimensions() {
+            write_output(name, "diff", &diff_image(&expected, actual, tolerance));
+        }
+    }
+
+    res
+}
+
+/// Compare two images pixel by pixel.
+pub fn compare(
+    expected: &RgbaImage,
+    actual: &RgbaImage,
+    tolerance: Tolerance,
+) -> Result<(), GoldenError> {
+    if expected.dimensions() != actual.dimensions() {
+        return Err(GoldenError::DimensionMismatch {
+            expected: expected.dimensions(),
+            actual: actual.dimensions(),
+        });
+    }
+
+    let mut differing = 0;
+    let mut max_channel_diff = 0;
+    for (e, a) in expected.data.iter().zip(&actual.data) {
+        let diff = channel_diff(*e, *a);
+        max_channel_diff = max_channel_diff.max(diff);
+        if diff > tolerance.per_channel {
+            differing += 1;
+        }
+    }
+
+    if differing > tolerance.max_pixels {
+        return Err(GoldenError::PixelMismatch {
+            differing,
+            max_channel_diff,
+        });
+    }
+
+    Ok(())
+}
+
+/// An image highlighting the differing pixels in red on top of a dimmed version of the
+/// actual image.
+pub fn diff_image(expected: &RgbaImage, actual: &RgbaImage, tolerance: Tolerance) -> RgbaImage {
+    let (width, height) = actual.dimensions();
+    let mut diff = RgbaImage::new(width, height, Rgba::BLACK);
+
+    for (i, (e, a)) in expected.data.iter().zip(&actual.data).enumerate() {
+        let pixel = if channel_diff(*e, *a) > tolerance.per_channel {
+            Rgba {
+                r: 255,
+                g: 0,
+                b: 0,
+                a: 255,
+            }
+        } else {
+            let gray = ((a.r as u16 + a.g as u16 + a.b as u16) / 3 / 4) as u8;
+            Rgba {
+                r: gray,
+                g: gray,
+                b: gray,
+                a: 255,
+            }
+        };
+        diff.put_pixel(i % width, i / width, pixel);
+    }
+
+    diff
+}
+
+fn channel_diff(a: Rgba, b: Rgba) -> u8 {
+    a.r.abs_diff(b.r)
+        .max(a.g.abs_diff(b.g))
+        .max(a.b.abs_diff(b.b))
+        .max(a.a.abs_diff(b.a))
+}
+
+fn write_output(name: &str, kind: &str, img: &RgbaImage) {
+    let dir = output_dir();
+    fs::create_dir_all(&dir).unwrap();
+    img.save(dir.join(format!("{name}.{kind}.png"))).unwrap();
+}
+
+fn read_qoi(path: &PathBuf) -> Option<RgbaImage> {
+    let data = fs::read(path).ok()?;
+    Some(qoi::decode(&data).unwrap())
+}
+
+fn write_qoi(path: &PathBuf, img: &RgbaImage) {
+    img.save(path).unwrap();
+}
@@ -0,0 +1,6 @@
+//! Shared helpers for the tests of the leo project.
+//!
+//! The integration tests themselves live in `tests/`.
+
+pub mod fuzz;
+pub mod golden;
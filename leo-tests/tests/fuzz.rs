@@ -0,0 +1,199 @@
+use std::fs;
+
+use imglib::{bmp, gif, jpeg, png, pnm, qoi};
+use leo_tests::fuzz::{Failure, TrackingAllocator, check_input, fuzz, sample_dir};
+
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
+
+fn sample(name: &str) -> Vec<u8> {
+    fs::read(sample_dir().join(name)).unwrap()
+}
+
+#[test]
+fn qoi() {
+    fuzz("qoi", &[sample("fuzz/sample.qoi")], &[0], |data| {
+        let _ = qoi::decode(data);
+    });
+}
+
+#[test]
+fn png() {
+    fuzz("png", &[sample("fuzz/sample.png")], &[0], |data| {
+        let _ = png::decode(data);
+    });
+}
+
+#[test]
+fn bmp() {
+    fuzz("bmp", &[sample("fuzz/sample.bmp")], &[0], |data| {
+        let _ = bmp::decode(data);
+    });
+}
+
+#[test]
+fn pnm() {
+    fuzz("pnm", &[sample("fuzz/sample.pam")], &[0], |data| {
+        let _ = pnm::decode(data);
+    });
+}
+
+#[test]
+fn jpeg() {
+    let samples = [sample("fuzz/sample.jpg"), sample("fuzz/progressive.jpg")];
+    fuzz("jpeg", &samples, &[0], |data| {
+        let _ = jpeg::decode(data);
+    });
+}
+
+#[test]
+fn gif() {
+    fuzz("gif", &[sample("fuzz/sample.gif")], &[0], |data| {
+        let _ = gif::decode_animation(data);
+    });
+}
+
+#[test]
+fn format_detection() {
+    let samples = [
+        sample("fuzz/sample.qoi"),
+        sample("fuzz/sample.png"),
+        sample("fuzz/sample.gif"),
+    ];
+    fuzz("load", &samples, &[0], |data| {
+        let _ = imglib::load(data);
+    });
+}
+
+/// The starts of the font's tables, that is where the offsets and counts are
+fn table_offsets(font: &[u8]) -> Vec<usize> {
+    let num_tables = u16::from_be_bytes([font[4], font[5]]) as usize;
+    let mut hot = vec![0];
+    hot.extend((0..num_tables).map(|i| {
+        let record = &font[12 + i * 16..];
+        u32::from_be_bytes([record[8], record[9], record[10], record[11]]) as usize
+    }));
+    hot
+}
+
+#[test]
+fn ttf() {
+    let font = sample("Roboto-Regular.ttf");
+    let hot = table_offsets(&font);
+
+    fuzz("ttf", &[font], &hot, |data| {
+        if let Ok(font) = ttflib::load_ttf(data) {
+            // characters that are not in the font use the missing glyph
+            for c in ['a', 'Q', '@', ' ', '\u{e9}', '\u{10ffff}'] {
+                let id = font.glyph_id_with_variation(c, '\u{fe0f}');
+                let _ = font.outline(id.unwrap_or(ttflib::GlyphId::NOTDEF));
+            }
+            let features = ttflib::Features {
+                tags: vec![*b"liga", *b"kern", *b"smcp"],
+                ..Default::default()
+            };
+            let _ = ttflib::shape("AVAfi To\u{e9}", &font, &features);
+            let _ = (font.family_name(), font.face_style());
+        }
+    });
+}
+
+#[test]
+fn otf() {
+    let font = sample("RobotoCFF-Regular.otf");
+    let hot = table_offsets(&font);
+
+    fuzz("otf", &[font], &hot, |data| {
+        if let Ok(font) = ttflib::load_ttf(data) {
+            for id in 0..font.num_glyphs() {
+                let _ = font.glyph_outline(ttflib::GlyphId(id));
+            }
+        }
+    });
+}
+
+/// Set the width and height of an image to the values, for every format in its own way
+fn with_dimensions(format: &str, data: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let mut data = data.to_vec();
+    match format {
+        "qoi" => {
+            data[4..8].copy_from_slice(&width.to_be_bytes());
+            data[8..12].copy_from_slice(&height.to_be_bytes());
+        }
+        "png" => {
+            data[16..20].copy_from_slice(&width.to_be_bytes());
+            data[20..24].copy_from_slice(&height.to_be_bytes());
+            let crc = png::crc32(&data[12..29]);
+            data[29..33].copy_from_slice(&crc.to_be_bytes());
+        }
+        "bmp" => {
+            data[18..22].copy_from_slice(&width.to_le_bytes());
+            data[22..26].copy_from_slice(&height.to_le_bytes());
+        }
+        "pam" => {
+            let text = String::from_utf8_lossy(&data).replacen(
+                "WIDTH 24\nHEIGHT 16",
+                &format!("WIDTH {width}\nHEIGHT {height}"),
+                1,
+            );
+            data = text.into_bytes();
+        }
+        "gif" => {
+            data[6..8].copy_from_slice(&(width as u16).to_le_bytes());
+            data[8..10].copy_from_slice(&(height as u16).to_le_bytes());
+            // the first frame covers the whole screen
+            let frame = data.iter().position(|&b| b == 0x2c).unwrap();
+            data[frame + 5..frame + 7].copy_from_slice(&(width as u16).to_le_bytes());
+            data[frame + 7..frame + 9].copy_from_slice(&(height as u16).to_le_bytes());
+        }
+        "jpg" => {
+            let sof = data.windows(2).position(|w| w == [0xff, 0xc0]).unwrap();
+            data[sof + 5..sof + 7].copy_from_slice(&(height as u16).to_be_bytes());
+            data[sof + 7..sof + 9].copy_from_slice(&(width as u16).to_be_bytes());
+        }
+        _ => unreachable!(),
+    }
+    data
+}
+
+#[test]
+fn huge_dimensions() {
+    // sizes below and above the pixel limit, which the few bytes of data can't fill
+    let sizes = [
+        (20_000, 20_000),
+        (65_535, 6_000),
+        (65_535, 65_535),
+        (1, 400_000_000),
+        (0x7fff_ffff, 2),
+        (u32::MAX, u32::MAX),
+    ];
+    for format in ["qoi", "png", "bmp", "pam", "gif", "jpg"] {
+        let valid = sample(&format!("fuzz/sample.{format}"));
+        for (width, height) in sizes {
+            // gif and jpeg store the size in 16 bits
+            if matches!(format, "gif" | "jpg") && width.max(height) > u16::MAX as u32 {
+                continue;
+            }
+            let data = with_dimensions(format, &valid, width, height);
+            let res = check_input(&data, &|data: &[u8]| {
+                assert!(imglib::load(data).is_err(), "the image can't be decoded");
+            });
+            assert_eq!(res, None, "{format} with size {width}x{height}");
+        }
+    }
+}
+
+#[test]
+fn failures_are_found() {
+    assert_eq!(check_input(&[1], &|_: &[u8]| {}), None);
+    assert_eq!(
+        check_input(&[1], &|data: &[u8]| panic!("bad {}", data[0])),
+        Some(Failure::Panic("bad 1".to_string()))
+    );
+    assert_eq!(
+        check_input(&[1], &|data: &[u8]| {
+            std::hint::black_box(vec![data[0]; 100 << 20]);
+        }),
+        Some(Failure::Allocation(100 << 20))
+    );
+}
@@ -0,0 +1,223 @@
+use drawlib::{
+    drawable::Drawable,
+    glyph_cache::GlyphCache,
+    path::Path,
+    shape_primitive::{circle::Circle, polyline::PolyLine, rect::Rect},
+    text::{get_char_path, text_primitives},
+};
+use imglib::{Rgba, RgbaImage};
+use leo_tests::golden::{Tolerance, assert_golden};
+use mathlib::{
+    color::ColA,
+    vectors::{Vec2, Vec2F},
+};
+use renderlib::{
+    material::Material,
+    primitive::{Mesh, MeshType, Primitive},
+};
+
+/// the renderer is deterministic but allow for tiny floating point differences
+/// between platforms along the edges of shapes.
+const TOLERANCE: Tolerance = Tolerance {
+    per_channel: 2,
+    max_pixels: 16,
+};
+
+fn render(width: usize, height: usize, primitives: &[Primitive]) -> RgbaImage {
+    let mut img = RgbaImage::new(width, height, Rgba::BLACK);
+    renderlib::draw_primitives(primitives, &mut img);
+    img
+}
+
+fn quad(x: f32, y: f32, width: f32, height: f32, material: Material) -> Primitive {
+    Primitive {
+        mesh: Mesh {
+            ty: MeshType::Triangle,
+            vertices: vec![
+                Vec2F::new(x, y),
+                Vec2F::new(x + width, y),
+                Vec2F::new(x + width, y + height),
+                Vec2F::new(x, y + height),
+            ],
+            indices: vec![0, 1, 2, 0, 2, 3],
+        },
+        material,
+    }
+}
+
+#[test]
+fn gradient_and_blending() {
+    let primitives = vec![
+        quad(
+            0.0,
+            0.0,
+            256.0,
+            128.0,
+            Material::SimpleGradient {
+                color1: ColA::RED,
+                color2: ColA::BLUE,
+                direction: 0.0,
+                size: 256.0,
+            },
+        ),
+        quad(
+            64.0,
+            64.0,
+            128.0,
+            128.0,
+            Material::SingleColor(ColA {
+                r: 0.0,
+                g: 1.0,
+                b: 0.0,
+                a: 0.5,
+            }),
+        ),
+    ];
+    assert_golden(
+        "gradient_and_blending",
+        &render(256, 256, &primitives),
+        TOLERANCE,
+    );
+}
+
+#[test]
+fn stroke_polyline() {
+    let line = PolyLine::new(vec![20.0, 20.0, 100.0, 200.0, 180.0, 40.0, 236.0, 236.0]);
+    assert_golden(
+        "stroke_polyline",
+        &render(256, 256, &line.to_primitives()),
+        TOLERANCE,
+    );
+}
+
+#[test]
+fn stroke_curves() {
+    let mut path = Path::new();
+    path.move_to(Vec2::new(20.0, 128.0));
+    path.q_bezier_to(Vec2::new(70.0, 0.0), Vec2::new(128.0, 128.0));
+    path.c_bezier_to(
+        Vec2::new(160.0, 256.0),
+        Vec2::new(200.0, 0.0),
+        Vec2::new(236.0, 200.0),
+    );
+    assert_golden(
+        "stroke_curves",
+        &render(256, 256, &path.to_primitives()),
+        TOLERANCE,
+    );
+}
+
+#[test]
+fn stroke_shapes() {
+    let mut primitives = Rect::new(20.0, 20.0, 120.0, 80.0, 0.0, 0.0).to_primitives();
+    primitives.append(&mut Rect::new(40.0, 140.0, 180.0, 90.0, 20.0, 30.0).to_primitives());
+    primitives.append(&mut Circle::new(190.0, 70.0, 45.0).to_primitives());
+    assert_golden("stroke_shapes", &render(256, 256, &primitives), TOLERANCE);
+}
+
+#[test]
+fn text_glyph() {
+    let source = std::fs::read("../test-data/Roboto-Regular.ttf").unwrap();
+    let font = ttflib::load_ttf(&source).unwrap();
+    let path = get_char_path('a', &font, 512.0);
+    assert_golden(
+        "text_glyph",
+        &render(320, 512, &path.to_primitives()),
+        TOLERANCE,
+    );
+}
+
+#[test]
+fn text_composite_glyph() {
+    let source = std::fs::read("../test-data/Roboto-Regular.ttf").unwrap();
+    let font = ttflib::load_ttf(&source).unwrap();
+    // e with a circumflex and a dot below, a composite that has a composite component
+    let path = get_char_path('\u{1ec7}', &font, 512.0);
+    assert_golden(
+        "text_composite_glyph",
+        &render(320, 640, &path.to_primitives()),
+        TOLERANCE,
+    );
+}
+
+#[test]
+fn text_cached_glyphs() {
+    let source = std::fs::read("../test-data/Roboto-Regular.ttf").unwrap();
+    let font = ttflib::load_ttf(&source).unwrap();
+    let mut cache = GlyphCache::new(256, 256);
+    let mut primitives = text_primitives(
+        "Hello, fine AVA W\u{f6}rld!",
+        &font,
+        28.0,
+        Vec2::new(8.0, 36.0),
+        ColA::WHITE,
+        &mut cache,
+    );
+    // the second line reuses the glyphs of the first
+    let glyphs = cache.len();
+    primitives.append(&mut text_primitives(
+        "Hello, fine AVA W\u{f6}rld!",
+        &font,
+        28.0,
+        Vec2::new(8.0, 76.0),
+        ColA::new(1.0, 0.8, 0.2, 0.8),
+        &mut cache,
+    ));
+    assert_eq!(cache.len(), glyphs);
+    assert_golden(
+        "text_cached_glyphs",
+        &render(320, 96, &primitives),
+        TOLERANCE,
+    );
+}
+
+#[test]
+fn text_small_hinted() {
+    let source = std::fs::read("../test-data/Roboto-Regular.ttf").unwrap();
+    let font = ttflib::load_ttf(&source).unwrap();
+    let mut primitives = Vec::new();
+    // hinted on the left and as the outlines are on the right
+    for (hinting, x) in [(true, 4.0), (false, 164.0)] {
+        let mut cache = GlyphCache::new(256, 256);
+        cache.set_hinting(hinting);
+        for (i, size) in [9.0, 11.0, 13.0].into_iter().enumerate() {
+            primitives.append(&mut text_primitives(
+                "Hinted text 123",
+                &font,
+                size,
+                Vec2::new(x, 14.0 + 18.0 * i as f32),
+                ColA::WHITE,
+                &mut cache,
+            ));
+        }
+    }
+    assert_golden(
+        "text_small_hinted",
+        &render(320, 64, &primitives),
+        TOLERANCE,
+    );
+}
+
+#[test]
+fn text_cff() {
+    let source = std::fs::read("../test-data/RobotoCFF-Regular.otf").unwrap();
+    let font = ttflib::load_ttf(&source).unwrap();
+    // cubic curves as a path and in the glyph cache
+    let mut primitives = get_char_path('\u{e1}', &font, 256.0).to_primitives();
+    let mut cache = GlyphCache::new(256, 256);
+    primitives.append(&mut text_primitives(
+        "Hebe, banana! \u{e1}no",
+        &font,
+        28.0,
+        Vec2::new(8.0, 300.0),
+        ColA::WHITE,
+        &mut cache,
+    ));
+    assert_golden("text_cff", &render(320, 320, &primitives), TOLERANCE);
+}
+
+#[test]
+fn gui_layout() {
+    let primitives = drawlib::tesselate(&guilib::gui_test());
+    assert_golden("gui_layout", &render(600, 1000, &primitives), TOLERANCE);
+}
@@ -0,0 +1,170 @@
+/*
+ * Writes the JPEG reference images in this directory with libjpeg (libjpeg-turbo 2.1.5),
+ * and the PNG with the pixels libjpeg decodes each of them to. The source image is made
+ * up, with smooth gradients, hard edges in saturated colors, fine detail and noise.
+ *
+ * libjpeg has no conversion from CMYK to RGB, the expected pixels of the Adobe files use
+ * the convention of Photoshop, which stores inverted CMYK: red is C * K / 255.
+ *
+ * Build and run it in this directory:
+ *   cc make_fixtures.c -o /tmp/make_fixtures -ljpeg -lpng && /tmp/make_fixtures
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <jpeglib.h>
+#include <png.h>
+
+#define WIDTH 227
+#define HEIGHT 149
+
+static unsigned char source[HEIGHT][WIDTH][3];
+
+static void make_source(void) {
+    unsigned int seed = 1;
+    for (int y = 0; y < HEIGHT; y++) {
+        for (int x = 0; x < WIDTH; x++) {
+            int r = x * 255 / WIDTH, g = y * 255 / HEIGHT, b = 255 - (x + y) * 255 / (WIDTH + HEIGHT);
+            int dx = x - 70, dy = y - 60;
+            if (dx * dx + dy * dy < 40 * 40) {
+                r = 230, g = 20, b = 40;
+            }
+            dx = x - 160, dy = y - 90;
+            if (dx * dx + dy * dy < 35 * 35) {
+                r = 10, g = 40, b = 220;
+            }
+            if (x > 120 && x < 200 && y > 15 && y < 45 && ((x / 2 + y / 2) % 2 == 0)) {
+                r = g = b = 250;
+            }
+            seed = seed * 1103515245 + 12345;
+            int noise = (int)((seed >> 16) % 21) - 10;
+            int v[3] = {r + noise, g + noise, b + noise};
+            for (int c = 0; c < 3; c++) {
+                source[y][x][c] = v[c] < 0 ? 0 : v[c] > 255 ? 255 : v[c];
+            }
+        }
+    }
+}
+
+struct options {
+    const char *name;
+    J_COLOR_SPACE space;
+    int quality;
+    int h, v; /* sampling factors of the luma component */
+    int progressive;
+    int restart_interval;
+    int optimize;
+};
+
+static void write_jpeg(const struct options *o) {
+    char path[64];
+    snprintf(path, sizeof path, "%s.jpg", o->name);
+    FILE *f = fopen(path, "wb");
+    struct jpeg_compress_struct cinfo;
+    struct jpeg_error_mgr jerr;
+    cinfo.err = jpeg_std_error(&jerr);
+    jpeg_create_compress(&cinfo);
+    jpeg_stdio_dest(&cinfo, f);
+
+    int gray = o->space == JCS_GRAYSCALE;
+    int cmyk = o->space == JCS_CMYK || o->space == JCS_YCCK;
+    cinfo.image_width = WIDTH;
+    cinfo.image_height = HEIGHT;
+    cinfo.input_components = gray ? 1 : cmyk ? 4 : 3;
+    cinfo.in_color_space = gray ? JCS_GRAYSCALE : cmyk ? JCS_CMYK : JCS_RGB;
+    jpeg_set_defaults(&cinfo);
+    jpeg_set_colorspace(&cinfo, o->space);
+    jpeg_set_quality(&cinfo, o->quality, TRUE);
+    cinfo.comp_info[0].h_samp_factor = o->h;
+    cinfo.comp_info[0].v_samp_factor = o->v;
+    cinfo.restart_interval = o->restart_interval;
+    cinfo.optimize_coding = o->optimize;
+    if (o->progressive) {
+        jpeg_simple_progression(&cinfo);
+    }
+    jpeg_start_compress(&cinfo, TRUE);
+
+    unsigned char row[WIDTH * 4];
+    while (cinfo.next_scanline < HEIGHT) {
+        unsigned char (*src)[3] = source[cinfo.next_scanline];
+        for (int x = 0; x < WIDTH; x++) {
+            int r = src[x][0], g = src[x][1], b = src[x][2];
+            if (gray) {
+                row[x] = (r * 77 + g * 150 + b * 29 + 128) >> 8;
+            } else if (cmyk) {
+                /* inverted cmyk, 255 is no ink */
+                int k = r > g ? (r > b ? r : b) : (g > b ? g : b);
+                row[x * 4] = k ? r * 255 / k : 0;
+                row[x * 4 + 1] = k ? g * 255 / k : 0;
+                row[x * 4 + 2] = k ? b * 255 / k : 0;
+                row[x * 4 + 3] = k;
+            } else {
+                memcpy(&row[x * 3], src[x], 3);
+            }
+        }
+        JSAMPROW rows[1] = {row};
+        jpeg_write_scanlines(&cinfo, rows, 1);
+    }
+    jpeg_finish_compress(&cinfo);
+    jpeg_destroy_compress(&cinfo);
+    fclose(f);
+}
+
+static void write_expected(const char *name) {
+    char path[64];
+    snprintf(path, sizeof path, "%s.jpg", name);
+    FILE *f = fopen(path, "rb");
+    struct jpeg_decompress_struct dinfo;
+    struct jpeg_error_mgr jerr;
+    dinfo.err = jpeg_std_error(&jerr);
+    jpeg_create_decompress(&dinfo);
+    jpeg_stdio_src(&dinfo, f);
+    jpeg_read_header(&dinfo, TRUE);
+    int cmyk = dinfo.jpeg_color_space == JCS_CMYK || dinfo.jpeg_color_space == JCS_YCCK;
+    dinfo.out_color_space = cmyk ? JCS_CMYK : JCS_RGB;
+    dinfo.dct_method = JDCT_ISLOW;
+    jpeg_start_decompress(&dinfo);
+
+    unsigned char *pixels = malloc(WIDTH * HEIGHT * 3);
+    unsigned char row[WIDTH * 4];
+    while (dinfo.output_scanline < HEIGHT) {
+        unsigned char *out = pixels + dinfo.output_scanline * WIDTH * 3;
+        JSAMPROW rows[1] = {row};
+        jpeg_read_scanlines(&dinfo, rows, 1);
+        for (int x = 0; x < WIDTH; x++) {
+            for (int c = 0; c < 3; c++) {
+                out[x * 3 + c] = cmyk ? (row[x * 4 + c] * row[x * 4 + 3] + 127) / 255 : row[x * 3 + c];
+            }
+        }
+    }
+    jpeg_finish_decompress(&dinfo);
+    jpeg_destroy_decompress(&dinfo);
+    fclose(f);
+
+    snprintf(path, sizeof path, "%s.png", name);
+    png_image image;
+    memset(&image, 0, sizeof image);
+    image.version = PNG_IMAGE_VERSION;
+    image.width = WIDTH;
+    image.height = HEIGHT;
+    image.format = PNG_FORMAT_RGB;
+    png_image_write_to_file(&image, path, 0, pixels, 0, NULL);
+    free(pixels);
+}
+
+int main(void) {
+    static const struct options files[] = {
+        {"baseline_420", JCS_YCbCr, 75, 2, 2, 0, 0, 0},
+        {"progressive_420", JCS_YCbCr, 85, 2, 2, 1, 0, 0},
+        {"restart_422", JCS_YCbCr, 90, 2, 1, 0, 5, 1},
+        {"grayscale", JCS_GRAYSCALE, 75, 1, 1, 0, 0, 0},
+        {"adobe_cmyk", JCS_CMYK, 90, 1, 1, 0, 0, 0},
+        {"adobe_ycck", JCS_YCCK, 90, 2, 2, 0, 0, 0},
+    };
+    make_source();
+    for (size_t i = 0; i < sizeof files / sizeof files[0]; i++) {
+        write_jpeg(&files[i]);
+        write_expected(files[i].name);
+    }
+    return 0;
+}
@@ -0,0 +1,407 @@
+#!/usr/bin/env python3
+"""Writes RobotoCFF-Regular.otf, an OpenType font with CFF outlines made from 11 glyphs of
+Roboto-Regular.ttf: .notdef, space, ! , H a b e n o and a with an acute accent.
+
+Roboto is licensed under the Apache License, Version 2.0, and so is this font, see
+README.md. The quadratic TrueType contours are converted to cubic curves exactly (control points
+rounded to half units), the metrics and OS/2 table are copied. The charstrings use the
+shorthand line and curve operators, 'H' has stem hints and a hint mask, 'a' is a local
+subroutine that 'a' with an acute accent calls before a global subroutine with the accent.
+
+Run it in test-data: python3 make_roboto_cff.py Roboto-Regular.ttf RobotoCFF-Regular.otf
+"""
+import struct, sys
+
+src = open(sys.argv[1], 'rb').read()
+n = struct.unpack('>H', src[4:6])[0]
+T = {}
+for i in range(n):
+    tag, cs, off, ln = struct.unpack('>4sIII', src[12 + 16 * i:28 + 16 * i])
+    T[tag] = src[off:off + ln]
+
+head = T[b'head']
+upem = struct.unpack('>H', head[18:20])[0]
+locfmt = struct.unpack('>h', head[50:52])[0]
+maxp = T[b'maxp']
+numg = struct.unpack('>H', maxp[4:6])[0]
+hhea = T[b'hhea']
+nh = struct.unpack('>H', hhea[34:36])[0]
+hmtx = T[b'hmtx']
+loca = T[b'loca']
+glyf = T[b'glyf']
+
+def loc(g):
+    if locfmt:
+        return struct.unpack('>II', loca[4 * g:4 * g + 8])
+    a, b = struct.unpack('>HH', loca[2 * g:2 * g + 4])
+    return 2 * a, 2 * b
+
+def metrics(g):
+    i = min(g, nh - 1)
+    aw = struct.unpack('>H', hmtx[4 * i:4 * i + 2])[0]
+    if g < nh:
+        lsb = struct.unpack('>h', hmtx[4 * i + 2:4 * i + 4])[0]
+    else:
+        p = 4 * nh + 2 * (g - nh)
+        lsb = struct.unpack('>h', hmtx[p:p + 2])[0]
+    return aw, lsb
+
+def cmap_lookup():
+    cm = T[b'cmap']
+    nt = struct.unpack('>H', cm[2:4])[0]
+    for i in range(nt):
+        pid, eid, off = struct.unpack('>HHI', cm[4 + 8 * i:12 + 8 * i])
+        fmt = struct.unpack('>H', cm[off:off + 2])[0]
+        if fmt == 4:
+            sub = cm[off:]
+            segx2 = struct.unpack('>H', sub[6:8])[0]
+            seg = segx2 // 2
+            ends = struct.unpack('>%dH' % seg, sub[14:14 + segx2])
+            starts = struct.unpack('>%dH' % seg, sub[16 + segx2:16 + 2 * segx2])
+            deltas = struct.unpack('>%dh' % seg, sub[16 + 2 * segx2:16 + 3 * segx2])
+            ros = 16 + 3 * segx2
+            rangeoffs = struct.unpack('>%dH' % seg, sub[ros:ros + segx2])
+            def look(c):
+                for k in range(seg):
+                    if starts[k] <= c <= ends[k]:
+                        if rangeoffs[k] == 0:
+                            return (c + deltas[k]) & 0xffff
+                        p = ros + 2 * k + rangeoffs[k] + 2 * (c - starts[k])
+                        g = struct.unpack('>H', sub[p:p + 2])[0]
+                        return (g + deltas[k]) & 0xffff if g else 0
+                return 0
+            return look
+
+look = cmap_lookup()
+
+def contours(g):
+    """contours as lists of (x, y, on) in font units, composites flattened"""
+    a, b = loc(g)
+    if a == b:
+        return []
+    d = glyf[a:b]
+    nc = struct.unpack('>h', d[0:2])[0]
+    if nc < 0:
+        out = []
+        p = 10
+        while True:
+            fl, gi = struct.unpack('>HH', d[p:p + 4]); p += 4
+            if fl & 1:
+                a1, a2 = struct.unpack('>hh', d[p:p + 4]); p += 4
+            else:
+                a1, a2 = struct.unpack('>bb', d[p:p + 2]); p += 2
+            assert fl & 2 and not fl & 0xc8
+            for c in contours(gi):
+                out.append([(x + a1, y + a2, on) for x, y, on in c])
+            if not fl & 0x20:
+                break
+        return out
+    ends = struct.unpack('>%dH' % nc, d[10:10 + 2 * nc])
+    npts = ends[-1] + 1
+    il = struct.unpack('>H', d[10 + 2 * nc:12 + 2 * nc])[0]
+    p = 12 + 2 * nc + il
+    flags = []
+    while len(flags) < npts:
+        f = d[p]; p += 1
+        flags.append(f)
+        if f & 8:
+            r = d[p]; p += 1
+            flags += [f] * r
+    xs = []
+    v = 0
+    for f in flags:
+        if f & 2:
+            dv = d[p]; p += 1
+            v += dv if f & 16 else -dv
+        elif not f & 16:
+            v += struct.unpack('>h', d[p:p + 2])[0]; p += 2
+        xs.append(v)
+    ys = []
+    v = 0
+    for f in flags:
+        if f & 4:
+            dv = d[p]; p += 1
+            v += dv if f & 32 else -dv
+        elif not f & 32:
+            v += struct.unpack('>h', d[p:p + 2])[0]; p += 2
+        ys.append(v)
+    out = []
+    s = 0
+    for e in ends:
+        out.append([(xs[i], ys[i], flags[i] & 1) for i in range(s, e + 1)])
+        s = e + 1
+    return out
+
+def half(v):
+    return round(v * 2) / 2
+
+def segments(contour):
+    """(start, [('L', p) | ('C', c1, c2, p)]) with cubic curves"""
+    pts = contour
+    k = next((i for i, p in enumerate(pts) if p[2]), None)
+    if k is None:
+        a, b = pts[0], pts[-1]
+        start = ((a[0] + b[0]) / 2, (a[1] + b[1]) / 2)
+        seq = pts + [(start[0], start[1], 1)]
+    else:
+        start = pts[k][:2]
+        seq = pts[k + 1:] + pts[:k + 1]
+    segs = []
+    cur = start
+    ctrl = None
+    def quad(c, p):
+        c1 = (half(cur[0] + 2 / 3 * (c[0] - cur[0])), half(cur[1] + 2 / 3 * (c[1] - cur[1])))
+        c2 = (half(p[0] + 2 / 3 * (c[0] - p[0])), half(p[1] + 2 / 3 * (c[1] - p[1])))
+        segs.append(('C', c1, c2, p))
+    for x, y, on in seq:
+        p = (x, y)
+        if on:
+            if ctrl is None:
+                segs.append(('L', p))
+            else:
+                quad(ctrl, p)
+                ctrl = None
+            cur = p
+        else:
+            if ctrl is not None:
+                mid = ((ctrl[0] + x) / 2, (ctrl[1] + y) / 2)
+                quad(ctrl, mid)
+                cur = mid
+            ctrl = p
+    # the closing line is implied
+    if segs and segs[-1][0] == 'L' and segs[-1][1] == start:
+        segs.pop()
+    return start, segs
+
+def num(v):
+    if v != int(v):
+        f = int(round(v * 65536))
+        return bytes([255]) + struct.pack('>i', f)
+    v = int(v)
+    if -107 <= v <= 107:
+        return bytes([v + 139])
+    if 108 <= v <= 1131:
+        v -= 108
+        return bytes([(v >> 8) + 247, v & 0xff])
+    if -1131 <= v <= -108:
+        v = -v - 108
+        return bytes([(v >> 8) + 251, v & 0xff])
+    return bytes([28]) + struct.pack('>h', v)
+
+RMOVETO, HMOVETO, VMOVETO = 21, 22, 4
+RLINETO, HLINETO, VLINETO = 5, 6, 7
+RRCURVETO, HHCURVETO, VVCURVETO, HVCURVETO, VHCURVETO = 8, 27, 26, 31, 30
+CALLSUBR, CALLGSUBR, RETURN, ENDCHAR = 10, 29, 11, 14
+HSTEM, VSTEM, HINTMASK = 1, 3, 19
+
+def ops(args, op):
+    return b''.join(num(a) for a in args) + bytes([op])
+
+def encode(contours, pen):
+    """charstring bytes for contours starting from pen, returns the bytes and the pen"""
+    out = b''
+    for c in contours:
+        start, segs = segments(c)
+        dx, dy = start[0] - pen[0], start[1] - pen[1]
+        if dx == 0:
+            out += ops([dy], VMOVETO)
+        elif dy == 0:
+            out += ops([dx], HMOVETO)
+        else:
+            out += ops([dx, dy], RMOVETO)
+        cur = start
+        group = []  # pending rlineto or rrcurveto args
+        gop = None
+        def flush():
+            nonlocal out, group, gop
+            if group:
+                out += ops(group, gop)
+            group, gop = [], None
+        for s in segs:
+            if s[0] == 'L':
+                p = s[1]
+                dx, dy = p[0] - cur[0], p[1] - cur[1]
+                if dx == 0:
+                    flush(); out += ops([dy], VLINETO)
+                elif dy == 0:
+                    flush(); out += ops([dx], HLINETO)
+                else:
+                    if gop != RLINETO or len(group) >= 46:
+                        flush()
+                    gop = RLINETO
+                    group += [dx, dy]
+                cur = p
+            else:
+                _, c1, c2, p = s
+                d1 = (c1[0] - cur[0], c1[1] - cur[1])
+                d2 = (c2[0] - c1[0], c2[1] - c1[1])
+                d3 = (p[0] - c2[0], p[1] - c2[1])
+                if d1[1] == 0 and d3[1] == 0:
+                    flush(); out += ops([d1[0], d2[0], d2[1], d3[0]], HHCURVETO)
+                elif d1[0] == 0 and d3[0] == 0:
+                    flush(); out += ops([d1[1], d2[0], d2[1], d3[1]], VVCURVETO)
+                elif d3[1] == 0 and d1[0] != 0 and d1[1] != 0:
+                    # hhcurveto with a vertical start
+                    flush(); out += ops([d1[1], d1[0], d2[0], d2[1], d3[0]], HHCURVETO)
+                elif d1[1] == 0 and d3[0] == 0:
+                    flush(); out += ops([d1[0], d2[0], d2[1], d3[1]], HVCURVETO)
+                elif d1[0] == 0 and d3[1] == 0:
+                    flush(); out += ops([d1[1], d2[0], d2[1], d3[0]], VHCURVETO)
+                else:
+                    if gop != RRCURVETO or len(group) >= 42:
+                        flush()
+                    gop = RRCURVETO
+                    group += [d1[0], d1[1], d2[0], d2[1], d3[0], d3[1]]
+                cur = p
+        flush()
+        pen = cur
+    return out, pen
+
+def last_point(contours):
+    start, segs = segments(contours[-1])
+    return segs[-1][-1] if segs else start
+
+chars = [None, ' ', '!', ',', 'H', 'a', 'b', 'e', 'n', 'o', 'á']
+gids = [0] + [look(ord(c)) for c in chars[1:]]
+assert all(gids[1:]), gids
+NOMINAL, DEFAULT = 600, 1100
+
+local_subrs = []
+global_subrs = []
+charstrings = []
+a_index = chars.index('a')
+a_contours = contours(gids[a_index])
+for k, c in enumerate(chars):
+    g = gids[k]
+    aw, lsb = metrics(g)
+    cs = contours(g)
+    width = b'' if aw == DEFAULT else num(aw - NOMINAL)
+    if c == 'H':
+        # stems and a hint mask that drawing ignores
+        body = width + ops([0, 170, 600, 160], HSTEM) + ops([150, 180, 700, 180], VSTEM)
+        body += bytes([HINTMASK, 0xf0])
+        body += encode(cs, (0, 0))[0]
+        width = b''
+    elif c == 'a':
+        # the outline is a subroutine that 'á' uses as well
+        sub, _ = encode(cs, (0, 0))
+        local_subrs.append(sub + bytes([RETURN]))
+        body = width + ops([0 - 107], CALLSUBR)
+        width = b''
+    elif c == 'á':
+        accent = cs[len(a_contours):]
+        assert cs[:len(a_contours)] == a_contours
+        sub, _ = encode(accent, last_point(a_contours))
+        global_subrs.append(sub + bytes([RETURN]))
+        body = width + ops([0 - 107], CALLSUBR) + ops([0 - 107], CALLGSUBR)
+        width = b''
+    else:
+        body = width + encode(cs, (0, 0))[0]
+        width = b''
+    charstrings.append(body + bytes([ENDCHAR]))
+
+def index(items):
+    if not items:
+        return struct.pack('>H', 0)
+    offs = [1]
+    for it in items:
+        offs.append(offs[-1] + len(it))
+    size = 1 if offs[-1] < 256 else 2 if offs[-1] < 65536 else 4
+    fmt = {1: '>B', 2: '>H', 4: '>I'}[size]
+    return (struct.pack('>HB', len(items), size) + b''.join(struct.pack(fmt, o) for o in offs)
+            + b''.join(items))
+
+def dict_int(v):
+    # always 5 bytes so that offsets can be filled in later
+    return bytes([29]) + struct.pack('>i', v)
+
+def dict_num(v):
+    if -107 <= v <= 107:
+        return bytes([v + 139])
+    return bytes([28]) + struct.pack('>h', v)
+
+name = b'RobotoCFF-Regular'
+strings = []
+header = bytes([1, 0, 4, 4])
+
+def build(cs_off, priv_off, priv_len):
+    top = (dict_num(-1509) + dict_num(-555) + dict_num(2352) + dict_num(2163) + bytes([5])  # FontBBox
+           + dict_int(cs_off) + bytes([17])
+           + dict_int(priv_len) + dict_int(priv_off) + bytes([18]))
+    return top
+
+subrs_index = index(local_subrs)
+private = (dict_int(DEFAULT) + bytes([20]) + dict_int(NOMINAL) + bytes([21]))
+private += dict_int(len(private) + 6) + bytes([19])
+top = build(0, 0, 0)
+pre = header + index([name]) + index([top]) + index(strings) + index(global_subrs)
+cs_off = len(pre)
+cs_index = index(charstrings)
+priv_off = cs_off + len(cs_index)
+top = build(cs_off, priv_off, len(private))
+pre = header + index([name]) + index([top]) + index(strings) + index(global_subrs)
+assert len(pre) == cs_off
+cff = pre + cs_index + private + subrs_index
+
+# the other tables
+num_glyphs = len(gids)
+new_head = bytearray(head)
+new_head[8:12] = b'\0\0\0\0'
+new_hhea = bytearray(hhea)
+new_hhea[34:36] = struct.pack('>H', num_glyphs)
+new_hmtx = b''.join(struct.pack('>Hh', *metrics(g)) for g in gids)
+new_maxp = struct.pack('>IH', 0x5000, num_glyphs)
+
+# cmap format 4 with one segment per character
+codes = sorted((ord(c), k) for k, c in enumerate(chars) if c)
+segs = [(c, c, k - c) for c, k in codes] + [(0xffff, 0xffff, 1)]
+segx2 = 2 * len(segs)
+sub = struct.pack('>HHHHHHH', 4, 0, 0, segx2, 0, 0, 0)
+sub += b''.join(struct.pack('>H', e) for s, e, d in segs) + b'\0\0'
+sub += b''.join(struct.pack('>H', s) for s, e, d in segs)
+sub += b''.join(struct.pack('>h', d if d < 32768 else d - 65536) for s, e, d in segs)
+sub += b'\0\0' * len(segs)
+sub = sub[:2] + struct.pack('>H', len(sub)) + sub[4:]
+new_cmap = struct.pack('>HHHHI', 0, 1, 3, 1, 12) + sub
+
+def name_table(records):
+    data = b''
+    recs = b''
+    for nid, text in records:
+        enc = text.encode('utf-16-be')
+        recs += struct.pack('>HHHHHH', 3, 1, 0x409, nid, len(enc), len(data))
+        data += enc
+    return struct.pack('>HHH', 0, len(records), 6 + len(recs)) + recs + data
+
+family = 'Roboto CFF'
+# Roboto's copyright and license records are kept
+new_name = name_table([(0, 'Copyright 2011 Google Inc. All Rights Reserved.'),
+                       (1, family), (2, 'Regular'), (4, family + ' Regular'),
+                       (6, family.replace(' ', '') + '-Regular'),
+                       (13, 'Licensed under the Apache License, Version 2.0'),
+                       (14, 'http://www.apache.org/licenses/LICENSE-2.0')])
+new_post = struct.pack('>IIhhIIIII', 0x30000, 0, -150, 100, 0, 0, 0, 0, 0)
+
+tables = {
+    b'CFF ': cff, b'OS/2': T[b'OS/2'], b'cmap': new_cmap, b'head': bytes(new_head),
+    b'hhea': bytes(new_hhea), b'hmtx': new_hmtx, b'maxp': new_maxp, b'name': new_name,
+    b'post': new_post,
+}
+
+def checksum(d):
+    d = d + b'\0' * (-len(d) % 4)
+    return sum(struct.unpack('>%dI' % (len(d) // 4), d)) & 0xffffffff
+
+tags = sorted(tables)
+nt = len(tags)
+es = max(i for i in range(8) if 2 ** i <= nt)
+out = struct.pack('>4sHHHH', b'OTTO', nt, 16 * 2 ** es, es, nt * 16 - 16 * 2 ** es)
+off = 12 + 16 * nt
+body = b''
+for t in tags:
+    d = tables[t]
+    out += struct.pack('>4sIII', t, checksum(d), off + len(body), len(d))
+    body += d + b'\0' * (-len(d) % 4)
+font = out + body
+open(sys.argv[2], 'wb').write(font)
+print('glyphs', gids, 'cff', len(cff), 'font', len(font))
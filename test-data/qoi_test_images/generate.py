@@ -0,0 +1,93 @@
+#!/usr/bin/env python3
+"""Writes the PNGs of the QOI reference images in this directory, make_reference.c encodes
+them with the reference encoder. The images are made up to cover every chunk type, both
+channel counts, runs longer than a chunk and differences that wrap around.
+
+Run it in this directory: python3 generate.py
+"""
+
+import math
+import struct
+import zlib
+
+
+def png_encode(width, height, pixels, channels):
+    """An 8 bit RGB or RGBA PNG without filters"""
+    raw = b"".join(
+        b"\0" + b"".join(bytes(px[:channels]) for px in pixels[y * width : (y + 1) * width])
+        for y in range(height)
+    )
+
+    def chunk(kind, data):
+        return struct.pack(">I", len(data)) + kind + data + struct.pack(">I", zlib.crc32(kind + data))
+
+    color_type = 2 if channels == 3 else 6
+    header = struct.pack(">IIBBBBB", width, height, 8, color_type, 0, 0, 0)
+    return (
+        b"\x89PNG\r\n\x1a\n"
+        + chunk(b"IHDR", header)
+        + chunk(b"IDAT", zlib.compress(raw, 9))
+        + chunk(b"IEND", b"")
+    )
+
+
+class Lcg:
+    """Deterministic noise that doesn't depend on the Python version"""
+
+    def __init__(self, seed):
+        self.state = seed
+
+    def next(self, n):
+        self.state = (self.state * 1103515245 + 12345) % 2**31
+        return (self.state >> 8) % n
+
+
+def gradient(x, y):
+    return (x * 4 % 256, y * 4 % 256, (x + y) * 2 % 256, 255)
+
+
+def noise():
+    rng = Lcg(1)
+    return lambda x, y: (rng.next(256), rng.next(256), rng.next(256), rng.next(256))
+
+
+def palette():
+    rng = Lcg(2)
+    colors = [(rng.next(256), rng.next(256), rng.next(256), 128 + rng.next(128)) for _ in range(16)]
+    return lambda x, y: colors[(x // 3 + y // 5 + rng.next(3)) % 16]
+
+
+def runs(x, y):
+    # rows of 200 pixels are longer than three run chunks, the last run ends the image
+    return (0, 0, 0, 255) if y != 1 or x < 150 else (10, 20, 30, 255)
+
+
+def circles(x, y):
+    a = 0
+    for cx, cy, r in ((20, 20, 15), (40, 30, 12)):
+        d = math.hypot(x - cx, y - cy) - r
+        a = max(a, min(255, max(0, round((0.5 - d) * 255))))
+    return (200, 80 + x * 2, 40 + y * 3, a)
+
+
+def wrap(x, y):
+    # channel values stepping around 255 to 0 in small and large steps
+    step = 1 if y % 2 == 0 else 17
+    v = (250 + x * step) % 256
+    return (v, (v + 3) % 256, (256 - v) % 256, 255)
+
+
+IMAGES = [
+    # name, width, height, channels, pixel
+    ("gradient", 64, 64, 3, gradient),
+    ("noise", 32, 32, 4, noise()),
+    ("palette", 48, 48, 4, palette()),
+    ("runs", 200, 3, 3, runs),
+    ("circles", 60, 50, 4, circles),
+    ("wrap", 40, 4, 3, wrap),
+]
+
+for name, width, height, channels, pixel in IMAGES:
+    pixels = [pixel(x, y) for y in range(height) for x in range(width)]
+    with open(name + ".png", "wb") as f:
+        f.write(png_encode(width, height, pixels, channels))
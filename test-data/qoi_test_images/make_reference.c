@@ -0,0 +1,65 @@
+/*
+ * Encodes the PNGs in this directory with the reference encoder, qoi.h from
+ * https://github.com/phoboslab/qoi (unmodified, MIT license in its header), into the QOI
+ * files the tests decode. The PNGs are written by generate.py.
+ *
+ * Build and run it in this directory:
+ *   cc make_reference.c -o /tmp/make_reference -lpng && /tmp/make_reference
+ */
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <png.h>
+
+#define QOI_IMPLEMENTATION
+#include "qoi.h"
+
+struct image {
+    const char *name;
+    unsigned char channels;
+    unsigned char colorspace;
+};
+
+int main(void) {
+    static const struct image images[] = {
+        {"gradient", 3, QOI_SRGB},
+        {"noise", 4, QOI_SRGB},
+        {"palette", 4, QOI_SRGB},
+        {"runs", 3, QOI_SRGB},
+        {"circles", 4, QOI_LINEAR},
+        {"wrap", 3, QOI_SRGB},
+    };
+    for (size_t i = 0; i < sizeof images / sizeof images[0]; i++) {
+        const struct image *img = &images[i];
+        char path[64];
+        snprintf(path, sizeof path, "%s.png", img->name);
+
+        png_image png;
+        memset(&png, 0, sizeof png);
+        png.version = PNG_IMAGE_VERSION;
+        if (!png_image_begin_read_from_file(&png, path)) {
+            fprintf(stderr, "%s: %s\n", path, png.message);
+            return 1;
+        }
+        png.format = img->channels == 4 ? PNG_FORMAT_RGBA : PNG_FORMAT_RGB;
+        unsigned char *pixels = malloc(PNG_IMAGE_SIZE(png));
+        if (!png_image_finish_read(&png, NULL, pixels, 0, NULL)) {
+            fprintf(stderr, "%s: %s\n", path, png.message);
+            return 1;
+        }
+
+        qoi_desc desc = {
+            .width = png.width,
+            .height = png.height,
+            .channels = img->channels,
+            .colorspace = img->colorspace,
+        };
+        snprintf(path, sizeof path, "%s.qoi", img->name);
+        if (!qoi_write(path, pixels, &desc)) {
+            fprintf(stderr, "can't write %s\n", path);
+            return 1;
+        }
+        free(pixels);
+    }
+    return 0;
+}